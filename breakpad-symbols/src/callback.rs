@@ -0,0 +1,467 @@
+//! Contains a `SymbolSupplier` that gets its symbols from a callback
+
+use crate::*;
+use futures_util::stream::{BoxStream, StreamExt};
+use std::future::Future;
+use std::io::{self, Read};
+
+/// The contents of a file, as produced by a [`SymbolFetcher`].
+pub enum SymbolData {
+    /// The whole file, e.g. a value from a key-value store or a symbol file
+    /// embedded with `include_bytes!`.
+    Bytes(Cow<'static, [u8]>),
+    /// A blocking reader over the file.
+    ///
+    /// The reader is read on the thread polling the supplier's futures, so this
+    /// should only be used for data that is already in memory (or otherwise
+    /// can't block for long, like a decompressor over in-memory data). Files
+    /// and network downloads should be read on a blocking thread of the
+    /// executor and returned as [`SymbolData::Bytes`], or returned as a
+    /// [`SymbolData::Stream`].
+    Read(Box<dyn Read + Send>),
+    /// A stream of chunks of the file, e.g. an object store download.
+    Stream(BoxStream<'static, io::Result<Vec<u8>>>),
+}
+
+impl SymbolData {
+    /// Parse the data as a symbol file, streaming it if possible.
+    ///
+    /// Every chunk of the input is passed to `callback`, see [`SymbolFile::parse`].
+    ///
+    /// [`SymbolData::Read`] is read synchronously, see its documentation.
+    pub async fn parse(self, callback: impl FnMut(&[u8])) -> Result<SymbolFile, SymbolError> {
+        match self {
+            SymbolData::Bytes(bytes) => SymbolFile::parse(&bytes[..], callback),
+            SymbolData::Read(reader) => SymbolFile::parse(reader, callback),
+            SymbolData::Stream(chunks) => SymbolFile::parse_stream(chunks, callback).await,
+        }
+    }
+
+    /// Read all of the data into memory.
+    ///
+    /// [`SymbolData::Read`] is read synchronously, see its documentation.
+    pub async fn into_bytes(self) -> io::Result<Vec<u8>> {
+        match self {
+            SymbolData::Bytes(bytes) => Ok(bytes.into_owned()),
+            SymbolData::Read(mut reader) => {
+                let mut bytes = Vec::new();
+                reader.read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+            SymbolData::Stream(mut chunks) => {
+                let mut bytes = Vec::new();
+                while let Some(chunk) = chunks.next().await {
+                    bytes.extend_from_slice(&chunk?);
+                }
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+impl From<Vec<u8>> for SymbolData {
+    fn from(bytes: Vec<u8>) -> Self {
+        SymbolData::Bytes(Cow::Owned(bytes))
+    }
+}
+
+impl From<&'static [u8]> for SymbolData {
+    fn from(bytes: &'static [u8]) -> Self {
+        SymbolData::Bytes(Cow::Borrowed(bytes))
+    }
+}
+
+impl From<String> for SymbolData {
+    fn from(text: String) -> Self {
+        text.into_bytes().into()
+    }
+}
+
+impl From<&'static str> for SymbolData {
+    fn from(text: &'static str) -> Self {
+        text.as_bytes().into()
+    }
+}
+
+/// Fetches the files for a [`CallbackSymbolSupplier`].
+///
+/// This is implemented for async closures that take a [`SimpleModule`] (a copy
+/// of the module's identifying information) and the [`FileKind`] that is
+/// wanted.
+#[async_trait]
+pub trait SymbolFetcher: Send + Sync {
+    /// Fetch the file of the given kind for `module`.
+    ///
+    /// Return [`SymbolError::NotFound`] if there is no such file.
+    async fn fetch(
+        &self,
+        module: &(dyn Module + Sync),
+        file_kind: FileKind,
+    ) -> Result<SymbolData, SymbolError>;
+}
+
+#[async_trait]
+impl<F, Fut> SymbolFetcher for F
+where
+    F: Fn(SimpleModule, FileKind) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<SymbolData, SymbolError>> + Send,
+{
+    async fn fetch(
+        &self,
+        module: &(dyn Module + Sync),
+        file_kind: FileKind,
+    ) -> Result<SymbolData, SymbolError> {
+        let module = SimpleModule {
+            base_address: Some(module.base_address()),
+            size: Some(module.size()),
+            code_file: Some(module.code_file().into_owned()),
+            code_identifier: module.code_identifier(),
+            debug_file: module.debug_file().map(Cow::into_owned),
+            debug_id: module.debug_identifier(),
+            version: module.version().map(Cow::into_owned),
+        };
+        self(module, file_kind).await
+    }
+}
+
+/// A place for a [`CallbackSymbolSupplier`] to keep symbols it has parsed.
+///
+/// Symbols are stored in their binary [`SymbolCache`] form, so loading them
+/// back doesn't involve parsing the text format again. Keys are relative paths
+/// like `<debug file>/<debug id>/<debug file>.symc`, so they can be used
+/// as-is with a directory, an object store or a key-value store.
+#[async_trait]
+pub trait SymbolDataCache: Send + Sync {
+    /// Get the data previously stored under `key`, if any.
+    async fn get(&self, key: &str) -> Option<Vec<u8>>;
+    /// Store `data` under `key`.
+    ///
+    /// Caching is best-effort, so failures should be logged and ignored.
+    async fn put(&self, key: &str, data: Vec<u8>);
+}
+
+#[async_trait]
+impl<T: SymbolDataCache + ?Sized> SymbolDataCache for Arc<T> {
+    async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        (**self).get(key).await
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>) {
+        (**self).put(key, data).await
+    }
+}
+
+/// A [`SymbolDataCache`] that keeps everything in memory.
+///
+/// This is mostly useful to share symbols between multiple [`Symbolizer`]s
+/// in one process.
+#[derive(Default)]
+pub struct MemorySymbolDataCache {
+    entries: Mutex<HashMap<String, Arc<[u8]>>>,
+}
+
+impl MemorySymbolDataCache {
+    /// Create an empty `MemorySymbolDataCache`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SymbolDataCache for MemorySymbolDataCache {
+    async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(key).map(|data| data.to_vec())
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key.to_owned(), data.into());
+    }
+}
+
+/// An implementation of `SymbolSupplier` that gets its files from a
+/// [`SymbolFetcher`], for symbols that live somewhere other than the local
+/// filesystem or a symbol server.
+///
+/// Symbol files are parsed straight from the fetched data, without touching
+/// the disk. Other kinds of files are only available through
+/// [`SymbolSupplier::locate_file`] if a directory to store them in has been
+/// configured with [`CallbackSymbolSupplier::files_dir`].
+pub struct CallbackSymbolSupplier<F> {
+    fetcher: F,
+    cache: Option<Box<dyn SymbolDataCache>>,
+    files_dir: Option<PathBuf>,
+}
+
+impl<F: SymbolFetcher> CallbackSymbolSupplier<F> {
+    /// Create a new `CallbackSymbolSupplier` that gets its files from `fetcher`.
+    pub fn new(fetcher: F) -> Self {
+        CallbackSymbolSupplier {
+            fetcher,
+            cache: None,
+            files_dir: None,
+        }
+    }
+
+    /// Keep the symbols that get parsed in `cache`, and look there before
+    /// fetching anything.
+    pub fn cache(mut self, cache: impl SymbolDataCache + 'static) -> Self {
+        self.cache = Some(Box::new(cache));
+        self
+    }
+
+    /// Store files that are requested through [`SymbolSupplier::locate_file`]
+    /// under `dir`, so that it can return a path to them.
+    ///
+    /// Files are stored in the [`SymbolLayout::Breakpad`] layout (like the
+    /// cache of the HTTP supplier), and are only fetched once.
+    pub fn files_dir(mut self, dir: PathBuf) -> Self {
+        self.files_dir = Some(dir);
+        self
+    }
+}
+
+/// The key a module's symbols are stored under in a [`SymbolDataCache`].
+fn cache_key(module: &(dyn Module + Sync)) -> Option<String> {
+    let lookup = breakpad_sym_lookup(module)?;
+    let key = SymbolCache::path_for_symbol_file(Path::new(&lookup.cache_rel));
+    Some(key.to_string_lossy().into_owned())
+}
+
+#[async_trait]
+impl<F: SymbolFetcher> SymbolSupplier for CallbackSymbolSupplier<F> {
+    #[tracing::instrument(name = "symbols", level = "trace", skip_all, fields(module = crate::basename(&module.code_file())))]
+    async fn locate_symbols(
+        &self,
+        module: &(dyn Module + Sync),
+    ) -> Result<LocateSymbolsResult, SymbolError> {
+        let data = self.fetcher.fetch(module, FileKind::BreakpadSym).await?;
+        let symbols = data.parse(|_| ()).await.map_err(|e| {
+            trace!("CallbackSymbolSupplier failed: {}", e);
+            e
+        })?;
+        trace!("CallbackSymbolSupplier parsed file!");
+
+        if let (Some(cache), Some(key)) = (&self.cache, cache_key(module)) {
+            let mut bytes = Vec::new();
+            match SymbolCache::write(&symbols, &mut bytes) {
+                Ok(()) => cache.put(&key, bytes).await,
+                Err(e) => warn!("Failed to build symbol cache {}: {}", key, e),
+            }
+        }
+
+        Ok(LocateSymbolsResult {
+            symbols,
+            extra_debug_info: None,
+        })
+    }
+
+    async fn locate_symbol_cache(
+        &self,
+        module: &(dyn Module + Sync),
+    ) -> Result<SymbolCache, SymbolError> {
+        let cache = self.cache.as_ref().ok_or(SymbolError::NotFound)?;
+        let key = cache_key(module).ok_or(SymbolError::NotFound)?;
+        let bytes = cache.get(&key).await.ok_or(SymbolError::NotFound)?;
+        SymbolCache::from_bytes(bytes).map_err(|e| {
+            // A bad entry shouldn't prevent fetching the symbols again.
+            warn!("Ignoring bad symbol cache {}: {}", key, e);
+            SymbolError::NotFound
+        })
+    }
+
+    async fn locate_file(
+        &self,
+        module: &(dyn Module + Sync),
+        file_kind: FileKind,
+    ) -> Result<PathBuf, FileError> {
+        let dir = self.files_dir.as_ref().ok_or(FileError::NotFound)?;
+        let lookup = lookup(module, file_kind).ok_or(FileError::NotFound)?;
+        let path = dir.join(&lookup.cache_rel);
+        if path.is_file() {
+            return Ok(path);
+        }
+
+        let data = self
+            .fetcher
+            .fetch(module, file_kind)
+            .await
+            .map_err(|_| FileError::NotFound)?;
+        let write = async {
+            let bytes = data.into_bytes().await?;
+            fs::create_dir_all(path.parent().unwrap_or(dir))?;
+            // Write to a temporary name first so a partial file is never visible.
+            let tmp_path = path.with_extension(format!("tmp{}", std::process::id()));
+            fs::write(&tmp_path, bytes)?;
+            fs::rename(&tmp_path, &path)
+        };
+        write.await.map_err(|e: io::Error| {
+            warn!("Failed to store {}: {}", path.display(), e);
+            FileError::NotFound
+        })?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const SYMBOLS: &str = "MODULE Linux x86 ABCD1234ABCD1234ABCDABCD12345678a foo
+FILE 1 foo.c
+FUNC 1000 30 10 some func
+1000 30 100 1
+";
+
+    fn module() -> SimpleModule {
+        let debug_id = DebugId::from_str("abcd1234-abcd-1234-abcd-abcd12345678-a").unwrap();
+        SimpleModule::new("foo.pdb", debug_id)
+    }
+
+    async fn assert_symbolized(symbolizer: &Symbolizer) {
+        let mut frame = SimpleFrame::with_instruction(0x1010);
+        symbolizer.fill_symbol(&module(), &mut frame).await.unwrap();
+        assert_eq!(frame.function.unwrap(), "some func");
+        assert_eq!(frame.source_file.unwrap(), "foo.c");
+        assert_eq!(frame.source_line.unwrap(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_symbol_data_variants() {
+        let chunks = || {
+            let chunks = SYMBOLS
+                .as_bytes()
+                .chunks(7)
+                .map(|chunk| Ok(chunk.to_vec()))
+                .collect::<Vec<_>>();
+            futures_util::stream::iter(chunks).boxed()
+        };
+        for data in [
+            SymbolData::from(SYMBOLS),
+            SymbolData::Read(Box::new(SYMBOLS.as_bytes())),
+            SymbolData::Stream(chunks()),
+        ] {
+            let symbols = data.parse(|_| ()).await.unwrap();
+            assert_eq!(symbols, SymbolFile::from_bytes(SYMBOLS.as_bytes()).unwrap());
+        }
+        let bytes = SymbolData::Stream(chunks()).into_bytes().await.unwrap();
+        assert_eq!(bytes, SYMBOLS.as_bytes());
+
+        let failing = futures_util::stream::iter(vec![
+            Ok(b"MODULE Linux x86 ABCD1234 foo\n".to_vec()),
+            Err(io::Error::new(io::ErrorKind::Other, "connection reset")),
+        ]);
+        assert!(matches!(
+            SymbolData::Stream(failing.boxed()).parse(|_| ()).await,
+            Err(SymbolError::LoadError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_callback_symbol_supplier() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let fetcher = {
+            let fetches = fetches.clone();
+            move |module: SimpleModule, file_kind: FileKind| {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match (module.debug_file.as_deref(), file_kind) {
+                        (Some("foo.pdb"), FileKind::BreakpadSym) => Ok(SYMBOLS.into()),
+                        _ => Err(SymbolError::NotFound),
+                    }
+                }
+            }
+        };
+
+        let symbolizer = Symbolizer::new(CallbackSymbolSupplier::new(fetcher.clone()));
+        assert_symbolized(&symbolizer).await;
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(symbolizer.stats().get("").unwrap().loaded_symbols);
+
+        let unknown = SimpleModule::new("bar.pdb", module().debug_id.unwrap());
+        let mut frame = SimpleFrame::with_instruction(0x1010);
+        assert!(symbolizer.fill_symbol(&unknown, &mut frame).await.is_err());
+
+        // Without a files dir there is nowhere to put other files.
+        assert!(CallbackSymbolSupplier::new(fetcher)
+            .locate_file(&module(), FileKind::BreakpadSym)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_callback_symbol_supplier_cache() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let fetcher = {
+            let fetches = fetches.clone();
+            move |_: SimpleModule, _: FileKind| {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                async { Ok(SYMBOLS.into()) }
+            }
+        };
+        let cache = Arc::new(MemorySymbolDataCache::new());
+
+        // The first symbolizer fetches and fills the cache, the second only
+        // needs the cache.
+        for _ in 0..2 {
+            let supplier = CallbackSymbolSupplier::new(fetcher.clone()).cache(cache.clone());
+            assert_symbolized(&Symbolizer::new(supplier)).await;
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(cache
+            .get("foo.pdb/ABCD1234ABCD1234ABCDABCD12345678a/foo.symc")
+            .await
+            .is_some());
+
+        // Bad cache entries are ignored.
+        cache
+            .put(
+                "foo.pdb/ABCD1234ABCD1234ABCDABCD12345678a/foo.symc",
+                b"garbage".to_vec(),
+            )
+            .await;
+        let supplier = CallbackSymbolSupplier::new(fetcher).cache(cache.clone());
+        assert_symbolized(&Symbolizer::new(supplier)).await;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_callback_symbol_supplier_files_dir() {
+        let t = tempfile::tempdir().unwrap();
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let fetcher = {
+            let fetches = fetches.clone();
+            move |_: SimpleModule, file_kind: FileKind| {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match file_kind {
+                        FileKind::ExtraDebugInfo => Ok(b"not really a pdb".to_vec().into()),
+                        _ => Err(SymbolError::NotFound),
+                    }
+                }
+            }
+        };
+        let supplier = CallbackSymbolSupplier::new(fetcher).files_dir(t.path().to_owned());
+
+        for _ in 0..2 {
+            let path = supplier
+                .locate_file(&module(), FileKind::ExtraDebugInfo)
+                .await
+                .unwrap();
+            assert_eq!(
+                path,
+                t.path()
+                    .join("foo.pdb/ABCD1234ABCD1234ABCDABCD12345678a/foo.pdb")
+            );
+            assert_eq!(fs::read(&path).unwrap(), b"not really a pdb");
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(supplier
+            .locate_file(&module(), FileKind::Binary)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! Demangling and normalizing function names.
+//!
+//! Symbol files are supposed to contain demangled names, but not every producer of
+//! them does that, and the demangled names of Rust functions still have a hash suffix
+//! that differs between builds.
+
+use std::borrow::Cow;
+
+use minidump_common::utils::generic_args;
+
+/// How to rewrite the function names in symbol files, see [`DemangleOptions::apply`].
+///
+/// The default leaves names as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DemangleOptions {
+    /// Demangle mangled Rust (`_ZN…`, `_R…`) and Itanium C++ (`_Z…`) names.
+    ///
+    /// This needs the `demangle` feature, without it names are left mangled.
+    pub demangle: bool,
+    /// Remove the hash suffix of Rust names (`::h0123456789abcdef`), and the crate
+    /// disambiguators of Rust v0 mangled names.
+    pub strip_rust_hashes: bool,
+    /// Remove generic and template arguments, so `Vec<T>::push` becomes `Vec::push`.
+    pub strip_generics: bool,
+}
+
+impl DemangleOptions {
+    /// Demangle names and strip the Rust hash suffixes.
+    pub fn normalize() -> Self {
+        Self {
+            demangle: true,
+            strip_rust_hashes: true,
+            strip_generics: false,
+        }
+    }
+
+    /// Rewrite the function name `name` according to these options.
+    pub fn apply<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        let mut name = Cow::Borrowed(name);
+        #[cfg(feature = "demangle")]
+        if self.demangle {
+            if let Some(demangled) = demangle(&name, self.strip_rust_hashes) {
+                name = Cow::Owned(demangled);
+            }
+        }
+        if self.strip_rust_hashes {
+            if let Some(stripped) = strip_rust_hash(&name) {
+                name = Cow::Owned(stripped.to_string());
+            }
+        }
+        if self.strip_generics {
+            if let Some(stripped) = strip_generics(&name) {
+                name = Cow::Owned(stripped);
+            }
+        }
+        name
+    }
+}
+
+/// Demangle a Rust or Itanium C++ mangled name, `None` if it isn't one.
+///
+/// `strip_rust_hashes` leaves out the hashes and crate disambiguators of Rust names.
+#[cfg(feature = "demangle")]
+pub fn demangle(name: &str, strip_rust_hashes: bool) -> Option<String> {
+    // Mach-O symbols have an extra leading underscore.
+    let mangled = match name.strip_prefix('_') {
+        Some(rest) if rest.starts_with("_Z") || rest.starts_with("_R") => rest,
+        _ => name,
+    };
+    if !mangled.starts_with("_Z") && !mangled.starts_with("_R") {
+        return None;
+    }
+    // Rust's legacy mangling is a subset of Itanium's, so try it first.
+    if let Ok(demangled) = rustc_demangle::try_demangle(mangled) {
+        return Some(if strip_rust_hashes {
+            format!("{demangled:#}")
+        } else {
+            demangled.to_string()
+        });
+    }
+    let symbol = cpp_demangle::Symbol::new(mangled).ok()?;
+    symbol.demangle(&cpp_demangle::DemangleOptions::new()).ok()
+}
+
+/// Remove the `::h0123456789abcdef` hash suffix of a legacy Rust name, `None` if it has none.
+pub fn strip_rust_hash(name: &str) -> Option<&str> {
+    let (rest, hash) = name.rsplit_once("::h")?;
+    (hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit())).then_some(rest)
+}
+
+/// Remove generic and template arguments (see [`generic_args`]), `None` if there are none
+/// or they aren't balanced.
+fn strip_generics(name: &str) -> Option<String> {
+    let args = generic_args(name)?;
+    if args.is_empty() {
+        return None;
+    }
+    let mut stripped = String::with_capacity(name.len());
+    let mut end = 0;
+    for arg in args {
+        let before = &name[end..arg.start];
+        // Drop the `::` of a turbofish too.
+        stripped.push_str(before.strip_suffix("::").unwrap_or(before));
+        end = arg.end;
+    }
+    stripped.push_str(&name[end..]);
+    Some(stripped)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "demangle")]
+    fn test_demangle() {
+        let demangle_only = DemangleOptions {
+            demangle: true,
+            ..DemangleOptions::default()
+        };
+        assert_eq!(
+            demangle_only.apply("_ZN3std2rt10lang_start17h0123456789abcdefE"),
+            "std::rt::lang_start::h0123456789abcdef"
+        );
+        assert_eq!(
+            demangle_only.apply("_ZNSt6vectorIiSaIiEE9push_backERKi"),
+            "std::vector<int, std::allocator<int> >::push_back(int const&)"
+        );
+        assert_eq!(demangle_only.apply("__ZN3foo3barEv"), "foo::bar()");
+        assert_eq!(demangle_only.apply("main"), "main");
+        assert_eq!(demangle_only.apply("_Zgarbage"), "_Zgarbage");
+        // Without `demangle`, names are left alone.
+        assert_eq!(
+            DemangleOptions::default().apply("_ZN3foo3barEv"),
+            "_ZN3foo3barEv"
+        );
+
+        let normalize = DemangleOptions::normalize();
+        assert_eq!(
+            normalize.apply("_ZN3std2rt10lang_start17h0123456789abcdefE"),
+            "std::rt::lang_start"
+        );
+        assert_eq!(normalize.apply("_RNvCs1234_7mycrate3foo"), "mycrate::foo");
+        let no_generics = DemangleOptions {
+            strip_generics: true,
+            ..normalize
+        };
+        assert_eq!(
+            no_generics.apply("_ZNSt6vectorIiSaIiEE9push_backERKi"),
+            "std::vector::push_back(int const&)"
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        let normalize = DemangleOptions::normalize();
+        assert_eq!(
+            normalize.apply("core::ptr::drop_in_place<u8>::h0123456789abcdef"),
+            "core::ptr::drop_in_place<u8>"
+        );
+        assert_eq!(normalize.apply("foo::hello"), "foo::hello");
+
+        let no_generics = DemangleOptions {
+            strip_generics: true,
+            ..DemangleOptions::normalize()
+        };
+        assert_eq!(
+            no_generics.apply("<alloc::vec::Vec<T, A> as core::ops::drop::Drop>::drop"),
+            "<alloc::vec::Vec as core::ops::drop::Drop>::drop"
+        );
+        assert_eq!(
+            no_generics.apply("core::ptr::read::<u8>"),
+            "core::ptr::read"
+        );
+        assert_eq!(no_generics.apply("operator<<(int)"), "operator<<(int)");
+        assert_eq!(no_generics.apply("unbalanced<thing"), "unbalanced<thing");
+    }
+}
@@ -15,15 +15,21 @@ type FileKey = (ModuleKey, FileKind);
 /// An implementation of `SymbolSupplier` that loads Breakpad text-format
 /// symbols from HTTP URLs.
 ///
-/// See [`crate::breakpad_sym_lookup`] for details on how paths are searched.
+/// See [`crate::breakpad_sym_lookup`] for details on how paths are searched,
+/// and [`SymbolLayout`] for the other layouts local paths and servers can use.
+/// The cache itself always uses the [`SymbolLayout::Breakpad`] layout.
+///
+/// Every symbol file that gets downloaded is also saved as a binary
+/// [`SymbolCache`] next to the text file in the cache, so that later runs can
+/// map it instead of parsing it again.
 pub struct HttpSymbolSupplier {
     /// File paths that are known to be in the cache
     #[allow(clippy::type_complexity)]
     cached_file_paths: CacheMap<FileKey, CachedAsyncResult<(PathBuf, Option<Url>), FileError>>,
     /// HTTP Client to use for fetching symbols.
     client: Client,
-    /// URLs to search for symbols.
-    urls: Vec<Url>,
+    /// URLs to search for symbols, and their layouts.
+    urls: Vec<(Url, SymbolLayout)>,
     /// A `SimpleSymbolSupplier` to use for local symbol paths.
     local: SimpleSymbolSupplier,
     /// A path at which to cache downloaded symbols.
@@ -51,21 +57,46 @@ impl HttpSymbolSupplier {
         urls: Vec<String>,
         cache: PathBuf,
         tmp: PathBuf,
-        mut local_paths: Vec<PathBuf>,
+        local_paths: Vec<PathBuf>,
+        timeout: Duration,
+    ) -> HttpSymbolSupplier {
+        Self::with_layouts(
+            urls.into_iter()
+                .map(|url| (url, SymbolLayout::Auto))
+                .collect(),
+            cache,
+            tmp,
+            local_paths
+                .into_iter()
+                .map(|path| (path, SymbolLayout::Auto))
+                .collect(),
+            timeout,
+        )
+    }
+
+    /// Create a new `HttpSymbolSupplier` where each of `urls` and
+    /// `local_paths` has its own [`SymbolLayout`].
+    ///
+    /// See [`HttpSymbolSupplier::new`].
+    pub fn with_layouts(
+        urls: Vec<(String, SymbolLayout)>,
+        cache: PathBuf,
+        tmp: PathBuf,
+        mut local_paths: Vec<(PathBuf, SymbolLayout)>,
         timeout: Duration,
     ) -> HttpSymbolSupplier {
         let client = Client::builder().timeout(timeout).build().unwrap();
         let urls = urls
             .into_iter()
-            .filter_map(|mut u| {
+            .filter_map(|(mut u, layout)| {
                 if !u.ends_with('/') {
                     u.push('/');
                 }
-                Url::parse(&u).ok()
+                Some((Url::parse(&u).ok()?, layout.for_url()))
             })
             .collect();
-        local_paths.push(cache.clone());
-        let local = SimpleSymbolSupplier::new(local_paths);
+        local_paths.push((cache.clone(), SymbolLayout::Breakpad));
+        let local = SimpleSymbolSupplier::with_layouts(local_paths);
         let cached_file_paths = Default::default();
         HttpSymbolSupplier {
             client,
@@ -77,6 +108,16 @@ impl HttpSymbolSupplier {
         }
     }
 
+    /// Enable or disable writing binary symbol caches for symbol files found
+    /// in local paths.
+    ///
+    /// See [`SimpleSymbolSupplier::binary_caches`]. Downloaded symbol files
+    /// always get a binary cache.
+    pub fn binary_caches(mut self, enable: bool) -> Self {
+        self.local = self.local.binary_caches(enable);
+        self
+    }
+
     #[tracing::instrument(level = "trace", skip(self, module), fields(module = crate::basename(&module.code_file())))]
     pub async fn locate_file_internal(
         &self,
@@ -97,9 +138,16 @@ impl HttpSymbolSupplier {
                 // the cache... is that ok? Maybe? Since only one will ever win the swap, and it's
                 // unlikely to get multiple hits... this might actually be ok!
                 if let Some(lookup) = lookup(module, file_kind) {
-                    for url in &self.urls {
-                        let fetch =
-                            fetch_lookup(&self.client, url, &lookup, &self.cache, &self.tmp).await;
+                    for (url, layout) in &self.urls {
+                        let fetch = fetch_lookup(
+                            &self.client,
+                            url,
+                            *layout,
+                            &lookup,
+                            &self.cache,
+                            &self.tmp,
+                        )
+                        .await;
 
                         if let Ok((path, url)) = fetch {
                             return Ok((path, url));
@@ -108,10 +156,11 @@ impl HttpSymbolSupplier {
 
                     // If we're allowed to look for mozilla's special CAB paths, do that
                     if cfg!(feature = "mozilla_cab_symbols") {
-                        for url in &self.urls {
+                        for (url, layout) in &self.urls {
                             let fetch = fetch_cab_lookup(
                                 &self.client,
                                 url,
+                                *layout,
                                 &lookup,
                                 &self.cache,
                                 &self.tmp,
@@ -239,12 +288,16 @@ async fn individual_lookup_debug_info_by_code_info(
 /// This returns a DebugInfoResult with the new debug file and debug identifier
 /// or None.
 async fn lookup_debug_info_by_code_info(
-    symbol_urls: &Vec<Url>,
+    symbol_urls: &[(Url, SymbolLayout)],
     module: &(dyn Module + Sync),
 ) -> Option<DebugInfoResult> {
     let lookup_path = code_info_breakpad_sym_lookup(module)?;
 
-    for base_url in symbol_urls {
+    // Only Breakpad symbol servers (tecken) support this.
+    let symbol_urls = symbol_urls
+        .iter()
+        .filter(|(_, layout)| *layout == SymbolLayout::Breakpad);
+    for (base_url, _) in symbol_urls {
         if let Some(result) =
             individual_lookup_debug_info_by_code_info(base_url, &lookup_path).await
         {
@@ -260,11 +313,12 @@ async fn lookup_debug_info_by_code_info(
     None
 }
 
-/// Fetch a symbol file from the URL made by combining `base_url` and `rel_path` using `client`,
-/// save the file contents under `cache` + `rel_path` and also return them.
+/// Fetch a symbol file from the URL made by combining `base_url` and `rel_path` (in `layout`)
+/// using `client`, save the file contents under `cache` + `rel_path` and also return them.
 async fn fetch_symbol_file(
     client: &Client,
     base_url: &Url,
+    layout: SymbolLayout,
     module: &(dyn Module + Sync),
     cache: &Path,
     tmp: &Path,
@@ -282,7 +336,7 @@ async fn fetch_symbol_file(
     // First try to GET the file from a server
     let sym_lookup = breakpad_sym_lookup(module).ok_or(SymbolError::MissingDebugFileOrId)?;
     let mut url = base_url
-        .join(&sym_lookup.server_rel)
+        .join(&layout.rel_path(&sym_lookup.server_rel))
         .map_err(|_| SymbolError::NotFound)?;
     let code_id = module.code_identifier().unwrap_or_default();
     url.query_pairs_mut()
@@ -319,11 +373,18 @@ async fn fetch_symbol_file(
     // Make note of what URL this symbol file was downloaded from.
     symbol_file.url = Some(url.to_string());
 
-    // Try to finish the cache file and atomically swap it into the cache.
+    // Try to finish the cache file and atomically swap it into the cache,
+    // along with a binary cache of the parsed symbols.
     if let Some(temp) = temp {
-        let _ = commit_cache_file(temp, &final_cache_path, &url).map_err(|e| {
+        let committed = commit_cache_file(temp, &final_cache_path, &url).map_err(|e| {
             warn!("Failed to save symbol file in local disk cache: {}", e);
         });
+        if committed.is_ok() {
+            let binary_cache_path = SymbolCache::path_for_symbol_file(&final_cache_path);
+            if let Err(e) = SymbolCache::write_to_path(&symbol_file, &binary_cache_path) {
+                warn!("Failed to save symbol cache in local disk cache: {}", e);
+            }
+        }
     }
 
     Ok(symbol_file)
@@ -336,13 +397,14 @@ async fn fetch_symbol_file(
 async fn fetch_lookup(
     client: &Client,
     base_url: &Url,
+    layout: SymbolLayout,
     lookup: &FileLookup,
     cache: &Path,
     tmp: &Path,
 ) -> Result<(PathBuf, Option<Url>), SymbolError> {
     // First try to GET the file from a server
     let url = base_url
-        .join(&lookup.server_rel)
+        .join(&layout.rel_path(&lookup.server_rel))
         .map_err(|_| SymbolError::NotFound)?;
     debug!("Trying {}", url);
     let mut res = client
@@ -378,11 +440,14 @@ async fn fetch_lookup(
 async fn fetch_cab_lookup(
     client: &Client,
     base_url: &Url,
+    layout: SymbolLayout,
     lookup: &FileLookup,
     cache: &Path,
     tmp: &Path,
 ) -> Result<(PathBuf, Option<Url>), FileError> {
-    let cab_lookup = moz_lookup(lookup.clone());
+    let mut cab_lookup = lookup.clone();
+    cab_lookup.server_rel = layout.rel_path(&cab_lookup.server_rel);
+    let cab_lookup = moz_lookup(cab_lookup);
     // First try to GET the file from a server
     let url = base_url
         .join(&cab_lookup.server_rel)
@@ -408,6 +473,7 @@ async fn fetch_cab_lookup(
 async fn fetch_cab_lookup(
     _client: &Client,
     _base_url: &Url,
+    _layout: SymbolLayout,
     _lookup: &FileLookup,
     _cache: &Path,
     _tmp: &Path,
@@ -508,10 +574,17 @@ impl SymbolSupplier for HttpSymbolSupplier {
         trace!("HttpSymbolSupplier search (SimpleSymbolSupplier found nothing)");
 
         // Second: try to directly download sym files
-        for url in &self.urls {
+        for (url, layout) in &self.urls {
             // First, try to get a breakpad .sym file from the symbol server
-            let sym =
-                fetch_symbol_file(&self.client, url, &lookup_module, &self.cache, &self.tmp).await;
+            let sym = fetch_symbol_file(
+                &self.client,
+                url,
+                *layout,
+                &lookup_module,
+                &self.cache,
+                &self.tmp,
+            )
+            .await;
             match sym {
                 Ok(symbols) => {
                     trace!("HttpSymbolSupplier parsed file!");
@@ -539,4 +612,12 @@ impl SymbolSupplier for HttpSymbolSupplier {
             .await
             .map(|(path, _url)| path)
     }
+
+    async fn locate_symbol_cache(
+        &self,
+        module: &(dyn Module + Sync),
+    ) -> Result<SymbolCache, SymbolError> {
+        // Binary caches are only ever stored locally (which includes our disk cache).
+        self.local.locate_symbol_cache(module).await
+    }
 }
@@ -0,0 +1,192 @@
+//! Directory layouts used by symbol stores.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use tracing::trace;
+
+/// The file symstore places at the root of a two-tier store.
+const TWO_TIER_INDEX: &str = "index2.txt";
+
+/// The file symstore leaves in place of a file that was stored by reference.
+const FILE_PTR: &str = "file.ptr";
+
+/// How files are arranged under a symbol path or symbol server URL.
+///
+/// Lookups (see [`crate::breakpad_sym_lookup`] and friends) produce paths in
+/// the [`SymbolLayout::Breakpad`] layout, `<debug file>/<debug id>/<file>`,
+/// which the other layouts are derived from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SymbolLayout {
+    /// Pick the layout based on the contents of a local directory: a
+    /// directory with an `index2.txt` is a [`SymbolLayout::TwoTier`] store,
+    /// anything else (including all URLs) uses [`SymbolLayout::Breakpad`].
+    #[default]
+    Auto,
+    /// `<debug file>/<debug id>/<file>`, the layout of Breakpad symbol
+    /// servers and of a single-tier symstore.
+    Breakpad,
+    /// `<2 chars>/<debug file>/<debug id>/<file>`, the layout of a two-tier
+    /// symstore, where the extra level is the first two characters of the
+    /// debug file name.
+    TwoTier,
+    /// `<debug id>.<extension>` directly in the directory, e.g.
+    /// `<debug id>.sym`.
+    Flat,
+}
+
+impl SymbolLayout {
+    /// Split an optional `<layout>:` prefix off of a symbol path or URL.
+    ///
+    /// Only known layout names are treated as a prefix, so Windows drive
+    /// letters and URL schemes are left alone. Without a prefix the layout is
+    /// [`SymbolLayout::Auto`].
+    pub fn from_prefixed(location: &str) -> (SymbolLayout, &str) {
+        location
+            .split_once(':')
+            .and_then(|(prefix, rest)| Some((prefix.parse().ok()?, rest)))
+            .unwrap_or((SymbolLayout::Auto, location))
+    }
+
+    /// Resolve [`SymbolLayout::Auto`] for the local directory `dir`.
+    pub(crate) fn for_dir(self, dir: &Path) -> SymbolLayout {
+        match self {
+            SymbolLayout::Auto if dir.join(TWO_TIER_INDEX).is_file() => SymbolLayout::TwoTier,
+            SymbolLayout::Auto => SymbolLayout::Breakpad,
+            layout => layout,
+        }
+    }
+
+    /// Resolve [`SymbolLayout::Auto`] for a symbol server URL.
+    #[cfg(feature = "http")]
+    pub(crate) fn for_url(self) -> SymbolLayout {
+        match self {
+            SymbolLayout::Auto => SymbolLayout::Breakpad,
+            layout => layout,
+        }
+    }
+
+    /// Rewrite `rel_path`, a `<debug file>/<debug id>/<file>` path, into
+    /// this layout.
+    ///
+    /// [`SymbolLayout::Auto`] is treated as [`SymbolLayout::Breakpad`], since
+    /// there is no directory to inspect.
+    pub fn rel_path(self, rel_path: &str) -> String {
+        let mut parts = rel_path.splitn(3, '/');
+        let (Some(name), Some(id), Some(file)) = (parts.next(), parts.next(), parts.next()) else {
+            return rel_path.to_owned();
+        };
+        match self {
+            SymbolLayout::Auto | SymbolLayout::Breakpad => rel_path.to_owned(),
+            SymbolLayout::TwoTier => {
+                let prefix: String = name.chars().take(2).collect();
+                [&prefix, rel_path].join("/")
+            }
+            SymbolLayout::Flat => match file.rsplit_once('.') {
+                Some((_, extension)) => format!("{id}.{extension}"),
+                None => id.to_owned(),
+            },
+        }
+    }
+
+    /// Find the file at `rel_path` (see [`SymbolLayout::rel_path`]) under the
+    /// local directory `dir`, following symstore `file.ptr` redirects.
+    pub(crate) fn find_file(self, dir: &Path, rel_path: &str) -> Option<PathBuf> {
+        let layout = self.for_dir(dir);
+        let path = dir.join(layout.rel_path(rel_path));
+        if path.is_file() {
+            return Some(path);
+        }
+        if layout == SymbolLayout::Flat {
+            return None;
+        }
+        let ptr_path = path.parent()?.join(FILE_PTR);
+        let target = read_file_ptr(&ptr_path)?;
+        // A relative pointer is resolved relative to the pointer file itself.
+        let target = ptr_path.parent()?.join(target);
+        if target.is_file() {
+            trace!("followed {} to {}", ptr_path.display(), target.display());
+            Some(target)
+        } else {
+            trace!(
+                "{} points to missing file {}",
+                ptr_path.display(),
+                target.display()
+            );
+            None
+        }
+    }
+}
+
+/// Read the target of a symstore `file.ptr`, which contains either
+/// `PATH:<path>` or `MSG:<reason the file isn't available>`.
+fn read_file_ptr(ptr_path: &Path) -> Option<PathBuf> {
+    let contents = fs::read_to_string(ptr_path).ok()?;
+    let contents = contents.trim();
+    if let Some(target) = contents.strip_prefix("PATH:") {
+        Some(PathBuf::from(target.trim()))
+    } else {
+        trace!("{}: {}", ptr_path.display(), contents);
+        None
+    }
+}
+
+/// An error parsing a [`SymbolLayout`] name.
+#[derive(Debug, thiserror::Error)]
+#[error("unknown symbol layout '{0}' (expected auto, breakpad, symstore or flat)")]
+pub struct ParseSymbolLayoutError(String);
+
+impl FromStr for SymbolLayout {
+    type Err = ParseSymbolLayoutError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(SymbolLayout::Auto),
+            "breakpad" => Ok(SymbolLayout::Breakpad),
+            "symstore" | "two-tier" => Ok(SymbolLayout::TwoTier),
+            "flat" => Ok(SymbolLayout::Flat),
+            _ => Err(ParseSymbolLayoutError(s.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rel_path() {
+        let rel = "foo.pdb/ABCD1234ABCD1234ABCDABCD12345678a/foo.sym";
+        assert_eq!(SymbolLayout::Breakpad.rel_path(rel), rel);
+        assert_eq!(
+            SymbolLayout::TwoTier.rel_path(rel),
+            "fo/foo.pdb/ABCD1234ABCD1234ABCDABCD12345678a/foo.sym"
+        );
+        assert_eq!(
+            SymbolLayout::Flat.rel_path(rel),
+            "ABCD1234ABCD1234ABCDABCD12345678a.sym"
+        );
+        assert_eq!(SymbolLayout::Flat.rel_path("libc.so/ABCD/libc"), "ABCD");
+    }
+
+    #[test]
+    fn test_from_prefixed() {
+        assert_eq!(
+            SymbolLayout::from_prefixed("symstore:/srv/symbols"),
+            (SymbolLayout::TwoTier, "/srv/symbols")
+        );
+        assert_eq!(
+            SymbolLayout::from_prefixed("flat:https://example.com/symbols"),
+            (SymbolLayout::Flat, "https://example.com/symbols")
+        );
+        assert_eq!(
+            SymbolLayout::from_prefixed("https://example.com/symbols"),
+            (SymbolLayout::Auto, "https://example.com/symbols")
+        );
+        assert_eq!(
+            SymbolLayout::from_prefixed(r"C:\symbols"),
+            (SymbolLayout::Auto, r"C:\symbols")
+        );
+    }
+}
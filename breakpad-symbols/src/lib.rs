@@ -41,23 +41,34 @@ use async_trait::async_trait;
 use cachemap2::CacheMap;
 use debugid::{CodeId, DebugId};
 use futures_util::lock::Mutex as FutMutex;
-use tracing::trace;
+use tracing::{trace, warn};
 
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use std::{borrow::Cow, sync::Arc};
 
+#[cfg(feature = "demangle")]
+pub use demangle::demangle;
+pub use demangle::{strip_rust_hash, DemangleOptions};
+pub use layout::{ParseSymbolLayoutError, SymbolLayout};
 pub use minidump_common::{traits::Module, utils::basename};
 pub use sym_file::walker;
 
-pub use crate::sym_file::{CfiRules, SymbolFile};
+pub use crate::sym_file::{
+    CfiRules, DiagnosticKind, Severity, SymbolCache, SymbolDiagnostic, SymbolFile,
+    SymbolMergeError, SymbolSection, SYMBOL_CACHE_EXTENSION, SYMBOL_CACHE_VERSION,
+};
 
+pub mod callback;
+mod demangle;
 #[cfg(feature = "http")]
 pub mod http;
+mod layout;
 mod sym_file;
 
+pub use callback::*;
 #[cfg(feature = "http")]
 pub use http::*;
 
@@ -427,21 +438,82 @@ pub trait SymbolSupplier {
         module: &(dyn Module + Sync),
         file_kind: FileKind,
     ) -> Result<PathBuf, FileError>;
+
+    /// Locate and load a binary [`SymbolCache`] for `module`.
+    ///
+    /// A symbol cache is much cheaper to load than a text symbol file, so the
+    /// [`Symbolizer`] tries this before [`locate_symbols`][Self::locate_symbols],
+    /// and falls back to that on any error. The default implementation never
+    /// finds a cache.
+    async fn locate_symbol_cache(
+        &self,
+        _module: &(dyn Module + Sync),
+    ) -> Result<SymbolCache, SymbolError> {
+        Err(SymbolError::NotFound)
+    }
 }
 
 /// An implementation of `SymbolSupplier` that loads Breakpad text-format symbols from local disk
 /// paths.
 ///
-/// See [`breakpad_sym_lookup`] for details on how paths are searched.
+/// See [`breakpad_sym_lookup`] for details on how paths are searched, and
+/// [`SymbolLayout`] for the other directory layouts that are supported.
+///
+/// A binary [`SymbolCache`] stored next to a symbol file (see
+/// [`SymbolCache::path_for_symbol_file`]) is used in place of the symbol file
+/// as long as it isn't older than it.
 pub struct SimpleSymbolSupplier {
-    /// Local disk paths in which to search for symbols.
-    paths: Vec<PathBuf>,
+    /// Local disk paths in which to search for symbols, and their layouts.
+    paths: Vec<(PathBuf, SymbolLayout)>,
+    /// Whether to write a binary symbol cache for every symbol file we parse.
+    binary_caches: bool,
 }
 
 impl SimpleSymbolSupplier {
     /// Instantiate a new `SimpleSymbolSupplier` that will search in `paths`.
+    ///
+    /// The layout of each directory is detected, see [`SymbolLayout::Auto`].
     pub fn new(paths: Vec<PathBuf>) -> SimpleSymbolSupplier {
-        SimpleSymbolSupplier { paths }
+        Self::with_layouts(
+            paths
+                .into_iter()
+                .map(|path| (path, SymbolLayout::Auto))
+                .collect(),
+        )
+    }
+
+    /// Instantiate a new `SimpleSymbolSupplier` that will search in `paths`,
+    /// each of which has its own [`SymbolLayout`].
+    pub fn with_layouts(paths: Vec<(PathBuf, SymbolLayout)>) -> SimpleSymbolSupplier {
+        SimpleSymbolSupplier {
+            paths,
+            binary_caches: false,
+        }
+    }
+
+    /// Enable or disable writing binary symbol caches.
+    ///
+    /// If enabled, whenever a text symbol file has to be parsed a
+    /// [`SymbolCache`] is written next to it, so that later lookups (in this
+    /// process or another one) can skip parsing. Defaults to `false`.
+    pub fn binary_caches(mut self, enable: bool) -> Self {
+        self.binary_caches = enable;
+        self
+    }
+}
+
+/// Whether the symbol cache at `cache_path` can be used in place of the
+/// symbol file at `sym_path` (which may not exist at all).
+fn symbol_cache_is_fresh(cache_path: &Path, sym_path: &Path) -> bool {
+    let Ok(cache) = fs::metadata(cache_path) else {
+        return false;
+    };
+    match fs::metadata(sym_path).and_then(|sym| sym.modified()) {
+        Ok(sym_modified) => cache
+            .modified()
+            .is_ok_and(|modified| modified >= sym_modified),
+        // Without a symbol file (or mtimes) to compare against, trust the cache.
+        Err(_) => cache.is_file(),
     }
 }
 
@@ -461,12 +533,63 @@ impl SymbolSupplier for SimpleSymbolSupplier {
             e
         })?;
         trace!("SimpleSymbolSupplier parsed file!");
+        if self.binary_caches {
+            let cache_path = SymbolCache::path_for_symbol_file(&file_path);
+            if let Err(e) = SymbolCache::write_to_path(&symbols, &cache_path) {
+                warn!(
+                    "Failed to write symbol cache {}: {}",
+                    cache_path.display(),
+                    e
+                );
+            }
+        }
         Ok(LocateSymbolsResult {
             symbols,
             extra_debug_info: None,
         })
     }
 
+    #[tracing::instrument(name = "symbols", level = "trace", skip_all, fields(module = crate::basename(&module.code_file())))]
+    async fn locate_symbol_cache(
+        &self,
+        module: &(dyn Module + Sync),
+    ) -> Result<SymbolCache, SymbolError> {
+        let lookup = lookup(module, FileKind::BreakpadSym).ok_or(SymbolError::NotFound)?;
+        for (path, layout) in self.paths.iter() {
+            // Mirror `locate_file`'s search order: a symbol file without a fresh
+            // cache in an earlier path takes precedence over a cache in a later one.
+            let (sym_path, check_module_id) = if path.is_file() {
+                (path.clone(), true)
+            } else if path.is_dir() {
+                let sym_path = layout
+                    .find_file(path, &lookup.cache_rel)
+                    .unwrap_or_else(|| path.join(layout.for_dir(path).rel_path(&lookup.cache_rel)));
+                (sym_path, false)
+            } else {
+                continue;
+            };
+            let cache_path = SymbolCache::path_for_symbol_file(&sym_path);
+            if symbol_cache_is_fresh(&cache_path, &sym_path) {
+                match SymbolCache::from_file(&cache_path) {
+                    Ok(cache) if !check_module_id || cache.module_id() == lookup.debug_id => {
+                        trace!("SimpleSymbolSupplier found cache {}", cache_path.display());
+                        return Ok(cache);
+                    }
+                    Ok(_) => {}
+                    Err(e) => trace!(
+                        "SimpleSymbolSupplier failed to load cache {}: {}",
+                        cache_path.display(),
+                        e
+                    ),
+                }
+            }
+            if !check_module_id && sym_path.is_file() {
+                break;
+            }
+        }
+        Err(SymbolError::NotFound)
+    }
+
     #[tracing::instrument(level = "trace", skip(self, module), fields(module = crate::basename(&module.code_file())))]
     async fn locate_file(
         &self,
@@ -475,7 +598,7 @@ impl SymbolSupplier for SimpleSymbolSupplier {
     ) -> Result<PathBuf, FileError> {
         trace!("SimpleSymbolSupplier search");
         if let Some(lookup) = lookup(module, file_kind) {
-            for path in self.paths.iter() {
+            for (path, layout) in self.paths.iter() {
                 if path.is_file() && file_kind == FileKind::BreakpadSym {
                     if let Ok(sf) = SymbolFile::from_file(path) {
                         if sf.module_id == lookup.debug_id {
@@ -484,8 +607,7 @@ impl SymbolSupplier for SimpleSymbolSupplier {
                         }
                     }
                 } else if path.is_dir() {
-                    let test_path = path.join(lookup.cache_rel.clone());
-                    if fs::metadata(&test_path).ok().is_some_and(|m| m.is_file()) {
+                    if let Some(test_path) = layout.find_file(path, &lookup.cache_rel) {
                         trace!("SimpleSymbolSupplier found file {}", test_path.display());
                         return Ok(test_path);
                     }
@@ -557,6 +679,36 @@ impl SymbolSupplier for StringSymbolSupplier {
     }
 }
 
+/// The symbols for a module, either parsed from a text symbol file or loaded
+/// from a binary symbol cache.
+enum ModuleSymbols {
+    Parsed(SymbolFile),
+    Cached(SymbolCache),
+}
+
+impl ModuleSymbols {
+    fn fill_symbol(&self, module: &dyn Module, frame: &mut dyn FrameSymbolizer) {
+        match self {
+            ModuleSymbols::Parsed(symbols) => symbols.fill_symbol(module, frame),
+            ModuleSymbols::Cached(cache) => cache.fill_symbol(module, frame),
+        }
+    }
+
+    fn walk_frame(&self, module: &dyn Module, walker: &mut dyn FrameWalker) -> Option<()> {
+        match self {
+            ModuleSymbols::Parsed(symbols) => symbols.walk_frame(module, walker),
+            ModuleSymbols::Cached(cache) => cache.walk_frame(module, walker),
+        }
+    }
+
+    fn url(&self) -> Option<&str> {
+        match self {
+            ModuleSymbols::Parsed(symbols) => symbols.url.as_deref(),
+            ModuleSymbols::Cached(cache) => cache.url(),
+        }
+    }
+}
+
 /// A trait for setting symbol information on something like a stack frame.
 pub trait FrameSymbolizer {
     /// Get the program counter value for this frame.
@@ -592,6 +744,13 @@ pub trait FrameWalker {
     fn set_cfa(&mut self, val: u64) -> Option<()>;
     /// Set whatever registers in the caller should be set based on the return address (e.g. rip).
     fn set_ra(&mut self, val: u64) -> Option<()>;
+    /// Whether the walker keeps the rules passed to [`FrameWalker::note_rule`].
+    fn wants_notes(&self) -> bool {
+        false
+    }
+    /// Record an unwinding rule that was evaluated, e.g. `.cfa: $rsp 16 +`, to explain
+    /// how the caller was found. The rule is only formatted if the walker keeps it.
+    fn note_rule(&mut self, _rule: std::fmt::Arguments<'_>) {}
 }
 
 /// A simple implementation of `FrameSymbolizer` that just holds data.
@@ -695,6 +854,69 @@ impl<T, E> CachedAsyncResult<T, E> {
         }
         guard.as_ref().unwrap().clone()
     }
+
+    /// Forget the result, so the next `get` computes it again.
+    pub async fn evict(&self) {
+        *self.inner.lock().await = None;
+    }
+}
+
+/// How much memory the parsed symbol files cached by a `Symbolizer` use.
+#[derive(Default)]
+struct MemoryUsage {
+    /// Incremented on every lookup, to order the entries by last use.
+    clock: u64,
+    /// The sum of the sizes of `entries`.
+    total: usize,
+    /// The size and last use of each cached parsed symbol file.
+    entries: HashMap<ModuleKey, (usize, u64)>,
+}
+
+impl MemoryUsage {
+    /// Record a lookup of the symbols of `key`, and return the least recently used entries
+    /// which have to be evicted to bring the total below `limit`.
+    ///
+    /// The size of the symbols is only computed when they are first seen (it takes a pass
+    /// over the whole file), later lookups just mark them as used.
+    fn touch(&mut self, key: &ModuleKey, symbols: &ModuleSymbols, limit: usize) -> Vec<ModuleKey> {
+        self.clock += 1;
+        let symbols = match symbols {
+            ModuleSymbols::Parsed(symbols) => symbols,
+            // Memory-mapped, so the OS can page it out.
+            ModuleSymbols::Cached(_) => return Vec::new(),
+        };
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.1 = self.clock;
+                return Vec::new();
+            }
+            None => {
+                let size = symbols.memory_size();
+                self.entries.insert(key.clone(), (size, self.clock));
+                self.total += size;
+            }
+        }
+
+        let mut evict = Vec::new();
+        if self.total > limit {
+            let mut lru: Vec<_> = self
+                .entries
+                .iter()
+                .filter(|(k, _)| *k != key)
+                .map(|(k, &(size, last_used))| (last_used, size, k.clone()))
+                .collect();
+            lru.sort_unstable_by_key(|&(last_used, ..)| last_used);
+            for (_, size, k) in lru {
+                if self.total <= limit {
+                    break;
+                }
+                self.entries.remove(&k);
+                self.total -= size;
+                evict.push(k);
+            }
+        }
+        evict
+    }
 }
 
 /// Symbolicate stack frames.
@@ -719,13 +941,14 @@ pub struct Symbolizer {
     /// Symbol supplier for locating symbols.
     supplier: Box<dyn SymbolSupplier + Send + Sync + 'static>,
     /// Cache of symbol locating results.
-    // TODO?: use lru-cache: https://crates.io/crates/lru-cache/
-    // note that using an lru-cache would mess up the fact that we currently
-    // use this for statistics collection. Splitting out statistics would be
-    // way messier but not impossible.
-    symbols: CacheMap<ModuleKey, CachedAsyncResult<SymbolFile, SymbolError>>,
+    ///
+    /// Entries are never removed, but with a `memory_limit` their results may be evicted.
+    symbols: CacheMap<ModuleKey, CachedAsyncResult<ModuleSymbols, SymbolError>>,
     pending_stats: Mutex<PendingSymbolStats>,
     stats: Mutex<HashMap<String, SymbolStats>>,
+    /// The most memory the cached parsed symbol files may use.
+    memory_limit: Option<usize>,
+    memory_usage: Mutex<MemoryUsage>,
 }
 
 impl Symbolizer {
@@ -736,9 +959,29 @@ impl Symbolizer {
             symbols: CacheMap::default(),
             pending_stats: Mutex::default(),
             stats: Mutex::default(),
+            memory_limit: None,
+            memory_usage: Mutex::default(),
         }
     }
 
+    /// Bound the memory used by cached symbol files to roughly `bytes`.
+    ///
+    /// When loading symbols goes over the limit, the least recently used parsed symbol files
+    /// are dropped and loaded again if they are needed later. Memory-mapped symbol caches
+    /// don't count towards the limit. By default the memory used is unbounded, which is best
+    /// when processing a single minidump.
+    pub fn memory_limit(mut self, bytes: usize) -> Symbolizer {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Get the estimated memory used by the cached parsed symbol files, in bytes.
+    ///
+    /// This is only tracked when a [`memory_limit`][Symbolizer::memory_limit] is set.
+    pub fn cached_memory_size(&self) -> usize {
+        self.memory_usage.lock().unwrap().total
+    }
+
     /// Helper method for non-minidump-using callers.
     ///
     /// Pass `debug_file` and `debug_id` describing a specific module,
@@ -840,28 +1083,64 @@ impl Symbolizer {
         }
     }
 
-    /// Gets the fully parsed SymbolFile for a given module (or an Error).
+    /// Gets the symbols for a given module (or an Error).
+    ///
+    /// A binary symbol cache is preferred, falling back to a fully parsed SymbolFile.
     ///
     /// This returns a CachedOperation which is guaranteed to already be resolved (lifetime stuff).
     async fn get_symbols(
         &self,
         module: &(dyn Module + Sync),
-    ) -> Arc<Result<SymbolFile, SymbolError>> {
+    ) -> Arc<Result<ModuleSymbols, SymbolError>> {
+        let key = module_key(module);
+        let result = self.load_symbols(module, &key).await;
+
+        if let (Some(limit), Ok(symbols)) = (self.memory_limit, result.as_ref()) {
+            let evict = self
+                .memory_usage
+                .lock()
+                .unwrap()
+                .touch(&key, symbols, limit);
+            for key in evict {
+                trace!("evicting symbols for module {}", key.0);
+                self.symbols.cache_default(key).evict().await;
+            }
+        }
+        result
+    }
+
+    /// Gets the cached symbols for a given module, loading them if needed.
+    async fn load_symbols(
+        &self,
+        module: &(dyn Module + Sync),
+        key: &ModuleKey,
+    ) -> Arc<Result<ModuleSymbols, SymbolError>> {
         self.symbols
-            .cache_default(module_key(module))
+            .cache_default(key.clone())
             .get(|| async {
                 trace!("locating symbols for module {}", module.code_file());
                 self.pending_stats.lock().unwrap().symbols_requested += 1;
-                let result = self.supplier.locate_symbols(module).await;
+                let result = match self.supplier.locate_symbol_cache(module).await {
+                    Ok(cache) => Ok((ModuleSymbols::Cached(cache), None)),
+                    Err(e) => {
+                        if e != SymbolError::NotFound {
+                            trace!("couldn't use symbol cache: {}", e);
+                        }
+                        self.supplier
+                            .locate_symbols(module)
+                            .await
+                            .map(|res| (ModuleSymbols::Parsed(res.symbols), res.extra_debug_info))
+                    }
+                };
                 self.pending_stats.lock().unwrap().symbols_processed += 1;
 
                 let mut stats = SymbolStats::default();
                 match &result {
-                    Ok(res) => {
-                        stats.symbol_url.clone_from(&res.symbols.url);
+                    Ok((symbols, extra_debug_info)) => {
+                        stats.symbol_url = symbols.url().map(String::from);
                         stats.loaded_symbols = true;
                         stats.corrupt_symbols = false;
-                        stats.extra_debug_info.clone_from(&res.extra_debug_info);
+                        stats.extra_debug_info.clone_from(extra_debug_info);
                     }
                     Err(SymbolError::NotFound) => {
                         stats.loaded_symbols = false;
@@ -880,7 +1159,7 @@ impl Symbolizer {
                 let key = leafname(module.code_file().as_ref()).to_string();
                 self.stats.lock().unwrap().insert(key, stats);
 
-                result.map(|r| r.symbols)
+                result.map(|(symbols, _)| symbols)
             })
             .await
     }
@@ -1123,6 +1402,90 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn test_simple_symbol_supplier_layouts() {
+        let t = tempfile::tempdir().unwrap();
+        let paths = mksubdirs(t.path(), &["symstore", "flat", "pointed"]);
+        let debug_id = DebugId::from_str("abcd1234-0000-0000-0000-abcd12345678-a").unwrap();
+        let m = SimpleModule::new("foo.pdb", debug_id);
+
+        // A two-tier symstore is detected by its index2.txt.
+        write_symbol_file(&paths[0].join("index2.txt"), b"");
+        let sym = paths[0].join("fo/foo.pdb/ABCD1234000000000000ABCD12345678a/foo.sym");
+        write_good_symbol_file(&sym);
+        let supplier = SimpleSymbolSupplier::new(vec![paths[0].clone()]);
+        assert_eq!(
+            supplier
+                .locate_file(&m, FileKind::BreakpadSym)
+                .await
+                .unwrap(),
+            sym.clone()
+        );
+
+        // ...but only if it really is one, unless the layout is explicit.
+        fs::remove_file(paths[0].join("index2.txt")).unwrap();
+        assert!(supplier
+            .locate_file(&m, FileKind::BreakpadSym)
+            .await
+            .is_err());
+        let supplier =
+            SimpleSymbolSupplier::with_layouts(vec![(paths[0].clone(), SymbolLayout::TwoTier)]);
+        assert_eq!(
+            supplier
+                .locate_file(&m, FileKind::BreakpadSym)
+                .await
+                .unwrap(),
+            sym.clone()
+        );
+
+        // Flat directories have to be asked for.
+        let flat_sym = paths[1].join("ABCD1234000000000000ABCD12345678a.sym");
+        write_good_symbol_file(&flat_sym);
+        let supplier = SimpleSymbolSupplier::new(vec![paths[1].clone()]);
+        assert!(supplier.locate_symbols(&m).await.is_err());
+        let supplier =
+            SimpleSymbolSupplier::with_layouts(vec![(paths[1].clone(), SymbolLayout::Flat)]);
+        assert_eq!(
+            supplier
+                .locate_file(&m, FileKind::BreakpadSym)
+                .await
+                .unwrap(),
+            flat_sym.clone()
+        );
+        assert!(supplier.locate_symbols(&m).await.is_ok());
+
+        // file.ptr redirects to a file stored elsewhere, relative paths are
+        // relative to the pointer.
+        let ptr_dir = paths[2].join("foo.pdb/ABCD1234000000000000ABCD12345678a");
+        write_symbol_file(
+            &ptr_dir.join("file.ptr"),
+            format!("PATH:{}\r\n", flat_sym.display()).as_bytes(),
+        );
+        let supplier = SimpleSymbolSupplier::new(vec![paths[2].clone()]);
+        assert_eq!(
+            supplier
+                .locate_file(&m, FileKind::BreakpadSym)
+                .await
+                .unwrap(),
+            flat_sym
+        );
+        write_symbol_file(
+            &ptr_dir.join("file.ptr"),
+            b"PATH:../../../symstore/fo/foo.pdb/ABCD1234000000000000ABCD12345678a/foo.sym",
+        );
+        assert_eq!(
+            supplier
+                .locate_file(&m, FileKind::BreakpadSym)
+                .await
+                .unwrap()
+                .canonicalize()
+                .unwrap(),
+            sym.canonicalize().unwrap()
+        );
+        write_symbol_file(&ptr_dir.join("file.ptr"), b"MSG:file was deleted");
+        assert!(supplier.locate_symbols(&m).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_symbolizer() {
         let t = tempfile::tempdir().unwrap();
@@ -1187,6 +1550,115 @@ FUNC 1000 30 10 another func
             .is_none());
     }
 
+    #[tokio::test]
+    async fn test_symbolizer_binary_cache() {
+        let t = tempfile::tempdir().unwrap();
+        let path = t.path();
+        let debug_id = DebugId::from_str("abcd1234-abcd-1234-abcd-abcd12345678-a").unwrap();
+        let module = SimpleModule::new("foo.pdb", debug_id);
+        let sym_path = path.join("foo.pdb/ABCD1234ABCD1234ABCDABCD12345678a/foo.sym");
+        let cache_path = path.join("foo.pdb/ABCD1234ABCD1234ABCDABCD12345678a/foo.symc");
+        write_symbol_file(
+            &sym_path,
+            b"MODULE Linux x86 ABCD1234ABCD1234ABCDABCD12345678a foo
+FILE 1 foo.c
+FUNC 1000 30 10 some func
+1000 30 100 1
+",
+        );
+
+        // Without binary caches enabled, nothing gets written.
+        let supplier = SimpleSymbolSupplier::new(vec![PathBuf::from(path)]);
+        assert!(supplier.locate_symbols(&module).await.is_ok());
+        assert!(!cache_path.exists());
+        assert_eq!(
+            supplier.locate_symbol_cache(&module).await.unwrap_err(),
+            SymbolError::NotFound
+        );
+
+        let supplier = SimpleSymbolSupplier::new(vec![PathBuf::from(path)]).binary_caches(true);
+        assert!(supplier.locate_symbols(&module).await.is_ok());
+        assert!(cache_path.exists());
+
+        // The cache is used even once the text symbol file is gone.
+        fs::remove_file(&sym_path).unwrap();
+        let symbolizer = Symbolizer::new(SimpleSymbolSupplier::new(vec![PathBuf::from(path)]));
+        let mut f1 = SimpleFrame::with_instruction(0x1010);
+        symbolizer.fill_symbol(&module, &mut f1).await.unwrap();
+        assert_eq!(f1.function.unwrap(), "some func");
+        assert_eq!(f1.source_file.unwrap(), "foo.c");
+        assert_eq!(f1.source_line.unwrap(), 100);
+        assert!(symbolizer.stats().get("").unwrap().loaded_symbols);
+
+        // A symbol file that is newer than its cache wins.
+        write_symbol_file(
+            &sym_path,
+            b"MODULE Linux x86 ABCD1234ABCD1234ABCDABCD12345678a foo
+FUNC 1000 30 10 newer func
+",
+        );
+        let newer = fs::metadata(&cache_path).unwrap().modified().unwrap()
+            + std::time::Duration::from_secs(10);
+        File::options()
+            .write(true)
+            .open(&sym_path)
+            .unwrap()
+            .set_modified(newer)
+            .unwrap();
+        let symbolizer = Symbolizer::new(SimpleSymbolSupplier::new(vec![PathBuf::from(path)]));
+        assert_eq!(
+            symbolizer
+                .get_symbol_at_address("foo.pdb", debug_id, 0x1010)
+                .await
+                .unwrap(),
+            "newer func"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_symbolizer_memory_limit() {
+        let t = tempfile::tempdir().unwrap();
+        let path = t.path();
+        let supplier = SimpleSymbolSupplier::new(vec![PathBuf::from(path)]);
+        // Only room for one symbol file at a time.
+        let symbolizer = Symbolizer::new(supplier).memory_limit(1);
+        let foo_id = DebugId::from_str("abcd1234-abcd-1234-abcd-abcd12345678-a").unwrap();
+        let bar_id = DebugId::from_str("ffff0000-0000-0000-0000-abcd12345678-a").unwrap();
+        let foo_path = path.join("foo.pdb/ABCD1234ABCD1234ABCDABCD12345678a/foo.sym");
+        write_symbol_file(
+            &foo_path,
+            b"MODULE Linux x86 ABCD1234ABCD1234ABCDABCD12345678a foo
+FUNC 1000 30 10 some func
+",
+        );
+        write_symbol_file(
+            &path.join("bar.pdb/FFFF0000000000000000ABCD12345678a/bar.sym"),
+            b"MODULE Linux x86 FFFF0000000000000000ABCD12345678a bar
+FUNC 1000 30 10 another func
+",
+        );
+
+        let lookup = |file, id| symbolizer.get_symbol_at_address(file, id, 0x1010);
+        assert_eq!(lookup("foo.pdb", foo_id).await.unwrap(), "some func");
+        let foo_size = symbolizer.cached_memory_size();
+        assert!(foo_size > 0);
+        // Still cached.
+        write_symbol_file(
+            &foo_path,
+            b"MODULE Linux x86 ABCD1234ABCD1234ABCDABCD12345678a foo
+FUNC 1000 30 10 newer func
+",
+        );
+        assert_eq!(lookup("foo.pdb", foo_id).await.unwrap(), "some func");
+        assert_eq!(symbolizer.pending_stats().symbols_requested, 1);
+
+        // Loading bar evicts foo, which is then loaded again.
+        assert_eq!(lookup("bar.pdb", bar_id).await.unwrap(), "another func");
+        assert_ne!(symbolizer.cached_memory_size(), foo_size);
+        assert_eq!(lookup("foo.pdb", foo_id).await.unwrap(), "newer func");
+        assert_eq!(symbolizer.pending_stats().symbols_requested, 3);
+    }
+
     #[tokio::test]
     async fn test_extra_debug_info() {
         let debug_info = DebugInfoResult {
@@ -0,0 +1,1018 @@
+//! A compact binary serialization of [`SymbolFile`]s.
+//!
+//! Parsing a large text `.sym` file is expensive in both time and memory (a
+//! few hundred megabytes of text can easily take seconds and gigabytes), so
+//! the result of a parse can be written out as a "symbol cache" next to the
+//! text file. A cache is laid out as a handful of flat tables of fixed-size
+//! little-endian records plus one blob of string data, so it can be memory
+//! mapped and queried in place. A lookup only materializes the single
+//! [`Function`] (or unwind entry) that covers the requested address.
+//!
+//! # Layout
+//!
+//! ```text
+//! header:     magic, version, flags, module_id, debug_file, url, section table
+//! strings:    all string data, referenced by (offset, len) pairs
+//! files:      (id, name), sorted by id
+//! origins:    (id, name), sorted by id
+//! functions:  (start, end, address, size, parameter_size, name, lines, inlinees), sorted by start
+//! lines:      (start, end, address, size, file, line), grouped by function
+//! inlinees:   (address, depth, size, call_file, call_line, origin), grouped by function
+//! publics:    (address, parameter_size, name), sorted by address
+//! cfi:        (start, end, address, size, add_rules, init_rules), sorted by start
+//! cfi_rules:  (address, rules), grouped by cfi entry
+//! win_fd:     STACK WIN 4 entries, sorted by start
+//! win_fpo:    STACK WIN 0 entries, sorted by start
+//! ```
+//!
+//! Every section starts at an 8-byte aligned offset, but all fields are
+//! decoded with explicit little-endian reads so neither alignment nor host
+//! endianness matter. The section table is validated when a cache is opened,
+//! and every individual read is bounds-checked, so a corrupt cache can
+//! produce garbage results but never a panic.
+
+use super::types::*;
+use super::{fill_function_symbol, walker};
+use crate::{FrameSymbolizer, FrameWalker, Module, SymbolError};
+use memmap2::Mmap;
+use range_map::{Range, RangeMap};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+const MAGIC: [u8; 8] = *b"BPSYMCCH";
+
+/// The current version of the binary symbol cache format.
+///
+/// Caches written with any other version are rejected, so the text symbol
+/// file will be parsed instead.
+pub const SYMBOL_CACHE_VERSION: u32 = 1;
+
+/// The extension given to a binary symbol cache stored next to a text symbol file.
+pub const SYMBOL_CACHE_EXTENSION: &str = "symc";
+
+const FLAG_HAS_URL: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Strings,
+    Files,
+    InlineOrigins,
+    Functions,
+    Lines,
+    Inlinees,
+    Publics,
+    Cfi,
+    CfiRules,
+    WinFrameData,
+    WinFpo,
+}
+
+const SECTIONS: [Section; 11] = [
+    Section::Strings,
+    Section::Files,
+    Section::InlineOrigins,
+    Section::Functions,
+    Section::Lines,
+    Section::Inlinees,
+    Section::Publics,
+    Section::Cfi,
+    Section::CfiRules,
+    Section::WinFrameData,
+    Section::WinFpo,
+];
+
+impl Section {
+    /// The size of one record in this section, in bytes.
+    fn record_size(self) -> usize {
+        match self {
+            Section::Strings => 1,
+            Section::Files | Section::InlineOrigins => 12,
+            Section::Functions => 56,
+            Section::Lines => 40,
+            Section::Inlinees => 32,
+            Section::Publics => 24,
+            Section::Cfi => 48,
+            Section::CfiRules => 16,
+            Section::WinFrameData | Section::WinFpo => 64,
+        }
+    }
+}
+
+// magic + version + flags + 3 string refs, then (offset, count) per section.
+const SECTION_TABLE_OFFSET: usize = 8 + 4 + 4 + 3 * 8;
+const HEADER_SIZE: usize = SECTION_TABLE_OFFSET + SECTIONS.len() * 16;
+
+// Values of the `kind` field of STACK WIN records.
+const WIN_PROGRAM_STRING: u32 = 0;
+const WIN_NO_BASE_POINTER: u32 = 1;
+const WIN_BASE_POINTER: u32 = 2;
+
+fn read_u32(rec: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&rec[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64(rec: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&rec[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// Find the first index in `0..len` for which `pred` is false, assuming
+/// `pred` is true for some prefix of the range and false afterwards.
+fn partition_point(len: usize, pred: impl Fn(usize) -> bool) -> usize {
+    let (mut lo, mut hi) = (0, len);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if pred(mid) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+/// The bytes backing a [`SymbolCache`].
+enum CacheData {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl Deref for CacheData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            CacheData::Mapped(map) => map,
+            CacheData::Owned(vec) => vec,
+        }
+    }
+}
+
+/// A binary symbol cache, queried in place without parsing the whole thing.
+///
+/// Supports the same lookups as [`SymbolFile::fill_symbol`] and
+/// [`SymbolFile::walk_frame`]. Use [`SymbolCache::write`] to produce one
+/// from a parsed [`SymbolFile`].
+pub struct SymbolCache {
+    data: CacheData,
+    /// `(offset, count)` of each section, indexed by `Section`.
+    sections: [(usize, usize); SECTIONS.len()],
+    module_id: String,
+    debug_file: String,
+    url: Option<String>,
+}
+
+impl std::fmt::Debug for SymbolCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SymbolCache")
+            .field("module_id", &self.module_id)
+            .field("debug_file", &self.debug_file)
+            .field("url", &self.url)
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+impl SymbolCache {
+    /// Memory-map the symbol cache at `path`.
+    pub fn from_file(path: &Path) -> Result<SymbolCache, SymbolError> {
+        let file = File::open(path)?;
+        // # Safety
+        // Caches are only ever replaced atomically (see `write_to_path`), never
+        // modified in place, so the mapping won't change under us.
+        let map = unsafe { Mmap::map(&file) }?;
+        Self::from_data(CacheData::Mapped(map))
+    }
+
+    /// Load a symbol cache from an in-memory buffer.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<SymbolCache, SymbolError> {
+        Self::from_data(CacheData::Owned(bytes))
+    }
+
+    fn from_data(data: CacheData) -> Result<SymbolCache, SymbolError> {
+        if data.len() < HEADER_SIZE || data[0..8] != MAGIC {
+            return Err(SymbolError::ParseError("not a symbol cache", 0));
+        }
+        if read_u32(&data, 8) != SYMBOL_CACHE_VERSION {
+            return Err(SymbolError::ParseError(
+                "unsupported symbol cache version",
+                0,
+            ));
+        }
+        let flags = read_u32(&data, 12);
+
+        let mut sections = [(0, 0); SECTIONS.len()];
+        for (i, section) in SECTIONS.iter().enumerate() {
+            let entry = SECTION_TABLE_OFFSET + i * 16;
+            let offset = usize::try_from(read_u64(&data, entry)).ok();
+            let count = usize::try_from(read_u64(&data, entry + 8)).ok();
+            let end = offset.zip(count).and_then(|(offset, count)| {
+                count
+                    .checked_mul(section.record_size())?
+                    .checked_add(offset)
+            });
+            match (offset, count, end) {
+                (Some(offset), Some(count), Some(end)) if end <= data.len() => {
+                    sections[i] = (offset, count);
+                }
+                _ => return Err(SymbolError::ParseError("corrupt symbol cache section", 0)),
+            }
+        }
+
+        let mut cache = SymbolCache {
+            data,
+            sections,
+            module_id: String::new(),
+            debug_file: String::new(),
+            url: None,
+        };
+        let header = &cache.data[..HEADER_SIZE];
+        let corrupt = || SymbolError::ParseError("corrupt symbol cache header", 0);
+        let module_id = cache.str_at(header, 16).ok_or_else(corrupt)?.to_string();
+        let debug_file = cache.str_at(header, 24).ok_or_else(corrupt)?.to_string();
+        let url = if flags & FLAG_HAS_URL != 0 {
+            Some(cache.str_at(header, 32).ok_or_else(corrupt)?.to_string())
+        } else {
+            None
+        };
+        cache.module_id = module_id;
+        cache.debug_file = debug_file;
+        cache.url = url;
+        Ok(cache)
+    }
+
+    /// The path of the symbol cache that belongs next to the text symbol file at `sym_path`.
+    pub fn path_for_symbol_file(sym_path: &Path) -> PathBuf {
+        sym_path.with_extension(SYMBOL_CACHE_EXTENSION)
+    }
+
+    /// The module id (from the `MODULE` line) of the cached symbol file.
+    pub fn module_id(&self) -> &str {
+        &self.module_id
+    }
+
+    /// The debug file name (from the `MODULE` line) of the cached symbol file.
+    pub fn debug_file(&self) -> &str {
+        &self.debug_file
+    }
+
+    /// If the cached symbol file was downloaded, this is the url it came from.
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// Serialize `symbols` as a binary symbol cache.
+    pub fn write<W: Write>(symbols: &SymbolFile, mut out: W) -> io::Result<()> {
+        let mut writer = CacheWriter::default();
+        let module_id = writer.strings.add(&symbols.module_id)?;
+        let debug_file = writer.strings.add(&symbols.debug_file)?;
+        let url = writer
+            .strings
+            .add(symbols.url.as_deref().unwrap_or_default())?;
+        writer.add_symbols(symbols)?;
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend_from_slice(&MAGIC);
+        header.put_u32(SYMBOL_CACHE_VERSION);
+        header.put_u32(if symbols.url.is_some() {
+            FLAG_HAS_URL
+        } else {
+            0
+        });
+        header.put_str(module_id);
+        header.put_str(debug_file);
+        header.put_str(url);
+
+        let tables = writer.tables();
+        let mut offset = HEADER_SIZE as u64;
+        for (section, table) in SECTIONS.iter().zip(&tables) {
+            header.put_u64(offset);
+            header.put_u64((table.len() / section.record_size()) as u64);
+            offset += padded_len(table.len()) as u64;
+        }
+        out.write_all(&header)?;
+
+        for table in &tables {
+            out.write_all(table)?;
+            out.write_all(&[0; 8][..padded_len(table.len()) - table.len()])?;
+        }
+        out.flush()
+    }
+
+    /// Serialize `symbols` as a binary symbol cache at `path`.
+    ///
+    /// The cache is written to a temporary file next to `path` and then
+    /// atomically moved into place, so concurrent readers will never observe
+    /// a partially written cache.
+    pub fn write_to_path(symbols: &SymbolFile, path: &Path) -> io::Result<()> {
+        let mut tmp_name = path.as_os_str().to_owned();
+        tmp_name.push(format!(".tmp{}", std::process::id()));
+        let tmp_path = PathBuf::from(tmp_name);
+
+        let result = File::create(&tmp_path)
+            .and_then(|file| Self::write(symbols, io::BufWriter::new(file)))
+            .and_then(|_| fs::rename(&tmp_path, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result
+    }
+
+    /// Fill in as much source information for `frame` as possible.
+    ///
+    /// See [`SymbolFile::fill_symbol`].
+    pub fn fill_symbol(&self, module: &dyn Module, frame: &mut dyn FrameSymbolizer) {
+        if frame.get_instruction() < module.base_address() {
+            return;
+        }
+        let addr = frame.get_instruction() - module.base_address();
+
+        if let Some(func) = self
+            .find_range(Section::Functions, addr)
+            .and_then(|idx| self.function(idx, addr))
+        {
+            let parameter_size = self
+                .win_parameter_size(Section::WinFrameData, addr)
+                .or_else(|| self.win_parameter_size(Section::WinFpo, addr))
+                .unwrap_or(func.parameter_size);
+            fill_function_symbol(
+                module,
+                frame,
+                addr,
+                &func,
+                parameter_size,
+                |id| self.lookup_name(Section::Files, id),
+                |id| self.lookup_name(Section::InlineOrigins, id),
+            );
+        } else if let Some(public) = self.find_nearest_public(addr) {
+            // See `SymbolFile::fill_symbol`: a PUBLIC can't extend past the
+            // nearest FUNC that precedes `addr`.
+            let prev_func = partition_point(self.count(Section::Functions), |idx| {
+                self.record(Section::Functions, idx)
+                    .is_some_and(|rec| read_u64(rec, 0) <= addr)
+            })
+            .checked_sub(1)
+            .and_then(|idx| self.record(Section::Functions, idx));
+            if let Some(prev_func) = prev_func {
+                if public.address <= read_u64(prev_func, 16) {
+                    return;
+                }
+            }
+
+            frame.set_function(
+                &public.name,
+                public.address + module.base_address(),
+                public.parameter_size,
+            );
+        }
+    }
+
+    /// Try to unwind `walker` using the cached STACK WIN and STACK CFI records.
+    ///
+    /// See [`SymbolFile::walk_frame`].
+    pub fn walk_frame(&self, module: &dyn Module, walker: &mut dyn FrameWalker) -> Option<()> {
+        if walker.get_instruction() < module.base_address() {
+            return None;
+        }
+        let addr = walker.get_instruction() - module.base_address();
+
+        // Preferentially use framedata over fpo, because if both are present,
+        // the former tends to be more precise (breakpad heuristic).
+        let win_stack_result = if let Some(info) = self.win_info(Section::WinFrameData, addr) {
+            walker::walk_with_stack_win_framedata(&info, walker)
+        } else if let Some(info) = self.win_info(Section::WinFpo, addr) {
+            walker::walk_with_stack_win_fpo(&info, walker)
+        } else {
+            None
+        };
+
+        win_stack_result.or_else(|| {
+            let info = self.cfi_info(addr)?;
+            walker::walk_with_stack_cfi(&info.init, &info.add_rules, walker)
+        })
+    }
+
+    /// Find the nearest `PublicSymbol` whose address is less than or equal to `addr`.
+    pub fn find_nearest_public(&self, addr: u64) -> Option<PublicSymbol> {
+        let idx = partition_point(self.count(Section::Publics), |idx| {
+            self.record(Section::Publics, idx)
+                .is_some_and(|rec| read_u64(rec, 0) <= addr)
+        })
+        .checked_sub(1)?;
+        let rec = self.record(Section::Publics, idx)?;
+        Some(PublicSymbol {
+            address: read_u64(rec, 0),
+            parameter_size: read_u32(rec, 8),
+            name: self.str_at(rec, 12)?.to_string(),
+        })
+    }
+
+    fn count(&self, section: Section) -> usize {
+        self.sections[section as usize].1
+    }
+
+    fn record(&self, section: Section, idx: usize) -> Option<&[u8]> {
+        let (offset, count) = self.sections[section as usize];
+        if idx >= count {
+            return None;
+        }
+        let size = section.record_size();
+        let start = offset + idx * size;
+        self.data.get(start..start + size)
+    }
+
+    /// Read the string referenced by the `(offset, len)` pair at `offset` in `rec`.
+    fn str_at(&self, rec: &[u8], offset: usize) -> Option<&str> {
+        let str_offset = read_u32(rec, offset) as usize;
+        let len = read_u32(rec, offset + 4) as usize;
+        let (strings, strings_len) = self.sections[Section::Strings as usize];
+        if str_offset.checked_add(len)? > strings_len {
+            return None;
+        }
+        let start = strings + str_offset;
+        std::str::from_utf8(self.data.get(start..start + len)?).ok()
+    }
+
+    /// Look up a name by id in the files or inline origins table.
+    fn lookup_name(&self, section: Section, id: u32) -> Option<&str> {
+        let idx = partition_point(self.count(section), |idx| {
+            self.record(section, idx)
+                .is_some_and(|rec| read_u32(rec, 0) < id)
+        });
+        let rec = self.record(section, idx)?;
+        if read_u32(rec, 0) != id {
+            return None;
+        }
+        self.str_at(rec, 4)
+    }
+
+    /// Find the record in a section sorted by range whose range contains `addr`.
+    ///
+    /// Every such record starts with the inclusive `(start, end)` of its range.
+    fn find_range(&self, section: Section, addr: u64) -> Option<usize> {
+        let idx = partition_point(self.count(section), |idx| {
+            self.record(section, idx)
+                .is_some_and(|rec| read_u64(rec, 0) <= addr)
+        })
+        .checked_sub(1)?;
+        let rec = self.record(section, idx)?;
+        (read_u64(rec, 8) >= addr).then_some(idx)
+    }
+
+    /// Materialize the function at `idx`, with only the line and inlinee
+    /// records that cover `addr` (the only ones symbolication will look at).
+    fn function(&self, idx: usize, addr: u64) -> Option<Function> {
+        let rec = self.record(Section::Functions, idx)?;
+        let lines_start = read_u32(rec, 32) as usize;
+        let lines_count = read_u32(rec, 36) as usize;
+        let inlinees_start = read_u32(rec, 40) as usize;
+        let inlinees_count = read_u32(rec, 44) as usize;
+
+        let line_idx = partition_point(lines_count, |idx| {
+            self.record(Section::Lines, lines_start.saturating_add(idx))
+                .is_some_and(|line| read_u64(line, 0) <= addr)
+        })
+        .checked_sub(1)
+        .map(|idx| lines_start.saturating_add(idx));
+        let lines = line_idx
+            .and_then(|idx| self.record(Section::Lines, idx))
+            .filter(|line| read_u64(line, 0) <= addr && addr <= read_u64(line, 8))
+            .map(|line| {
+                (
+                    Range::new(read_u64(line, 0), read_u64(line, 8)),
+                    SourceLine {
+                        address: read_u64(line, 16),
+                        size: read_u32(line, 24),
+                        file: read_u32(line, 28),
+                        line: read_u32(line, 32),
+                    },
+                )
+            });
+        // Inlinees are sorted by (depth, address), so walk down the depths
+        // picking the same candidate `Function::get_inlinee_at_depth` would,
+        // until one doesn't cover the address.
+        let inlinee_at =
+            |idx: usize| self.record(Section::Inlinees, inlinees_start.saturating_add(idx));
+        let mut inlinees = Vec::new();
+        for depth in 0u32.. {
+            let Some(inlinee) = partition_point(inlinees_count, |idx| {
+                inlinee_at(idx)
+                    .is_some_and(|rec| (read_u32(rec, 8), read_u64(rec, 0)) <= (depth, addr))
+            })
+            .checked_sub(1)
+            .and_then(inlinee_at)
+            .map(|rec| Inlinee {
+                address: read_u64(rec, 0),
+                depth: read_u32(rec, 8),
+                size: read_u32(rec, 12),
+                call_file: read_u32(rec, 16),
+                call_line: read_u32(rec, 20),
+                origin_id: read_u32(rec, 24),
+            }) else {
+                break;
+            };
+            let covers_addr = inlinee
+                .address
+                .checked_add(inlinee.size as u64)
+                .is_some_and(|end| addr < end);
+            if inlinee.depth != depth || !covers_addr {
+                break;
+            }
+            inlinees.push(inlinee);
+        }
+
+        Some(Function {
+            address: read_u64(rec, 16),
+            size: read_u32(rec, 24),
+            parameter_size: read_u32(rec, 28),
+            name: self.str_at(rec, 48)?.to_string(),
+            lines: RangeMap::try_from_iter(lines).unwrap_or_else(|_| RangeMap::new()),
+            inlinees,
+        })
+    }
+
+    fn win_parameter_size(&self, section: Section, addr: u64) -> Option<u32> {
+        let rec = self.record(section, self.find_range(section, addr)?)?;
+        Some(read_u32(rec, 36))
+    }
+
+    fn win_info(&self, section: Section, addr: u64) -> Option<StackInfoWin> {
+        let rec = self.record(section, self.find_range(section, addr)?)?;
+        let program_string_or_base_pointer = match read_u32(rec, 52) {
+            WIN_PROGRAM_STRING => WinStackThing::ProgramString(self.str_at(rec, 56)?.to_string()),
+            WIN_NO_BASE_POINTER => WinStackThing::AllocatesBasePointer(false),
+            WIN_BASE_POINTER => WinStackThing::AllocatesBasePointer(true),
+            _ => return None,
+        };
+        Some(StackInfoWin {
+            address: read_u64(rec, 16),
+            size: read_u32(rec, 24),
+            prologue_size: read_u32(rec, 28),
+            epilogue_size: read_u32(rec, 32),
+            parameter_size: read_u32(rec, 36),
+            saved_register_size: read_u32(rec, 40),
+            local_size: read_u32(rec, 44),
+            max_stack_size: read_u32(rec, 48),
+            program_string_or_base_pointer,
+        })
+    }
+
+    /// Get the STACK CFI entry covering `addr`, with only the add rules that
+    /// apply at `addr`.
+    fn cfi_info(&self, addr: u64) -> Option<StackInfoCfi> {
+        let rec = self.record(Section::Cfi, self.find_range(Section::Cfi, addr)?)?;
+        let rules_start = read_u32(rec, 28) as usize;
+        let rules_count = read_u32(rec, 32) as usize;
+
+        let mut add_rules = Vec::new();
+        for idx in rules_start..rules_start.saturating_add(rules_count) {
+            let rule = self.record(Section::CfiRules, idx)?;
+            let address = read_u64(rule, 0);
+            // Don't use add rules that come after this address
+            if address > addr {
+                break;
+            }
+            add_rules.push(CfiRules {
+                address,
+                rules: self.str_at(rule, 8)?.to_string(),
+            });
+        }
+
+        Some(StackInfoCfi {
+            init: CfiRules {
+                address: read_u64(rec, 16),
+                rules: self.str_at(rec, 40)?.to_string(),
+            },
+            size: read_u32(rec, 24),
+            add_rules,
+        })
+    }
+}
+
+fn padded_len(len: usize) -> usize {
+    len.next_multiple_of(8)
+}
+
+fn too_large<T>(_: T) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "symbol file too large for symbol cache",
+    )
+}
+
+fn to_u32(val: usize) -> io::Result<u32> {
+    u32::try_from(val).map_err(too_large)
+}
+
+trait PutLe {
+    fn put_u32(&mut self, val: u32);
+    fn put_u64(&mut self, val: u64);
+    fn put_str(&mut self, s: StrRef);
+}
+
+impl PutLe for Vec<u8> {
+    fn put_u32(&mut self, val: u32) {
+        self.extend_from_slice(&val.to_le_bytes());
+    }
+    fn put_u64(&mut self, val: u64) {
+        self.extend_from_slice(&val.to_le_bytes());
+    }
+    fn put_str(&mut self, s: StrRef) {
+        self.put_u32(s.0);
+        self.put_u32(s.1);
+    }
+}
+
+/// An `(offset, len)` reference into the strings section.
+#[derive(Clone, Copy)]
+struct StrRef(u32, u32);
+
+#[derive(Default)]
+struct StringTable<'a> {
+    data: Vec<u8>,
+    /// File names and CFI rules are heavily repeated, so dedupe all strings.
+    known: HashMap<&'a str, StrRef>,
+}
+
+impl<'a> StringTable<'a> {
+    fn add(&mut self, s: &'a str) -> io::Result<StrRef> {
+        if let Some(&r) = self.known.get(s) {
+            return Ok(r);
+        }
+        let r = StrRef(to_u32(self.data.len())?, to_u32(s.len())?);
+        self.data.extend_from_slice(s.as_bytes());
+        to_u32(self.data.len())?;
+        self.known.insert(s, r);
+        Ok(r)
+    }
+}
+
+#[derive(Default)]
+struct CacheWriter<'a> {
+    strings: StringTable<'a>,
+    files: Vec<u8>,
+    inline_origins: Vec<u8>,
+    functions: Vec<u8>,
+    lines: Vec<u8>,
+    inlinees: Vec<u8>,
+    publics: Vec<u8>,
+    cfi: Vec<u8>,
+    cfi_rules: Vec<u8>,
+    win_framedata: Vec<u8>,
+    win_fpo: Vec<u8>,
+}
+
+impl<'a> CacheWriter<'a> {
+    fn add_symbols(&mut self, symbols: &'a SymbolFile) -> io::Result<()> {
+        for (table, names) in [
+            (&mut self.files, &symbols.files),
+            (&mut self.inline_origins, &symbols.inline_origins),
+        ] {
+            let mut names: Vec<_> = names.iter().collect();
+            names.sort_by_key(|&(&id, _)| id);
+            for (&id, name) in names {
+                table.put_u32(id);
+                table.put_str(self.strings.add(name)?);
+            }
+        }
+
+        for (range, func) in symbols.functions.ranges_values() {
+            let lines_start = to_u32(self.lines.len() / Section::Lines.record_size())?;
+            for (line_range, line) in func.lines.ranges_values() {
+                self.lines.put_u64(line_range.start);
+                self.lines.put_u64(line_range.end);
+                self.lines.put_u64(line.address);
+                self.lines.put_u32(line.size);
+                self.lines.put_u32(line.file);
+                self.lines.put_u32(line.line);
+                self.lines.put_u32(0);
+            }
+            let inlinees_start = to_u32(self.inlinees.len() / Section::Inlinees.record_size())?;
+            for inlinee in &func.inlinees {
+                self.inlinees.put_u64(inlinee.address);
+                self.inlinees.put_u32(inlinee.depth);
+                self.inlinees.put_u32(inlinee.size);
+                self.inlinees.put_u32(inlinee.call_file);
+                self.inlinees.put_u32(inlinee.call_line);
+                self.inlinees.put_u32(inlinee.origin_id);
+                self.inlinees.put_u32(0);
+            }
+
+            self.functions.put_u64(range.start);
+            self.functions.put_u64(range.end);
+            self.functions.put_u64(func.address);
+            self.functions.put_u32(func.size);
+            self.functions.put_u32(func.parameter_size);
+            self.functions.put_u32(lines_start);
+            self.functions.put_u32(to_u32(func.lines.num_ranges())?);
+            self.functions.put_u32(inlinees_start);
+            self.functions.put_u32(to_u32(func.inlinees.len())?);
+            self.functions.put_str(self.strings.add(&func.name)?);
+        }
+
+        for public in &symbols.publics {
+            self.publics.put_u64(public.address);
+            self.publics.put_u32(public.parameter_size);
+            self.publics.put_str(self.strings.add(&public.name)?);
+            self.publics.put_u32(0);
+        }
+
+        for (range, info) in symbols.cfi_stack_info.ranges_values() {
+            let rules_start = to_u32(self.cfi_rules.len() / Section::CfiRules.record_size())?;
+            for rule in &info.add_rules {
+                self.cfi_rules.put_u64(rule.address);
+                self.cfi_rules.put_str(self.strings.add(&rule.rules)?);
+            }
+            self.cfi.put_u64(range.start);
+            self.cfi.put_u64(range.end);
+            self.cfi.put_u64(info.init.address);
+            self.cfi.put_u32(info.size);
+            self.cfi.put_u32(rules_start);
+            self.cfi.put_u32(to_u32(info.add_rules.len())?);
+            self.cfi.put_u32(0);
+            self.cfi.put_str(self.strings.add(&info.init.rules)?);
+        }
+
+        for (table, infos) in [
+            (&mut self.win_framedata, &symbols.win_stack_framedata_info),
+            (&mut self.win_fpo, &symbols.win_stack_fpo_info),
+        ] {
+            for (range, info) in infos.ranges_values() {
+                table.put_u64(range.start);
+                table.put_u64(range.end);
+                table.put_u64(info.address);
+                table.put_u32(info.size);
+                table.put_u32(info.prologue_size);
+                table.put_u32(info.epilogue_size);
+                table.put_u32(info.parameter_size);
+                table.put_u32(info.saved_register_size);
+                table.put_u32(info.local_size);
+                table.put_u32(info.max_stack_size);
+                match &info.program_string_or_base_pointer {
+                    WinStackThing::ProgramString(program) => {
+                        table.put_u32(WIN_PROGRAM_STRING);
+                        table.put_str(self.strings.add(program)?);
+                    }
+                    WinStackThing::AllocatesBasePointer(allocates) => {
+                        table.put_u32(if *allocates {
+                            WIN_BASE_POINTER
+                        } else {
+                            WIN_NO_BASE_POINTER
+                        });
+                        table.put_str(StrRef(0, 0));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The finished tables, in the order of `SECTIONS`.
+    fn tables(self) -> [Vec<u8>; SECTIONS.len()] {
+        [
+            self.strings.data,
+            self.files,
+            self.inline_origins,
+            self.functions,
+            self.lines,
+            self.inlinees,
+            self.publics,
+            self.cfi,
+            self.cfi_rules,
+            self.win_framedata,
+            self.win_fpo,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{SimpleFrame, SimpleModule};
+    use std::ffi::OsStr;
+
+    fn testdata_path(rel_path: &str) -> PathBuf {
+        let mut path = std::env::current_dir().unwrap();
+        if path.file_name() == Some(OsStr::new("rust-minidump")) {
+            path.push("breakpad-symbols");
+        }
+        path.push(rel_path);
+        path
+    }
+
+    fn round_trip(sym: &SymbolFile) -> SymbolCache {
+        let mut bytes = Vec::new();
+        SymbolCache::write(sym, &mut bytes).unwrap();
+        SymbolCache::from_bytes(bytes).unwrap()
+    }
+
+    /// Records every value the unwinder writes, so two walks can be compared.
+    #[derive(Default)]
+    struct RecordingWalker {
+        instruction: u64,
+        caller: Vec<(String, u64)>,
+    }
+
+    impl FrameWalker for RecordingWalker {
+        fn get_instruction(&self) -> u64 {
+            self.instruction
+        }
+        fn has_grand_callee(&self) -> bool {
+            false
+        }
+        fn get_grand_callee_parameter_size(&self) -> u32 {
+            0
+        }
+        fn get_register_at_address(&self, address: u64) -> Option<u64> {
+            Some(address.wrapping_mul(3))
+        }
+        fn get_callee_register(&self, name: &str) -> Option<u64> {
+            Some(0x1000 + name.len() as u64 * 0x10)
+        }
+        fn set_caller_register(&mut self, name: &str, val: u64) -> Option<()> {
+            self.caller.push((name.to_string(), val));
+            Some(())
+        }
+        fn clear_caller_register(&mut self, name: &str) {
+            self.caller.push((format!("clear {name}"), 0));
+        }
+        fn set_cfa(&mut self, val: u64) -> Option<()> {
+            self.caller.push(("cfa".to_string(), val));
+            Some(())
+        }
+        fn set_ra(&mut self, val: u64) -> Option<()> {
+            self.caller.push(("ra".to_string(), val));
+            Some(())
+        }
+    }
+
+    #[derive(Debug, Default, PartialEq)]
+    struct FullFrame {
+        function: Option<(String, u64, u32)>,
+        source: Option<(String, u32, u64)>,
+        inlines: Vec<(String, Option<String>, Option<u32>)>,
+    }
+
+    impl FrameSymbolizer for (u64, FullFrame) {
+        fn get_instruction(&self) -> u64 {
+            self.0
+        }
+        fn set_function(&mut self, name: &str, base: u64, parameter_size: u32) {
+            self.1.function = Some((name.to_string(), base, parameter_size));
+        }
+        fn set_source_file(&mut self, file: &str, line: u32, base: u64) {
+            self.1.source = Some((file.to_string(), line, base));
+        }
+        fn add_inline_frame(&mut self, name: &str, file: Option<&str>, line: Option<u32>) {
+            self.1
+                .inlines
+                .push((name.to_string(), file.map(String::from), line));
+        }
+    }
+
+    /// Check that every interesting address resolves identically with the
+    /// parsed symbol file and with its cache.
+    fn assert_cache_matches(rel_path: &str) {
+        let sym = SymbolFile::from_file(&testdata_path(rel_path)).unwrap();
+        let cache = round_trip(&sym);
+        assert_eq!(cache.module_id(), sym.module_id);
+        assert_eq!(cache.debug_file(), sym.debug_file);
+
+        let module = SimpleModule {
+            base_address: Some(0x10000),
+            ..SimpleModule::default()
+        };
+        let mut addrs = vec![0, 0xFFFF_FFFF];
+        for (range, func) in sym.functions.ranges_values() {
+            addrs.extend([range.start, range.end, range.end + 1]);
+            addrs.extend(func.inlinees.iter().map(|i| i.address));
+            // Sampling the line records keeps this quick on large symbol files.
+            addrs.extend(
+                func.lines
+                    .ranges_values()
+                    .step_by(8)
+                    .map(|(r, _)| r.start + 1),
+            );
+        }
+        addrs.extend(sym.publics.iter().flat_map(|p| [p.address, p.address + 1]));
+        for infos in [&sym.win_stack_framedata_info, &sym.win_stack_fpo_info] {
+            addrs.extend(infos.ranges_values().map(|(r, _)| r.start));
+        }
+        for (range, info) in sym.cfi_stack_info.ranges_values() {
+            addrs.push(range.start);
+            addrs.extend(info.add_rules.iter().map(|r| r.address));
+        }
+
+        for addr in addrs {
+            let instruction = addr + 0x10000;
+            let mut expected = (instruction, FullFrame::default());
+            let mut actual = (instruction, FullFrame::default());
+            sym.fill_symbol(&module, &mut expected);
+            cache.fill_symbol(&module, &mut actual);
+            assert_eq!(actual.1, expected.1, "fill_symbol at {addr:#x}");
+
+            let mut expected = RecordingWalker {
+                instruction,
+                ..RecordingWalker::default()
+            };
+            let mut actual = RecordingWalker {
+                instruction,
+                ..RecordingWalker::default()
+            };
+            let expected_result = sym.walk_frame(&module, &mut expected);
+            let actual_result = cache.walk_frame(&module, &mut actual);
+            assert_eq!(actual_result, expected_result, "walk_frame at {addr:#x}");
+            // CFI rules are applied in hash order, so only the set of
+            // recovered registers is meaningful.
+            actual.caller.sort();
+            expected.caller.sort();
+            assert_eq!(actual.caller, expected.caller, "walk_frame at {addr:#x}");
+        }
+    }
+
+    #[test]
+    fn test_cache_matches_stack_win_symbols() {
+        assert_cache_matches(
+            "testdata/symbols/test_app.pdb/5A9832E5287241C1838ED98914E9B7FF1/test_app.sym",
+        );
+    }
+
+    #[test]
+    fn test_cache_matches_cfi_and_inline_symbols() {
+        assert_cache_matches(
+            "../testdata/symbols/crash-client/509C0610949836F7B70BD88BCF03E5400/crash-client.sym",
+        );
+    }
+
+    #[test]
+    fn test_cache_from_file() {
+        let sym = SymbolFile::from_bytes(
+            b"MODULE Linux x86 ffff0000 bar
+INFO URL https://symbols.example.com/bar
+FILE 53 bar.c
+PUBLIC 1234 10 some public
+FUNC 1000 30 10 another func
+1000 30 7 53
+",
+        )
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let sym_path = dir.path().join("bar.sym");
+        let path = SymbolCache::path_for_symbol_file(&sym_path);
+        assert_eq!(path, dir.path().join("bar.symc"));
+        SymbolCache::write_to_path(&sym, &path).unwrap();
+
+        let cache = SymbolCache::from_file(&path).unwrap();
+        assert_eq!(cache.module_id(), "ffff0000");
+        assert_eq!(cache.debug_file(), "bar");
+        assert_eq!(cache.url(), Some("https://symbols.example.com/bar"));
+
+        let module = SimpleModule::default();
+        let mut frame = SimpleFrame::with_instruction(0x1010);
+        cache.fill_symbol(&module, &mut frame);
+        assert_eq!(frame.function.unwrap(), "another func");
+        assert_eq!(frame.source_file.unwrap(), "bar.c");
+        assert_eq!(frame.source_line.unwrap(), 7);
+
+        let mut frame = SimpleFrame::with_instruction(0x1240);
+        cache.fill_symbol(&module, &mut frame);
+        assert_eq!(frame.function.unwrap(), "some public");
+    }
+
+    #[test]
+    fn test_corrupt_cache() {
+        let sym =
+            SymbolFile::from_bytes(b"MODULE Linux x86 ffff0000 bar\nFUNC 1000 30 10 f\n").unwrap();
+        let mut bytes = Vec::new();
+        SymbolCache::write(&sym, &mut bytes).unwrap();
+
+        assert!(SymbolCache::from_bytes(b"MODULE Linux x86 ffff0000 bar\n".to_vec()).is_err());
+        assert!(SymbolCache::from_bytes(bytes[..HEADER_SIZE - 1].to_vec()).is_err());
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[8] += 1;
+        assert!(SymbolCache::from_bytes(wrong_version).is_err());
+
+        // Point the functions section past the end of the file.
+        let mut bad_section = bytes.clone();
+        let entry = SECTION_TABLE_OFFSET + Section::Functions as usize * 16;
+        bad_section[entry..entry + 8].copy_from_slice(&(bytes.len() as u64).to_le_bytes());
+        assert!(SymbolCache::from_bytes(bad_section).is_err());
+
+        // Garbage in the strings is tolerated, it just produces no name.
+        let mut bad_string = bytes;
+        let name_offset = bad_string.len() - 8;
+        bad_string[name_offset..].copy_from_slice(&[0xff; 8]);
+        let cache = SymbolCache::from_bytes(bad_string).unwrap();
+        let mut frame = SimpleFrame::with_instruction(0x1010);
+        cache.fill_symbol(&SimpleModule::default(), &mut frame);
+        assert!(frame.function.is_none());
+    }
+}
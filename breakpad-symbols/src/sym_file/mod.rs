@@ -1,18 +1,29 @@
 // Copyright 2015 Ted Mielczarek. See the COPYRIGHT
 // file at the top-level directory of this distribution.
 use crate::{FrameSymbolizer, FrameWalker, Module, SymbolError};
+use futures_util::{Stream, StreamExt};
 
 pub use crate::sym_file::types::*;
+pub use cache::{SymbolCache, SYMBOL_CACHE_EXTENSION, SYMBOL_CACHE_VERSION};
 pub use parser::SymbolParser;
+use range_map::{Range, RangeMap};
+use std::collections::HashMap;
+use std::fmt::Debug;
 use std::fs::File;
 use std::io::Read;
+use std::mem::size_of;
 use std::ops::Deref;
 use std::path::Path;
 use tracing::trace;
+pub use validate::{DiagnosticKind, Severity, SymbolDiagnostic};
+pub use writer::{SymbolMergeError, SymbolSection};
 
+mod cache;
 mod parser;
 mod types;
+mod validate;
 pub mod walker;
+mod writer;
 
 // # Sync VS Async
 //
@@ -193,10 +204,30 @@ impl SymbolFile {
     /// `parse` but async
     #[cfg(feature = "http")]
     pub async fn parse_async(
-        mut response: reqwest::Response,
+        response: reqwest::Response,
+        callback: impl FnMut(&[u8]),
+    ) -> Result<SymbolFile, SymbolError> {
+        let chunks = futures_util::stream::unfold(response, |mut response| async move {
+            let chunk = response
+                .chunk()
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+                .transpose()?;
+            Some((chunk, response))
+        });
+        Self::parse_stream(chunks, callback).await
+    }
+
+    /// `parse` but async, reading the input from a stream of chunks.
+    ///
+    /// The stream ends at the first `None`, and the first `Err` aborts
+    /// parsing.
+    pub async fn parse_stream<B: AsRef<[u8]>>(
+        chunks: impl Stream<Item = std::io::Result<B>>,
         mut callback: impl FnMut(&[u8]),
     ) -> Result<SymbolFile, SymbolError> {
-        let mut chunk;
+        futures_util::pin_mut!(chunks);
+        let mut chunk: Option<B>;
         let mut slice = &[][..];
         let mut input_reader = &mut slice;
         let mut buf = circular::Buffer::with_capacity(INITIAL_BUFFER_CAPACITY);
@@ -237,19 +268,19 @@ impl SymbolFile {
             }
 
             // Little rube-goldberg machine to stream the contents:
-            // * get a chunk (Bytes) from the Response
+            // * get a chunk from the stream
             // * get its underlying slice
             // * then get a mutable reference to that slice
             // * then Read that mutable reference in our circular buffer
             // * when the slice runs out, get the next chunk and repeat
-            if input_reader.is_empty() {
-                chunk = response
-                    .chunk()
-                    .await
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
-                    .unwrap_or_default();
-                slice = &chunk[..];
+            // * skip empty chunks, so only the end of the stream reads as EOF
+            while input_reader.is_empty() {
+                chunk = chunks.next().await.transpose()?;
+                slice = chunk.as_ref().map_or(&[][..], AsRef::as_ref);
                 input_reader = &mut slice;
+                if chunk.is_none() {
+                    break;
+                }
             }
 
             // Read the data in, and tell the circular buffer about the new data
@@ -333,6 +364,23 @@ impl SymbolFile {
         Self::parse(file, |_| ())
     }
 
+    /// Check a symbol file for problems, reporting each one with its line.
+    ///
+    /// Unlike [`SymbolFile::parse`], this doesn't give up at the first
+    /// corrupt line, and it also reports problems the parser silently works
+    /// around, like overlapping records or records referring to `FILE` or
+    /// `INLINE_ORIGIN` records that don't exist. The returned diagnostics are
+    /// sorted by line; an `Err` is only returned if `input` can't be read.
+    pub fn validate<R: Read>(input: R) -> Result<Vec<SymbolDiagnostic>, SymbolError> {
+        validate::validate(input)
+    }
+
+    /// Check the symbol file at `path` for problems, see [`SymbolFile::validate`].
+    pub fn validate_file(path: &Path) -> Result<Vec<SymbolDiagnostic>, SymbolError> {
+        let file = File::open(path)?;
+        Self::validate(file)
+    }
+
     /// Fill in as much source information for `frame` as possible.
     pub fn fill_symbol(&self, module: &dyn Module, frame: &mut dyn FrameSymbolizer) {
         // Look for a FUNC covering the address first.
@@ -357,80 +405,15 @@ impl SymbolFile {
                 func.parameter_size
             };
 
-            frame.set_function(
-                &func.name,
-                func.address + module.base_address(),
+            fill_function_symbol(
+                module,
+                frame,
+                addr,
+                func,
                 parameter_size,
+                |id| self.files.get(&id).map(Deref::deref),
+                |id| self.inline_origins.get(&id).map(Deref::deref),
             );
-
-            // See if there's source line and inline info as well.
-            //
-            // In the following, we transform data between two different representations of inline calls.
-            // The input shape has function names associated with the location of the call to that function.
-            // The output shape has function names associated with a location *inside* that function.
-            //
-            // Input:
-            //
-            //   (
-            //       outer_name,
-            //       inline_calls: [ // Each location is the line of the *call* to the function
-            //           (inline_call_location[0], inline_name[0]),
-            //           (inline_call_location[1], inline_name[1]),
-            //           (inline_call_location[2], inline_name[2]),
-            //       ]
-            //       innermost_location,
-            //   )
-            //
-            // Output:
-            //
-            //   ( // Each location is the line *inside* the function
-            //       (outer_name, inline_call_location[0]),
-            //       inlines: [
-            //           (inline_name[0], inline_call_location[1]),
-            //           (inline_name[1], inline_call_location[2]),
-            //           (inline_name[2], innermost_location),
-            //       ]
-            //   )
-            if let Some((file_id, line, address, next_inline_origin)) =
-                func.get_outermost_sourceloc(addr)
-            {
-                if let Some(file) = self.files.get(&file_id) {
-                    frame.set_source_file(file, line, address + module.base_address());
-                }
-
-                if let Some(mut inline_origin) = next_inline_origin {
-                    // There is an inline call at the address.
-                    // Enumerate all inlines at the address one by one by looking up
-                    // successively deeper call depths.
-                    // The call to `get_outermost_source_location` above looked up depth 0, so here
-                    // we start at depth 1.
-                    for depth in 1.. {
-                        match func.get_inlinee_at_depth(depth, addr) {
-                            Some((call_file_id, call_line, _address, next_inline_origin)) => {
-                                // We found another inline frame.
-                                let call_file = self.files.get(&call_file_id).map(Deref::deref);
-                                if let Some(name) = self.inline_origins.get(&inline_origin) {
-                                    frame.add_inline_frame(name, call_file, Some(call_line));
-                                }
-
-                                inline_origin = next_inline_origin;
-                            }
-                            None => break,
-                        }
-                    }
-                    // We've run out of inline calls but we still have to output the final frame.
-                    let (file, line) = match func.get_innermost_sourceloc(addr) {
-                        Some((file_id, line, _)) => (
-                            self.files.get(&file_id).map(Deref::deref),
-                            if line != 0 { Some(line) } else { None },
-                        ),
-                        None => (None, None),
-                    };
-                    if let Some(name) = self.inline_origins.get(&inline_origin) {
-                        frame.add_inline_frame(name, file, line);
-                    }
-                }
-            }
         } else if let Some(public) = self.find_nearest_public(addr) {
             // We couldn't find a valid FUNC record, but we could find a PUBLIC record.
             // Unfortauntely, PUBLIC records don't have end-points, so this could be
@@ -525,6 +508,144 @@ impl SymbolFile {
     pub fn find_nearest_public(&self, addr: u64) -> Option<&PublicSymbol> {
         self.publics.iter().rev().find(|&p| p.address <= addr)
     }
+
+    /// Estimate how many bytes of memory this symbol file uses.
+    ///
+    /// This counts the records and the strings they own, but not allocator
+    /// overhead or unused capacity, so it is only good for bounding caches of
+    /// symbol files.
+    pub fn memory_size(&self) -> usize {
+        fn ranges<V: Clone + Debug + Eq>(
+            map: &RangeMap<u64, V>,
+            owned: impl Fn(&V) -> usize,
+        ) -> usize {
+            map.ranges_values()
+                .map(|(_, value)| size_of::<(Range<u64>, V)>() + owned(value))
+                .sum()
+        }
+        fn names(map: &HashMap<u32, String>) -> usize {
+            map.values()
+                .map(|name| size_of::<(u32, String)>() + name.len())
+                .sum()
+        }
+
+        let functions = ranges(&self.functions, |func| {
+            func.name.len()
+                + func.lines.num_ranges() * size_of::<(Range<u64>, SourceLine)>()
+                + func.inlinees.len() * size_of::<Inlinee>()
+        });
+        let publics: usize = self
+            .publics
+            .iter()
+            .map(|public| size_of::<PublicSymbol>() + public.name.len())
+            .sum();
+        let cfi = ranges(&self.cfi_stack_info, |info| {
+            info.init.rules.len()
+                + info
+                    .add_rules
+                    .iter()
+                    .map(|rules| size_of::<CfiRules>() + rules.rules.len())
+                    .sum::<usize>()
+        });
+        let win = |info: &StackInfoWin| match &info.program_string_or_base_pointer {
+            WinStackThing::ProgramString(program) => program.len(),
+            WinStackThing::AllocatesBasePointer(_) => 0,
+        };
+        size_of::<SymbolFile>()
+            + names(&self.files)
+            + names(&self.inline_origins)
+            + functions
+            + publics
+            + cfi
+            + ranges(&self.win_stack_framedata_info, win)
+            + ranges(&self.win_stack_fpo_info, win)
+    }
+}
+
+/// Fill in `frame` from the FUNC record `func`, which covers the module-relative `addr`.
+///
+/// This is shared by [`SymbolFile`] and [`SymbolCache`], which store the names of
+/// files and inline origins differently.
+pub(crate) fn fill_function_symbol<'a>(
+    module: &dyn Module,
+    frame: &mut dyn FrameSymbolizer,
+    addr: u64,
+    func: &Function,
+    parameter_size: u32,
+    files: impl Fn(u32) -> Option<&'a str>,
+    inline_origins: impl Fn(u32) -> Option<&'a str>,
+) {
+    frame.set_function(
+        &func.name,
+        func.address + module.base_address(),
+        parameter_size,
+    );
+
+    // See if there's source line and inline info as well.
+    //
+    // In the following, we transform data between two different representations of inline calls.
+    // The input shape has function names associated with the location of the call to that function.
+    // The output shape has function names associated with a location *inside* that function.
+    //
+    // Input:
+    //
+    //   (
+    //       outer_name,
+    //       inline_calls: [ // Each location is the line of the *call* to the function
+    //           (inline_call_location[0], inline_name[0]),
+    //           (inline_call_location[1], inline_name[1]),
+    //           (inline_call_location[2], inline_name[2]),
+    //       ]
+    //       innermost_location,
+    //   )
+    //
+    // Output:
+    //
+    //   ( // Each location is the line *inside* the function
+    //       (outer_name, inline_call_location[0]),
+    //       inlines: [
+    //           (inline_name[0], inline_call_location[1]),
+    //           (inline_name[1], inline_call_location[2]),
+    //           (inline_name[2], innermost_location),
+    //       ]
+    //   )
+    if let Some((file_id, line, address, next_inline_origin)) = func.get_outermost_sourceloc(addr) {
+        if let Some(file) = files(file_id) {
+            frame.set_source_file(file, line, address + module.base_address());
+        }
+
+        if let Some(mut inline_origin) = next_inline_origin {
+            // There is an inline call at the address.
+            // Enumerate all inlines at the address one by one by looking up
+            // successively deeper call depths.
+            // The call to `get_outermost_source_location` above looked up depth 0, so here
+            // we start at depth 1.
+            for depth in 1.. {
+                match func.get_inlinee_at_depth(depth, addr) {
+                    Some((call_file_id, call_line, _address, next_inline_origin)) => {
+                        // We found another inline frame.
+                        let call_file = files(call_file_id);
+                        if let Some(name) = inline_origins(inline_origin) {
+                            frame.add_inline_frame(name, call_file, Some(call_line));
+                        }
+
+                        inline_origin = next_inline_origin;
+                    }
+                    None => break,
+                }
+            }
+            // We've run out of inline calls but we still have to output the final frame.
+            let (file, line) = match func.get_innermost_sourceloc(addr) {
+                Some((file_id, line, _)) => {
+                    (files(file_id), if line != 0 { Some(line) } else { None })
+                }
+                None => (None, None),
+            };
+            if let Some(name) = inline_origins(inline_origin) {
+                frame.add_inline_frame(name, file, line);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -625,4 +746,24 @@ FUNC 1000 30 10 another func
 ",
         );
     }
+
+    #[tokio::test]
+    async fn test_symbolfile_from_stream_with_empty_chunks() {
+        let bytes = b"MODULE Linux x86 ffff0000 bar
+FILE 53 bar.c
+PUBLIC 1234 10 some public
+FUNC 1000 30 10 another func
+1000 30 7 53
+";
+        // An empty chunk first, and between every line.
+        let mut chunks = vec![Ok(&b""[..])];
+        for line in bytes.split_inclusive(|&byte| byte == b'\n') {
+            chunks.push(Ok(line));
+            chunks.push(Ok(b""));
+        }
+        let sym = SymbolFile::parse_stream(futures_util::stream::iter(chunks), |_| ())
+            .await
+            .unwrap();
+        assert_eq!(sym, SymbolFile::from_bytes(bytes).unwrap());
+    }
 }
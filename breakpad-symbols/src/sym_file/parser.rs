@@ -24,7 +24,7 @@ use crate::sym_file::types::*;
 use crate::SymbolError;
 
 #[derive(Debug)]
-enum Line {
+pub(super) enum Line {
     Module(String, String, String, String),
     Info(Info),
     File(u32, String),
@@ -102,7 +102,7 @@ fn non_space(input: &[u8]) -> IResult<&[u8], &[u8]> {
 ///
 /// This is different from `line_ending` which doesn't accept `\r` if it isn't
 /// followed by `\n`.
-fn my_eol(input: &[u8]) -> IResult<&[u8], &[u8]> {
+pub(super) fn my_eol(input: &[u8]) -> IResult<&[u8], &[u8]> {
     preceded(take_while(|b| b == b'\r'), tag(b"\n"))(input)
 }
 
@@ -170,7 +170,7 @@ fn file_line(input: &[u8]) -> IResult<&[u8], (u32, String)> {
 }
 
 // Matches an INLINE_ORIGIN record.
-fn inline_origin_line(input: &[u8]) -> IResult<&[u8], (u32, String)> {
+pub(super) fn inline_origin_line(input: &[u8]) -> IResult<&[u8], (u32, String)> {
     let (input, _) = terminated(tag("INLINE_ORIGIN"), space1)(input)?;
     let (input, (id, function)) = cut(tuple((
         terminated(decimal_u32, space1),
@@ -199,7 +199,7 @@ fn public_line(input: &[u8]) -> IResult<&[u8], PublicSymbol> {
 }
 
 // Matches line data after a FUNC record.
-fn func_line_data(input: &[u8]) -> IResult<&[u8], SourceLine> {
+pub(super) fn func_line_data(input: &[u8]) -> IResult<&[u8], SourceLine> {
     let (input, (address, size, line, file)) = tuple((
         terminated(hex_str::<u64>, space1),
         terminated(hex_str::<u32>, space1),
@@ -248,7 +248,7 @@ fn inline_address_range(input: &[u8]) -> IResult<&[u8], (u64, u32)> {
 // Matches an INLINE record.
 ///
 /// An INLINE record has the form `INLINE <inline_nest_level> <call_site_line> <call_site_file_id> <origin_id> [<address> <size>]+`.
-fn inline_line(input: &[u8]) -> IResult<&[u8], impl Iterator<Item = Inlinee>> {
+pub(super) fn inline_line(input: &[u8]) -> IResult<&[u8], impl Iterator<Item = Inlinee>> {
     let (input, _) = terminated(tag("INLINE"), space1)(input)?;
     let (input, (depth, call_line, call_file, origin_id)) = cut(tuple((
         terminated(decimal_u32, space1),
@@ -354,7 +354,7 @@ fn stack_win_line(input: &[u8]) -> IResult<&[u8], WinFrameType> {
 }
 
 // Matches a STACK CFI record.
-fn stack_cfi(input: &[u8]) -> IResult<&[u8], CfiRules> {
+pub(super) fn stack_cfi(input: &[u8]) -> IResult<&[u8], CfiRules> {
     let (input, _) = terminated(tag("STACK CFI"), space1)(input)?;
     let (input, (address, rules)) = cut(tuple((
         terminated(hex_str::<u64>, space1),
@@ -391,7 +391,7 @@ fn stack_cfi_init(input: &[u8]) -> IResult<&[u8], StackInfoCfi> {
 }
 
 // Parse any of the line data that can occur in the body of a symbol file.
-fn line(input: &[u8]) -> IResult<&[u8], Line> {
+pub(super) fn line(input: &[u8]) -> IResult<&[u8], Line> {
     alt((
         map(info_url, Line::Info),
         map(info_line, |_| Line::Info(Info::Unknown)),
@@ -414,6 +414,8 @@ fn line(input: &[u8]) -> IResult<&[u8], Line> {
 /// whole input is consumed. Then call [`finish`][].
 #[derive(Debug, Default)]
 pub struct SymbolParser {
+    os: String,
+    cpu: String,
     module_id: String,
     debug_file: String,
     files: HashMap<u32, String>,
@@ -534,7 +536,7 @@ impl SymbolParser {
             // Now store the item in our partial SymbolFile (or make it the cur_item
             // if it has potential sublines we need to parse first).
             match line {
-                Line::Module(_platform, _arch, module_id, debug_file) => {
+                Line::Module(os, cpu, module_id, debug_file) => {
                     // We don't use this but it MUST be the first line
                     if self.lines != 0 {
                         return Err(SymbolError::ParseError(
@@ -542,6 +544,8 @@ impl SymbolParser {
                             self.lines,
                         ));
                     }
+                    self.os = os;
+                    self.cpu = cpu;
                     self.module_id = module_id;
                     self.debug_file = debug_file;
                 }
@@ -704,6 +708,8 @@ impl SymbolParser {
         self.publics.sort();
 
         SymbolFile {
+            os: self.os,
+            cpu: self.cpu,
             module_id: self.module_id,
             debug_file: self.debug_file,
             files: self.files,
@@ -246,6 +246,10 @@ impl StackInfoWin {
 /// A parsed .sym file containing debug symbols.
 #[derive(Debug, PartialEq, Eq)]
 pub struct SymbolFile {
+    /// The operating system from the MODULE record, see [`SymbolFile::os`].
+    pub(crate) os: String,
+    /// The CPU architecture from the MODULE record, see [`SymbolFile::cpu`].
+    pub(crate) cpu: String,
     pub module_id: String,
     pub debug_file: String,
     /// The set of source files involved in compilation.
@@ -313,3 +317,15 @@ pub struct SymbolFile {
     /// still be correct.
     pub cfi_eval_corruptions: u64,
 }
+
+impl SymbolFile {
+    /// The operating system from the MODULE record, e.g. `Linux` or `windows`.
+    pub fn os(&self) -> &str {
+        &self.os
+    }
+
+    /// The CPU architecture from the MODULE record, e.g. `x86_64`.
+    pub fn cpu(&self) -> &str {
+        &self.cpu
+    }
+}
@@ -0,0 +1,809 @@
+//! Validation ("linting") of Breakpad text symbol files.
+//!
+//! [`SymbolParser`][super::SymbolParser] is deliberately forgiving: it
+//! silently drops overlapping ranges, sorts records that are out of order and
+//! ignores references to records that don't exist, while a single corrupt line
+//! makes it reject the whole file. That's the right call when processing
+//! crashes, but it makes it hard to tell what is wrong with a file that
+//! produces bad stacks. This module instead walks the file line by line and
+//! reports every problem it finds, each with the line it was found on.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+
+use range_map::Range;
+
+use super::parser::{self, Line};
+use super::walker::check_cfi_rules;
+use super::{Function, Inlinee, SourceLine, StackInfoCfi};
+use crate::SymbolError;
+
+/// How serious a [`SymbolDiagnostic`] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The parser tolerates this, but the file is probably not what its
+    /// producer intended.
+    Warning,
+    /// The parser rejects the file, or lookups in it will give wrong or
+    /// missing results.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// The kind of problem a [`SymbolDiagnostic`] reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DiagnosticKind {
+    /// A line that isn't a valid record.
+    Syntax,
+    /// The file doesn't start with a `MODULE` record.
+    MissingModule,
+    /// A `MODULE` record after the first line.
+    MisplacedModule,
+    /// A record with an empty or overflowing address range, which is ignored.
+    InvalidRange,
+    /// A `FUNC` record overlapping an earlier one.
+    OverlappingFunctions,
+    /// A line record overlapping an earlier one in the same function. This is
+    /// common in files generated from PDBs.
+    OverlappingLines,
+    /// A line record that isn't inside its function.
+    LineOutsideFunction,
+    /// An `INLINE` range that isn't inside its function.
+    InlineOutsideFunction,
+    /// A line record or `INLINE` record referring to a missing `FILE`.
+    DanglingFile,
+    /// An `INLINE` record referring to a missing `INLINE_ORIGIN`.
+    DanglingInlineOrigin,
+    /// A `FILE` record reusing an earlier record's id.
+    DuplicateFile,
+    /// An `INLINE_ORIGIN` record reusing an earlier record's id.
+    DuplicateInlineOrigin,
+    /// A `STACK CFI INIT` record overlapping an earlier one.
+    OverlappingCfi,
+    /// A `STACK CFI` record that isn't inside its `STACK CFI INIT` range.
+    CfiOutsideInit,
+    /// `STACK CFI` rules that can't be evaluated.
+    InvalidCfi,
+    /// A record at a lower address than the record of the same kind before it.
+    OutOfOrder,
+}
+
+impl DiagnosticKind {
+    /// How serious this kind of problem is.
+    pub fn severity(self) -> Severity {
+        use DiagnosticKind::*;
+        match self {
+            Syntax | MisplacedModule | OverlappingFunctions | DanglingFile
+            | DanglingInlineOrigin | OverlappingCfi | InvalidCfi => Severity::Error,
+            MissingModule
+            | InvalidRange
+            | OverlappingLines
+            | LineOutsideFunction
+            | InlineOutsideFunction
+            | DuplicateFile
+            | DuplicateInlineOrigin
+            | CfiOutsideInit
+            | OutOfOrder => Severity::Warning,
+        }
+    }
+
+    /// A short kebab-case name for this kind of problem, e.g. `dangling-file`.
+    pub fn name(self) -> &'static str {
+        use DiagnosticKind::*;
+        match self {
+            Syntax => "syntax",
+            MissingModule => "missing-module",
+            MisplacedModule => "misplaced-module",
+            InvalidRange => "invalid-range",
+            OverlappingFunctions => "overlapping-functions",
+            OverlappingLines => "overlapping-lines",
+            LineOutsideFunction => "line-outside-function",
+            InlineOutsideFunction => "inline-outside-function",
+            DanglingFile => "dangling-file",
+            DanglingInlineOrigin => "dangling-inline-origin",
+            DuplicateFile => "duplicate-file",
+            DuplicateInlineOrigin => "duplicate-inline-origin",
+            OverlappingCfi => "overlapping-cfi",
+            CfiOutsideInit => "cfi-outside-init",
+            InvalidCfi => "invalid-cfi",
+            OutOfOrder => "out-of-order",
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A problem found by [`SymbolFile::validate`][super::SymbolFile::validate].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymbolDiagnostic {
+    /// What kind of problem this is.
+    pub kind: DiagnosticKind,
+    /// The (1-based) line the problem was found on.
+    pub line: u64,
+    /// The (1-based) line of another record involved in the problem, e.g. the
+    /// earlier of two overlapping functions.
+    pub related_line: Option<u64>,
+    /// A human-readable description of the problem.
+    pub message: String,
+}
+
+impl SymbolDiagnostic {
+    /// How serious this problem is.
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+}
+
+impl fmt::Display for SymbolDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}[{}]: {}",
+            self.line,
+            self.severity(),
+            self.kind,
+            self.message
+        )?;
+        if let Some(related_line) = self.related_line {
+            write!(f, " (see line {related_line})")?;
+        }
+        Ok(())
+    }
+}
+
+/// The multi-line record whose sublines are currently being validated.
+enum Item {
+    Function {
+        range: Option<Range<u64>>,
+        // (range, line) of the previous line record.
+        last_line: Option<(Range<u64>, u64)>,
+    },
+    StackCfi {
+        range: Option<Range<u64>>,
+        last_address: u64,
+    },
+}
+
+#[derive(Default)]
+struct Validator {
+    diagnostics: Vec<SymbolDiagnostic>,
+    line: u64,
+    cur_item: Option<Item>,
+
+    // id => line of the defining record.
+    files: HashMap<u32, u64>,
+    inline_origins: HashMap<u32, u64>,
+    // id => first line referring to it. These can only be checked at the end,
+    // as nothing requires FILE and INLINE_ORIGIN records to come first.
+    file_refs: HashMap<u32, u64>,
+    inline_origin_refs: HashMap<u32, u64>,
+
+    // (range, line) of every record, checked for overlaps at the end.
+    functions: Vec<(Range<u64>, u64)>,
+    cfi: Vec<(Range<u64>, u64)>,
+
+    // (address, line) of the previous record of each kind.
+    last_function: Option<(u64, u64)>,
+    last_public: Option<(u64, u64)>,
+    last_cfi: Option<(u64, u64)>,
+}
+
+impl Validator {
+    fn report(&mut self, kind: DiagnosticKind, related_line: Option<u64>, message: String) {
+        self.diagnostics.push(SymbolDiagnostic {
+            kind,
+            line: self.line,
+            related_line,
+            message,
+        });
+    }
+
+    /// Validate one line of input, including its line ending.
+    fn validate_line(&mut self, input: &[u8]) {
+        self.line += 1;
+
+        // As in the parser, a subline that doesn't parse ends the current item
+        // and is retried as a top-level record.
+        match self.cur_item.take() {
+            Some(Item::Function { range, last_line }) => {
+                if let Some(last_line) = self.function_subline(input, range, last_line) {
+                    self.cur_item = Some(Item::Function { range, last_line });
+                    return;
+                }
+            }
+            Some(Item::StackCfi {
+                range,
+                last_address,
+            }) => {
+                if let Ok((_, rules)) = parser::stack_cfi(input) {
+                    self.stack_cfi_subline(rules.address, &rules.rules, range, last_address);
+                    self.cur_item = Some(Item::StackCfi {
+                        range,
+                        last_address: rules.address,
+                    });
+                    return;
+                }
+            }
+            None => {}
+        }
+
+        if parser::my_eol(input).is_ok() {
+            return;
+        }
+
+        let line = match parser::line(input) {
+            Ok((_, line)) => line,
+            Err(_) => {
+                let text = String::from_utf8_lossy(input);
+                let text = text.trim_end();
+                self.report(
+                    DiagnosticKind::Syntax,
+                    None,
+                    format!("can't parse line: {text}"),
+                );
+                return;
+            }
+        };
+
+        if self.line == 1 && !matches!(line, Line::Module(..)) {
+            self.report(
+                DiagnosticKind::MissingModule,
+                None,
+                "file doesn't start with a MODULE record".to_owned(),
+            );
+        }
+
+        match line {
+            Line::Module(..) => {
+                if self.line != 1 {
+                    self.report(
+                        DiagnosticKind::MisplacedModule,
+                        None,
+                        "MODULE record after the start of the file".to_owned(),
+                    );
+                }
+            }
+            Line::Info(_) | Line::StackWin(_) => {}
+            Line::File(id, _) => self.file(id),
+            Line::InlineOrigin(id, _) => self.inline_origin(id),
+            Line::Public(public) => {
+                if let Some((last_address, last_line)) = self.last_public {
+                    if public.address < last_address {
+                        self.report(
+                            DiagnosticKind::OutOfOrder,
+                            Some(last_line),
+                            format!(
+                                "PUBLIC {:x} comes after PUBLIC {:x}",
+                                public.address, last_address
+                            ),
+                        );
+                    }
+                }
+                self.last_public = Some((public.address, self.line));
+            }
+            Line::Function(function, _, _) => self.function(&function),
+            Line::StackCfi(info) => self.stack_cfi_init(&info),
+        }
+    }
+
+    fn file(&mut self, id: u32) {
+        if let Some(&first) = self.files.get(&id) {
+            self.report(
+                DiagnosticKind::DuplicateFile,
+                Some(first),
+                format!("FILE {id} is defined more than once"),
+            );
+        } else {
+            self.files.insert(id, self.line);
+        }
+    }
+
+    fn inline_origin(&mut self, id: u32) {
+        if let Some(&first) = self.inline_origins.get(&id) {
+            self.report(
+                DiagnosticKind::DuplicateInlineOrigin,
+                Some(first),
+                format!("INLINE_ORIGIN {id} is defined more than once"),
+            );
+        } else {
+            self.inline_origins.insert(id, self.line);
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        if let Some((last_address, last_line)) = self.last_function {
+            if function.address < last_address {
+                self.report(
+                    DiagnosticKind::OutOfOrder,
+                    Some(last_line),
+                    format!(
+                        "FUNC {:x} comes after FUNC {:x}",
+                        function.address, last_address
+                    ),
+                );
+            }
+        }
+        self.last_function = Some((function.address, self.line));
+
+        let range = function.memory_range();
+        match range {
+            Some(range) => self.functions.push((range, self.line)),
+            None => self.report(
+                DiagnosticKind::InvalidRange,
+                None,
+                format!(
+                    "FUNC {:x} has an invalid size ({:x})",
+                    function.address, function.size
+                ),
+            ),
+        }
+        self.cur_item = Some(Item::Function {
+            range,
+            last_line: None,
+        });
+    }
+
+    /// Validate a possible subline of a FUNC record, returning the new
+    /// `last_line` of the function if it was one.
+    fn function_subline(
+        &mut self,
+        input: &[u8],
+        range: Option<Range<u64>>,
+        last_line: Option<(Range<u64>, u64)>,
+    ) -> Option<Option<(Range<u64>, u64)>> {
+        if input.starts_with(b"INLINE_ORIGIN ") {
+            let (_, (id, _)) = parser::inline_origin_line(input).ok()?;
+            self.inline_origin(id);
+            return Some(last_line);
+        }
+        if input.starts_with(b"INLINE ") {
+            let (_, inlinees) = parser::inline_line(input).ok()?;
+            for inlinee in inlinees {
+                self.inlinee(&inlinee, range);
+            }
+            return Some(last_line);
+        }
+        let (_, line) = parser::func_line_data(input).ok()?;
+        Some(self.source_line(&line, range, last_line).or(last_line))
+    }
+
+    fn inlinee(&mut self, inlinee: &Inlinee, function: Option<Range<u64>>) {
+        self.inline_origin_refs
+            .entry(inlinee.origin_id)
+            .or_insert(self.line);
+        self.file_refs.entry(inlinee.call_file).or_insert(self.line);
+
+        let end = inlinee
+            .address
+            .checked_add(inlinee.size as u64)
+            .filter(|_| inlinee.size > 0);
+        let Some(end) = end else {
+            self.report(
+                DiagnosticKind::InvalidRange,
+                None,
+                format!(
+                    "INLINE range {:x} has an invalid size ({:x})",
+                    inlinee.address, inlinee.size
+                ),
+            );
+            return;
+        };
+        if let Some(function) = function {
+            if inlinee.address < function.start || end - 1 > function.end {
+                self.report(
+                    DiagnosticKind::InlineOutsideFunction,
+                    None,
+                    format!(
+                        "INLINE range {:x}-{:x} is outside of its function ({:x}-{:x})",
+                        inlinee.address,
+                        end,
+                        function.start,
+                        function.end + 1
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Validate a line record, returning its range and line if it has a valid
+    /// one.
+    fn source_line(
+        &mut self,
+        line: &SourceLine,
+        function: Option<Range<u64>>,
+        last_line: Option<(Range<u64>, u64)>,
+    ) -> Option<(Range<u64>, u64)> {
+        self.file_refs.entry(line.file).or_insert(self.line);
+
+        // Zero-sized line records are common in files generated from PDBs, and
+        // the parser quietly drops them, so don't complain about them.
+        if line.size == 0 {
+            return None;
+        }
+        let Some(end) = line.address.checked_add(line.size as u64 - 1) else {
+            self.report(
+                DiagnosticKind::InvalidRange,
+                None,
+                format!(
+                    "line record {:x} has an invalid size ({:x})",
+                    line.address, line.size
+                ),
+            );
+            return None;
+        };
+        let range = Range::new(line.address, end);
+
+        if let Some(function) = function {
+            if range.start < function.start || range.end > function.end {
+                self.report(
+                    DiagnosticKind::LineOutsideFunction,
+                    None,
+                    format!(
+                        "line record {:x}-{:x} is outside of its function ({:x}-{:x})",
+                        range.start,
+                        range.end + 1,
+                        function.start,
+                        function.end + 1
+                    ),
+                );
+            }
+        }
+        if let Some((last_range, last_line)) = last_line {
+            if range.start < last_range.start {
+                self.report(
+                    DiagnosticKind::OutOfOrder,
+                    Some(last_line),
+                    format!(
+                        "line record {:x} comes after line record {:x}",
+                        range.start, last_range.start
+                    ),
+                );
+            } else if range.start <= last_range.end {
+                self.report(
+                    DiagnosticKind::OverlappingLines,
+                    Some(last_line),
+                    format!(
+                        "line record {:x}-{:x} overlaps line record {:x}-{:x}",
+                        range.start,
+                        range.end + 1,
+                        last_range.start,
+                        last_range.end + 1
+                    ),
+                );
+            }
+        }
+        Some((range, self.line))
+    }
+
+    fn stack_cfi_init(&mut self, info: &StackInfoCfi) {
+        let address = info.init.address;
+        if let Some((last_address, last_line)) = self.last_cfi {
+            if address < last_address {
+                self.report(
+                    DiagnosticKind::OutOfOrder,
+                    Some(last_line),
+                    format!(
+                        "STACK CFI INIT {address:x} comes after STACK CFI INIT {last_address:x}"
+                    ),
+                );
+            }
+        }
+        self.last_cfi = Some((address, self.line));
+
+        let range = info.memory_range();
+        match range {
+            Some(range) => self.cfi.push((range, self.line)),
+            None => self.report(
+                DiagnosticKind::InvalidRange,
+                None,
+                format!(
+                    "STACK CFI INIT {:x} has an invalid size ({:x})",
+                    address, info.size
+                ),
+            ),
+        }
+        if let Err(e) = check_cfi_rules(&info.init.rules, true) {
+            self.report(DiagnosticKind::InvalidCfi, None, e);
+        }
+        self.cur_item = Some(Item::StackCfi {
+            range,
+            last_address: address,
+        });
+    }
+
+    fn stack_cfi_subline(
+        &mut self,
+        address: u64,
+        rules: &str,
+        range: Option<Range<u64>>,
+        last_address: u64,
+    ) {
+        if let Some(range) = range {
+            if address < range.start || address > range.end {
+                self.report(
+                    DiagnosticKind::CfiOutsideInit,
+                    None,
+                    format!(
+                        "STACK CFI {:x} is outside of its STACK CFI INIT ({:x}-{:x})",
+                        address,
+                        range.start,
+                        range.end + 1
+                    ),
+                );
+            }
+        }
+        if address < last_address {
+            self.report(
+                DiagnosticKind::OutOfOrder,
+                None,
+                format!("STACK CFI {address:x} comes after STACK CFI {last_address:x}"),
+            );
+        }
+        if let Err(e) = check_cfi_rules(rules, false) {
+            self.report(DiagnosticKind::InvalidCfi, None, e);
+        }
+    }
+
+    /// Run the checks that need the whole file, and return all diagnostics
+    /// sorted by line.
+    fn finish(mut self) -> Vec<SymbolDiagnostic> {
+        if self.line == 0 {
+            self.line = 1;
+            self.report(
+                DiagnosticKind::MissingModule,
+                None,
+                "file is empty".to_owned(),
+            );
+        }
+
+        let mut dangling = Vec::new();
+        for (&id, &line) in &self.file_refs {
+            if !self.files.contains_key(&id) {
+                dangling.push(SymbolDiagnostic {
+                    kind: DiagnosticKind::DanglingFile,
+                    line,
+                    related_line: None,
+                    message: format!("FILE {id} is not defined"),
+                });
+            }
+        }
+        for (&id, &line) in &self.inline_origin_refs {
+            if !self.inline_origins.contains_key(&id) {
+                dangling.push(SymbolDiagnostic {
+                    kind: DiagnosticKind::DanglingInlineOrigin,
+                    line,
+                    related_line: None,
+                    message: format!("INLINE_ORIGIN {id} is not defined"),
+                });
+            }
+        }
+        self.diagnostics.extend(dangling);
+
+        let functions = std::mem::take(&mut self.functions);
+        self.diagnostics.extend(find_overlaps(
+            functions,
+            DiagnosticKind::OverlappingFunctions,
+            "FUNC",
+        ));
+        let cfi = std::mem::take(&mut self.cfi);
+        self.diagnostics.extend(find_overlaps(
+            cfi,
+            DiagnosticKind::OverlappingCfi,
+            "STACK CFI INIT",
+        ));
+
+        self.diagnostics
+            .sort_by(|a, b| (a.line, a.kind).cmp(&(b.line, b.kind)));
+        self.diagnostics
+    }
+}
+
+/// Report every range which overlaps a range that starts before it.
+fn find_overlaps(
+    mut ranges: Vec<(Range<u64>, u64)>,
+    kind: DiagnosticKind,
+    record: &str,
+) -> Vec<SymbolDiagnostic> {
+    ranges.sort_by_key(|&(range, line)| (range.start, line));
+
+    let mut diagnostics = Vec::new();
+    // The range reaching furthest so far, which anything starting before its
+    // end overlaps.
+    let mut furthest: Option<(Range<u64>, u64)> = None;
+    for (range, line) in ranges {
+        if let Some((other, other_line)) = furthest {
+            if range.start <= other.end {
+                diagnostics.push(SymbolDiagnostic {
+                    kind,
+                    line,
+                    related_line: Some(other_line),
+                    message: format!(
+                        "{} {:x}-{:x} overlaps {} {:x}-{:x}",
+                        record,
+                        range.start,
+                        range.end + 1,
+                        record,
+                        other.start,
+                        other.end + 1
+                    ),
+                });
+            }
+            if other.end >= range.end {
+                continue;
+            }
+        }
+        furthest = Some((range, line));
+    }
+    diagnostics
+}
+
+/// Validate a whole symbol file, see [`SymbolFile::validate`][super::SymbolFile::validate].
+pub(super) fn validate<R: Read>(input: R) -> Result<Vec<SymbolDiagnostic>, SymbolError> {
+    let mut reader = BufReader::new(input);
+    let mut validator = Validator::default();
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        // The parser rejects a final line without a line ending, but the line
+        // itself can still be checked.
+        let truncated = buf.last() != Some(&b'\n');
+        if truncated {
+            buf.push(b'\n');
+        }
+        validator.validate_line(&buf);
+        if truncated {
+            validator.report(
+                DiagnosticKind::Syntax,
+                None,
+                "last line has no line ending".to_owned(),
+            );
+        }
+    }
+    Ok(validator.finish())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use DiagnosticKind::*;
+
+    fn validate_str(input: &str) -> Vec<(u64, DiagnosticKind, Option<u64>)> {
+        validate(input.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.line, d.kind, d.related_line))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_clean() {
+        let diagnostics = validate(
+            std::fs::File::open(
+                "testdata/symbols/test_app.pdb/5A9832E5287241C1838ED98914E9B7FF1/test_app.sym",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(diagnostics, vec![]);
+
+        let input = "MODULE Linux x86_64 ffff0000 bar
+FILE 0 foo.c
+INLINE_ORIGIN 0 inlined
+FUNC 1000 30 0 func
+INLINE 0 12 0 0 1010 10
+1000 10 11 0
+1010 20 12 0
+PUBLIC 1100 0 public
+STACK CFI INIT 1000 30 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+STACK CFI 1001 .cfa: $rsp 16 + $rbp: .cfa -16 + ^
+";
+        assert_eq!(validate_str(input), vec![]);
+    }
+
+    #[test]
+    fn test_validate_syntax() {
+        let input = "MODULE Linux x86_64 ffff0000 bar
+FILE 0 foo.c
+FUNK 1000 30 0 func
+MODULE Linux x86_64 ffff0000 bar
+PUBLIC 1100 0 public";
+        assert_eq!(
+            validate_str(input),
+            vec![
+                (3, Syntax, None),
+                (4, MisplacedModule, None),
+                (5, Syntax, None),
+            ]
+        );
+        assert_eq!(validate_str(""), vec![(1, MissingModule, None)]);
+        assert_eq!(
+            validate_str("FILE 0 foo.c\n"),
+            vec![(1, MissingModule, None)]
+        );
+    }
+
+    #[test]
+    fn test_validate_functions() {
+        let input = "MODULE Linux x86_64 ffff0000 bar
+FILE 0 foo.c
+FILE 0 bar.c
+FUNC 2000 30 0 func1
+2000 10 11 0
+2008 10 12 0
+2040 10 13 1
+FUNC 1000 1000 0 func2
+INLINE 0 12 0 7 1010 10 3000 10
+FUNC 1100 10 0 func3
+FUNC 4000 0 0 func4
+";
+        assert_eq!(
+            validate_str(input),
+            vec![
+                (3, DuplicateFile, Some(2)),
+                (6, OverlappingLines, Some(5)),
+                (7, LineOutsideFunction, None),
+                (7, DanglingFile, None),
+                (8, OutOfOrder, Some(4)),
+                (9, InlineOutsideFunction, None),
+                (9, DanglingInlineOrigin, None),
+                (10, OverlappingFunctions, Some(8)),
+                (11, InvalidRange, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_cfi() {
+        let input = "MODULE Linux x86_64 ffff0000 bar
+STACK CFI INIT 1000 30 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+STACK CFI 1040 .cfa: $rsp 16 +
+STACK CFI 1004 .cfa: $rsp 16 + +
+STACK CFI INIT 1010 30 .cfa: $rsp 8 +
+STACK CFI INIT 2000 30 .cfa: .cfa 8 + .ra: .cfa -8 + ^
+STACK CFI INIT 3000 30 .cfa: $rsp 8 + .ra: .cfa -8 + ^ rbx:
+STACK CFI INIT 4000 30 .cfa: $rsp 8 + .ra: .undef
+";
+        assert_eq!(
+            validate_str(input),
+            vec![
+                (3, CfiOutsideInit, None),
+                (4, InvalidCfi, None),
+                (4, OutOfOrder, None),
+                (5, OverlappingCfi, Some(2)),
+                (5, InvalidCfi, None),
+                (6, InvalidCfi, None),
+                (7, InvalidCfi, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_display() {
+        let diagnostic = SymbolDiagnostic {
+            kind: OverlappingFunctions,
+            line: 10,
+            related_line: Some(8),
+            message: "FUNC 1100-1110 overlaps FUNC 1000-2010".to_owned(),
+        };
+        assert_eq!(
+            diagnostic.to_string(),
+            "10: error[overlapping-functions]: FUNC 1100-1110 overlaps FUNC 1000-2010 (see line 8)"
+        );
+    }
+}
@@ -524,7 +524,17 @@ pub fn walk_with_stack_cfi(
 
     walker.set_cfa(cfa)?;
     walker.set_ra(ra)?;
-
+    walker.note_rule(format_args!(".cfa: {cfa_expr}"));
+    walker.note_rule(format_args!(".ra: {ra_expr}"));
+
+    let mut exprs = exprs.into_iter().collect::<Vec<_>>();
+    if walker.wants_notes() {
+        // Sort the registers so the rules are noted in a stable order.
+        exprs.sort_by_key(|(reg, _)| match reg {
+            CfiReg::Other(reg) => *reg,
+            _ => "",
+        });
+    }
     for (reg, expr) in exprs {
         if let CfiReg::Other(reg) = reg {
             // If this eval fails, just don't emit this particular register
@@ -534,6 +544,7 @@ pub fn walk_with_stack_cfi(
             match eval_cfi_expr(expr, walker, Some(cfa)) {
                 Some(val) => {
                     walker.set_caller_register(reg, val);
+                    walker.note_rule(format_args!("{reg}: {expr}"));
                     trace!("successfully evaluated {}", reg);
                 }
                 None => {
@@ -727,6 +738,67 @@ fn eval_cfi_expr(expr: &str, walker: &mut dyn FrameWalker, cfa: Option<u64>) ->
     }
 }
 
+/// Check that the `REG: EXPR` pairs of a STACK CFI record are well-formed,
+/// without evaluating them.
+///
+/// An `init` record must define both `.cfa` and `.ra`. On failure, returns a
+/// description of the first problem found.
+pub(crate) fn check_cfi_rules(rules: &str, init: bool) -> Result<(), String> {
+    let mut exprs = HashMap::new();
+    if parse_cfi_exprs(rules, &mut exprs).is_none() {
+        return Err("rules aren't a list of `REG: EXPR` pairs".to_owned());
+    }
+    if init {
+        for (reg, name) in [(CfiReg::Cfa, ".cfa"), (CfiReg::Ra, ".ra")] {
+            if !exprs.contains_key(&reg) {
+                return Err(format!("initial rules don't define {name}"));
+            }
+        }
+    }
+    for (reg, expr) in exprs {
+        let name = match reg {
+            CfiReg::Cfa => ".cfa",
+            CfiReg::Ra => ".ra",
+            CfiReg::Other(name) => name,
+        };
+        check_cfi_expr(expr, reg == CfiReg::Cfa)
+            .map_err(|e| format!("expression for {name} ({expr}) {e}"))?;
+    }
+    Ok(())
+}
+
+/// Check that a postfix expression would leave exactly one value on the
+/// stack, mirroring the operators [`eval_cfi_expr`] understands.
+fn check_cfi_expr(expr: &str, is_cfa: bool) -> Result<(), String> {
+    let mut depth = 0usize;
+    for token in expr.split_ascii_whitespace() {
+        let (pops, pushes) = match token {
+            "+" | "-" | "*" | "/" | "%" | "@" => (2, 1),
+            "^" => (1, 1),
+            ".cfa" if is_cfa => return Err("uses the CFA to compute itself".to_owned()),
+            ".cfa" => (0, 1),
+            ".undef" => return Ok(()),
+            _ if token.starts_with('.') => return Err(format!("uses unknown value {token}")),
+            _ if token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '$' | '_' | '.' | '-')) =>
+            {
+                (0, 1)
+            }
+            _ => return Err(format!("has unknown token {token}")),
+        };
+        depth = depth
+            .checked_sub(pops)
+            .ok_or_else(|| format!("has too few operands for {token}"))?
+            + pushes;
+    }
+    match depth {
+        1 => Ok(()),
+        0 => Err("is empty".to_owned()),
+        _ => Err("leaves extra values on the stack".to_owned()),
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum CfiReg<'a> {
     Cfa,
@@ -963,7 +1035,9 @@ pub fn walk_with_stack_win_framedata(
     if let WinStackThing::ProgramString(ref expr) = info.program_string_or_base_pointer {
         trace!("trying STACK WIN framedata -- {}", expr);
         clear_stack_win_caller_registers(walker);
-        eval_win_expr(expr, info, walker)
+        eval_win_expr(expr, info, walker)?;
+        walker.note_rule(format_args!("{expr}"));
+        Some(())
     } else {
         unreachable!()
     }
@@ -1060,6 +1134,7 @@ mod test {
         callee_regs: HashMap<&'static str, Reg>,
         caller_regs: HashMap<&'static str, Reg>,
         stack: Vec<u8>,
+        rules: Vec<String>,
     }
 
     trait Int {
@@ -1138,6 +1213,12 @@ mod test {
             self.caller_regs.insert("ra", Reg::from_u64(val));
             Some(())
         }
+        fn wants_notes(&self) -> bool {
+            true
+        }
+        fn note_rule(&mut self, rule: std::fmt::Arguments<'_>) {
+            self.rules.push(rule.to_string());
+        }
     }
 
     impl<Reg: Int + Copy> TestFrameWalker<Reg> {
@@ -1146,6 +1227,7 @@ mod test {
                 stack,
                 callee_regs,
                 caller_regs: HashMap::new(),
+                rules: Vec::new(),
 
                 // Arbitrary values
                 instruction: Reg::from_u64(0xF1CEFA32),
@@ -1460,6 +1542,7 @@ mod test {
                 .collect(),
             caller_regs: HashMap::new(),
             stack,
+            rules: Vec::new(),
         };
 
         // these are all dummy values
@@ -1510,6 +1593,10 @@ mod test {
         assert_eq!(walker.caller_regs["cfa"], FINAL_CFA as u64);
         assert_eq!(walker.caller_regs["ra"], FINAL_RA);
         assert_eq!(walker.caller_regs["rax"], FINAL_RAX);
+        assert_eq!(
+            walker.rules,
+            [".cfa: $rsp 24 +", ".ra: .cfa -8 + ^", "rax: .cfa -16 + ^"]
+        );
     }
 
     #[test]
@@ -0,0 +1,326 @@
+//! Writing, merging and stripping [`SymbolFile`]s.
+//!
+//! The output is canonical Breakpad text: records are grouped by kind, sorted
+//! by id or address, and written one `INLINE` record per address range. Only
+//! what the parser keeps is written back, so `INFO` records other than the
+//! `INFO URL` and the `m` (multiple) markers on `FUNC` and `PUBLIC` records
+//! are lost.
+
+use std::io::{self, BufWriter, Write};
+
+use range_map::RangeMap;
+
+use super::{SymbolFile, WinStackThing};
+
+/// A kind of record in a [`SymbolFile`], for [`SymbolFile::merge`] and
+/// [`SymbolFile::strip`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SymbolSection {
+    /// `FUNC` records with their line and `INLINE` records, along with the
+    /// `FILE` and `INLINE_ORIGIN` records they refer to.
+    Functions,
+    /// `PUBLIC` records.
+    Publics,
+    /// `STACK CFI INIT` and `STACK CFI` records.
+    StackCfi,
+    /// `STACK WIN` records.
+    StackWin,
+}
+
+impl SymbolSection {
+    /// All sections.
+    pub const ALL: [SymbolSection; 4] = [
+        SymbolSection::Functions,
+        SymbolSection::Publics,
+        SymbolSection::StackCfi,
+        SymbolSection::StackWin,
+    ];
+}
+
+/// An error merging two [`SymbolFile`]s.
+#[derive(Debug, thiserror::Error)]
+#[error("can't merge symbols for {other_debug_file} ({other_module_id}) into symbols for {debug_file} ({module_id})")]
+pub struct SymbolMergeError {
+    pub debug_file: String,
+    pub module_id: String,
+    pub other_debug_file: String,
+    pub other_module_id: String,
+}
+
+impl SymbolFile {
+    /// Write this symbol file out as Breakpad text.
+    ///
+    /// Parsing the output gives back an identical `SymbolFile` (apart from
+    /// the statistics fields).
+    pub fn write<W: Write>(&self, out: W) -> io::Result<()> {
+        let mut out = BufWriter::new(out);
+
+        // A file without a MODULE record doesn't get one made up for it.
+        if !self.module_id.is_empty() {
+            writeln!(
+                out,
+                "MODULE {} {} {} {}",
+                self.os, self.cpu, self.module_id, self.debug_file
+            )?;
+        }
+        if let Some(url) = &self.url {
+            writeln!(out, "INFO URL {url}")?;
+        }
+
+        let mut files: Vec<_> = self.files.iter().collect();
+        files.sort();
+        for (id, name) in files {
+            writeln!(out, "FILE {id} {name}")?;
+        }
+        let mut inline_origins: Vec<_> = self.inline_origins.iter().collect();
+        inline_origins.sort();
+        for (id, name) in inline_origins {
+            writeln!(out, "INLINE_ORIGIN {id} {name}")?;
+        }
+
+        for func in self.functions.ranges_values().map(|(_, func)| func) {
+            writeln!(
+                out,
+                "FUNC {:x} {:x} {:x} {}",
+                func.address, func.size, func.parameter_size, func.name
+            )?;
+            for inlinee in &func.inlinees {
+                writeln!(
+                    out,
+                    "INLINE {} {} {} {} {:x} {:x}",
+                    inlinee.depth,
+                    inlinee.call_line,
+                    inlinee.call_file,
+                    inlinee.origin_id,
+                    inlinee.address,
+                    inlinee.size
+                )?;
+            }
+            for line in func.lines.ranges_values().map(|(_, line)| line) {
+                writeln!(
+                    out,
+                    "{:x} {:x} {} {}",
+                    line.address, line.size, line.line, line.file
+                )?;
+            }
+        }
+
+        for public in &self.publics {
+            writeln!(
+                out,
+                "PUBLIC {:x} {:x} {}",
+                public.address, public.parameter_size, public.name
+            )?;
+        }
+
+        for (ty, infos) in [
+            (4, &self.win_stack_framedata_info),
+            (0, &self.win_stack_fpo_info),
+        ] {
+            for info in infos.ranges_values().map(|(_, info)| info) {
+                let (has_program_string, rest) = match &info.program_string_or_base_pointer {
+                    WinStackThing::ProgramString(program) => (1, program.as_str()),
+                    WinStackThing::AllocatesBasePointer(true) => (0, "1"),
+                    WinStackThing::AllocatesBasePointer(false) => (0, "0"),
+                };
+                writeln!(
+                    out,
+                    "STACK WIN {} {:x} {:x} {:x} {:x} {:x} {:x} {:x} {:x} {} {}",
+                    ty,
+                    info.address,
+                    info.size,
+                    info.prologue_size,
+                    info.epilogue_size,
+                    info.parameter_size,
+                    info.saved_register_size,
+                    info.local_size,
+                    info.max_stack_size,
+                    has_program_string,
+                    rest
+                )?;
+            }
+        }
+
+        for info in self.cfi_stack_info.ranges_values().map(|(_, info)| info) {
+            writeln!(
+                out,
+                "STACK CFI INIT {:x} {:x} {}",
+                info.init.address, info.size, info.init.rules
+            )?;
+            for rules in &info.add_rules {
+                writeln!(out, "STACK CFI {:x} {}", rules.address, rules.rules)?;
+            }
+        }
+
+        out.flush()
+    }
+
+    /// Replace the given sections of this symbol file with the ones from
+    /// `other`, e.g. to combine the functions from one dump_syms run with the
+    /// CFI of another.
+    ///
+    /// Sections are replaced as a whole: the records of a listed section are
+    /// all dropped and the ones from `other` are used instead, even where the
+    /// two don't overlap. Sections that aren't listed are left alone.
+    ///
+    /// Both files must be for the same module (their debug ids must match).
+    pub fn merge(
+        &mut self,
+        other: SymbolFile,
+        sections: &[SymbolSection],
+    ) -> Result<(), SymbolMergeError> {
+        if !self.module_id.eq_ignore_ascii_case(&other.module_id) {
+            return Err(SymbolMergeError {
+                debug_file: self.debug_file.clone(),
+                module_id: self.module_id.clone(),
+                other_debug_file: other.debug_file,
+                other_module_id: other.module_id,
+            });
+        }
+        if sections.contains(&SymbolSection::Functions) {
+            self.functions = other.functions;
+            self.files = other.files;
+            self.inline_origins = other.inline_origins;
+        }
+        if sections.contains(&SymbolSection::Publics) {
+            self.publics = other.publics;
+        }
+        if sections.contains(&SymbolSection::StackCfi) {
+            self.cfi_stack_info = other.cfi_stack_info;
+        }
+        if sections.contains(&SymbolSection::StackWin) {
+            self.win_stack_framedata_info = other.win_stack_framedata_info;
+            self.win_stack_fpo_info = other.win_stack_fpo_info;
+        }
+        Ok(())
+    }
+
+    /// Remove a section from this symbol file, e.g. to make a CFI-only
+    /// symbol file for unwinding on a device that symbolicates elsewhere.
+    pub fn strip(&mut self, section: SymbolSection) {
+        match section {
+            SymbolSection::Functions => {
+                self.functions = RangeMap::new();
+                self.files.clear();
+                self.inline_origins.clear();
+            }
+            SymbolSection::Publics => self.publics.clear(),
+            SymbolSection::StackCfi => self.cfi_stack_info = RangeMap::new(),
+            SymbolSection::StackWin => {
+                self.win_stack_framedata_info = RangeMap::new();
+                self.win_stack_fpo_info = RangeMap::new();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    fn round_trip(sym: &SymbolFile) -> SymbolFile {
+        let mut out = Vec::new();
+        sym.write(&mut out).unwrap();
+        SymbolFile::from_bytes(&out).unwrap()
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        for path in [
+            "testdata/symbols/test_app.pdb/5A9832E5287241C1838ED98914E9B7FF1/test_app.sym",
+            "../testdata/symbols/crash-client/509C0610949836F7B70BD88BCF03E5400/crash-client.sym",
+        ] {
+            let sym = SymbolFile::from_file(Path::new(path)).unwrap();
+            assert_eq!(round_trip(&sym), sym, "{path}");
+        }
+    }
+
+    #[test]
+    fn test_write_canonical() {
+        let input = b"MODULE Linux x86_64 ffff0000 bar
+INFO URL https://symbols.example.com/bar
+PUBLIC 2000 0 public
+STACK CFI INIT 1000 30 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+STACK CFI 1001 .cfa: $rsp 16 +
+FUNC m 1000 30 0 func
+1010 20 12 1
+1000 10 11 0
+INLINE 0 12 0 0 1010 10 1020 8
+FILE 1 foo.h
+FILE 0 foo.c
+INLINE_ORIGIN 0 inlined
+STACK WIN 4 1000 30 4 0 0 0 8 0 1 $T0 .raSearch = $eip $T0 ^ = $esp $T0 4 + =
+STACK WIN 0 2000 10 1 0 4 0 0 0 0 1
+";
+        let sym = SymbolFile::from_bytes(input).unwrap();
+        let mut out = Vec::new();
+        sym.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "MODULE Linux x86_64 ffff0000 bar
+INFO URL https://symbols.example.com/bar
+FILE 0 foo.c
+FILE 1 foo.h
+INLINE_ORIGIN 0 inlined
+FUNC 1000 30 0 func
+INLINE 0 12 0 0 1010 10
+INLINE 0 12 0 0 1020 8
+1000 10 11 0
+1010 20 12 1
+PUBLIC 2000 0 public
+STACK WIN 4 1000 30 4 0 0 0 8 0 1 $T0 .raSearch = $eip $T0 ^ = $esp $T0 4 + =
+STACK WIN 0 2000 10 1 0 4 0 0 0 0 1
+STACK CFI INIT 1000 30 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+STACK CFI 1001 .cfa: $rsp 16 +
+"
+        );
+    }
+
+    #[test]
+    fn test_merge_and_strip() {
+        let functions = SymbolFile::from_bytes(
+            b"MODULE Linux x86_64 ffff0000 bar
+FILE 0 foo.c
+FUNC 1000 30 0 func
+1000 30 11 0
+PUBLIC 2000 0 public
+STACK CFI INIT 1000 30 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+",
+        )
+        .unwrap();
+        let cfi = SymbolFile::from_bytes(
+            b"MODULE Linux x86_64 FFFF0000 bar
+STACK CFI INIT 1000 20 .cfa: $rsp 16 + .ra: .cfa -8 + ^
+STACK CFI INIT 1020 10 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+",
+        )
+        .unwrap();
+        let other_module = SymbolFile::from_bytes(b"MODULE Linux x86_64 eeee0000 baz\n").unwrap();
+        assert_eq!(functions.os(), "Linux");
+        assert_eq!(functions.cpu(), "x86_64");
+
+        let mut merged = round_trip(&functions);
+        merged
+            .merge(round_trip(&cfi), &[SymbolSection::StackCfi])
+            .unwrap();
+        assert_eq!(merged.functions, functions.functions);
+        assert_eq!(merged.publics, functions.publics);
+        assert_eq!(merged.cfi_stack_info, cfi.cfi_stack_info);
+        assert_eq!(round_trip(&merged), merged);
+
+        assert!(merged.merge(other_module, &SymbolSection::ALL).is_err());
+
+        merged.strip(SymbolSection::Functions);
+        merged.strip(SymbolSection::Publics);
+        let mut out = Vec::new();
+        merged.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "MODULE Linux x86_64 ffff0000 bar
+STACK CFI INIT 1000 20 .cfa: $rsp 16 + .ra: .cfa -8 + ^
+STACK CFI INIT 1020 10 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+"
+        );
+    }
+}
@@ -1,4 +1,6 @@
-//! Utility functions, only pathname handling at the moment.
+//! Utility functions for handling paths and names.
+
+use std::ops::Range;
 
 pub fn basename(f: &str) -> &str {
     match f.rfind(['/', '\\']) {
@@ -6,3 +8,129 @@ pub fn basename(f: &str) -> &str {
         Some(index) => &f[(index + 1)..],
     }
 }
+
+/// Match a name against a pattern where `*` matches any run of characters and `?` any
+/// single character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*` if the rest doesn't match.
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, star_n)) => {
+                    p = star + 1;
+                    n = star_n + 1;
+                    backtrack = Some((star, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Find the generic (or template) argument lists of a function name, including their angle
+/// brackets, `None` if they aren't balanced.
+///
+/// Only `<` following part of a name, `operator()` or the `::` of Rust's `function::<T>`
+/// turbofish opens arguments, so qualified paths like Rust's `<Foo as Trait>::method` and
+/// C++'s `operator<` are kept. Only the outermost lists are returned, not the ones nested in them.
+pub fn generic_args(name: &str) -> Option<Vec<Range<usize>>> {
+    let mut args = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    let mut prev = None;
+    let mut chars = name.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        if depth > 0 {
+            match c {
+                '<' => depth += 1,
+                // Not the arrow of a function type like `Box<dyn Fn() -> u8>`.
+                '>' if prev != Some('-') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        args.push(start..idx + 1);
+                    }
+                }
+                _ => {}
+            }
+        } else if c == '<' && name[..idx].ends_with("operator") {
+            // `operator<`, `operator<<`, `operator<=` and `operator<=>`.
+            while let Some(&(_, '<' | '=' | '>')) = chars.peek() {
+                chars.next();
+            }
+        } else if c == '<'
+            && (name[..idx].ends_with("::")
+                || name[..idx].ends_with("operator()")
+                || prev
+                    .is_some_and(|prev: char| prev.is_alphanumeric() || prev == '_' || prev == '}'))
+        {
+            start = idx;
+            depth = 1;
+        }
+        prev = Some(c);
+    }
+    (depth == 0).then_some(args)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.dmp", "crash.dmp"));
+        assert!(glob_match("*.dmp", ".dmp"));
+        assert!(!glob_match("*.dmp", "crash.dmp.json"));
+        assert!(glob_match("crash-??.dmp", "crash-01.dmp"));
+        assert!(!glob_match("crash-??.dmp", "crash-1.dmp"));
+        assert!(glob_match("*-*.dmp", "a-b-c.dmp"));
+        assert!(glob_match("*", "anything"));
+        assert!(!glob_match("a*b", "acbd"));
+        assert!(glob_match(
+            "core::panicking::*",
+            "core::panicking::panic_fmt"
+        ));
+        assert!(!glob_match("malloc", "malloc_usable_size"));
+        assert!(glob_match("libc.so.?@*", "libc.so.6@0x1234"));
+    }
+
+    #[test]
+    fn test_generic_args() {
+        fn args(name: &str) -> Option<Vec<&str>> {
+            generic_args(name).map(|args| args.into_iter().map(|r| &name[r]).collect())
+        }
+        assert_eq!(
+            args("std::vector<int, std::allocator<int> >::push_back(int const&)"),
+            Some(vec!["<int, std::allocator<int> >"])
+        );
+        assert_eq!(
+            args("<alloc::vec::Vec<T, A> as core::ops::drop::Drop>::drop"),
+            Some(vec!["<T, A>"])
+        );
+        assert_eq!(args("core::ptr::read::<u8>"), Some(vec!["<u8>"]));
+        assert_eq!(
+            args("std::function<void ()>::operator()<int>"),
+            Some(vec!["<void ()>", "<int>"])
+        );
+        assert_eq!(
+            args("Box<dyn Fn() -> u8>::call"),
+            Some(vec!["<dyn Fn() -> u8>"])
+        );
+        assert_eq!(args("foo::{{closure}}<u8>"), Some(vec!["<u8>"]));
+        assert_eq!(args("operator<<(int)"), Some(vec![]));
+        assert_eq!(args("Foo::operator<=>(Foo const&)"), Some(vec![]));
+        assert_eq!(args("unbalanced<thing"), None);
+    }
+}
@@ -0,0 +1,353 @@
+//! Async stacks: the logical await chains of the async tasks and coroutines on a thread.
+//!
+//! The physical stack of a thread running an async task is mostly the executor polling it:
+//! the task's futures are state machines whose `poll` (or `resume`) functions call each
+//! other, so the await chain shows up as the frames between the executor's polling frame
+//! and the innermost frame. Those frames are recognized by their names:
+//!
+//! * Rust async functions and blocks are closures (`my_app::handler::{{closure}}`, or
+//!   `my_app::handler::{async_fn#0}` with v0 mangling), other futures implement
+//!   `<T as core::future::future::Future>::poll`.
+//! * C++20 coroutines are resumed through a `[clone .resume]` (GCC), `(.resume)` (Clang)
+//!   or `$_ResumeCoro$` (MSVC) function.
+//! * Executors are known runtimes (tokio, futures, async-std, smol, ...) and
+//!   `std::coroutine_handle<T>::resume`.
+
+use std::io::{self, Write};
+
+use breakpad_symbols::strip_rust_hash;
+use minidump_common::utils::glob_match;
+use minidump_unwind::CallStack;
+
+use crate::signature::frame_names;
+
+/// Frames that poll or resume tasks, by runtime.
+const EXECUTOR_FRAMES: &[&str] = &[
+    "tokio::runtime::*",
+    "tokio::task::*",
+    "<tokio::runtime::*",
+    "<tokio::task::*",
+    "futures_executor::*",
+    "<futures_executor::*",
+    "async_std::task::*",
+    "<async_std::task::*",
+    "async_executor::*",
+    "<async_executor::*",
+    "async_task::*",
+    "<async_task::*",
+    "smol::*",
+    "pollster::*",
+    "wasm_bindgen_futures::*",
+    "<wasm_bindgen_futures::*",
+    "*coroutine_handle<*>::resume*",
+    "*coroutine_handle<*>::operator()*",
+];
+
+/// Futures whose `poll` is glue between the futures of a task (pinning, combinators,
+/// `select!` and `join!`) rather than part of its await chain.
+const GLUE_FUTURES: &[&str] = &[
+    "core::*",
+    "std::*",
+    "alloc::*",
+    "futures::*",
+    "futures_util::*",
+    "futures_core::*",
+    "tokio::*",
+    "&*",
+    "Pin<*",
+];
+
+/// The suffixes of the functions that resume C++20 coroutines.
+const COROUTINE_SUFFIXES: &[&str] = &[" [clone .resume]", " [clone .actor]", " (.resume)"];
+
+/// The logical stack of the async tasks running on a thread.
+///
+/// Built by [`AsyncStack::from_call_stack`], innermost first like the physical stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsyncStack {
+    pub frames: Vec<AsyncFrame>,
+}
+
+/// A frame of an [`AsyncStack`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsyncFrame {
+    /// The index of the physical frame (in [`CallStack::frames`]) this comes from.
+    pub frame: usize,
+    /// The name of the function, without the closure and coroutine suffixes of futures.
+    pub function_name: String,
+    pub kind: AsyncFrameKind,
+}
+
+/// What an [`AsyncFrame`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsyncFrameKind {
+    /// A synchronous function called by the innermost task.
+    Sync,
+    /// An async function, async block, future or coroutine awaiting its callee.
+    Await,
+    /// The executor frame polling the task, standing for all of the executor's frames.
+    Executor,
+}
+
+impl AsyncFrameKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AsyncFrameKind::Sync => "sync",
+            AsyncFrameKind::Await => "await",
+            AsyncFrameKind::Executor => "executor",
+        }
+    }
+}
+
+/// How a frame takes part in an async stack.
+enum Role {
+    Sync,
+    Await(String),
+    Executor,
+}
+
+impl AsyncStack {
+    /// Reconstruct the async stack of a thread, if it's running a task of a known executor.
+    ///
+    /// Each task is its await chain, innermost first, followed by the executor frame that
+    /// polls it. The executor's other frames and the glue between futures are left out;
+    /// synchronous frames are only kept at the top of the innermost task, where they're
+    /// what the task is doing. Consecutive frames of the same async function (its async
+    /// blocks) are merged. When an executor polls a task from within another task (e.g.
+    /// `block_on` in an async function), the outer task follows.
+    ///
+    /// Closures can't be told apart from async blocks with legacy Rust mangling, so
+    /// closures called from a task may show up as awaiting frames.
+    pub fn from_call_stack(stack: &CallStack) -> Option<AsyncStack> {
+        let names = frame_names(stack);
+
+        let mut frames = Vec::new();
+        // The frames of the current task, until its executor is found.
+        let mut task: Vec<AsyncFrame> = Vec::new();
+        let mut awaiting = false;
+        let mut innermost = true;
+        for (frame, name) in names {
+            match role(&name) {
+                Role::Executor => {
+                    if awaiting {
+                        frames.append(&mut task);
+                        frames.push(AsyncFrame {
+                            frame,
+                            function_name: name,
+                            kind: AsyncFrameKind::Executor,
+                        });
+                    }
+                    task.clear();
+                    awaiting = false;
+                    innermost = false;
+                }
+                Role::Await(function_name) => {
+                    let merged = task.last().is_some_and(|last| {
+                        last.kind == AsyncFrameKind::Await && last.function_name == function_name
+                    });
+                    if !merged {
+                        task.push(AsyncFrame {
+                            frame,
+                            function_name,
+                            kind: AsyncFrameKind::Await,
+                        });
+                    }
+                    awaiting = true;
+                }
+                Role::Sync if innermost && !awaiting => task.push(AsyncFrame {
+                    frame,
+                    function_name: name,
+                    kind: AsyncFrameKind::Sync,
+                }),
+                Role::Sync => {}
+            }
+        }
+        (!frames.is_empty()).then_some(AsyncStack { frames })
+    }
+
+    /// Write the async stack in the style of [`CallStack::print`].
+    pub fn print<T: Write>(&self, f: &mut T) -> io::Result<()> {
+        let mut idx = 0;
+        for frame in &self.frames {
+            match frame.kind {
+                AsyncFrameKind::Sync => writeln!(f, "{idx:2}  {}", frame.function_name)?,
+                AsyncFrameKind::Await => writeln!(f, "{idx:2}  await {}", frame.function_name)?,
+                AsyncFrameKind::Executor => {
+                    writeln!(f, "    polled by {}", frame.function_name)?;
+                    continue;
+                }
+            }
+            idx += 1;
+        }
+        Ok(())
+    }
+}
+
+fn role(name: &str) -> Role {
+    let name = strip_rust_hash(name).unwrap_or(name);
+    if EXECUTOR_FRAMES.iter().any(|p| glob_match(p, name)) {
+        return Role::Executor;
+    }
+
+    // Rust async functions and blocks.
+    let mut function = name;
+    while let Some((parent, last)) = function.rsplit_once("::") {
+        let is_closure = last == "{{closure}}"
+            || ["{closure#", "{async_fn#", "{async_block#"]
+                .iter()
+                .any(|prefix| last.starts_with(prefix));
+        if !is_closure {
+            break;
+        }
+        function = parent;
+    }
+    if function.len() != name.len() {
+        return Role::Await(function.to_owned());
+    }
+
+    // Other Rust futures.
+    if let Some(future) = name
+        .strip_prefix('<')
+        .and_then(|name| name.strip_suffix(" as core::future::future::Future>::poll"))
+    {
+        if GLUE_FUTURES.iter().any(|p| glob_match(p, future)) {
+            return Role::Sync;
+        }
+        return Role::Await(name.to_owned());
+    }
+
+    // C++20 coroutines.
+    if let Some(function) = COROUTINE_SUFFIXES
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+    {
+        return Role::Await(function.to_owned());
+    }
+    if let Some((function, _)) = name.split_once("$_ResumeCoro$") {
+        return Role::Await(function.to_owned());
+    }
+
+    Role::Sync
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{frame, stack};
+
+    fn printed(stack: &CallStack) -> String {
+        let mut output = Vec::new();
+        AsyncStack::from_call_stack(stack)
+            .unwrap()
+            .print(&mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_tokio() {
+        let thread = stack(vec![
+            frame("my_app::db::parse_row", &[]),
+            frame(
+                "my_app::db::query::{{closure}}",
+                &["my_app::db::query::{{closure}}::{{closure}}"],
+            ),
+            frame(
+                "<core::pin::Pin<P> as core::future::future::Future>::poll",
+                &[],
+            ),
+            frame(
+                "<my_app::Retry<F> as core::future::future::Future>::poll",
+                &[],
+            ),
+            frame("my_app::handler::{async_fn#0}::h0123456789abcdef", &[]),
+            frame(
+                "tokio::runtime::task::core::Core<T,S>::poll::{{closure}}",
+                &[],
+            ),
+            frame("tokio::runtime::task::harness::poll_future", &[]),
+            frame("std::panicking::try", &[]),
+            frame(
+                "tokio::runtime::scheduler::multi_thread::worker::Context::run_task",
+                &[],
+            ),
+            frame("start_thread", &[]),
+        ]);
+        let async_stack = AsyncStack::from_call_stack(&thread).unwrap();
+        assert_eq!(async_stack.frames[0].frame, 0);
+        assert_eq!(async_stack.frames[2].frame, 3);
+        assert_eq!(async_stack.frames[4].frame, 5);
+        assert_eq!(
+            printed(&thread),
+            " 0  my_app::db::parse_row
+ 1  await my_app::db::query
+ 2  await <my_app::Retry<F> as core::future::future::Future>::poll
+ 3  await my_app::handler
+    polled by tokio::runtime::task::core::Core<T,S>::poll::{{closure}}
+"
+        );
+    }
+
+    #[test]
+    fn test_nested_executors() {
+        let thread = stack(vec![
+            frame("read", &[]),
+            frame("my_app::load::{{closure}}", &[]),
+            frame("futures_executor::local_pool::block_on", &[]),
+            frame("my_app::load_blocking", &[]),
+            frame("my_app::main::{{closure}}", &[]),
+            frame("tokio::runtime::park::CachedParkThread::block_on", &[]),
+            frame("my_app::main", &[]),
+        ]);
+        assert_eq!(
+            printed(&thread),
+            " 0  read
+ 1  await my_app::load
+    polled by futures_executor::local_pool::block_on
+ 2  await my_app::main
+    polled by tokio::runtime::park::CachedParkThread::block_on
+"
+        );
+    }
+
+    #[test]
+    fn test_coroutines() {
+        let thread = stack(vec![
+            frame("Socket::read(Buffer&)", &[]),
+            frame("fetch(Url const&) [clone .resume]", &[]),
+            frame("handle(Request) (.resume)", &[]),
+            frame(
+                "EventLoop::run()",
+                &["std::__n4861::coroutine_handle<void>::resume() const"],
+            ),
+            frame("main", &[]),
+        ]);
+        assert_eq!(
+            printed(&thread),
+            " 0  Socket::read(Buffer&)
+ 1  await fetch(Url const&)
+ 2  await handle(Request)
+    polled by std::__n4861::coroutine_handle<void>::resume() const
+"
+        );
+    }
+
+    #[test]
+    fn test_no_async_stack() {
+        // No executor.
+        let thread = stack(vec![
+            frame("my_app::run::{{closure}}", &[]),
+            frame("my_app::main", &[]),
+        ]);
+        assert_eq!(AsyncStack::from_call_stack(&thread), None);
+        // An executor running a blocking task.
+        let thread = stack(vec![
+            frame("my_app::compress", &[]),
+            frame(
+                "<tokio::runtime::blocking::task::BlockingTask<T> as core::future::future::Future>::poll",
+                &[],
+            ),
+        ]);
+        assert_eq!(AsyncStack::from_call_stack(&thread), None);
+    }
+}
@@ -0,0 +1,402 @@
+//! Additional output formats for a [`ProcessState`][].
+//!
+//! [`ProcessState::print`][] and [`ProcessState::print_json`][] are the primary outputs,
+//! these are for feeding crash reports into other tools:
+//!
+//! * [SARIF][] for code scanning dashboards
+//! * Markdown for tickets
+//! * Breakpad's `minidump_stackwalk -m` pipe-separated output, and a TSV variant of it
+//!
+//! [SARIF]: https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+
+use std::io;
+use std::io::prelude::*;
+
+use minidump::*;
+use minidump_common::utils::basename;
+use minidump_unwind::CallStack;
+use serde_json::json;
+
+use crate::process_state::ProcessState;
+
+/// A frame of a thread's stack, with each inlined function as its own frame.
+struct FrameRow<'a> {
+    module: Option<String>,
+    function: Option<&'a str>,
+    file: Option<&'a str>,
+    line: Option<u32>,
+    /// The offset of the instruction from the start of the source line, function or
+    /// module, or its absolute address (in that order of preference).
+    ///
+    /// This is `None` for inlined functions.
+    offset: Option<u64>,
+    trust: &'static str,
+}
+
+fn frame_rows(stack: &CallStack) -> Vec<FrameRow> {
+    let mut rows = vec![];
+    for frame in &stack.frames {
+        let module = frame
+            .module
+            .as_ref()
+            .map(|module| basename(&module.code_file()).to_string());
+        for inline in &frame.inlines {
+            rows.push(FrameRow {
+                module: module.clone(),
+                function: Some(&inline.function_name),
+                file: inline.source_file_name.as_deref(),
+                line: inline.source_line,
+                offset: None,
+                trust: "inline",
+            });
+        }
+
+        let addr = frame.instruction;
+        let base = match (&frame.module, frame.function_base) {
+            (Some(_), Some(function_base)) => frame.source_line_base.unwrap_or(function_base),
+            (Some(module), None) => module.base_address(),
+            (None, _) => 0,
+        };
+        rows.push(FrameRow {
+            module,
+            function: frame.function_name.as_deref(),
+            file: frame.source_file_name.as_deref(),
+            line: frame.source_line,
+            offset: Some(addr.wrapping_sub(base)),
+            trust: frame.trust.as_str(),
+        });
+    }
+    rows
+}
+
+/// Converts a source path from a symbol file to a SARIF artifact URI.
+fn sarif_uri(path: &str) -> String {
+    let path = path.replace('\\', "/").replace(' ', "%20");
+    let bytes = path.as_bytes();
+    if path.starts_with('/') {
+        format!("file://{path}")
+    } else if bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && &bytes[1..3] == b":/" {
+        // A Windows path, like `c:/src/main.cpp`
+        format!("file:///{path}")
+    } else {
+        path
+    }
+}
+
+fn sarif_location(row: &FrameRow) -> serde_json::Value {
+    let mut location = json!({});
+    if let Some(file) = row.file {
+        let mut physical = json!({ "artifactLocation": { "uri": sarif_uri(file) } });
+        // SARIF lines are 1-based, 0 means we don't know.
+        if let Some(line) = row.line.filter(|&line| line > 0) {
+            physical["region"] = json!({ "startLine": line });
+        }
+        location["physicalLocation"] = physical;
+    }
+    if let Some(function) = row.function {
+        location["logicalLocations"] = json!([{
+            "fullyQualifiedName": function,
+            "kind": "function",
+        }]);
+    }
+    location
+}
+
+fn markdown_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_[]<>|#".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl ProcessState {
+    /// Outputs a [SARIF 2.1.0][] log describing the crash.
+    ///
+    /// The log has a single result for the crash (none if the process didn't crash),
+    /// located at the innermost frame of the crashing thread which has source
+    /// information, with the crashing thread's stack and the crash signature as a
+    /// fingerprint.
+    ///
+    /// [SARIF 2.1.0]: https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+    pub fn print_sarif<T: Write>(&self, f: &mut T, pretty: bool) -> Result<(), serde_json::Error> {
+        self.set_print_context();
+
+        let mut rules = vec![];
+        let mut results = vec![];
+        if let Some(ref crash_info) = self.exception_info {
+            let reason = crash_info.reason.to_string();
+            let thread = self
+                .requesting_thread
+                .and_then(|idx| Some((idx, self.threads.get(idx)?)));
+            let rows = thread
+                .map(|(_, stack)| frame_rows(stack))
+                .unwrap_or_default();
+
+            let mut message = format!("{} at {}", reason, crash_info.address);
+            if let Some(function) = rows.first().and_then(|row| row.function) {
+                message.push_str(&format!(" in {function}"));
+            }
+
+            let mut result = json!({
+                "ruleId": reason,
+                "ruleIndex": 0,
+                "level": "error",
+                "message": { "text": message },
+                "locations": rows
+                    .iter()
+                    .find(|row| row.file.is_some())
+                    .map(sarif_location)
+                    .into_iter()
+                    .collect::<Vec<_>>(),
+            });
+            if let Some((idx, stack)) = thread {
+                result["stacks"] = json!([{
+                    "message": { "text": format!("Thread {idx}") },
+                    "frames": rows.iter().map(|row| {
+                        let mut frame = json!({
+                            "location": sarif_location(row),
+                            "threadId": stack.thread_id,
+                        });
+                        if let Some(ref module) = row.module {
+                            frame["module"] = module.as_str().into();
+                        }
+                        frame
+                    }).collect::<Vec<_>>(),
+                }]);
+            }
+            if let Some(ref signature) = self.signature {
+                result["partialFingerprints"] = json!({ "signature/v1": signature });
+            }
+
+            rules.push(json!({
+                "id": reason,
+                "shortDescription": { "text": format!("The process crashed with {reason}") },
+            }));
+            results.push(result);
+        }
+
+        let output = json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "rust-minidump",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "informationUri": "https://github.com/rust-minidump/rust-minidump",
+                        "rules": rules,
+                    },
+                },
+                "results": results,
+            }],
+        });
+
+        if pretty {
+            serde_json::to_writer_pretty(f, &output)
+        } else {
+            serde_json::to_writer(f, &output)
+        }
+    }
+
+    /// Write a Markdown summary of the process state to `f`.
+    ///
+    /// This includes the crash, the system, a table of the crashing thread's frames and
+    /// the loaded modules, and is intended for pasting into tickets.
+    pub fn print_markdown<T: Write>(&self, f: &mut T) -> io::Result<()> {
+        self.set_print_context();
+
+        if let Some(ref crash_info) = self.exception_info {
+            writeln!(
+                f,
+                "# Crash: {}",
+                markdown_escape(&crash_info.reason.to_string())
+            )?;
+            writeln!(f)?;
+            writeln!(f, "- **Crash address:** {}", crash_info.address)?;
+        } else {
+            writeln!(f, "# No crash")?;
+            writeln!(f)?;
+        }
+        if let Some(ref signature) = self.signature {
+            writeln!(f, "- **Signature:** {}", markdown_escape(signature))?;
+        }
+        if let Some(ref assertion) = self.assertion {
+            writeln!(f, "- **Assertion:** {}", markdown_escape(assertion))?;
+        }
+        let sys = &self.system_info;
+        write!(f, "- **Operating system:** {}", sys.os.long_name())?;
+        if let Some(ref ver) = sys.format_os_version() {
+            write!(f, " {}", markdown_escape(ver))?;
+        }
+        writeln!(f)?;
+        write!(f, "- **CPU:** {}", sys.cpu)?;
+        if let Some(ref info) = sys.cpu_info {
+            write!(f, " ({})", markdown_escape(info))?;
+        }
+        writeln!(
+            f,
+            ", {} CPU{}",
+            sys.cpu_count,
+            if sys.cpu_count > 1 { "s" } else { "" }
+        )?;
+
+        if let Some(idx) = self.requesting_thread {
+            if let Some(stack) = self.threads.get(idx) {
+                writeln!(f)?;
+                write!(f, "## Thread {idx}")?;
+                if let Some(ref name) = stack.thread_name {
+                    write!(f, " ({})", markdown_escape(name))?;
+                }
+                writeln!(f)?;
+                writeln!(f)?;
+                writeln!(f, "| # | Module | Function | Source | Found by |")?;
+                writeln!(f, "|--:|--------|----------|--------|----------|")?;
+                for (frame_idx, row) in frame_rows(stack).iter().enumerate() {
+                    let source = match (row.file, row.line) {
+                        (Some(file), Some(line)) => format!("{}:{}", basename(file), line),
+                        (Some(file), None) => basename(file).to_string(),
+                        _ => String::new(),
+                    };
+                    let function = match (row.function, row.offset) {
+                        (Some(function), _) => function.to_string(),
+                        (None, Some(offset)) => format!("+{offset:#x}"),
+                        (None, None) => String::new(),
+                    };
+                    writeln!(
+                        f,
+                        "| {} | {} | {} | {} | {} |",
+                        frame_idx,
+                        markdown_escape(row.module.as_deref().unwrap_or("")),
+                        markdown_escape(&function),
+                        markdown_escape(&source),
+                        row.trust,
+                    )?;
+                }
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(f, "## Modules")?;
+        writeln!(f)?;
+        writeln!(f, "| Module | Version | Debug ID | Address range |")?;
+        writeln!(f, "|--------|---------|----------|---------------|")?;
+        for module in self.modules.by_addr() {
+            writeln!(
+                f,
+                "| {} | {} | {} | {:#010x} - {:#010x} |",
+                markdown_escape(basename(&module.code_file())),
+                markdown_escape(&module.version().unwrap_or_default()),
+                module.debug_identifier().unwrap_or_default().breakpad(),
+                module.base_address(),
+                module.base_address() + module.size() - 1,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write a tab-separated summary of the process state to `f`.
+    ///
+    /// This has the same records as Breakpad's `minidump_stackwalk -m`:
+    ///
+    /// ```text
+    /// OS    <os>    <os version>
+    /// CPU   <cpu>   <cpu info>   <cpu count>
+    /// Crash <reason>    <address>   <crashing thread>
+    /// Module    <file>  <version>   <debug file>    <debug id>  <base>  <end>   <main>
+    ///
+    /// <thread>  <frame> <module>    <function>  <file>  <line>  <offset>
+    /// ```
+    ///
+    /// The crashing thread's frames come first. Inlined functions get their own frames,
+    /// with no offset.
+    pub fn print_tsv<T: Write>(&self, f: &mut T) -> io::Result<()> {
+        self.print_records(f, '\t')
+    }
+
+    /// Write the process state to `f` in the pipe-separated format of Breakpad's
+    /// `minidump_stackwalk -m`.
+    ///
+    /// This has the same records as [`ProcessState::print_tsv`][], separated by `|`
+    /// instead of tabs. As in Breakpad, any `|` in a value is replaced with `_`.
+    pub fn print_machine_readable<T: Write>(&self, f: &mut T) -> io::Result<()> {
+        self.print_records(f, '|')
+    }
+
+    fn print_records<T: Write>(&self, f: &mut T, separator: char) -> io::Result<()> {
+        let field = |value: &str| value.replace([separator, '\n', '\r'], "_");
+
+        let sys = &self.system_info;
+        writeln!(
+            f,
+            "OS{separator}{}{separator}{}",
+            field(&sys.os.long_name()),
+            field(&sys.format_os_version().unwrap_or_default()),
+        )?;
+        writeln!(
+            f,
+            "CPU{separator}{}{separator}{}{separator}{}",
+            sys.cpu,
+            field(sys.cpu_info.as_deref().unwrap_or("")),
+            sys.cpu_count,
+        )?;
+
+        if let Some(ref crash_info) = self.exception_info {
+            write!(
+                f,
+                "Crash{separator}{}{separator}{:#x}{separator}",
+                field(&crash_info.reason.to_string()),
+                crash_info.address.0,
+            )?;
+        } else if let Some(ref assertion) = self.assertion {
+            write!(f, "Assert{separator}{}{separator}", field(assertion))?;
+        } else {
+            write!(f, "No crash{separator}{separator}")?;
+        }
+        if let Some(idx) = self.requesting_thread {
+            write!(f, "{idx}")?;
+        }
+        writeln!(f)?;
+
+        let main_address = self.modules.main_module().map(|m| m.base_address());
+        for module in self.modules.by_addr() {
+            writeln!(
+                f,
+                "Module{separator}{}{separator}{}{separator}{}{separator}{}{separator}{:#010x}{separator}{:#010x}{separator}{}",
+                field(basename(&module.code_file())),
+                field(&module.version().unwrap_or_default()),
+                field(basename(&module.debug_file().unwrap_or_default())),
+                module.debug_identifier().unwrap_or_default().breakpad(),
+                module.base_address(),
+                module.base_address() + module.size() - 1,
+                u8::from(main_address == Some(module.base_address())),
+            )?;
+        }
+        writeln!(f)?;
+
+        let threads = self
+            .requesting_thread
+            .into_iter()
+            .chain((0..self.threads.len()).filter(|&idx| Some(idx) != self.requesting_thread));
+        for idx in threads {
+            let Some(stack) = self.threads.get(idx) else {
+                continue;
+            };
+            for (frame_idx, row) in frame_rows(stack).iter().enumerate() {
+                writeln!(
+                    f,
+                    "{idx}{separator}{frame_idx}{separator}{}{separator}{}{separator}{}{separator}{}{separator}{}",
+                    field(row.module.as_deref().unwrap_or("")),
+                    field(row.function.unwrap_or("")),
+                    field(row.file.unwrap_or("")),
+                    row.line.map(|line| line.to_string()).unwrap_or_default(),
+                    row.offset.map(|offset| format!("{offset:#x}")).unwrap_or_default(),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -83,10 +83,21 @@ async fn main() -> Result<(), ()> {
 doc_comment::doctest!("../README.md");
 
 mod arg_recovery;
+mod async_stack;
 mod evil;
+mod formats;
 mod op_analysis;
 mod process_state;
 mod processor;
+mod recursion;
+mod signature;
+mod source;
+#[cfg(test)]
+mod test_util;
 
+pub use crate::async_stack::*;
 pub use crate::process_state::*;
 pub use crate::processor::*;
+pub use crate::recursion::*;
+pub use crate::signature::*;
+pub use crate::source::*;
@@ -5,16 +5,20 @@
 
 use std::borrow::{Borrow, Cow};
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io;
 use std::io::prelude::*;
 use std::time::SystemTime;
 
+use crate::async_stack::AsyncStack;
 use crate::op_analysis::{InstructionPointerUpdate, InstructionProperties, MemoryAccessList};
 use minidump::system_info::PointerWidth;
 use minidump::*;
 use minidump_common::utils::basename;
-use minidump_unwind::{CallStack, CallStackInfo, SymbolStats, SystemInfo};
+use minidump_unwind::{
+    CallStack, CallStackInfo, CollapsedRecursion, SourceContext, SymbolStats, SystemInfo,
+    UnwindNote,
+};
 use serde_json::json;
 
 #[derive(Default)]
@@ -229,6 +233,9 @@ pub struct ExceptionInfo {
     pub possible_bit_flips: Vec<PossibleBitFlip>,
     /// Whether the crash reason/address is inconsistent with crashing instruction and memory info
     pub inconsistencies: Vec<CrashInconsistency>,
+    /// Whether the crash is probably a stack overflow: the crashing instruction hit a likely
+    /// guard page, and the crashing thread's stack has a cycle of recursive frames.
+    pub probable_stack_overflow: bool,
 }
 
 /// Info about a memory address that was adjusted from its reported value
@@ -503,6 +510,16 @@ pub struct ProcessState {
     pub unimplemented_streams: Vec<MinidumpUnimplementedStream>,
     pub symbol_stats: HashMap<String, SymbolStats>,
     pub linux_memory_map_count: Option<usize>,
+    /// A short summary of the requesting thread's stack for grouping similar crashes.
+    ///
+    /// See [`SignatureRules`][crate::SignatureRules].
+    pub signature: Option<String>,
+    /// The logical stacks of the async tasks and coroutines running on the threads, by
+    /// thread index.
+    ///
+    /// Only filled in if [`ProcessorOptions::async_stacks`][crate::ProcessorOptions::async_stacks]
+    /// is set, and only for threads running a task of a known executor.
+    pub async_stacks: BTreeMap<usize, AsyncStack>,
 }
 
 fn json_registers(ctx: &MinidumpContext) -> serde_json::Value {
@@ -525,6 +542,86 @@ fn json_registers(ctx: &MinidumpContext) -> serde_json::Value {
     json!(output)
 }
 
+/// Adds the optional `source_context` field to a frame's JSON.
+fn with_source_context(
+    mut frame: serde_json::Value,
+    context: &Option<SourceContext>,
+) -> serde_json::Value {
+    if let Some(context) = context {
+        frame["source_context"] = json!({
+            "start_line": context.start_line,
+            "lines": context.lines,
+        });
+    }
+    frame
+}
+
+/// Adds the optional `unwind_notes` field to a frame's JSON.
+fn with_unwind_notes(mut frame: serde_json::Value, notes: &[UnwindNote]) -> serde_json::Value {
+    if !notes.is_empty() {
+        frame["unwind_notes"] = json!(notes.iter().map(ToString::to_string).collect::<Vec<_>>());
+    }
+    frame
+}
+
+/// Adds the optional `async_frames` field to a thread's JSON.
+fn with_async_frames(
+    mut thread: serde_json::Value,
+    async_stack: Option<&AsyncStack>,
+) -> serde_json::Value {
+    if let Some(async_stack) = async_stack {
+        thread["async_frames"] = json!(async_stack
+            .frames
+            .iter()
+            .map(|frame| json!({
+                "frame": frame.frame,
+                "function": frame.function_name,
+                // sync | await | executor
+                "kind": frame.kind.as_str(),
+            }))
+            .collect::<Vec<_>>());
+    }
+    thread
+}
+
+/// Adds the optional `collapsed_recursions` field to a thread's JSON.
+fn with_collapsed_recursions(
+    mut thread: serde_json::Value,
+    recursions: &[CollapsedRecursion],
+) -> serde_json::Value {
+    if !recursions.is_empty() {
+        thread["collapsed_recursions"] = json!(recursions
+            .iter()
+            .map(|recursion| json!({
+                "frame": recursion.frame,
+                "first_frame": recursion.original_frame,
+                "last_frame": recursion.original_frame
+                    + recursion.cycle_len * recursion.repetitions
+                    - 1,
+                "cycle_len": recursion.cycle_len,
+                "repetitions": recursion.repetitions,
+            }))
+            .collect::<Vec<_>>());
+    }
+    thread
+}
+
+/// Adds the optional `unwind_stopped` field to a thread's JSON.
+fn with_unwind_stopped(mut thread: serde_json::Value, info: &CallStackInfo) -> serde_json::Value {
+    if info.stopped_early().is_some() {
+        thread["unwind_stopped"] = json!(info.as_str());
+    }
+    thread
+}
+
+/// Adds the optional `jit` field to a frame's JSON.
+fn with_jit(mut frame: serde_json::Value, jit: bool) -> serde_json::Value {
+    if jit {
+        frame["jit"] = json!(true);
+    }
+    frame
+}
+
 fn eq_some<T: PartialEq>(opt: Option<T>, val: T) -> bool {
     match opt {
         Some(v) => v == val,
@@ -599,6 +696,13 @@ impl ProcessState {
                 writeln!(f, "Crash address: {}", crash_info.address)?;
             }
 
+            if crash_info.probable_stack_overflow {
+                writeln!(
+                    f,
+                    "Probable stack overflow: recursion on the crashing thread hit a guard page"
+                )?;
+            }
+
             if let Some(ref crashing_instruction_str) = crash_info.instruction_str {
                 writeln!(f, "Crashing instruction: `{crashing_instruction_str}`")?;
             }
@@ -679,6 +783,9 @@ impl ProcessState {
         } else {
             writeln!(f, "No crash")?;
         }
+        if let Some(ref signature) = self.signature {
+            writeln!(f, "Signature: {signature}")?;
+        }
 
         if let Some(ref assertion) = self.assertion {
             writeln!(f, "Assertion: {assertion}")?;
@@ -751,6 +858,7 @@ impl ProcessState {
                 stack.thread_id
             )?;
             stack.print(f)?;
+            self.print_async_stack(f, requesting_thread)?;
             writeln!(f)?;
         }
 
@@ -775,6 +883,7 @@ impl ProcessState {
                 stack.thread_id
             )?;
             stack.print(f)?;
+            self.print_async_stack(f, i)?;
         }
         write!(
             f,
@@ -860,6 +969,14 @@ Unknown streams encountered:
         Ok(())
     }
 
+    fn print_async_stack<T: Write>(&self, f: &mut T, thread: usize) -> io::Result<()> {
+        if let Some(async_stack) = self.async_stacks.get(&thread) {
+            writeln!(f, "Async stack:")?;
+            async_stack.print(f)?;
+        }
+        Ok(())
+    }
+
     /// Outputs json in a schema compatible with mozilla's Socorro crash reporting servers.
     ///
     /// See the top level documentation of this library for the stable JSON schema.
@@ -878,6 +995,8 @@ Unknown streams encountered:
             // Currently unused, we either produce no output or successful output.
             // OK | ERROR_* | SYMBOL_SUPPLIER_INTERRUPTED
             "status": "OK",
+            // Summary of the crashing thread's stack for grouping crashes | null
+            "signature": self.signature,
             "system_info": {
                 // Linux | Windows NT | Mac OS X
                 "os": sys.os.long_name(),
@@ -1044,20 +1163,22 @@ Unknown streams encountered:
             }).collect::<Vec<_>>(),
             "pid": self.process_id,
             "thread_count": self.threads.len(),
-            "threads": self.threads.iter().map(|thread| json!({
+            "threads": self.threads.iter().enumerate().map(|(thread_idx, thread)| with_collapsed_recursions(with_unwind_stopped(with_async_frames(json!({
                 "frame_count": thread.frames.len(),
                 // optional
                 "last_error_value": thread.last_error_value.map(|error| error.to_string()),
                 // optional
                 "thread_name": thread.thread_name,
                 "thread_id" : thread.thread_id,
-                "frames": thread.frames.iter().enumerate().map(|(idx, frame)| json!({
+                "frames": thread.frames.iter().enumerate().map(|(idx, frame)| with_jit(with_unwind_notes(with_source_context(json!({
                     "frame": idx,
                     // optional
                     "module": frame.module.as_ref().map(|module| basename(&module.name)),
                     // optional
                     "function": frame.function_name,
                     // optional
+                    "raw_function": frame.raw_function_name,
+                    // optional
                     "file": frame.source_file_name,
                     // optional
                     "line": frame.source_line,
@@ -1065,11 +1186,13 @@ Unknown streams encountered:
                     // optional
                     "inlines": if !frame.inlines.is_empty() {
                         Some(frame.inlines.iter().map(|frame| {
-                            json!({
+                            with_source_context(json!({
                                 "function": frame.function_name,
+                                // optional
+                                "raw_function": frame.raw_function_name,
                                 "file": frame.source_file_name,
                                 "line": frame.source_line,
-                            })
+                            }), &frame.source_context)
                         }).collect::<Vec<_>>())
                     } else {
                         None
@@ -1095,10 +1218,10 @@ Unknown streams encountered:
                         .map(|func_base| frame.instruction - func_base)
                         .map(json_hex),
                     "missing_symbols": frame.function_name.is_none(),
-                    // none | scan | cfi_scan | frame_pointer | cfi | context | prewalked
+                    // none | scan | verified_scan | cfi_scan | frame_pointer | cfi | context | prewalked | signal_context
                     "trust": frame.trust.as_str()
-                })).collect::<Vec<_>>(),
-            })).collect::<Vec<_>>(),
+                }), &frame.source_context), &frame.unwind_notes), frame.jit)).collect::<Vec<_>>(),
+            }), self.async_stacks.get(&thread_idx)), &thread.info), &thread.collapsed_recursions)).collect::<Vec<_>>(),
 
             "unloaded_modules": self.unloaded_modules.iter().map(|module| json!({
                 "base_addr": json_hex(module.raw.base_of_image),
@@ -1114,6 +1237,15 @@ Unknown streams encountered:
             })).collect::<Vec<_>>()),
         });
 
+        if self
+            .exception_info
+            .as_ref()
+            .is_some_and(|info| info.probable_stack_overflow)
+        {
+            // Only add the `probable_stack_overflow` field when it is affirmative.
+            output["crash_info"]["probable_stack_overflow"] = true.into();
+        }
+
         if let Some(requesting_thread) = self.requesting_thread {
             // Copy the crashing thread into a top-level "crashing_thread" field and:
             // * Add a "threads_index" field to indicate which thread it was
@@ -1156,7 +1288,7 @@ Unknown streams encountered:
         }
     }
 
-    fn set_print_context(&self) {
+    pub(crate) fn set_print_context(&self) {
         SERIALIZATION_CONTEXT.with(|ctx| {
             ctx.borrow_mut().pointer_width = Some(self.system_info.cpu.pointer_width());
         });
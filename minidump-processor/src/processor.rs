@@ -1,22 +1,25 @@
 // Copyright 2015 Ted Mielczarek. See the COPYRIGHT
 // file at the top-level directory of this distribution.
 
+use std::borrow::Cow;
 use std::collections::{BTreeMap, BTreeSet};
 use std::ops::{Deref, RangeInclusive};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 use minidump::system_info::PointerWidth;
 use minidump::*;
 use minidump_unwind::{
-    walk_stack, CallStack, CallStackInfo, FrameTrust, StackFrame, SymbolProvider, SystemInfo,
+    walk_stack_with_options, CallStack, CallStackInfo, DemangleOptions, FrameTrust, StackFrame,
+    SymbolProvider, SystemInfo, UnwindOptions,
 };
 
-use crate::op_analysis::MemoryAddressInfo;
+use crate::op_analysis::{InstructionPointerUpdate, MemoryAddressInfo};
 use crate::process_state::{LinuxStandardBase, ProcessState};
 use crate::{
-    arg_recovery, evil, AdjustedAddress, CrashInconsistency, LinuxProcLimits, LinuxProcStatus,
+    arg_recovery, collapse_recursion, evil, find_recursion, AdjustedAddress, AsyncStack,
+    CrashInconsistency, LinuxProcLimits, LinuxProcStatus, SignatureRules, SourceFiles,
 };
 
 /// Configuration of the processor's exact behaviour.
@@ -71,6 +74,60 @@ pub struct ProcessorOptions<'a> {
     ///
     /// See [`PendingProcessorStats`] and [`PendingProcessorStatSubscriptions`].
     pub stat_reporter: Option<&'a PendingProcessorStats>,
+
+    /// The rules for the crash signature ([`ProcessState::signature`]).
+    ///
+    /// `None` uses [`SignatureRules::default`].
+    pub signature_rules: Option<&'a SignatureRules>,
+
+    /// Where to find the source files of the crashing thread's frames.
+    ///
+    /// If set, the frames (and inlined frames) of the crashing thread get the
+    /// source code around their line as their `source_context`. `None` skips this.
+    pub source_files: Option<&'a SourceFiles>,
+
+    /// How to rewrite the function names from symbol files, e.g. to demangle them.
+    ///
+    /// When a name is rewritten, the original is kept in the frame's
+    /// `raw_function_name`. The default leaves names as they are. Demangling needs the
+    /// `demangle` feature.
+    pub demangle: DemangleOptions,
+
+    /// Whether to record the notes on how each frame was found ([`StackFrame::unwind_notes`]).
+    ///
+    /// They explain bad stacks (e.g. which values stack scanning rejected, and why), but
+    /// are off by default as they're verbose and slow unwinding down.
+    pub unwind_notes: bool,
+
+    /// Whether to reconstruct the logical stacks of async tasks and coroutines
+    /// ([`ProcessState::async_stacks`]).
+    pub async_stacks: bool,
+
+    /// The most frames to unwind per thread, `None` for no limit.
+    pub max_frames: Option<usize>,
+
+    /// The most frames per thread to find with stack scanning, `None` for no limit.
+    pub max_scanned_frames: Option<usize>,
+
+    /// How long to spend unwinding each thread, `None` for no limit.
+    pub thread_timeout: Option<Duration>,
+
+    /// How long to spend unwinding all the threads, `None` for no limit.
+    ///
+    /// The threads that are still unwinding when this runs out stop where they are, and
+    /// the threads that haven't started are only symbolicated.
+    pub total_timeout: Option<Duration>,
+
+    /// Whether to only unwind the requesting thread.
+    ///
+    /// The other threads only have their symbolicated context frame.
+    pub crashing_thread_only: bool,
+
+    /// Whether to collapse the cycles of recursive frames in a stack to a single copy of
+    /// each ([`CallStack::collapsed_recursions`]).
+    ///
+    /// Stack overflows often leave tens of thousands of identical recursive frames.
+    pub collapse_recursion: bool,
 }
 
 /// A subscription to various live updates during minidump processing.
@@ -279,6 +336,13 @@ impl ProcessorOptions<'_> {
     ///
     /// * `evil_json: None`
     /// * `recover_function_args: false`
+    /// * `signature_rules: None`
+    /// * `source_files: None`
+    /// * `demangle: DemangleOptions::default()`
+    /// * `unwind_notes: false`
+    /// * `async_stacks: false`
+    /// * no unwind limits or timeouts
+    /// * `collapse_recursion: false`
     ///
     /// Unlike stable_all, you shouldn't expect this to change its results much.
     ///
@@ -293,6 +357,17 @@ impl ProcessorOptions<'_> {
             evil_json: None,
             recover_function_args: false,
             stat_reporter: None,
+            signature_rules: None,
+            source_files: None,
+            demangle: DemangleOptions::default(),
+            unwind_notes: false,
+            async_stacks: false,
+            max_frames: None,
+            max_scanned_frames: None,
+            thread_timeout: None,
+            total_timeout: None,
+            crashing_thread_only: false,
+            collapse_recursion: false,
         }
     }
 
@@ -300,9 +375,13 @@ impl ProcessorOptions<'_> {
     ///
     /// * `evil_json: None`
     /// * `recover_function_args: false`
-    ///
-    /// (At this precise moment this is identical to stable_basic, but may diverge
-    /// as we introduce more features.)
+    /// * `signature_rules: None`
+    /// * `source_files: None`
+    /// * `demangle: DemangleOptions::default()`
+    /// * `unwind_notes: false`
+    /// * `async_stacks: false`
+    /// * no unwind limits or timeouts
+    /// * `collapse_recursion: false`
     ///
     /// Everything included by stable_basic, but willing to enable more interesting
     /// features and spend extra time trying to find extra insights. This is the default
@@ -312,6 +391,17 @@ impl ProcessorOptions<'_> {
             evil_json: None,
             recover_function_args: false,
             stat_reporter: None,
+            signature_rules: None,
+            source_files: None,
+            demangle: DemangleOptions::default(),
+            unwind_notes: false,
+            async_stacks: false,
+            max_frames: None,
+            max_scanned_frames: None,
+            thread_timeout: None,
+            total_timeout: None,
+            crashing_thread_only: false,
+            collapse_recursion: false,
         }
     }
 
@@ -319,6 +409,13 @@ impl ProcessorOptions<'_> {
     ///
     /// * `evil_json: None`
     /// * `recover_function_args: true`
+    /// * `signature_rules: None`
+    /// * `source_files: None`
+    /// * `demangle: DemangleOptions::default()`
+    /// * `unwind_notes: false`
+    /// * `async_stacks: true`
+    /// * no unwind limits or timeouts
+    /// * `collapse_recursion: true`
     ///
     /// (evil_json is still "disabled" because you need to give it needs a path.)
     ///
@@ -328,6 +425,17 @@ impl ProcessorOptions<'_> {
             evil_json: None,
             recover_function_args: true,
             stat_reporter: None,
+            signature_rules: None,
+            source_files: None,
+            demangle: DemangleOptions::default(),
+            unwind_notes: false,
+            async_stacks: true,
+            max_frames: None,
+            max_scanned_frames: None,
+            thread_timeout: None,
+            total_timeout: None,
+            crashing_thread_only: false,
+            collapse_recursion: true,
         }
     }
 
@@ -628,8 +736,6 @@ impl<'a> MinidumpInfo<'a> {
 
     /// Get details about the minidump exception, if available.
     pub fn get_exception_details(&self) -> Option<ExceptionDetails<'a>> {
-        use crate::op_analysis::InstructionPointerUpdate;
-
         let exception = self.exception.as_ref()?;
 
         let reason = exception.get_crash_reason(self.system_info.os, self.system_info.cpu);
@@ -920,7 +1026,6 @@ impl<'a> MinidumpInfo<'a> {
         &self,
         exception_details: &ExceptionDetails,
     ) -> bool {
-        use crate::op_analysis::InstructionPointerUpdate;
         const NON_CANONICAL_RANGE: RangeInclusive<u64> =
             0x0000_8000_0000_0000..=0xffff_7fff_ffff_ffff;
         let info = &exception_details.info;
@@ -1074,6 +1179,7 @@ impl<'a> MinidumpInfo<'a> {
                     thread_id: id,
                     thread_name: name,
                     last_error_value: thread.last_error(self.system_info.cpu, &self.memory_list),
+                    collapsed_recursions: Vec::new(),
                 }
             })
             .collect();
@@ -1120,6 +1226,8 @@ impl<'a> MinidumpInfo<'a> {
             unimplemented_streams,
             symbol_stats,
             linux_memory_map_count: self.linux_memory_map_count,
+            signature: None,
+            async_stacks: BTreeMap::new(),
         };
 
         // Report the unwalked result
@@ -1129,10 +1237,15 @@ impl<'a> MinidumpInfo<'a> {
 
         {
             let memory_list = &self.memory_list;
+            let memory_info = &self.memory_info;
             let modules = &state.modules;
             let system_info = &state.system_info;
             let unloaded_modules = &state.unloaded_modules;
             let options = &self.options;
+            let requesting_thread = state.requesting_thread;
+            let total_deadline = options
+                .total_timeout
+                .map(|timeout| Instant::now() + timeout);
 
             futures_util::future::join_all(
                 state
@@ -1160,7 +1273,22 @@ impl<'a> MinidumpInfo<'a> {
                             }
                         }
 
-                        walk_stack(
+                        let skipped = options.crashing_thread_only && requesting_thread != Some(i);
+                        let thread_deadline = options
+                            .thread_timeout
+                            .map(|timeout| Instant::now() + timeout);
+                        let mut unwind_options = UnwindOptions::default();
+                        unwind_options.code_memory = Some(memory_list);
+                        unwind_options.memory_info = Some(memory_info);
+                        unwind_options.max_frames = options.max_frames;
+                        unwind_options.max_scanned_frames = options.max_scanned_frames;
+                        unwind_options.unwind_notes = options.unwind_notes;
+                        unwind_options.deadline = match (thread_deadline, total_deadline) {
+                            (Some(thread), Some(total)) => Some(thread.min(total)),
+                            (thread, total) => thread.or(total),
+                        };
+
+                        walk_stack_with_options(
                             i,
                             |frame_idx: usize, frame: &StackFrame| {
                                 if let Some(reporter) = options.stat_reporter {
@@ -1168,12 +1296,17 @@ impl<'a> MinidumpInfo<'a> {
                                 }
                             },
                             stack,
-                            stack_memory,
+                            // Without stack memory, only the context frame is symbolicated.
+                            stack_memory.filter(|_| !skipped),
                             modules,
                             system_info,
                             symbol_provider,
+                            &unwind_options,
                         )
                         .await;
+                        if skipped && stack.info == CallStackInfo::Ok {
+                            stack.info = CallStackInfo::UnwindSkipped;
+                        }
 
                         for frame in &mut stack.frames {
                             // If the frame doesn't have a loaded module, try to find an unloaded module
@@ -1199,6 +1332,10 @@ impl<'a> MinidumpInfo<'a> {
                             arg_recovery::fill_arguments(stack, stack_memory);
                         }
 
+                        if options.collapse_recursion {
+                            collapse_recursion(stack);
+                        }
+
                         // Report the unwalked result
                         if let Some(reporter) = options.stat_reporter {
                             reporter.inc_processed_threads();
@@ -1213,6 +1350,46 @@ impl<'a> MinidumpInfo<'a> {
         let symbol_stats = symbol_provider.stats();
         state.symbol_stats = symbol_stats;
 
+        if self.options.demangle != DemangleOptions::default() {
+            for thread in &mut state.threads {
+                demangle_function_names(&self.options.demangle, thread);
+            }
+        }
+
+        let default_rules;
+        let rules = match self.options.signature_rules {
+            Some(rules) => rules,
+            None => {
+                default_rules = SignatureRules::default();
+                &default_rules
+            }
+        };
+        state.signature = state
+            .requesting_thread
+            .and_then(|thread| rules.signature(&state.threads[thread]));
+
+        if let (Some(info), Some(thread)) = (&mut state.exception_info, state.requesting_thread) {
+            let stack = &state.threads[thread];
+            let is_recursive =
+                !stack.collapsed_recursions.is_empty() || !find_recursion(stack).is_empty();
+            info.probable_stack_overflow = is_recursive && info.hits_guard_page();
+        }
+
+        if self.options.async_stacks {
+            state.async_stacks = state
+                .threads
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, thread)| Some((idx, AsyncStack::from_call_stack(thread)?)))
+                .collect();
+        }
+
+        if let (Some(source_files), Some(thread)) =
+            (self.options.source_files, state.requesting_thread)
+        {
+            source_files.fill_source_context(&mut state.threads[thread]);
+        }
+
         Ok(state)
     }
 }
@@ -1229,9 +1406,25 @@ impl crate::ExceptionInfo {
             instruction_pointer_update: None,
             possible_bit_flips: Default::default(),
             inconsistencies: Default::default(),
+            probable_stack_overflow: false,
         }
     }
 
+    /// Whether the crashing instruction accessed (or jumped to) a likely guard page.
+    fn hits_guard_page(&self) -> bool {
+        let accesses_guard_page = self.memory_access_list.as_ref().is_some_and(|accesses| {
+            accesses
+                .iter()
+                .any(|access| access.address_info.is_likely_guard_page)
+        });
+        let jumps_to_guard_page = matches!(
+            &self.instruction_pointer_update,
+            Some(InstructionPointerUpdate::Update { address_info })
+                if address_info.is_likely_guard_page
+        );
+        accesses_guard_page || jumps_to_guard_page
+    }
+
     fn with_op_analysis(
         reason: CrashReason,
         address: crate::Address,
@@ -1248,6 +1441,7 @@ impl crate::ExceptionInfo {
             instruction_pointer_update: op_analysis.instruction_pointer_update,
             possible_bit_flips: Default::default(),
             inconsistencies: Default::default(),
+            probable_stack_overflow: false,
         }
     }
 }
@@ -1258,6 +1452,30 @@ struct ExceptionDetails<'a> {
     instruction_registers: BTreeSet<&'static str>,
 }
 
+/// Rewrite the function names of `stack`'s frames, keeping the original names.
+fn demangle_function_names(options: &DemangleOptions, stack: &mut CallStack) {
+    fn rewrite(options: &DemangleOptions, name: &mut String, raw_name: &mut Option<String>) {
+        if let Cow::Owned(rewritten) = options.apply(name) {
+            if rewritten != *name {
+                *raw_name = Some(std::mem::replace(name, rewritten));
+            }
+        }
+    }
+
+    for frame in &mut stack.frames {
+        if let Some(name) = &mut frame.function_name {
+            rewrite(options, name, &mut frame.raw_function_name);
+        }
+        for inline in &mut frame.inlines {
+            rewrite(
+                options,
+                &mut inline.function_name,
+                &mut inline.raw_function_name,
+            );
+        }
+    }
+}
+
 /// If a non-canonical access caused a crash, return the real address
 ///
 /// Amd64 has the concept of a "canonical addressing", which requires that the upper 16 bits of
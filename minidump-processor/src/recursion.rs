@@ -0,0 +1,151 @@
+//! Recursion detection: the cycles of recursive frames that fill the stacks of stack
+//! overflows.
+//!
+//! A stack that overflowed through recursion often has tens of thousands of frames that are
+//! the same few calls over and over. Frames are compared by instruction address, so a cycle
+//! is the same call sites repeating, and each run of a cycle is collapsed to one copy of it.
+
+use minidump_unwind::{CallStack, CollapsedRecursion};
+
+/// The fewest repetitions of a cycle of frames that count as recursion.
+const MIN_REPETITIONS: usize = 16;
+
+/// The most frames in a cycle.
+const MAX_CYCLE_LEN: usize = 32;
+
+/// Find the cycles of recursive frames in a stack, in order.
+///
+/// The [`CollapsedRecursion::frame`] of each cycle is its index in the stack as it is, as
+/// nothing has been collapsed yet.
+pub fn find_recursion(stack: &CallStack) -> Vec<CollapsedRecursion> {
+    let addresses: Vec<u64> = stack.frames.iter().map(|frame| frame.instruction).collect();
+    let mut recursions = Vec::new();
+    let mut start = 0;
+    while start < addresses.len() {
+        // The cycle repeating over the most frames from here, the shortest one on a tie.
+        let mut best: Option<(usize, usize)> = None;
+        for cycle_len in 1..=MAX_CYCLE_LEN.min((addresses.len() - start) / MIN_REPETITIONS) {
+            let mut end = start + cycle_len;
+            while end < addresses.len() && addresses[end] == addresses[end - cycle_len] {
+                end += 1;
+            }
+            let repetitions = (end - start) / cycle_len;
+            let is_better = best.is_none_or(|(best_len, best_repetitions)| {
+                cycle_len * repetitions > best_len * best_repetitions
+            });
+            if repetitions >= MIN_REPETITIONS && is_better {
+                best = Some((cycle_len, repetitions));
+            }
+        }
+        match best {
+            Some((cycle_len, repetitions)) => {
+                recursions.push(CollapsedRecursion {
+                    frame: start,
+                    original_frame: start,
+                    cycle_len,
+                    repetitions,
+                });
+                start += cycle_len * repetitions;
+            }
+            None => start += 1,
+        }
+    }
+    recursions
+}
+
+/// Collapse the cycles of recursive frames of a stack to a single copy of each, recording
+/// them in [`CallStack::collapsed_recursions`].
+pub fn collapse_recursion(stack: &mut CallStack) {
+    let mut recursions = find_recursion(stack);
+    if recursions.is_empty() {
+        return;
+    }
+
+    // Keep the first copy of each cycle.
+    let is_collapsed = |idx: usize| {
+        recursions.iter().any(|recursion| {
+            let kept_end = recursion.original_frame + recursion.cycle_len;
+            let end = recursion.original_frame + recursion.cycle_len * recursion.repetitions;
+            (kept_end..end).contains(&idx)
+        })
+    };
+    let mut idx = 0;
+    stack.frames.retain(|_| {
+        let keep = !is_collapsed(idx);
+        idx += 1;
+        keep
+    });
+
+    let mut collapsed = 0;
+    for recursion in &mut recursions {
+        recursion.frame = recursion.original_frame - collapsed;
+        collapsed += recursion.cycle_len * (recursion.repetitions - 1);
+    }
+    stack.collapsed_recursions = recursions;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{frame_at, stack};
+
+    fn addresses(stack: &CallStack) -> Vec<u64> {
+        stack.frames.iter().map(|frame| frame.instruction).collect()
+    }
+
+    #[test]
+    fn test_collapse_recursion() {
+        // Two frames, a cycle of 3 frames repeated 1000 times (and a bit), a cycle of one
+        // frame repeated 20 times, then the outermost frames.
+        let mut thread = stack(
+            [
+                vec![0x10, 0x20],
+                [0x30, 0x40, 0x50].repeat(1000),
+                vec![0x30, 0x40],
+                vec![0x60; 20],
+                vec![0x70, 0x80],
+            ]
+            .concat()
+            .into_iter()
+            .map(frame_at),
+        );
+        collapse_recursion(&mut thread);
+
+        assert_eq!(
+            addresses(&thread),
+            [0x10, 0x20, 0x30, 0x40, 0x50, 0x30, 0x40, 0x60, 0x70, 0x80]
+        );
+        assert_eq!(
+            thread.collapsed_recursions,
+            [
+                CollapsedRecursion {
+                    frame: 2,
+                    original_frame: 2,
+                    cycle_len: 3,
+                    repetitions: 1000,
+                },
+                CollapsedRecursion {
+                    frame: 7,
+                    original_frame: 3004,
+                    cycle_len: 1,
+                    repetitions: 20,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_recursion() {
+        // Not enough repetitions.
+        let mut thread = stack(
+            [vec![0x10], [0x20, 0x30].repeat(10)]
+                .concat()
+                .into_iter()
+                .map(frame_at),
+        );
+        collapse_recursion(&mut thread);
+        assert_eq!(thread.frames.len(), 21);
+        assert!(thread.collapsed_recursions.is_empty());
+        assert!(find_recursion(&stack(vec![])).is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+//! Crash signatures: short summaries of the crashing stack for grouping similar crashes.
+
+use std::fs;
+use std::path::Path;
+
+use minidump::Module;
+use minidump_common::utils::{basename, generic_args, glob_match};
+use minidump_unwind::CallStack;
+
+/// Frames that are never part of a signature, because they are how the program died rather
+/// than why.
+const DEFAULT_IRRELEVANT: &[&str] = &[
+    "abort",
+    "raise",
+    "__GI_abort",
+    "__GI_raise",
+    "__pthread_kill*",
+    "pthread_kill",
+    "tgkill",
+    "__abort_with_payload",
+    "_purecall",
+    "RaiseException",
+    "_CxxThrowException",
+    "__cxa_throw",
+    "std::terminate",
+    "KiUserExceptionDispatcher",
+    "NtTerminateProcess",
+    "ZwTerminateProcess",
+    "rust_panic",
+    "__rust_start_panic",
+    "panic_abort::*",
+    "core::panicking::*",
+    "std::panicking::*",
+];
+
+/// Frames that are added to a signature without ending it, because the interesting part is
+/// their caller (allocators and string/memory functions).
+const DEFAULT_PREFIX: &[&str] = &[
+    "malloc",
+    "calloc",
+    "realloc",
+    "free",
+    "memcpy",
+    "memmove",
+    "memset",
+    "memcmp",
+    "strlen",
+    "strcmp",
+    "strcpy",
+    "operator new",
+    "operator new[]",
+    "operator delete",
+    "operator delete[]",
+    "je_*",
+    "RtlAllocateHeap",
+    "RtlFreeHeap",
+    "HeapAlloc",
+    "HeapFree",
+    "alloc::alloc::*",
+    "__rust_alloc*",
+    "__rust_dealloc",
+    "__rust_realloc",
+    "__rdl_*",
+];
+
+/// The most frames a signature has by default.
+const DEFAULT_MAX_FRAMES: usize = 10;
+
+/// Rules for generating crash signatures.
+///
+/// A signature is built from the frames of the crashing thread, innermost first and
+/// including inlined frames. Each frame is named by its function, with template arguments
+/// collapsed to `<T>` and the parameter list removed, or `module@0xoffset` if it has no
+/// symbols. Then:
+///
+/// * frames matching an `irrelevant` pattern are skipped,
+/// * frames matching a `prefix` pattern are added and the signature continues,
+/// * the first other frame is added and ends the signature.
+///
+/// The frames are joined with ` | `. Patterns match the whole frame name, with `*`
+/// matching any run of characters and `?` any single character.
+///
+/// [`SignatureRules::default`] skips abort and exception throwing frames and continues past
+/// allocator and memory functions.
+#[derive(Debug, Clone)]
+pub struct SignatureRules {
+    irrelevant: Vec<String>,
+    prefix: Vec<String>,
+    max_frames: usize,
+}
+
+/// An error loading [`SignatureRules`].
+#[derive(Debug, thiserror::Error)]
+pub enum SignatureRulesError {
+    #[error("couldn't read signature rules: {0}")]
+    ReadError(#[from] std::io::Error),
+    #[error("line {line}: {message}")]
+    ParseError { line: usize, message: String },
+}
+
+impl Default for SignatureRules {
+    fn default() -> Self {
+        SignatureRules {
+            irrelevant: DEFAULT_IRRELEVANT.iter().map(|s| s.to_string()).collect(),
+            prefix: DEFAULT_PREFIX.iter().map(|s| s.to_string()).collect(),
+            max_frames: DEFAULT_MAX_FRAMES,
+        }
+    }
+}
+
+impl SignatureRules {
+    /// Load rules from a file (see [`SignatureRules::parse`]).
+    pub fn from_file(path: &Path) -> Result<Self, SignatureRulesError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse rules, which are added to the default rules.
+    ///
+    /// Each line is one of:
+    ///
+    /// * `irrelevant <pattern>` - skip frames matching the pattern
+    /// * `prefix <pattern>` - continue the signature after frames matching the pattern
+    /// * `max_frames <count>` - the most frames a signature has
+    /// * `clear` - forget the rules so far, including the default ones
+    ///
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn parse(text: &str) -> Result<Self, SignatureRulesError> {
+        let mut rules = SignatureRules::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| SignatureRulesError::ParseError {
+                line: idx + 1,
+                message,
+            };
+            let (directive, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let arg = arg.trim();
+            match (directive, arg) {
+                ("clear", "") => {
+                    rules.irrelevant.clear();
+                    rules.prefix.clear();
+                }
+                ("irrelevant" | "prefix" | "max_frames", "") => {
+                    return Err(error(format!("{directive} needs an argument")))
+                }
+                ("irrelevant", pattern) => rules.irrelevant.push(pattern.to_owned()),
+                ("prefix", pattern) => rules.prefix.push(pattern.to_owned()),
+                ("max_frames", count) => {
+                    rules.max_frames = count
+                        .parse()
+                        .ok()
+                        .filter(|&count| count > 0)
+                        .ok_or_else(|| error(format!("invalid max_frames '{count}'")))?;
+                }
+                _ => return Err(error(format!("unknown rule '{line}'"))),
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Generate the signature of a call stack, if it has any frames.
+    pub fn signature(&self, stack: &CallStack) -> Option<String> {
+        let names = frame_names(stack).map(|(_, name)| normalize_function(&name));
+
+        let mut signature = Vec::new();
+        for name in names {
+            if self.irrelevant.iter().any(|p| glob_match(p, &name)) {
+                continue;
+            }
+            let is_prefix = self.prefix.iter().any(|p| glob_match(p, &name));
+            signature.push(name);
+            if !is_prefix || signature.len() >= self.max_frames {
+                break;
+            }
+        }
+        (!signature.is_empty()).then(|| signature.join(" | "))
+    }
+}
+
+/// The names of the frames of a call stack, innermost first and including inlined frames,
+/// with the index of the frame they're in.
+///
+/// Frames without symbols are named `module@0xoffset`, or `@0xaddress` outside of modules.
+pub(crate) fn frame_names(stack: &CallStack) -> impl Iterator<Item = (usize, String)> + '_ {
+    stack.frames.iter().enumerate().flat_map(|(idx, frame)| {
+        let inlines = frame
+            .inlines
+            .iter()
+            .map(move |inline| (idx, inline.function_name.clone()));
+        let name = match (&frame.function_name, &frame.module) {
+            (Some(function), _) => function.clone(),
+            (None, Some(module)) => format!(
+                "{}@{:#x}",
+                basename(&module.code_file()),
+                frame.instruction - module.base_address()
+            ),
+            (None, None) => format!("@{:#x}", frame.instruction),
+        };
+        inlines.chain(std::iter::once((idx, name)))
+    })
+}
+
+/// Normalize a function name for a signature: template arguments are collapsed to `<T>`,
+/// the parameter list and qualifiers after it are removed, and whitespace is collapsed.
+pub fn normalize_function(name: &str) -> String {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    strip_parameters(&collapse_templates(&name)).to_owned()
+}
+
+/// Replace the template arguments in `name` (see [`generic_args`]) with `T`.
+fn collapse_templates(name: &str) -> String {
+    let Some(args) = generic_args(name) else {
+        // Unbalanced, so leave it alone.
+        return name.to_owned();
+    };
+    let mut output = String::with_capacity(name.len());
+    let mut end = 0;
+    for arg in args {
+        output.push_str(&name[end..arg.start]);
+        output.push_str("<T>");
+        end = arg.end;
+    }
+    output.push_str(&name[end..]);
+    output
+}
+
+/// Remove the parameter list (and any qualifiers after it) from a function name.
+fn strip_parameters(name: &str) -> &str {
+    let mut end = name.trim_end();
+    for qualifier in [" const", " volatile", " &&", " &", "&&", "&"] {
+        end = end.strip_suffix(qualifier).unwrap_or(end).trim_end();
+    }
+    if !end.ends_with(')') {
+        return name.trim_end();
+    }
+    let mut depth = 0;
+    for (idx, c) in end.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' => {
+                depth -= 1;
+                if depth == 0 {
+                    let function = end[..idx].trim_end();
+                    // Don't strip `(anonymous namespace)` and friends down to nothing.
+                    return if function.is_empty() || function.ends_with("::") {
+                        name.trim_end()
+                    } else {
+                        function
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+    name.trim_end()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{frame, stack};
+
+    #[test]
+    fn test_normalize_function() {
+        assert_eq!(normalize_function("main"), "main");
+        assert_eq!(normalize_function("foo(int, char*)"), "foo");
+        assert_eq!(
+            normalize_function("std::vector<int, std::allocator<int> >::push_back(int const&)"),
+            "std::vector<T>::push_back"
+        );
+        assert_eq!(
+            normalize_function("Foo::bar(std::map<int, int>) const"),
+            "Foo::bar"
+        );
+        assert_eq!(
+            normalize_function("(anonymous namespace)::Crash()"),
+            "(anonymous namespace)::Crash"
+        );
+        assert_eq!(
+            normalize_function("std::ostream::operator<<(int)"),
+            "std::ostream::operator<<"
+        );
+        assert_eq!(
+            normalize_function("Foo::operator<=(Foo const&)"),
+            "Foo::operator<="
+        );
+        assert_eq!(
+            normalize_function("<alloc::vec::Vec<u8> as core::ops::drop::Drop>::drop"),
+            "<alloc::vec::Vec<T> as core::ops::drop::Drop>::drop"
+        );
+        assert_eq!(
+            normalize_function("core::ptr::write_volatile::<u8>"),
+            "core::ptr::write_volatile::<T>"
+        );
+        assert_eq!(
+            normalize_function("operator new(unsigned long)"),
+            "operator new"
+        );
+        assert_eq!(normalize_function("broken<thing"), "broken<thing");
+    }
+
+    #[test]
+    fn test_signature() {
+        let rules = SignatureRules::default();
+        let crash = stack(vec![
+            frame(Some("abort"), &[]),
+            frame(Some("malloc(unsigned long)"), &[]),
+            frame(Some("Foo::run()"), &["Foo::helper<int>(int)"]),
+            frame(Some("main"), &[]),
+        ]);
+        assert_eq!(rules.signature(&crash).unwrap(), "malloc | Foo::helper<T>");
+        assert_eq!(
+            rules.signature(&stack(vec![frame(None, &[])])).unwrap(),
+            "@0x1234"
+        );
+        assert_eq!(rules.signature(&stack(vec![])), None);
+
+        let rules = SignatureRules::parse(
+            "# Everything is a prefix\nclear\nprefix *\nirrelevant main\nmax_frames 3\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rules.signature(&crash).unwrap(),
+            "abort | malloc | Foo::helper<T>"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = SignatureRules::parse("prefix foo\nskip bar\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: unknown rule 'skip bar'");
+        let err = SignatureRules::parse("max_frames 0").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: invalid max_frames '0'");
+        let err = SignatureRules::parse("prefix").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: prefix needs an argument");
+    }
+}
@@ -0,0 +1,315 @@
+//! Finding the source code around a frame's source line.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use minidump_unwind::{CallStack, SourceContext};
+
+/// The largest source file read.
+const MAX_SOURCE_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Where to find the source files named by symbol files, for
+/// [`ProcessorOptions::source_files`][crate::ProcessorOptions::source_files].
+///
+/// Symbol files name source files as they were on the build machine, or, for
+/// source-indexed builds like Mozilla's, as version control paths like
+/// `hg:hg.mozilla.org/mozilla-central:dom/base/Element.cpp:a1b2c3`. Both kinds are
+/// looked up in local checkouts:
+///
+/// * [`SourceFiles::remap`][] looks up the files under a path prefix (or in a VCS
+///   repository) in a local directory.
+/// * [`SourceFiles::root`][] looks up relative paths and VCS paths in a local directory.
+///
+/// Symbol files are input like minidumps, so only files inside those directories are read:
+/// paths with `..` components are ignored, and so are files that symbolic links lead out of
+/// the directories. Absolute paths are only tried as they are, after any remapped ones, with
+/// [`SourceFiles::absolute_paths`][].
+#[derive(Debug, Clone)]
+pub struct SourceFiles {
+    remaps: Vec<(String, PathBuf)>,
+    roots: Vec<PathBuf>,
+    absolute_paths: bool,
+    context_lines: u32,
+}
+
+impl SourceFiles {
+    /// Find the source code `context_lines` lines before and after each frame's line.
+    pub fn new(context_lines: u32) -> Self {
+        Self {
+            remaps: vec![],
+            roots: vec![],
+            absolute_paths: false,
+            context_lines,
+        }
+    }
+
+    /// Look up the source files under `prefix` in `dir`.
+    ///
+    /// `prefix` is either a path prefix (e.g. `/builds/worker/checkouts/gecko`), or the
+    /// repository of VCS paths (e.g. `hg.mozilla.org/mozilla-central`).
+    pub fn remap(mut self, prefix: impl Into<String>, dir: impl Into<PathBuf>) -> Self {
+        self.remaps.push((prefix.into(), dir.into()));
+        self
+    }
+
+    /// Look up relative paths and VCS paths in `dir`.
+    pub fn root(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.roots.push(dir.into());
+        self
+    }
+
+    /// Also read files at their absolute paths, wherever they are.
+    ///
+    /// This is only safe with trusted symbol files, as they can then name any file that
+    /// can be read.
+    pub fn absolute_paths(mut self, enabled: bool) -> Self {
+        self.absolute_paths = enabled;
+        self
+    }
+
+    /// Fill in the [`SourceContext`] of every frame (and inlined frame) of `stack`.
+    pub fn fill_source_context(&self, stack: &mut CallStack) {
+        let mut files = HashMap::new();
+        for frame in &mut stack.frames {
+            for inline in &mut frame.inlines {
+                inline.source_context = self.context(
+                    &mut files,
+                    inline.source_file_name.as_deref(),
+                    inline.source_line,
+                );
+            }
+            frame.source_context = self.context(
+                &mut files,
+                frame.source_file_name.as_deref(),
+                frame.source_line,
+            );
+        }
+    }
+
+    fn context(
+        &self,
+        files: &mut HashMap<String, Option<Vec<String>>>,
+        file: Option<&str>,
+        line: Option<u32>,
+    ) -> Option<SourceContext> {
+        let file = file?;
+        // Lines are 1-based, 0 means we don't know.
+        let idx = line?.checked_sub(1)? as usize;
+        let lines = files
+            .entry(file.to_string())
+            .or_insert_with(|| self.read(file))
+            .as_ref()?;
+        if idx >= lines.len() {
+            return None;
+        }
+        let context_lines = self.context_lines as usize;
+        let start = idx.saturating_sub(context_lines);
+        let end = lines.len().min(idx + context_lines + 1);
+        Some(SourceContext {
+            start_line: start as u32 + 1,
+            lines: lines[start..end].to_vec(),
+        })
+    }
+
+    fn read(&self, file: &str) -> Option<Vec<String>> {
+        let contents = self
+            .candidates(file)
+            .into_iter()
+            .find_map(|path| self.read_file(&path))?;
+        Some(
+            String::from_utf8_lossy(&contents)
+                .lines()
+                .map(String::from)
+                .collect(),
+        )
+    }
+
+    /// Read the file at `path` if it's in one of the directories (or absolute paths are
+    /// allowed) and isn't too large.
+    fn read_file(&self, path: &Path) -> Option<Vec<u8>> {
+        let path = fs::canonicalize(path).ok()?;
+        let allowed = self.absolute_paths
+            || self
+                .remaps
+                .iter()
+                .map(|(_, dir)| dir)
+                .chain(&self.roots)
+                .any(|dir| fs::canonicalize(dir).is_ok_and(|dir| path.starts_with(dir)));
+        if !allowed {
+            return None;
+        }
+        let file = File::open(&path).ok()?;
+        if file.metadata().ok()?.len() > MAX_SOURCE_FILE_SIZE {
+            return None;
+        }
+        let mut contents = Vec::new();
+        file.take(MAX_SOURCE_FILE_SIZE + 1)
+            .read_to_end(&mut contents)
+            .ok()?;
+        (contents.len() as u64 <= MAX_SOURCE_FILE_SIZE).then_some(contents)
+    }
+
+    /// The local paths `file` may be found at, in order of preference.
+    fn candidates(&self, file: &str) -> Vec<PathBuf> {
+        let vcs_path = parse_vcs_path(file);
+        let path = vcs_path.map_or(file, |(_, path)| path).replace('\\', "/");
+        if path.split('/').any(|component| component == "..") {
+            return vec![];
+        }
+
+        let mut candidates = vec![];
+        for (prefix, dir) in &self.remaps {
+            let prefix = prefix.replace('\\', "/");
+            let prefix = prefix.trim_end_matches('/');
+            let rest = match vcs_path {
+                Some((repository, _)) => (repository == prefix).then_some(path.as_str()),
+                None => path
+                    .strip_prefix(prefix)
+                    .filter(|rest| rest.is_empty() || rest.starts_with('/')),
+            };
+            if let Some(rest) = rest {
+                candidates.push(dir.join(rest.trim_start_matches('/')));
+            }
+        }
+
+        let absolute = path.starts_with('/') || is_windows_absolute(&path);
+        if vcs_path.is_some() || !absolute {
+            for root in &self.roots {
+                candidates.push(root.join(&path));
+            }
+        }
+        if vcs_path.is_none() && absolute && self.absolute_paths {
+            candidates.push(PathBuf::from(file));
+        }
+        candidates
+    }
+}
+
+/// Splits a source server path, `hg:<repository>:<path>:<revision>` or
+/// `git:<repository>:<path>:<revision>`, into its repository and path.
+fn parse_vcs_path(file: &str) -> Option<(&str, &str)> {
+    let rest = file
+        .strip_prefix("hg:")
+        .or_else(|| file.strip_prefix("git:"))?;
+    let mut parts = rest.splitn(3, ':');
+    let repository = parts.next()?;
+    let path = parts.next()?;
+    let _revision = parts.next()?;
+    Some((repository, path))
+}
+
+/// Whether `path` (with `/` separators) starts with a drive letter, like `c:/src`.
+fn is_windows_absolute(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && &bytes[1..3] == b":/"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vcs_path() {
+        assert_eq!(
+            parse_vcs_path("hg:hg.mozilla.org/mozilla-central:dom/base/Element.cpp:a1b2c3"),
+            Some(("hg.mozilla.org/mozilla-central", "dom/base/Element.cpp"))
+        );
+        assert_eq!(
+            parse_vcs_path("git:github.com/rust-lang/rust:library/core/src/ptr/mod.rs:fdca237"),
+            Some(("github.com/rust-lang/rust", "library/core/src/ptr/mod.rs"))
+        );
+        assert_eq!(parse_vcs_path("hg:incomplete"), None);
+        assert_eq!(parse_vcs_path("/src/main.rs"), None);
+    }
+
+    #[test]
+    fn test_candidates() {
+        let files = SourceFiles::new(3)
+            .remap("/builds/worker/checkouts/gecko/", "/src/gecko")
+            .remap("hg.mozilla.org/mozilla-central", "/src/gecko")
+            .remap(r"c:\build", "/src/windows")
+            .root("/src/root")
+            .absolute_paths(true);
+
+        assert_eq!(
+            files.candidates("/builds/worker/checkouts/gecko/dom/base/Element.cpp"),
+            vec![
+                PathBuf::from("/src/gecko/dom/base/Element.cpp"),
+                PathBuf::from("/builds/worker/checkouts/gecko/dom/base/Element.cpp"),
+            ]
+        );
+        assert_eq!(
+            files.candidates("hg:hg.mozilla.org/mozilla-central:dom/base/Element.cpp:a1b2c3"),
+            vec![
+                PathBuf::from("/src/gecko/dom/base/Element.cpp"),
+                PathBuf::from("/src/root/dom/base/Element.cpp"),
+            ]
+        );
+        assert_eq!(
+            files.candidates(r"c:\build\app\main.cc"),
+            vec![
+                PathBuf::from("/src/windows/app/main.cc"),
+                PathBuf::from(r"c:\build\app\main.cc"),
+            ]
+        );
+        assert_eq!(
+            files.candidates("src/lib.rs"),
+            vec![PathBuf::from("/src/root/src/lib.rs")]
+        );
+        // A prefix only matches whole path components.
+        assert_eq!(
+            files.candidates("/builds/worker/checkouts/gecko-dev/main.cc"),
+            vec![PathBuf::from("/builds/worker/checkouts/gecko-dev/main.cc")]
+        );
+
+        // Paths can't leave the directories.
+        assert!(files.candidates("../../etc/passwd").is_empty());
+        assert!(files
+            .candidates("/builds/worker/checkouts/gecko/../../../etc/passwd")
+            .is_empty());
+        assert!(files
+            .candidates(r"hg:hg.mozilla.org/mozilla-central:..\..\etc\passwd:a1b2c3")
+            .is_empty());
+
+        // Absolute paths are only tried as they are when enabled.
+        let files = files.absolute_paths(false);
+        assert_eq!(
+            files.candidates("/builds/worker/checkouts/gecko/dom/base/Element.cpp"),
+            vec![PathBuf::from("/src/gecko/dom/base/Element.cpp")]
+        );
+        assert!(files.candidates("/etc/passwd").is_empty());
+    }
+
+    #[test]
+    fn test_read_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("main.c"), "int main() {}\n").unwrap();
+        let outside = dir.path().join("secret.txt");
+        fs::write(&outside, "secret\n").unwrap();
+        fs::write(
+            root.join("large.c"),
+            vec![b'\n'; MAX_SOURCE_FILE_SIZE as usize + 1],
+        )
+        .unwrap();
+
+        let files = SourceFiles::new(3).root(&root);
+        assert_eq!(files.read("main.c"), Some(vec!["int main() {}".to_owned()]));
+        assert_eq!(files.read(outside.to_str().unwrap()), None);
+        assert_eq!(files.read("large.c"), None);
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&outside, root.join("link.c")).unwrap();
+            assert_eq!(files.read("link.c"), None);
+        }
+
+        let files = files.absolute_paths(true);
+        assert_eq!(
+            files.read(outside.to_str().unwrap()),
+            Some(vec!["secret".to_owned()])
+        );
+    }
+}
@@ -0,0 +1,38 @@
+//! Builders of the frames and stacks that the unit tests analyze.
+
+use minidump::format::CONTEXT_AMD64;
+use minidump::{MinidumpContext, MinidumpRawContext};
+use minidump_unwind::{CallStack, CallStackInfo, FrameTrust, InlineFrame, StackFrame};
+
+/// An x86-64 frame at `instruction`, found by stack scanning.
+pub(crate) fn frame_at(instruction: u64) -> StackFrame {
+    let context = MinidumpContext::from_raw(MinidumpRawContext::Amd64(CONTEXT_AMD64::default()));
+    let mut frame = StackFrame::from_context(context, FrameTrust::Scan);
+    frame.instruction = instruction;
+    frame
+}
+
+/// A frame in `function` (if it's known), with frames of the `inlines` functions inlined
+/// into it, innermost first.
+pub(crate) fn frame<'a>(function: impl Into<Option<&'a str>>, inlines: &[&str]) -> StackFrame {
+    let mut frame = frame_at(0x1234);
+    frame.function_name = function.into().map(String::from);
+    frame.inlines = inlines
+        .iter()
+        .map(|inline| InlineFrame {
+            function_name: inline.to_string(),
+            raw_function_name: None,
+            source_file_name: None,
+            source_line: None,
+            source_context: None,
+        })
+        .collect();
+    frame
+}
+
+/// The stack of a thread with `frames`, innermost first.
+pub(crate) fn stack(frames: impl IntoIterator<Item = StackFrame>) -> CallStack {
+    let mut stack = CallStack::with_info(1, CallStackInfo::Ok);
+    stack.frames = frames.into_iter().collect();
+    stack
+}
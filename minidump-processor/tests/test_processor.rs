@@ -7,9 +7,10 @@ use minidump::{
     Module,
 };
 use minidump_common::format::MemoryProtection;
-use minidump_processor::{Limit, LinuxStandardBase, ProcessState};
+use minidump_processor::{Limit, LinuxStandardBase, ProcessState, ProcessorOptions};
 use minidump_unwind::{simple_symbol_supplier, CallStackInfo, FrameTrust, Symbolizer};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use minidump_synth::*;
 use test_assembler::*;
@@ -134,6 +135,84 @@ async fn test_processor_symbols() {
     );
 }
 
+#[tokio::test]
+async fn test_processor_unwind_limits() {
+    let dump = read_test_minidump().unwrap();
+    let provider = Symbolizer::new(simple_symbol_supplier(vec![]));
+    let process = |options: ProcessorOptions<'static>| {
+        minidump_processor::process_minidump_with_options(&dump, &provider, options)
+    };
+
+    let mut options = ProcessorOptions::stable_basic();
+    options.max_frames = Some(2);
+    let state = process(options).await.unwrap();
+    assert_eq!(state.threads[0].info, CallStackInfo::FrameLimitReached);
+    assert_eq!(state.threads[0].frames.len(), 2);
+
+    // A limit the stack doesn't reach doesn't truncate it.
+    let mut options = ProcessorOptions::stable_basic();
+    options.max_frames = Some(4);
+    let state = process(options).await.unwrap();
+    assert_eq!(state.threads[0].info, CallStackInfo::Ok);
+    assert_eq!(state.threads[0].frames.len(), 4);
+
+    let mut options = ProcessorOptions::stable_basic();
+    options.total_timeout = Some(Duration::ZERO);
+    let state = process(options).await.unwrap();
+    assert_eq!(state.threads[0].info, CallStackInfo::TimedOut);
+    assert_eq!(state.threads[0].frames.len(), 1);
+
+    // The dump thread is still skipped as such.
+    let mut options = ProcessorOptions::stable_basic();
+    options.crashing_thread_only = true;
+    let state = process(options).await.unwrap();
+    assert_eq!(state.threads[0].info, CallStackInfo::Ok);
+    assert_eq!(state.threads[0].frames.len(), 4);
+    assert_eq!(state.threads[1].info, CallStackInfo::DumpThreadSkipped);
+}
+
+#[tokio::test]
+async fn test_processor_crashing_thread_only() {
+    // Three threads, the second of which crashed.
+    let mut dump = SynthMinidump::with_endian(Endian::Little).add_system_info(
+        SystemInfo::new(Endian::Little).set_processor_architecture(
+            minidump_common::format::ProcessorArchitecture::PROCESSOR_ARCHITECTURE_AMD64 as u16,
+        ),
+    );
+    for id in 1..=3 {
+        let stack_start = id as u64 * 0x10000;
+        let context = minidump_synth::amd64_context(Endian::Little, 0x1000, stack_start);
+        let stack = Memory::with_section(
+            Section::with_endian(Endian::Little).append_repeated(0, 0x100),
+            stack_start,
+        );
+        dump = dump
+            .add_thread(Thread::new(Endian::Little, id, &stack, &context))
+            .add(context)
+            .add_memory(stack);
+    }
+    let mut ex = Exception::new(Endian::Little);
+    ex.thread_id = 2;
+    let dump = Minidump::read(dump.add_exception(ex).finish().unwrap()).unwrap();
+    let provider = Symbolizer::new(simple_symbol_supplier(vec![]));
+
+    let mut options = ProcessorOptions::stable_basic();
+    options.crashing_thread_only = true;
+    let state = minidump_processor::process_minidump_with_options(&dump, &provider, options)
+        .await
+        .unwrap();
+    assert_eq!(state.requesting_thread, Some(1));
+    assert_eq!(state.threads[1].info, CallStackInfo::Ok);
+    // The other threads only get their context frame.
+    for idx in [0, 2] {
+        let thread = &state.threads[idx];
+        assert_eq!(thread.info, CallStackInfo::UnwindSkipped);
+        assert_eq!(thread.frames.len(), 1);
+        assert_eq!(thread.frames[0].trust, FrameTrust::Context);
+        assert_eq!(thread.frames[0].instruction, 0x1000);
+    }
+}
+
 fn minimal_minidump() -> SynthMinidump {
     let context = minidump_synth::x86_context(Endian::Little, 0xabcd1234, 0x1010);
     let stack = Memory::with_section(
@@ -508,3 +587,102 @@ async fn test_guard_pages() {
     assert_eq!(access_list.accesses[0].address_info.address, 0x81000);
     assert!(access_list.accesses[0].address_info.is_likely_guard_page);
 }
+
+/// A dump of a thread whose stack holds `return_addresses`, crashing on an access to the
+/// guard page below its stack.
+fn stack_overflow_minidump(return_addresses: impl Iterator<Item = u64>) -> SynthMinidump {
+    let context = minidump_synth::amd64_context(Endian::Little, 0x2000, 0x82000);
+
+    // The bytes here are the opcode `mov al, [rsp - 0x1000]`, which touches the guard page.
+    let code = Memory::with_section(
+        Section::with_endian(Endian::Little)
+            .append_bytes(&[0x8a, 0x84, 0x24, 0x00, 0xf0, 0xff, 0xff]),
+        0x2000,
+    );
+    // The code isn't in a module, but it's executable, so scanning accepts return addresses
+    // into it.
+    let code_info = MemoryInfo::new(
+        Endian::Little,
+        0x2000,
+        0x2000,
+        0,
+        4096,
+        0,
+        MemoryProtection::PAGE_EXECUTE_READ.bits(),
+        0,
+    );
+    let stack = Memory::with_section(
+        return_addresses.fold(Section::with_endian(Endian::Little), |section, address| {
+            section.D64(address)
+        }),
+        0x82000,
+    );
+    let guard_page_info = MemoryInfo::new(
+        Endian::Little,
+        0x81000,
+        0x81000,
+        0,
+        4096,
+        0,
+        MemoryProtection::PAGE_NOACCESS.bits(),
+        0,
+    );
+    let stack_info = MemoryInfo::new(
+        Endian::Little,
+        0x82000,
+        0x82000,
+        0,
+        4096,
+        0,
+        MemoryProtection::PAGE_READWRITE.bits(),
+        0,
+    );
+
+    let thread = Thread::new(Endian::Little, 1, &stack, &context);
+    let system_info = SystemInfo::new(Endian::Little).set_processor_architecture(
+        minidump_common::format::ProcessorArchitecture::PROCESSOR_ARCHITECTURE_AMD64 as u16,
+    );
+
+    let context_label = context.file_offset();
+    let context_size = context.file_size();
+
+    let dump = SynthMinidump::with_endian(Endian::Little).add(context);
+
+    let mut ex = Exception::new(Endian::Little);
+    ex.thread_id = 1;
+    ex.exception_record.exception_address = 0x81000;
+    ex.thread_context = (
+        context_size.value().unwrap() as u32,
+        context_label.value().unwrap() as u32,
+    );
+
+    dump.add_thread(thread)
+        .add_exception(ex)
+        .add_system_info(system_info)
+        .add_memory(code)
+        .add_memory(stack)
+        .add_memory_info(code_info)
+        .add_memory_info(guard_page_info)
+        .add_memory_info(stack_info)
+}
+
+#[tokio::test]
+async fn test_probable_stack_overflow() {
+    // The same call over and over, then its caller.
+    let recursive = std::iter::repeat_n(0x2100, 20).chain([0x2200]);
+    let state = read_synth_dump(stack_overflow_minidump(recursive)).await;
+    assert!(state.threads[0].frames.len() > 20);
+    let info = state.exception_info.expect("missing exception info");
+    let access_list = info.memory_access_list.expect("no memory accesses");
+    assert!(access_list.accesses[0].address_info.is_likely_guard_page);
+    assert!(info.probable_stack_overflow);
+
+    // Hitting a guard page without recursion isn't enough.
+    let distinct = (0..20).map(|idx| 0x2100 + idx * 0x10);
+    let state = read_synth_dump(stack_overflow_minidump(distinct)).await;
+    assert!(state.threads[0].frames.len() > 20);
+    let info = state.exception_info.expect("missing exception info");
+    let access_list = info.memory_access_list.expect("no memory accesses");
+    assert!(access_list.accesses[0].address_info.is_likely_guard_page);
+    assert!(!info.probable_stack_overflow);
+}
@@ -0,0 +1,272 @@
+//! Processing many minidumps in one run (`--batch`).
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures_util::stream::{self, StreamExt};
+use minidump::Minidump;
+use minidump_common::utils::glob_match;
+use minidump_processor::ProcessorOptions;
+use serde_json::json;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use crate::SharedSymbols;
+
+/// Everything the minidumps of a batch share.
+pub struct Batch {
+    pub symbols: SharedSymbols,
+    pub options: ProcessorOptions<'static>,
+}
+
+/// Why a minidump in a batch couldn't be processed.
+struct BatchError {
+    /// The `ERROR_*` status for the output.
+    status: String,
+    message: String,
+}
+
+impl BatchError {
+    fn new(name: &str, message: impl ToString) -> Self {
+        BatchError {
+            status: format!("ERROR_{name}"),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Batch {
+    /// Process the minidumps listed by `source` (see `--batch`), `jobs` at a time, and write
+    /// the results to `output`.
+    pub async fn run(self, source: &str, jobs: usize, output: Option<&Path>) -> io::Result<()> {
+        let paths = list_minidumps(source)?;
+        let mut output = BatchOutput::new(output)?;
+        let batch = Arc::new(self);
+
+        let mut results = stream::iter(paths)
+            .map(|path| {
+                let batch = batch.clone();
+                let task_path = path.clone();
+                let task = tokio::spawn(async move { batch.process(&task_path).await });
+                async move { (path, task_result(task).await) }
+            })
+            .buffered(jobs.max(1));
+
+        let mut processed = 0;
+        let mut failed = 0;
+        while let Some((path, result)) = results.next().await {
+            processed += 1;
+            if let Err(e) = &result {
+                failed += 1;
+                error!(
+                    "{} - Error processing {}: {}",
+                    e.status,
+                    path.display(),
+                    e.message
+                );
+            }
+            output.write(&path, result)?;
+        }
+        output.finish()?;
+        info!("processed {processed} minidumps, {failed} failed");
+        Ok(())
+    }
+
+    /// Process one minidump, returning its JSON report.
+    async fn process(&self, path: &Path) -> Result<Vec<u8>, BatchError> {
+        let dump = Minidump::read_path(path).map_err(|e| BatchError::new(e.name(), &e))?;
+        let provider = self
+            .symbols
+            .provider(&dump)
+            .await
+            .map_err(|e| BatchError::new(e.name(), &e))?;
+
+        let state = minidump_processor::process_minidump_with_options(
+            &dump,
+            &provider,
+            self.options.clone(),
+        )
+        .await
+        .map_err(|e| BatchError::new(e.name(), &e))?;
+
+        let mut report = Vec::new();
+        state
+            .print_json(&mut report, false)
+            .map_err(|e| BatchError::new("JsonError", e))?;
+        Ok(report)
+    }
+}
+
+/// Wait for the task processing a minidump, turning a panic into an error for its report.
+async fn task_result(task: JoinHandle<Result<Vec<u8>, BatchError>>) -> Result<Vec<u8>, BatchError> {
+    task.await
+        .unwrap_or_else(|e| Err(BatchError::new("PanicError", e)))
+}
+
+/// Where the reports of a batch go.
+enum BatchOutput {
+    /// One line per minidump.
+    Lines(BufWriter<Box<dyn Write>>),
+    /// One file per minidump in this directory, with the names used so far.
+    Directory(PathBuf, HashSet<OsString>),
+}
+
+impl BatchOutput {
+    fn new(path: Option<&Path>) -> io::Result<Self> {
+        Ok(match path {
+            Some(path) if path.is_dir() => BatchOutput::Directory(path.to_owned(), HashSet::new()),
+            Some(path) => BatchOutput::Lines(BufWriter::new(Box::new(File::create(path)?))),
+            None => BatchOutput::Lines(BufWriter::new(Box::new(io::stdout()))),
+        })
+    }
+
+    fn write(&mut self, path: &Path, result: Result<Vec<u8>, BatchError>) -> io::Result<()> {
+        let minidump_path = json!(path.display().to_string());
+        let report = match result {
+            // Add the path to the report by splicing it in as the first field.
+            Ok(report) => {
+                let mut spliced = format!("{{\"minidump_path\":{minidump_path},").into_bytes();
+                spliced.extend_from_slice(report.get(1..).unwrap_or_default());
+                spliced
+            }
+            Err(e) => json!({
+                "minidump_path": minidump_path,
+                "status": e.status,
+                "error": e.message,
+            })
+            .to_string()
+            .into_bytes(),
+        };
+        match self {
+            BatchOutput::Lines(out) => {
+                out.write_all(&report)?;
+                out.write_all(b"\n")
+            }
+            BatchOutput::Directory(dir, used) => {
+                // Dumps with the same name from different directories get numbered files.
+                let name = path.file_name().unwrap_or_default();
+                let mut file_name = name.to_owned();
+                file_name.push(".json");
+                let mut n = 1;
+                while !used.insert(file_name.clone()) {
+                    n += 1;
+                    file_name = name.to_owned();
+                    file_name.push(format!(".{n}.json"));
+                }
+                fs::write(dir.join(file_name), report)
+            }
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            BatchOutput::Lines(mut out) => out.flush(),
+            BatchOutput::Directory(..) => Ok(()),
+        }
+    }
+}
+
+/// List the minidumps to process for a `--batch` argument.
+fn list_minidumps(source: &str) -> io::Result<Vec<PathBuf>> {
+    let paths = if source == "-" {
+        let mut paths = Vec::new();
+        for line in io::stdin().lock().lines() {
+            let line = line?;
+            let line = line.trim();
+            if !line.is_empty() {
+                paths.push(PathBuf::from(line));
+            }
+        }
+        paths
+    } else if Path::new(source).is_dir() {
+        files_in(Path::new(source), |_| true)?
+    } else {
+        let path = Path::new(source);
+        let (dir, pattern) = match (path.parent(), path.file_name().and_then(|f| f.to_str())) {
+            (Some(dir), Some(pattern)) if pattern.contains(['*', '?']) => (dir, pattern),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("'{source}' is not a directory or a pattern matching files"),
+                ))
+            }
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        files_in(dir, |name| glob_match(pattern, name))?
+    };
+    if paths.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no minidumps found for '{source}'"),
+        ));
+    }
+    Ok(paths)
+}
+
+/// List the files in `dir` whose names match `filter`, sorted.
+fn files_in(dir: &Path, filter: impl Fn(&str) -> bool) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let matches = entry.file_name().to_str().is_some_and(&filter);
+        if matches && entry.file_type()?.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_task_panic() {
+        let ok = tokio::spawn(async { Ok(b"{}".to_vec()) });
+        assert_eq!(task_result(ok).await.ok(), Some(b"{}".to_vec()));
+
+        let panicked = tokio::spawn(async { panic!("unwinding went wrong") });
+        let e = task_result(panicked).await.unwrap_err();
+        assert_eq!(e.status, "ERROR_PanicError");
+        assert!(e.message.contains("unwinding went wrong"), "{}", e.message);
+    }
+
+    #[test]
+    fn test_directory_output_collisions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut output = BatchOutput::new(Some(dir.path())).unwrap();
+        for path in ["a/crash.dmp", "b/crash.dmp", "c/crash.dmp", "other.dmp"] {
+            output
+                .write(Path::new(path), Ok(br#"{"status":"OK"}"#.to_vec()))
+                .unwrap();
+        }
+        output.finish().unwrap();
+
+        let report = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(
+            report("crash.dmp.json"),
+            r#"{"minidump_path":"a/crash.dmp","status":"OK"}"#
+        );
+        assert_eq!(
+            report("crash.dmp.2.json"),
+            r#"{"minidump_path":"b/crash.dmp","status":"OK"}"#
+        );
+        assert_eq!(
+            report("crash.dmp.3.json"),
+            r#"{"minidump_path":"c/crash.dmp","status":"OK"}"#
+        );
+        assert_eq!(
+            report("other.dmp.json"),
+            r#"{"minidump_path":"other.dmp","status":"OK"}"#
+        );
+    }
+}
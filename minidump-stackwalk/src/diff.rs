@@ -0,0 +1,563 @@
+//! Comparing the crashes in two minidumps (`--diff`).
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::path::Path;
+
+use minidump::{Minidump, Module};
+use minidump_common::utils::basename;
+use minidump_processor::{ProcessState, ProcessorOptions};
+use serde_json::json;
+
+use crate::SharedSymbols;
+
+/// A value that differs between the two minidumps, `None` if it is missing in one of them.
+struct Change {
+    name: String,
+    old: Option<String>,
+    new: Option<String>,
+}
+
+impl Change {
+    /// A change of `name`, if `old` and `new` differ.
+    fn compare(name: impl Into<String>, old: Option<String>, new: Option<String>) -> Option<Self> {
+        (old != new).then(|| Change {
+            name: name.into(),
+            old,
+            new,
+        })
+    }
+
+    fn json(&self) -> serde_json::Value {
+        json!({
+            "name": self.name,
+            "old": self.old,
+            "new": self.new,
+        })
+    }
+
+    fn print(&self, f: &mut impl Write) -> io::Result<()> {
+        let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "<none>".into());
+        writeln!(
+            f,
+            "  {}: {} -> {}",
+            self.name,
+            show(&self.old),
+            show(&self.new)
+        )
+    }
+}
+
+/// A frame of the crashing thread, as compared.
+#[derive(Debug, Clone, PartialEq)]
+struct Frame {
+    /// What frames are aligned by: `module!function`, or `module+offset` without symbols.
+    ///
+    /// Both are independent of where the module was loaded.
+    key: String,
+    /// Where in the function the frame is, like `+0x1c [file.cpp : 12]`.
+    location: String,
+}
+
+impl Frame {
+    fn json(&self) -> serde_json::Value {
+        json!({
+            "frame": self.key,
+            "location": (!self.location.is_empty()).then_some(&self.location),
+        })
+    }
+}
+
+/// How a frame of the crashing thread differs.
+enum FrameDiff {
+    Same(Frame),
+    /// The same function, but somewhere else in it.
+    Moved(Frame, Frame),
+    /// Only in the old minidump.
+    Removed(Frame),
+    /// Only in the new minidump.
+    Added(Frame),
+}
+
+/// The differences between two processed minidumps.
+pub struct DumpDiff {
+    old_path: String,
+    new_path: String,
+    crash: Vec<Change>,
+    /// The crashing threads of both minidumps, aligned.
+    frames: Vec<FrameDiff>,
+    modules: Vec<Change>,
+    system_info: Vec<Change>,
+}
+
+/// Process a minidump for comparison.
+async fn process(
+    symbols: &SharedSymbols,
+    options: ProcessorOptions<'_>,
+    path: &Path,
+) -> io::Result<ProcessState> {
+    let error = |name: &str, err: &dyn std::fmt::Display| {
+        io::Error::new(
+            io::ErrorKind::Other,
+            format!("{name} - Error processing {}: {err}", path.display()),
+        )
+    };
+    let dump = Minidump::read_path(path).map_err(|e| error(e.name(), &e))?;
+    let provider = symbols
+        .provider(&dump)
+        .await
+        .map_err(|e| error(e.name(), &e))?;
+    minidump_processor::process_minidump_with_options(&dump, &provider, options)
+        .await
+        .map_err(|e| error(e.name(), &e))
+}
+
+impl DumpDiff {
+    /// Process both minidumps and compare them.
+    pub async fn new(
+        symbols: &SharedSymbols,
+        options: ProcessorOptions<'_>,
+        old_path: &Path,
+        new_path: &Path,
+    ) -> io::Result<Self> {
+        let old = process(symbols, options.clone(), old_path).await?;
+        let new = process(symbols, options, new_path).await?;
+        Ok(DumpDiff {
+            old_path: old_path.display().to_string(),
+            new_path: new_path.display().to_string(),
+            crash: crash_changes(&old, &new),
+            frames: align_frames(&crashing_frames(&old), &crashing_frames(&new)),
+            modules: module_changes(&old, &new),
+            system_info: system_info_changes(&old, &new),
+        })
+    }
+
+    pub fn print(&self, f: &mut impl Write) -> io::Result<()> {
+        writeln!(f, "--- {}", self.old_path)?;
+        writeln!(f, "+++ {}", self.new_path)?;
+        print_changes(f, "Crash", &self.crash)?;
+        self.print_frames(f)?;
+        print_changes(f, "Modules", &self.modules)?;
+        print_changes(f, "System info", &self.system_info)
+    }
+
+    fn print_frames(&self, f: &mut impl Write) -> io::Result<()> {
+        writeln!(f)?;
+        writeln!(f, "Crashing thread:")?;
+        if self.frames.is_empty() {
+            writeln!(f, "  <no frames>")?;
+        }
+        let line = |frame: &Frame| format!("{} {}", frame.key, frame.location);
+        for frame in &self.frames {
+            let text = match frame {
+                FrameDiff::Same(frame) => format!("    {}", line(frame)),
+                FrameDiff::Moved(old, new) => {
+                    format!("  ~ {} {} -> {}", old.key, old.location, new.location)
+                }
+                FrameDiff::Removed(frame) => format!("  - {}", line(frame)),
+                FrameDiff::Added(frame) => format!("  + {}", line(frame)),
+            };
+            writeln!(f, "{}", text.trim_end())?;
+        }
+        Ok(())
+    }
+
+    pub fn print_json(&self, f: &mut impl Write, pretty: bool) -> io::Result<()> {
+        let changes = |changes: &[Change]| changes.iter().map(Change::json).collect::<Vec<_>>();
+        let frames = self
+            .frames
+            .iter()
+            .map(|frame| match frame {
+                FrameDiff::Same(frame) => json!({
+                    "status": "same",
+                    "old": frame.json(),
+                    "new": frame.json(),
+                }),
+                FrameDiff::Moved(old, new) => json!({
+                    "status": "moved",
+                    "old": old.json(),
+                    "new": new.json(),
+                }),
+                FrameDiff::Removed(frame) => json!({
+                    "status": "removed",
+                    "old": frame.json(),
+                    "new": null,
+                }),
+                FrameDiff::Added(frame) => json!({
+                    "status": "added",
+                    "old": null,
+                    "new": frame.json(),
+                }),
+            })
+            .collect::<Vec<_>>();
+        let output = json!({
+            "old": self.old_path,
+            "new": self.new_path,
+            "crash": changes(&self.crash),
+            "crashing_thread": frames,
+            "modules": changes(&self.modules),
+            "system_info": changes(&self.system_info),
+        });
+        if pretty {
+            serde_json::to_writer_pretty(&mut *f, &output)?;
+        } else {
+            serde_json::to_writer(&mut *f, &output)?;
+        }
+        writeln!(f)
+    }
+}
+
+fn print_changes(f: &mut impl Write, title: &str, changes: &[Change]) -> io::Result<()> {
+    writeln!(f)?;
+    writeln!(f, "{title}:")?;
+    if changes.is_empty() {
+        writeln!(f, "  (no differences)")?;
+    }
+    for change in changes {
+        change.print(f)?;
+    }
+    Ok(())
+}
+
+fn crash_changes(old: &ProcessState, new: &ProcessState) -> Vec<Change> {
+    let reason = |state: &ProcessState| state.exception_info.as_ref().map(|e| e.reason.to_string());
+    let address =
+        |state: &ProcessState| state.exception_info.as_ref().map(|e| e.address.to_string());
+    let thread = |state: &ProcessState| state.requesting_thread.map(|i| i.to_string());
+    vec![
+        Change::compare("reason", reason(old), reason(new)),
+        Change::compare("address", address(old), address(new)),
+        Change::compare("crashing_thread", thread(old), thread(new)),
+        Change::compare("signature", old.signature.clone(), new.signature.clone()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn system_info_changes(old: &ProcessState, new: &ProcessState) -> Vec<Change> {
+    let (old, new) = (&old.system_info, &new.system_info);
+    vec![
+        Change::compare(
+            "os",
+            Some(old.os.long_name().into()),
+            Some(new.os.long_name().into()),
+        ),
+        Change::compare(
+            "os_ver",
+            old.format_os_version().map(String::from),
+            new.format_os_version().map(String::from),
+        ),
+        Change::compare(
+            "cpu_arch",
+            Some(old.cpu.to_string()),
+            Some(new.cpu.to_string()),
+        ),
+        Change::compare("cpu_info", old.cpu_info.clone(), new.cpu_info.clone()),
+        Change::compare(
+            "cpu_count",
+            Some(old.cpu_count.to_string()),
+            Some(new.cpu_count.to_string()),
+        ),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Compare the version and debug id of the modules, by file name.
+fn module_changes(old: &ProcessState, new: &ProcessState) -> Vec<Change> {
+    fn modules(state: &ProcessState) -> BTreeMap<String, String> {
+        state
+            .modules
+            .iter()
+            .map(|module| {
+                let version = module.version().unwrap_or_else(|| "<no version>".into());
+                let debug_id = module
+                    .debug_identifier()
+                    .map(|id| id.breakpad().to_string())
+                    .unwrap_or_else(|| "<no debug id>".into());
+                (
+                    basename(&module.code_file()).to_string(),
+                    format!("{version} ({debug_id})"),
+                )
+            })
+            .collect()
+    }
+    let (mut old, mut new) = (modules(old), modules(new));
+    let names: std::collections::BTreeSet<String> = old.keys().chain(new.keys()).cloned().collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let (old, new) = (old.remove(&name), new.remove(&name));
+            Change::compare(name, old, new)
+        })
+        .collect()
+}
+
+/// The frames of the requesting thread (including inlined ones), innermost first.
+fn crashing_frames(state: &ProcessState) -> Vec<Frame> {
+    let Some(thread) = state.requesting_thread.map(|i| &state.threads[i]) else {
+        return Vec::new();
+    };
+    let source = |file: &Option<String>, line: Option<u32>| match (file, line) {
+        (Some(file), Some(line)) => format!(" [{} : {line}]", basename(file)),
+        _ => String::new(),
+    };
+    let mut frames = Vec::new();
+    for frame in &thread.frames {
+        let module = frame
+            .module
+            .as_ref()
+            .map(|module| basename(&module.code_file()).to_string());
+        let module_name = module.as_deref().unwrap_or_default();
+        for inline in &frame.inlines {
+            frames.push(Frame {
+                key: format!("{module_name}!{}", inline.function_name),
+                location: source(&inline.source_file_name, inline.source_line)
+                    .trim_start()
+                    .to_owned(),
+            });
+        }
+        let (key, location) = match (&frame.function_name, &frame.module) {
+            (Some(function), _) => {
+                let offset = frame.instruction - frame.function_base.unwrap_or(frame.instruction);
+                (
+                    format!("{module_name}!{function}"),
+                    format!(
+                        "+{offset:#x}{}",
+                        source(&frame.source_file_name, frame.source_line)
+                    ),
+                )
+            }
+            (None, Some(module)) => (
+                format!(
+                    "{module_name}+{:#x}",
+                    frame.instruction - module.base_address()
+                ),
+                String::new(),
+            ),
+            (None, None) => (format!("{:#x}", frame.instruction), String::new()),
+        };
+        frames.push(Frame { key, location });
+    }
+    frames
+}
+
+/// Align two lists of frames by their keys, using their longest common subsequence.
+///
+/// The frames both stacks start and end with are matched up front, which handles most of
+/// the deep (e.g. recursive) stacks cheaply. The rest is aligned with Hirschberg's algorithm,
+/// which only needs memory linear in the number of frames.
+fn align_frames(old: &[Frame], new: &[Frame]) -> Vec<FrameDiff> {
+    // Compare numbers rather than strings.
+    let mut ids = HashMap::new();
+    let mut id = |key| {
+        let next = ids.len();
+        *ids.entry(key).or_insert(next)
+    };
+    let old_ids: Vec<usize> = old.iter().map(|frame| id(frame.key.as_str())).collect();
+    let new_ids: Vec<usize> = new.iter().map(|frame| id(frame.key.as_str())).collect();
+
+    let prefix = old_ids
+        .iter()
+        .zip(&new_ids)
+        .take_while(|(o, n)| o == n)
+        .count();
+    let suffix = old_ids[prefix..]
+        .iter()
+        .rev()
+        .zip(new_ids[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+
+    let mut steps: Vec<Step> = (0..prefix).map(|i| Step::Both(i, i)).collect();
+    align(
+        &old_ids[prefix..old_end],
+        &new_ids[prefix..new_end],
+        (prefix, prefix),
+        &mut steps,
+    );
+    steps.extend((0..suffix).map(|k| Step::Both(old_end + k, new_end + k)));
+
+    steps
+        .into_iter()
+        .map(|step| match step {
+            Step::Both(i, j) if old[i] == new[j] => FrameDiff::Same(old[i].clone()),
+            Step::Both(i, j) => FrameDiff::Moved(old[i].clone(), new[j].clone()),
+            Step::Old(i) => FrameDiff::Removed(old[i].clone()),
+            Step::New(j) => FrameDiff::Added(new[j].clone()),
+        })
+        .collect()
+}
+
+/// A step of an alignment, with the indices of the frames in the old and new stacks.
+enum Step {
+    Both(usize, usize),
+    Old(usize),
+    New(usize),
+}
+
+/// Align `old` and `new` (which start at `offsets` in the whole stacks) with Hirschberg's
+/// algorithm, appending the steps to `steps`.
+fn align(old: &[usize], new: &[usize], offsets: (usize, usize), steps: &mut Vec<Step>) {
+    let (i0, j0) = offsets;
+    match old {
+        [] => steps.extend((j0..j0 + new.len()).map(Step::New)),
+        _ if new.is_empty() => steps.extend((i0..i0 + old.len()).map(Step::Old)),
+        [key] => {
+            let found = new.iter().position(|k| k == key);
+            let matched = found.unwrap_or(new.len());
+            steps.extend((j0..j0 + matched).map(Step::New));
+            match found {
+                Some(j) => steps.push(Step::Both(i0, j0 + j)),
+                None => steps.push(Step::Old(i0)),
+            }
+            steps.extend((j0 + matched + 1..j0 + new.len()).map(Step::New));
+        }
+        _ => {
+            // Split `new` where the halves of `old` have the longest common subsequences.
+            let mid = old.len() / 2;
+            let forward = lcs_lengths(old[..mid].iter(), new.iter());
+            let backward = lcs_lengths(old[mid..].iter().rev(), new.iter().rev());
+            let split = (0..=new.len())
+                .max_by_key(|&k| (forward[k] + backward[new.len() - k], Reverse(k)))
+                .unwrap_or_default();
+            align(&old[..mid], &new[..split], (i0, j0), steps);
+            align(&old[mid..], &new[split..], (i0 + mid, j0 + split), steps);
+        }
+    }
+}
+
+/// The lengths of the longest common subsequences of `old` and every prefix of `new`.
+fn lcs_lengths<'a>(
+    old: impl Iterator<Item = &'a usize>,
+    new: impl Iterator<Item = &'a usize> + Clone,
+) -> Vec<usize> {
+    let mut row = vec![0; new.clone().count() + 1];
+    for key in old {
+        // row[j] is still the length for the previous element of `old` until it's updated.
+        let mut diagonal = 0;
+        for (j, new_key) in new.clone().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if key == new_key {
+                diagonal + 1
+            } else {
+                above.max(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(key: &str, location: &str) -> Frame {
+        Frame {
+            key: key.into(),
+            location: location.into(),
+        }
+    }
+
+    #[test]
+    fn test_align_frames() {
+        let old = [
+            frame("app!crash", "+0x10"),
+            frame("app!helper", "+0x4"),
+            frame("app!main", "+0x20"),
+        ];
+        let new = [
+            frame("app!crash", "+0x14"),
+            frame("app!new_helper", "+0x8"),
+            frame("app!main", "+0x20"),
+        ];
+        let diff = align_frames(&old, &new);
+        assert_eq!(diff.len(), 4);
+        assert!(
+            matches!(&diff[0], FrameDiff::Moved(o, n) if o.location == "+0x10" && n.location == "+0x14")
+        );
+        assert!(matches!(&diff[1], FrameDiff::Added(f) if f.key == "app!new_helper"));
+        assert!(matches!(&diff[2], FrameDiff::Removed(f) if f.key == "app!helper"));
+        assert!(matches!(&diff[3], FrameDiff::Same(f) if f.key == "app!main"));
+
+        assert!(align_frames(&[], &[]).is_empty());
+        assert!(matches!(
+            align_frames(&old[..1], &[])[..],
+            [FrameDiff::Removed(_)]
+        ));
+    }
+
+    /// The keys of an alignment, prefixed like in the human output.
+    fn keys(diff: &[FrameDiff]) -> Vec<String> {
+        diff.iter()
+            .map(|frame| match frame {
+                FrameDiff::Same(f) => format!(" {}", f.key),
+                FrameDiff::Moved(f, _) => format!("~{}", f.key),
+                FrameDiff::Removed(f) => format!("-{}", f.key),
+                FrameDiff::Added(f) => format!("+{}", f.key),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_align_frames_lcs() {
+        let frames = |keys: &str| -> Vec<Frame> {
+            keys.chars().map(|c| frame(&c.to_string(), "")).collect()
+        };
+        let diff = align_frames(&frames("xabcbdab"), &frames("ybdcaba"));
+        let common: String = diff
+            .iter()
+            .filter_map(|frame| match frame {
+                FrameDiff::Same(f) => Some(f.key.as_str()),
+                _ => None,
+            })
+            .collect();
+        // The longest common subsequence, every other frame is added or removed once.
+        assert_eq!(common.len(), 4);
+        assert_eq!(diff.len(), 8 + 7 - 4);
+    }
+
+    #[test]
+    fn test_align_large_stacks() {
+        // Two stack overflows 40000 frames deep, recursing through different functions in
+        // the middle of their stacks.
+        let stack = |middle: &str| -> Vec<Frame> {
+            let mut frames = vec![frame("app!crash", "+0x10")];
+            frames.extend((0..20_000).map(|_| frame("app!recurse", "+0x8")));
+            frames.extend((0..2_000).map(|i| frame(&format!("app!{middle}{}", i % 7), "")));
+            frames.extend((0..18_000).map(|_| frame("app!recurse", "+0x8")));
+            frames.push(frame("app!main", "+0x20"));
+            frames
+        };
+        let old = stack("a");
+        let new = stack("b");
+        let diff = align_frames(&old, &new);
+        assert_eq!(diff.len(), 2 + 38_000 + 2 * 2_000);
+        let keys = keys(&diff);
+        assert_eq!(keys[0], " app!crash");
+        assert_eq!(keys[1], " app!recurse");
+        assert_eq!(keys.iter().filter(|k| k.starts_with('-')).count(), 2_000);
+        assert_eq!(keys.iter().filter(|k| k.starts_with('+')).count(), 2_000);
+        assert_eq!(keys.last().unwrap(), " app!main");
+
+        // A deeper overflow of the same recursion.
+        let deeper: Vec<Frame> = old[..30_000]
+            .iter()
+            .cloned()
+            .chain(old[20_000..].iter().cloned())
+            .collect();
+        let diff = align_frames(&old, &deeper);
+        assert_eq!(diff.len(), deeper.len());
+        assert_eq!(
+            diff.iter()
+                .filter(|frame| matches!(frame, FrameDiff::Added(_)))
+                .count(),
+            10_000
+        );
+    }
+}
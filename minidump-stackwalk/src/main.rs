@@ -5,27 +5,37 @@ use std::fs::File;
 use std::io::Write;
 use std::ops::Deref;
 use std::panic;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use minidump::*;
 use minidump_processor::{
-    PendingProcessorStatSubscriptions, PendingProcessorStats, ProcessorOptions,
+    LinuxProcStatus, PendingProcessorStatSubscriptions, PendingProcessorStats, ProcessorOptions,
+    SignatureRules, SourceFiles,
 };
 use minidump_unwind::{
-    debuginfo::DebugInfoSymbolProvider, http_symbol_supplier, simple_symbol_supplier,
-    MultiSymbolProvider, SymbolProvider, Symbolizer,
+    debuginfo::{
+        BinaryUnwindSymbolProvider, DebugInfoSymbolProvider, DumpImageSymbolProvider,
+        PeUnwindSymbolProvider,
+    },
+    DemangleOptions, HttpSymbolSupplier, MultiSymbolProvider, PerfMapSymbolProvider, Severity,
+    SimpleSymbolSupplier, SymbolFile, SymbolLayout, SymbolProvider, Symbolizer,
 };
 
 use clap::{
     builder::{PossibleValuesParser, TypedValueParser},
     ArgGroup, CommandFactory, Parser,
 };
-use tracing::error;
 use tracing::level_filters::LevelFilter;
+use tracing::{error, warn};
+
+mod batch;
+mod diff;
+mod serve;
 
 /// Analyzes minidumps and produces a report (either human-readable or JSON)
 ///
@@ -60,7 +70,14 @@ use tracing::level_filters::LevelFilter;
     "json",
     "human",
     "cyborg",
+    "sarif",
+    "markdown",
+    "tsv",
+    "machine_readable",
     "dump",
+    "validate_symbols",
+    "batch",
+    "serve",
     "help_markdown",
 ])))]
 #[clap(override_usage("minidump-stackwalk [FLAGS] [OPTIONS] <minidump> [--] [symbols-path]..."))]
@@ -93,6 +110,39 @@ struct Cli {
     #[arg(long)]
     cyborg: Option<PathBuf>,
 
+    /// Emit a SARIF 2.1.0 log, for code scanning tools
+    ///
+    /// The crash is reported as a single result, located at the crashing thread's
+    /// innermost frame with source information, and includes the crashing thread's stack.
+    /// The crash signature is used as a fingerprint.
+    ///
+    /// Can be pretty-printed with --pretty
+    #[arg(long)]
+    sarif: bool,
+
+    /// Emit a Markdown summary, for tickets
+    ///
+    /// This has the crash, system info, a table of the crashing thread's frames and the
+    /// loaded modules.
+    #[arg(long)]
+    markdown: bool,
+
+    /// Emit tab-separated records, like Breakpad's `minidump_stackwalk -m`
+    ///
+    /// The records are `OS`, `CPU`, `Crash` (or `Assert` or `No crash`) and one `Module`
+    /// for each loaded module, followed by an empty line and one line for each frame:
+    /// `thread`, `frame`, `module`, `function`, `file`, `line`, `offset`. The crashing
+    /// thread comes first.
+    #[arg(long)]
+    tsv: bool,
+
+    /// Emit Breakpad's pipe-separated machine-readable output
+    ///
+    /// This is the output of Breakpad's `minidump_stackwalk -m`, for tools that parse it.
+    /// It has the same records as --tsv, separated by `|`.
+    #[arg(short, long)]
+    machine_readable: bool,
+
     /// Dump the 'raw' contents of the minidump
     ///
     /// This is an implementation of the functionality of the old minidump_dump tool.
@@ -104,6 +154,91 @@ struct Cli {
     #[arg(long)]
     dump: bool,
 
+    /// Check a breakpad symbol file for problems instead of analyzing a minidump
+    ///
+    /// Every problem found is printed as `<file>:<line>: <severity>[<kind>]: <message>`,
+    /// including ones that minidump-stackwalk normally works around silently, such as
+    /// overlapping FUNC or STACK CFI INIT ranges, line and INLINE records that refer to
+    /// missing FILE or INLINE_ORIGIN records, unparseable STACK CFI rules, and records that
+    /// aren't sorted by address.
+    ///
+    /// Exits with an error if any problem has "error" severity.
+    #[arg(long)]
+    validate_symbols: Option<PathBuf>,
+
+    /// Process many minidumps, emitting one line of JSON for each
+    ///
+    /// Takes a directory (every file in it is processed), a glob pattern for the file name
+    /// like `dumps/*.dmp`, or `-` to read a list of paths from stdin, one per line.
+    ///
+    /// Dumps are processed concurrently (see --batch-jobs) with a single symbol provider, so
+    /// every symbol file is only downloaded and parsed once for the whole batch. Each line
+    /// is the --json output with an additional `minidump_path` field, or just
+    /// `minidump_path`, `status` (`ERROR_*`) and `error` if the dump couldn't be processed.
+    /// Lines are written in input order.
+    ///
+    /// The lines are written to --output-file (or stdout). If --output-file is an existing
+    /// directory, each dump's JSON is instead written to `<minidump file name>.json` in it.
+    /// Dumps with the same file name as an earlier one get `<minidump file name>.<n>.json`,
+    /// counting from 2.
+    #[arg(long, value_name = "DUMPS")]
+    #[arg(conflicts_with_all = ["minidump", "pretty", "brief", "evil_json"])]
+    batch: Option<String>,
+
+    /// How many minidumps --batch processes at once (defaults to the number of CPUs)
+    #[arg(long, requires = "batch")]
+    batch_jobs: Option<usize>,
+
+    /// Compare the minidump with another one, reporting the differences
+    ///
+    /// Both minidumps are processed, then the crash reason and address, the crashing thread's
+    /// frames, the module versions and the system info are compared. The frames are aligned
+    /// by function (or module offset without symbols), so they can be compared even if the
+    /// modules were loaded at different addresses. Frames prefixed with `-` are only in the
+    /// minidump, `+` only in OTHER, and `~` in a different place in the same function.
+    ///
+    /// The report is human-readable, or JSON with --json.
+    #[arg(long, value_name = "OTHER")]
+    #[arg(conflicts_with_all = ["cyborg", "dump", "brief", "evil_json"])]
+    diff: Option<PathBuf>,
+
+    /// Run an HTTP server that processes minidumps, listening on `ADDR`
+    ///
+    /// Takes a `host:port` address, or `unix:<path>` for a Unix domain socket. Once the server
+    /// is ready, `listening on <address>` is printed to stdout. Symbols are shared by all
+    /// requests (see --symbols-memory-limit-mb). The server runs until interrupted. Endpoints:
+    ///
+    /// * `POST /process` - the body is a minidump, the response is its --json output.
+    ///   Takes the query parameters `features` (like --features, the other processing options
+    ///   still apply), `recover_function_args` and `pretty` (`true` or `false`). Errors are a
+    ///   JSON object with `status` (`ERROR_*`) and `error` fields.
+    ///
+    /// * `GET /health` - `{"status":"ok"}` while the server is running.
+    ///
+    /// * `GET /metrics` - JSON counters for requests and the shared symbols.
+    ///
+    /// See --serve-max-body-mb, --serve-timeout-secs and --serve-connections for the limits
+    /// on what clients can send.
+    #[arg(long, value_name = "ADDR")]
+    #[arg(conflicts_with_all = ["minidump", "pretty", "brief", "evil_json"])]
+    #[arg(verbatim_doc_comment)]
+    serve: Option<String>,
+
+    /// The largest minidump --serve accepts, in megabytes
+    #[arg(long, value_name = "MB", default_value_t = 256, requires = "serve")]
+    serve_max_body_mb: usize,
+
+    /// How long a --serve client has to send its request, in seconds
+    #[arg(long, value_name = "SECS", default_value_t = 60, requires = "serve")]
+    serve_timeout_secs: u64,
+
+    /// How many connections --serve handles at once
+    ///
+    /// Further connections wait to be accepted, which bounds the memory used by requests to
+    /// this many minidumps (see --serve-max-body-mb).
+    #[arg(long, value_name = "N", default_value_t = 16, requires = "serve")]
+    serve_connections: usize,
+
     /// Print --help but formatted as markdown (used for generating docs)
     #[arg(long, hide = true)]
     help_markdown: bool,
@@ -120,7 +255,7 @@ struct Cli {
     ///  
     /// stable-all enables: nothing (currently identical to stable-basic)
     ///  
-    /// unstable-all enables: `--recover-function-args`
+    /// unstable-all enables: `--recover-function-args`, `--async-stacks`, `--collapse-recursion`
     ///  
     /// minidump-stackwalk wants to be a reliable and stable tool, but we also want to be able
     /// to introduce new features which may be experimental or expensive. To balance these two
@@ -164,7 +299,7 @@ struct Cli {
     #[arg(long)]
     no_color: bool,
 
-    /// Pretty-print --json output
+    /// Pretty-print --json or --sarif output
     #[arg(long)]
     pretty: bool,
 
@@ -197,10 +332,195 @@ struct Cli {
     #[arg(long)]
     recover_function_args: bool,
 
+    /// Explain how each frame was found from its callee
+    ///
+    /// Adds notes to the frames of the --human output (and an `unwind_notes` array to those of
+    /// the --json output): the call frame info rules that were evaluated, the values stack
+    /// scanning rejected as return addresses and why, where the return address was found,
+    /// and which registers were recovered. Useful for debugging bad stacks.
+    #[arg(long)]
+    explain_unwind: bool,
+
+    /// Reconstruct the logical stacks of async tasks and coroutines
+    ///
+    /// Threads running a task of a known executor (tokio, futures, async-std, smol, or C++20
+    /// coroutines resumed through `std::coroutine_handle`) get an async stack after their
+    /// physical one: the await chain of the task, from the names of its async functions,
+    /// blocks and coroutines, followed by the executor frame that polls it. Included in the
+    /// --json output as an `async_frames` array on the threads. Enabled by
+    /// `--features=unstable-all`.
+    #[arg(long)]
+    async_stacks: bool,
+
+    /// The most frames to unwind per thread
+    ///
+    /// Threads that have more stop there, with `<unwinding stopped: frame limit reached>` in
+    /// the --human output and `"unwind_stopped": "frame_limit_reached"` in the --json output.
+    #[arg(long, value_name = "N")]
+    max_frames: Option<usize>,
+
+    /// The most frames per thread to find with stack scanning
+    ///
+    /// Corrupted stacks can yield thousands of scanned frames. Threads stop before their
+    /// next scanned frame once they have this many (`"unwind_stopped": "scan_limit_reached"`).
+    #[arg(long, value_name = "N")]
+    max_scanned_frames: Option<usize>,
+
+    /// How long to spend unwinding each thread, in seconds
+    ///
+    /// Threads that take longer stop where they are (`"unwind_stopped": "timed_out"`).
+    #[arg(long, value_name = "SECS")]
+    thread_timeout_secs: Option<u64>,
+
+    /// How long to spend unwinding all the threads, in seconds
+    ///
+    /// Threads that are still unwinding when this runs out stop where they are, and threads
+    /// that haven't started only get their context frame (`"unwind_stopped": "timed_out"`).
+    #[arg(long, value_name = "SECS")]
+    unwind_timeout_secs: Option<u64>,
+
+    /// Only unwind the crashing (or dump requesting) thread
+    ///
+    /// The other threads only get their context frame (`"unwind_stopped": "unwind_skipped"`).
+    /// Useful for dumps with thousands of threads.
+    #[arg(long)]
+    crashing_thread_only: bool,
+
+    /// Collapse cycles of recursive frames to a single copy of the cycle
+    ///
+    /// Stack overflows often leave tens of thousands of identical recursive frames. Cycles of
+    /// up to 32 frames repeated at least 16 times are collapsed, with a note after the copy
+    /// that was kept in the --human output and a `collapsed_recursions` array on the threads of
+    /// the --json output. Enabled by `--features=unstable-all`.
+    #[arg(long)]
+    collapse_recursion: bool,
+
+    /// How to rewrite the function names from symbol files
+    ///
+    /// * `raw` - leave the names as they are in the symbol files
+    /// * `demangle` - demangle mangled Rust (`_ZN…`, `_R…`) and Itanium C++ (`_Z…`) names
+    /// * `normalize` - also strip the hash suffixes of Rust names (`::h0123456789abcdef`)
+    /// * `normalize-no-generics` - also drop generic and template arguments
+    ///
+    /// Rewritten names are also used for the crash signature. The --json output keeps the
+    /// original name of rewritten frames in the `raw_function` field.
+    #[arg(long, default_value = "raw")]
+    #[arg(value_parser = ["raw", "demangle", "normalize", "normalize-no-generics"])]
+    #[arg(verbatim_doc_comment)]
+    function_names: String,
+
+    /// A file with rules for the crash signature
+    ///
+    /// The signature summarizes the crashing thread's stack for grouping similar crashes, and
+    /// is printed in the --human output and the `signature` field of the --json output. It
+    /// joins the normalized function names of the innermost frames with ` | `, skipping
+    /// frames like `abort` and continuing past allocator frames like `malloc`.
+    ///
+    /// The file has one rule per line, which are added to the default rules:
+    ///
+    /// * `irrelevant <pattern>` - skip frames matching the pattern
+    /// * `prefix <pattern>` - continue the signature after frames matching the pattern
+    /// * `max_frames <count>` - the most frames a signature has (default 10)
+    /// * `clear` - forget the rules so far, including the default ones
+    ///
+    /// Patterns match the whole frame name, with `*` matching anything. Frames without
+    /// symbols are named `module@0xoffset`. Lines starting with `#` are comments.
+    #[arg(long, value_name = "FILE")]
+    #[arg(verbatim_doc_comment)]
+    signature_rules: Option<PathBuf>,
+
+    /// A local source checkout to show the crashing thread's source code from
+    ///
+    /// Takes `DIR` or `PREFIX=DIR`. Source files recorded under `PREFIX` (a path like
+    /// `/builds/worker/checkouts/gecko`, or the repository of source server paths like
+    /// `hg.mozilla.org/mozilla-central`) are looked for under `DIR`. Without a prefix,
+    /// relative paths and all source server paths (`hg:` and `git:`) are looked for under
+    /// `DIR`. Can be passed multiple times. Paths with `..` components and files outside
+    /// of the directories (e.g. through symbolic links) are ignored, absolute paths are only
+    /// looked for as they are with --source-absolute-paths.
+    ///
+    /// The source code around each of the crashing thread's frames is printed in the
+    /// --human output, and is the `source_context` field of the --json output.
+    #[arg(long, value_name = "[PREFIX=]DIR", value_parser = parse_source_root)]
+    source_root: Vec<(Option<String>, PathBuf)>,
+
+    /// How many lines of source code --source-root shows before and after each frame's line
+    #[arg(long, default_value_t = 3, requires = "source_root")]
+    source_context_lines: u32,
+
+    /// Also look for absolute source file paths as they are, outside of --source-root
+    ///
+    /// Only use this with trusted symbol files, as they can name any file on this machine,
+    /// which then ends up in the output.
+    #[arg(long, requires = "source_root")]
+    source_absolute_paths: bool,
+
     /// Use debug information from local files referred to by the minidump, if present.
     #[arg(long)]
     use_local_debuginfo: bool,
 
+    /// Look for local debug information recorded under one path prefix under another
+    ///
+    /// Takes `FROM=TO`, e.g. `--debuginfo-remap /data/app=/srv/apps` looks for
+    /// `/data/app/foo/libfoo.so` at `/srv/apps/foo/libfoo.so`. Can be passed multiple times,
+    /// the first rule with a matching prefix is used.
+    ///
+    /// Files whose debug id doesn't match the module in the minidump are always ignored.
+    #[arg(long, value_name = "FROM=TO", value_parser = parse_remap)]
+    #[arg(requires = "use_local_debuginfo")]
+    debuginfo_remap: Vec<(PathBuf, PathBuf)>,
+
+    /// A copy of the crashed system's root file system to look for local debug information in
+    ///
+    /// Modules are looked for under each sysroot after their recorded path, and separate
+    /// debug files under its `usr/lib/debug/.build-id`. Can be passed multiple times.
+    #[arg(long, requires = "use_local_debuginfo")]
+    sysroot: Vec<PathBuf>,
+
+    /// A directory with a `.build-id` tree of separate debug files
+    ///
+    /// Debug files are looked up by the module's build id, as `.build-id/xx/yyyy.debug`.
+    /// Can be passed multiple times.
+    #[arg(long, requires = "use_local_debuginfo")]
+    debuginfo_dir: Vec<PathBuf>,
+
+    /// Unwind Windows x86-64 frames with the unwind info in their binaries
+    ///
+    /// Frames of modules without `STACK CFI` in their symbol file are unwound with the
+    /// `.pdata`/`.xdata` of the module's PE binary, instead of frame pointers or stack
+    /// scanning. The binaries are looked for in the same places as the symbol files (e.g.
+    /// the Microsoft symbol server), so this needs --symbols-url or --symbols-path.
+    #[arg(long)]
+    pe_unwind_info: bool,
+
+    /// Unwind Mach-O and ELF frames with the unwind tables in their binaries
+    ///
+    /// Frames of modules without `STACK CFI` in their symbol file are unwound with the
+    /// compact unwind info and `__eh_frame` of the module's Mach-O binary (fat binaries
+    /// included), or the `.eh_frame` of its ELF binary. The binaries are looked for in the
+    /// same places as the symbol files, so this needs --symbols-url or --symbols-path.
+    #[arg(long)]
+    binary_unwind_info: bool,
+
+    /// Unwind with the unwind tables of module images captured in the minidump
+    ///
+    /// Full-memory minidumps contain the loaded modules, including their unwind tables (PE
+    /// `.pdata`, ELF `.eh_frame`, Mach-O `__unwind_info`). They are used for frames that no
+    /// symbol file or binary has unwind info for, so stacks can be unwound accurately without
+    /// the binaries. Supported on x86-64, and on ARM64 for ELF and Mach-O modules.
+    #[arg(long)]
+    use_dump_images: bool,
+
+    /// Symbolicate JITed code with the perf map of the dump's process in DIR
+    ///
+    /// JIT runtimes (e.g. node with `--perf-basic-prof`, or .NET with
+    /// `DOTNET_PerfMapEnabled=1`) write the names of the functions they generate to
+    /// `/tmp/perf-<pid>.map`, or to a `jit-<pid>.dump` jitdump. Frames outside of any module
+    /// are symbolicated with the `perf-<pid>.map` and `jit-<pid>.dump` in DIR for the process
+    /// id of the dump, e.g. a copy of the /tmp of the machine that crashed.
+    #[arg(long, value_name = "DIR")]
+    perf_map: Option<PathBuf>,
+
     /// base URL from which URLs to symbol files can be constructed
     ///
     /// If multiple symbols-url values are provided, they will each be tried in order until
@@ -214,6 +534,9 @@ struct Cli {
     /// Example symbols-url values:
     /// * microsoft's symbol-server: <https://msdl.microsoft.com/download/symbols/>
     /// * mozilla's symbols-server: <https://symbols.mozilla.org/>
+    ///
+    /// Servers with a different layout can be selected with a prefix, the same
+    /// as symbols-path (e.g. `symstore:https://example.com/symbols/`).
     #[arg(long)]
     #[arg(verbatim_doc_comment)]
     symbols_url: Vec<String>,
@@ -253,14 +576,47 @@ struct Cli {
     #[arg(long, default_value_t = 1000)]
     symbols_download_timeout_secs: u64,
 
+    /// Write a binary symbol cache (`.symc`) next to every symbol file that gets loaded
+    ///
+    /// Parsing a large text symbol file costs seconds of CPU and a lot of memory on every
+    /// run. A binary cache is memory-mapped and queried lazily instead, so subsequent runs
+    /// that find a cache no older than its symbol file skip the text parse entirely.
+    ///
+    /// Downloaded symbols in symbols-cache always get a binary cache. This flag also
+    /// builds them for symbol files found in symbols-path directories, which requires
+    /// write access to those directories.
+    #[arg(long)]
+    build_symbol_caches: bool,
+
+    /// Bound the memory used by parsed symbol files, in megabytes
+    ///
+    /// When loading symbols goes over the limit, the least recently used symbol files are
+    /// dropped, and loaded again if they are needed later. This is mostly useful for
+    /// --batch and --serve, which keep symbols around for many minidumps. Memory-mapped
+    /// binary symbol caches (see --build-symbol-caches) don't count towards the limit.
+    #[arg(long, value_name = "MB")]
+    symbols_memory_limit_mb: Option<usize>,
+
     /// Path to the minidump file to analyze
-    minidump: PathBuf,
+    #[arg(required_unless_present_any = ["validate_symbols", "batch", "serve"])]
+    minidump: Option<PathBuf>,
 
     /// Path to a symbol file.
     ///
     /// If multiple symbols-path values are provided, all symbol files will be merged
     /// into minidump-stackwalk's symbol database.
+    ///
+    /// Directories are expected to use the `<debug file>/<debug id>/<file>.sym` layout,
+    /// or to be a two-tier symstore if they contain an `index2.txt`. Another layout can
+    /// be selected by prefixing the path with it:
+    ///
+    /// * `breakpad:` - `<debug file>/<debug id>/<file>.sym`
+    /// * `symstore:` - `<2 chars>/<debug file>/<debug id>/<file>.sym` (two-tier symstore)
+    /// * `flat:` - `<debug id>.sym`
+    ///
+    /// Symstore `file.ptr` redirects are followed in all but flat directories.
     #[arg(long)]
+    #[arg(verbatim_doc_comment)]
     symbols_path: Vec<PathBuf>,
 
     /// Path to a symbol file. (Passed positionally)
@@ -337,10 +693,35 @@ async fn main_result() -> std::io::Result<()> {
         return Ok(());
     }
 
+    if let Some(sym_path) = &cli.validate_symbols {
+        let mut output: Box<dyn Write> = match &cli.output_file {
+            Some(output_path) => Box::new(File::create(output_path)?),
+            None => Box::new(std::io::stdout()),
+        };
+        return validate_symbols(sym_path, &mut output);
+    }
+
     let temp_dir = std::env::temp_dir();
 
-    let mut symbols_paths = cli.symbols_path;
-    symbols_paths.extend(cli.symbols_path_legacy);
+    let symbols_paths: Vec<_> = cli
+        .symbols_path
+        .into_iter()
+        .chain(cli.symbols_path_legacy)
+        .map(
+            |path| match path.to_str().map(SymbolLayout::from_prefixed) {
+                Some((layout, path)) => (PathBuf::from(path), layout),
+                None => (path, SymbolLayout::Auto),
+            },
+        )
+        .collect();
+    let symbols_urls: Vec<_> = cli
+        .symbols_url
+        .iter()
+        .map(|url| {
+            let (layout, url) = SymbolLayout::from_prefixed(url);
+            (url.to_owned(), layout)
+        })
+        .collect();
 
     // Default to env::temp_dir()/rust-minidump-cache
     let symbols_cache = cli
@@ -352,6 +733,44 @@ async fn main_result() -> std::io::Result<()> {
 
     let timeout = Duration::from_secs(cli.symbols_download_timeout_secs);
 
+    let mut symbolizer = if !symbols_urls.is_empty() {
+        Some(Symbolizer::new(
+            HttpSymbolSupplier::with_layouts(
+                symbols_urls,
+                symbols_cache,
+                symbols_tmp,
+                symbols_paths,
+                timeout,
+            )
+            .binary_caches(cli.build_symbol_caches),
+        ))
+    } else if !symbols_paths.is_empty() {
+        Some(Symbolizer::new(
+            SimpleSymbolSupplier::with_layouts(symbols_paths)
+                .binary_caches(cli.build_symbol_caches),
+        ))
+    } else {
+        None
+    };
+    if let Some(limit) = cli.symbols_memory_limit_mb {
+        symbolizer =
+            symbolizer.map(|symbolizer| symbolizer.memory_limit(limit.saturating_mul(1024 * 1024)));
+    }
+
+    let pe_unwind_info = cli.pe_unwind_info;
+    let binary_unwind_info = cli.binary_unwind_info;
+    let use_dump_images = cli.use_dump_images;
+    let perf_map = cli.perf_map;
+    let local_debuginfo = if cli.use_local_debuginfo {
+        Some(LocalDebugInfo {
+            remaps: cli.debuginfo_remap,
+            sysroots: cli.sysroot,
+            dirs: cli.debuginfo_dir,
+        })
+    } else {
+        None
+    };
+
     // Determine the kind of output we're producing -- dump, json, human, or cyborg (both).
     // Although we have a --human argument it's mostly just there to make the documentation
     // more clear. human output is enabled by default, and --json disables it.
@@ -360,7 +779,8 @@ async fn main_result() -> std::io::Result<()> {
     // Human is just enabled if nothing else is
     let raw_dump = cli.dump;
     let mut json = cli.json;
-    let mut human = !json && !raw_dump;
+    let mut human =
+        !json && !raw_dump && !cli.sarif && !cli.markdown && !cli.tsv && !cli.machine_readable;
     // Cyborg is just "desugarred" to --json --human
     if cli.cyborg.is_some() {
         human = true;
@@ -369,7 +789,7 @@ async fn main_result() -> std::io::Result<()> {
 
     // Now check if arguments that tweak the output are valid. We can't use
     // Arg::requires because clap doesn't understand --json being implicitly enabled.
-    if cli.pretty && !json {
+    if cli.pretty && !(json || cli.sarif) {
         error!("Humans must be hideous! (The --pretty and --human flags cannot both be set)");
         std::process::exit(1);
     }
@@ -379,20 +799,131 @@ async fn main_result() -> std::io::Result<()> {
         std::process::exit(1);
     }
 
-    // Pick the default options
-    let mut options = match &*cli.features {
-        "stable-basic" => ProcessorOptions::stable_basic(),
-        "stable-all" => ProcessorOptions::stable_all(),
-        "unstable-all" => ProcessorOptions::unstable_all(),
-        _ => unimplemented!("unknown --features value"),
+    let mut overrides = OptionOverrides {
+        recover_function_args: cli.recover_function_args,
+        unwind_notes: cli.explain_unwind,
+        async_stacks: cli.async_stacks,
+        max_frames: cli.max_frames,
+        max_scanned_frames: cli.max_scanned_frames,
+        thread_timeout: cli.thread_timeout_secs.map(Duration::from_secs),
+        total_timeout: cli.unwind_timeout_secs.map(Duration::from_secs),
+        crashing_thread_only: cli.crashing_thread_only,
+        collapse_recursion: cli.collapse_recursion,
+        demangle: match &*cli.function_names {
+            "raw" => DemangleOptions::default(),
+            "demangle" => DemangleOptions {
+                demangle: true,
+                ..DemangleOptions::default()
+            },
+            "normalize" => DemangleOptions::normalize(),
+            "normalize-no-generics" => DemangleOptions {
+                strip_generics: true,
+                ..DemangleOptions::normalize()
+            },
+            _ => unimplemented!("unknown --function-names value"),
+        },
+        signature_rules: None,
+        source_files: None,
+        // Like the signature rules and source files, this is needed for the whole run.
+        evil_json: cli
+            .evil_json
+            .clone()
+            .map(|path| &*Box::leak(path.into_boxed_path())),
     };
+    if let Some(path) = &cli.signature_rules {
+        match SignatureRules::from_file(path) {
+            // The rules are needed for the whole run.
+            Ok(rules) => overrides.signature_rules = Some(Box::leak(Box::new(rules))),
+            Err(err) => {
+                error!("Error loading signature rules: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if !cli.source_root.is_empty() {
+        let mut source_files =
+            SourceFiles::new(cli.source_context_lines).absolute_paths(cli.source_absolute_paths);
+        for (prefix, dir) in &cli.source_root {
+            source_files = match prefix {
+                Some(prefix) => source_files.remap(prefix.as_str(), dir),
+                None => source_files.root(dir),
+            };
+        }
+        // Like the signature rules, these are needed for the whole run.
+        overrides.source_files = Some(Box::leak(Box::new(source_files)));
+    }
+
+    let base_options = overrides
+        .options(&cli.features)
+        .expect("unknown --features value");
+
+    if let Some(source) = &cli.batch {
+        let jobs = cli
+            .batch_jobs
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |jobs| jobs.get()));
+        let batch = batch::Batch {
+            symbols: SharedSymbols::new(
+                symbolizer,
+                local_debuginfo,
+                pe_unwind_info,
+                binary_unwind_info,
+                use_dump_images,
+                perf_map,
+            ),
+            options: base_options,
+        };
+        return batch.run(source, jobs, cli.output_file.as_deref()).await;
+    }
+
+    if let Some(addr) = &cli.serve {
+        let server = serve::Server::new(
+            SharedSymbols::new(
+                symbolizer,
+                local_debuginfo,
+                pe_unwind_info,
+                binary_unwind_info,
+                use_dump_images,
+                perf_map,
+            ),
+            base_options,
+            overrides,
+            serve::ServeLimits {
+                max_body_size: cli.serve_max_body_mb.saturating_mul(1024 * 1024),
+                read_timeout: Duration::from_secs(cli.serve_timeout_secs),
+                max_connections: cli.serve_connections,
+            },
+        );
+        return server.run(addr).await;
+    }
+
+    if let Some(other_path) = &cli.diff {
+        // clap requires a minidump in this mode.
+        let minidump_path = cli.minidump.as_deref().expect("no minidump path");
+        let symbols = SharedSymbols::new(
+            symbolizer,
+            local_debuginfo,
+            pe_unwind_info,
+            binary_unwind_info,
+            use_dump_images,
+            perf_map,
+        );
+        let diff = diff::DumpDiff::new(&symbols, base_options, minidump_path, other_path).await?;
+        let mut output: Box<dyn Write> = match &cli.output_file {
+            Some(output_path) => Box::new(File::create(output_path)?),
+            None => Box::new(std::io::stdout()),
+        };
+        return if json {
+            diff.print_json(&mut output, cli.pretty)
+        } else {
+            diff.print(&mut output)
+        };
+    }
 
-    // Now overload the defaults
-    options.evil_json = cli.evil_json.as_deref();
-    options.recover_function_args = cli.recover_function_args;
+    let mut options = base_options;
 
     // Register for instractive updates, if we want them
-    let interactive_enabled = !json && !cli.no_interactive && cli.output_file.is_none();
+    let interactive_enabled = human && !json && !cli.no_interactive && cli.output_file.is_none();
     let mut processor_stats = None;
     if interactive_enabled {
         let mut subscriptions = PendingProcessorStatSubscriptions::default();
@@ -404,7 +935,9 @@ async fn main_result() -> std::io::Result<()> {
 
     // Ok now let's do the thing!!!!
 
-    match Minidump::read_path(cli.minidump) {
+    // clap requires a minidump in every other mode.
+    let minidump_path = cli.minidump.expect("no minidump path");
+    match Minidump::read_path(minidump_path) {
         Ok(dump) => {
             let mut stdout;
             let mut output_f;
@@ -423,36 +956,21 @@ async fn main_result() -> std::io::Result<()> {
                 return print_minidump_dump(&dump, &mut output, cli.brief);
             }
 
-            let mut provider = MultiSymbolProvider::new();
-
-            let modules = dump.get_stream::<MinidumpModuleList>().unwrap_or_default();
-
-            if cli.use_local_debuginfo {
-                let system_info = match dump.get_stream::<MinidumpSystemInfo>() {
-                    Err(e) => {
-                        error!("Error getting system info stream from dump (required for local debug info): {}", e);
-                        std::process::exit(1);
-                    }
-                    Ok(s) => s,
-                };
-                provider.add(Box::new(
-                    DebugInfoSymbolProvider::new(&system_info, &modules).await,
-                ));
-            }
-
-            if !cli.symbols_url.is_empty() {
-                provider.add(Box::new(Symbolizer::new(http_symbol_supplier(
-                    symbols_paths,
-                    cli.symbols_url,
-                    symbols_cache,
-                    symbols_tmp,
-                    timeout,
-                ))));
-            } else if !symbols_paths.is_empty() {
-                provider.add(Box::new(Symbolizer::new(simple_symbol_supplier(
-                    symbols_paths,
-                ))));
-            }
+            let symbols = SharedSymbols::new(
+                symbolizer,
+                local_debuginfo,
+                pe_unwind_info,
+                binary_unwind_info,
+                use_dump_images,
+                perf_map,
+            );
+            let provider = match symbols.provider(&dump).await {
+                Ok(provider) => provider,
+                Err(e) => {
+                    error!("Error getting system info stream from dump (required for local debug info): {}", e);
+                    std::process::exit(1);
+                }
+            };
 
             let interactive_ui = processor_stats
                 .as_ref()
@@ -507,6 +1025,16 @@ async fn main_result() -> std::io::Result<()> {
                             state.print_json(&mut output, cli.pretty)?;
                         }
                     }
+
+                    if cli.sarif {
+                        state.print_sarif(&mut output, cli.pretty)?;
+                    } else if cli.markdown {
+                        state.print_markdown(&mut output)?;
+                    } else if cli.tsv {
+                        state.print_tsv(&mut output)?;
+                    } else if cli.machine_readable {
+                        state.print_machine_readable(&mut output)?;
+                    }
                     Ok(())
                 }
                 Err(err) => {
@@ -522,6 +1050,248 @@ async fn main_result() -> std::io::Result<()> {
     }
 }
 
+/// Where to find local debug information for --use-local-debuginfo.
+struct LocalDebugInfo {
+    remaps: Vec<(PathBuf, PathBuf)>,
+    sysroots: Vec<PathBuf>,
+    dirs: Vec<PathBuf>,
+}
+
+impl LocalDebugInfo {
+    /// Create a provider for the modules of `dump`.
+    async fn provider<'a, T: Deref<Target = [u8]> + 'a>(
+        &self,
+        dump: &'a Minidump<'a, T>,
+        modules: &MinidumpModuleList,
+    ) -> Result<DebugInfoSymbolProvider, Error> {
+        let system_info = dump.get_stream::<MinidumpSystemInfo>()?;
+        let mut builder = DebugInfoSymbolProvider::builder();
+        for (from, to) in &self.remaps {
+            builder = builder.remap_path_prefix(from, to);
+        }
+        for sysroot in &self.sysroots {
+            builder = builder.sysroot(sysroot);
+        }
+        for dir in &self.dirs {
+            builder = builder.debug_dir(dir);
+        }
+        Ok(builder.build(&system_info, modules).await)
+    }
+}
+
+/// The processor options set on the command line, which override those of the `--features`
+/// preset.
+///
+/// --serve applies them again when a request picks other features.
+#[derive(Clone, Copy)]
+struct OptionOverrides {
+    recover_function_args: bool,
+    unwind_notes: bool,
+    async_stacks: bool,
+    max_frames: Option<usize>,
+    max_scanned_frames: Option<usize>,
+    thread_timeout: Option<Duration>,
+    total_timeout: Option<Duration>,
+    crashing_thread_only: bool,
+    collapse_recursion: bool,
+    demangle: DemangleOptions,
+    signature_rules: Option<&'static SignatureRules>,
+    source_files: Option<&'static SourceFiles>,
+    evil_json: Option<&'static Path>,
+}
+
+impl OptionOverrides {
+    /// The options of the `features` preset with the overrides applied, `None` if there is no
+    /// such preset.
+    fn options(&self, features: &str) -> Option<ProcessorOptions<'static>> {
+        let mut options = match features {
+            "stable-basic" => ProcessorOptions::stable_basic(),
+            "stable-all" => ProcessorOptions::stable_all(),
+            "unstable-all" => ProcessorOptions::unstable_all(),
+            _ => return None,
+        };
+        // Flags can only enable what the preset doesn't.
+        options.recover_function_args |= self.recover_function_args;
+        options.unwind_notes |= self.unwind_notes;
+        options.async_stacks |= self.async_stacks;
+        options.crashing_thread_only |= self.crashing_thread_only;
+        options.collapse_recursion |= self.collapse_recursion;
+        options.max_frames = self.max_frames.or(options.max_frames);
+        options.max_scanned_frames = self.max_scanned_frames.or(options.max_scanned_frames);
+        options.thread_timeout = self.thread_timeout.or(options.thread_timeout);
+        options.total_timeout = self.total_timeout.or(options.total_timeout);
+        options.demangle = self.demangle;
+        options.signature_rules = self.signature_rules.or(options.signature_rules);
+        options.source_files = self.source_files.or(options.source_files);
+        options.evil_json = self.evil_json.or(options.evil_json);
+        Some(options)
+    }
+}
+
+/// The symbols of the minidumps to process, shared by all of them with --batch or --serve.
+struct SharedSymbols {
+    /// Shared so each symbol file is only loaded once.
+    symbolizer: Option<Arc<Symbolizer>>,
+    local_debuginfo: Option<LocalDebugInfo>,
+    /// Shared so each binary is only parsed once.
+    pe_unwind: Option<Arc<PeUnwindSymbolProvider<Arc<Symbolizer>>>>,
+    binary_unwind_info: bool,
+    use_dump_images: bool,
+    perf_map: Option<PathBuf>,
+}
+
+impl SharedSymbols {
+    fn new(
+        symbolizer: Option<Symbolizer>,
+        local_debuginfo: Option<LocalDebugInfo>,
+        pe_unwind_info: bool,
+        binary_unwind_info: bool,
+        use_dump_images: bool,
+        perf_map: Option<PathBuf>,
+    ) -> Self {
+        let symbolizer = symbolizer.map(Arc::new);
+        let pe_unwind = symbolizer
+            .as_ref()
+            .filter(|_| pe_unwind_info)
+            .map(|symbolizer| Arc::new(PeUnwindSymbolProvider::new(symbolizer.clone())));
+        SharedSymbols {
+            symbolizer,
+            local_debuginfo,
+            pe_unwind,
+            binary_unwind_info,
+            use_dump_images,
+            perf_map,
+        }
+    }
+
+    /// Create a symbol provider for `dump`.
+    async fn provider<'a, T: Deref<Target = [u8]> + 'a>(
+        &self,
+        dump: &'a Minidump<'a, T>,
+    ) -> Result<MultiSymbolProvider, Error> {
+        let mut provider = MultiSymbolProvider::new();
+        if let Some(local_debuginfo) = &self.local_debuginfo {
+            let modules = dump.get_stream::<MinidumpModuleList>().unwrap_or_default();
+            provider.add(Box::new(local_debuginfo.provider(dump, &modules).await?));
+        }
+        if let Some(symbolizer) = &self.symbolizer {
+            provider.add(Box::new(symbolizer.clone()));
+        }
+        if let Some(pe_unwind) = &self.pe_unwind {
+            provider.add(Box::new(pe_unwind.clone()));
+        }
+        if let Some(symbolizer) = self.symbolizer.as_ref().filter(|_| self.binary_unwind_info) {
+            // The unwinders depend on the dump's CPU, so they can't be shared.
+            if let Some(binaries) = binary_unwind_provider(dump, symbolizer.clone()) {
+                provider.add(Box::new(binaries));
+            }
+        }
+        if self.use_dump_images {
+            let modules = dump.get_stream::<MinidumpModuleList>().unwrap_or_default();
+            if let Some(dump_images) = dump_image_provider(dump, &modules) {
+                provider.add(Box::new(dump_images));
+            }
+        }
+        if let Some(perf_map) = &self.perf_map {
+            // Each process has its own perf map.
+            if let Some(perf_map) = perf_map_provider(dump, perf_map) {
+                provider.add(Box::new(perf_map));
+            }
+        }
+        Ok(provider)
+    }
+}
+
+/// Create a provider for the unwind tables of the binaries `symbolizer` finds for the modules of
+/// `dump`, `None` if it has no system info.
+fn binary_unwind_provider<'a, T: Deref<Target = [u8]> + 'a>(
+    dump: &'a Minidump<'a, T>,
+    symbolizer: Arc<Symbolizer>,
+) -> Option<BinaryUnwindSymbolProvider<Arc<Symbolizer>>> {
+    let system_info = dump.get_stream::<MinidumpSystemInfo>().ok()?;
+    Some(BinaryUnwindSymbolProvider::new(&system_info, symbolizer))
+}
+
+/// Create a provider for the unwind tables of the module images in `dump`, `None` if it has no
+/// memory (or system info).
+fn dump_image_provider<'a, T: Deref<Target = [u8]> + 'a>(
+    dump: &'a Minidump<'a, T>,
+    modules: &MinidumpModuleList,
+) -> Option<DumpImageSymbolProvider> {
+    let system_info = dump.get_stream::<MinidumpSystemInfo>().ok()?;
+    let memory = dump.get_memory()?;
+    Some(DumpImageSymbolProvider::new(&system_info, modules, &memory))
+}
+
+/// Load the perf map of `dump`'s process from `dir`, `None` if the dump has no process id or
+/// the perf map can't be read.
+fn perf_map_provider<'a, T: Deref<Target = [u8]> + 'a>(
+    dump: &'a Minidump<'a, T>,
+    dir: &Path,
+) -> Option<PerfMapSymbolProvider> {
+    // The same process id as `ProcessState::process_id`.
+    let pid = match dump.get_stream::<MinidumpMiscInfo>() {
+        Ok(misc_info) => misc_info.raw.process_id().cloned(),
+        Err(_) => dump
+            .get_stream::<MinidumpLinuxProcStatus>()
+            .ok()
+            .map(|status| LinuxProcStatus::from(status).pid),
+    }?;
+    match PerfMapSymbolProvider::for_process(dir, pid) {
+        Ok(perf_map) => Some(perf_map),
+        Err(e) => {
+            warn!("Couldn't read the perf map of process {pid}: {e}");
+            None
+        }
+    }
+}
+
+/// Parse a `FROM=TO` path remapping rule.
+fn parse_remap(rule: &str) -> Result<(PathBuf, PathBuf), String> {
+    let (from, to) = rule
+        .split_once('=')
+        .ok_or_else(|| format!("expected FROM=TO, got '{rule}'"))?;
+    Ok((from.into(), to.into()))
+}
+
+/// Parse a `[PREFIX=]DIR` source root.
+fn parse_source_root(root: &str) -> Result<(Option<String>, PathBuf), String> {
+    Ok(match root.split_once('=') {
+        Some((prefix, dir)) => (Some(prefix.to_string()), dir.into()),
+        None => (None, root.into()),
+    })
+}
+
+fn validate_symbols(sym_path: &Path, output: &mut dyn Write) -> std::io::Result<()> {
+    let diagnostics = match SymbolFile::validate_file(sym_path) {
+        Ok(diagnostics) => diagnostics,
+        Err(err) => {
+            error!("Error reading symbol file: {}", err);
+            std::process::exit(1);
+        }
+    };
+    for diagnostic in &diagnostics {
+        writeln!(output, "{}:{}", sym_path.display(), diagnostic)?;
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity() == Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+    writeln!(
+        output,
+        "{}: {} error(s), {} warning(s)",
+        sym_path.display(),
+        errors,
+        warnings
+    )?;
+    output.flush()?;
+    if errors > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
 fn print_help_markdown(out: &mut dyn Write) -> std::io::Result<()> {
     let app_name = "minidump-stackwalk";
     let pretty_app_name = "minidump-stackwalk";
@@ -615,7 +1385,7 @@ fn print_help_markdown(out: &mut dyn Write) -> std::io::Result<()> {
                 writeln!(out, "#### `{line}`")?;
                 continue;
             }
-            if line == "[SYMBOLS_PATH_LEGACY]..." {
+            if line == "[MINIDUMP]" || line == "[SYMBOLS_PATH_LEGACY]..." {
                 writeln!(out, "#### `{line}`")?;
                 continue;
             }
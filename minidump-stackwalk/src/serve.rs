@@ -0,0 +1,448 @@
+//! Processing minidumps sent over HTTP (`--serve`).
+
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use minidump::Minidump;
+use minidump_processor::{ProcessError, ProcessorOptions};
+use serde_json::json;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::sync::Semaphore;
+use tracing::{error, info, warn};
+
+use crate::{OptionOverrides, SharedSymbols};
+
+/// The largest request head (request line and headers) accepted.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+/// The most headers a request may have.
+const MAX_HEADERS: usize = 64;
+
+/// What a server accepts from its clients.
+pub struct ServeLimits {
+    /// The largest minidump accepted, in bytes.
+    pub max_body_size: usize,
+    /// How long a client has to send its request.
+    pub read_timeout: Duration,
+    /// The most connections handled at once, others wait to be accepted.
+    pub max_connections: usize,
+}
+
+/// A minidump processing server.
+pub struct Server {
+    symbols: SharedSymbols,
+    options: ProcessorOptions<'static>,
+    /// The command line options, applied to the features a request picks.
+    overrides: OptionOverrides,
+    limits: ServeLimits,
+    /// The number of `/process` requests received.
+    requests: AtomicU64,
+    /// The number of `/process` requests that failed.
+    failed_requests: AtomicU64,
+    /// The number of minidumps being processed.
+    in_flight: AtomicU64,
+}
+
+/// A parsed HTTP request.
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+/// A JSON HTTP response.
+struct Response {
+    status: u16,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: serde_json::Value) -> Self {
+        Response {
+            status,
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    /// An error response, shaped like the errors of --batch.
+    fn error(status: u16, name: &str, message: impl ToString) -> Self {
+        Response::json(
+            status,
+            json!({
+                "status": format!("ERROR_{name}"),
+                "error": message.to_string(),
+            }),
+        )
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            408 => "Request Timeout",
+            411 => "Length Required",
+            413 => "Payload Too Large",
+            431 => "Request Header Fields Too Large",
+            _ => "Internal Server Error",
+        }
+    }
+
+    async fn write<W: AsyncWrite + Unpin>(&self, out: &mut W) -> io::Result<()> {
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            self.reason(),
+            self.body.len()
+        );
+        out.write_all(head.as_bytes()).await?;
+        out.write_all(&self.body).await?;
+        out.flush().await
+    }
+}
+
+/// A connection to the server.
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+/// Where the server accepts connections.
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, std::path::PathBuf),
+}
+
+impl Listener {
+    async fn bind(addr: &str) -> io::Result<Self> {
+        if let Some(path) = addr.strip_prefix("unix:") {
+            #[cfg(unix)]
+            return Ok(Listener::Unix(UnixListener::bind(path)?, path.into()));
+            #[cfg(not(unix))]
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("unix sockets aren't supported on this platform ({path})"),
+            ));
+        }
+        Ok(Listener::Tcp(TcpListener::bind(addr).await?))
+    }
+
+    /// The address to print, which has the actual port if port 0 was requested.
+    fn local_addr(&self) -> io::Result<String> {
+        Ok(match self {
+            Listener::Tcp(listener) => listener.local_addr()?.to_string(),
+            #[cfg(unix)]
+            Listener::Unix(_, path) => format!("unix:{}", path.display()),
+        })
+    }
+
+    async fn accept(&self) -> io::Result<Box<dyn Connection>> {
+        Ok(match self {
+            Listener::Tcp(listener) => Box::new(listener.accept().await?.0),
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => Box::new(listener.accept().await?.0),
+        })
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Listener::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+impl Server {
+    pub fn new(
+        symbols: SharedSymbols,
+        options: ProcessorOptions<'static>,
+        overrides: OptionOverrides,
+        limits: ServeLimits,
+    ) -> Self {
+        Server {
+            symbols,
+            options,
+            overrides,
+            limits,
+            requests: AtomicU64::new(0),
+            failed_requests: AtomicU64::new(0),
+            in_flight: AtomicU64::new(0),
+        }
+    }
+
+    /// Serve requests on `addr` (see `--serve`) until interrupted.
+    pub async fn run(self, addr: &str) -> io::Result<()> {
+        let listener = Listener::bind(addr).await?;
+        println!("listening on {}", listener.local_addr()?);
+
+        let connections = Arc::new(Semaphore::new(self.limits.max_connections.max(1)));
+        let server = Arc::new(self);
+        loop {
+            // Wait for a connection to be done with before accepting another one.
+            let (permit, connection) = tokio::select! {
+                permit = connections.clone().acquire_owned() => {
+                    let permit = permit.expect("the semaphore is never closed");
+                    (permit, listener.accept().await)
+                }
+                _ = tokio::signal::ctrl_c() => break,
+            };
+            match connection {
+                Ok(connection) => {
+                    let server = server.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = server.handle(connection).await {
+                            warn!("error handling connection: {}", e);
+                        }
+                        drop(permit);
+                    });
+                }
+                Err(e) => error!("error accepting connection: {}", e),
+            }
+        }
+        info!("shutting down");
+        Ok(())
+    }
+
+    /// Answer the one request of a connection.
+    async fn handle(&self, connection: Box<dyn Connection>) -> io::Result<()> {
+        let mut connection = BufReader::new(connection);
+        let request = read_request(&mut connection, self.limits.max_body_size);
+        let response = match tokio::time::timeout(self.limits.read_timeout, request).await {
+            Ok(request) => match request? {
+                Ok(request) => self.respond(request).await,
+                Err(response) => response,
+            },
+            Err(_) => Response::error(408, "Timeout", "the request wasn't received in time"),
+        };
+        response.write(&mut connection).await
+    }
+
+    async fn respond(&self, request: Request) -> Response {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/process") => {
+                self.requests.fetch_add(1, Ordering::Relaxed);
+                self.in_flight.fetch_add(1, Ordering::Relaxed);
+                let response = self.process(&request.query, request.body).await;
+                self.in_flight.fetch_sub(1, Ordering::Relaxed);
+                if response.status != 200 {
+                    self.failed_requests.fetch_add(1, Ordering::Relaxed);
+                }
+                response
+            }
+            ("GET", "/health") => Response::json(200, json!({ "status": "ok" })),
+            ("GET", "/metrics") => Response::json(200, self.metrics()),
+            (_, "/process" | "/health" | "/metrics") => Response::error(
+                405,
+                "MethodNotAllowed",
+                format!("{} is not allowed for {}", request.method, request.path),
+            ),
+            _ => Response::error(
+                404,
+                "NotFound",
+                format!("no such endpoint {}", request.path),
+            ),
+        }
+    }
+
+    /// Process a minidump, returning its JSON report.
+    async fn process(&self, query: &[(String, String)], dump: Vec<u8>) -> Response {
+        let (options, pretty) = match self.parse_options(query) {
+            Ok(options) => options,
+            Err(message) => return Response::error(400, "BadRequest", message),
+        };
+        let dump = match Minidump::read(dump) {
+            Ok(dump) => dump,
+            Err(e) => return Response::error(400, e.name(), e),
+        };
+        // Failing to set up the symbols is the server's problem rather than the minidump's.
+        let provider = match self.symbols.provider(&dump).await {
+            Ok(provider) => provider,
+            Err(e) => return Response::error(500, e.name(), e),
+        };
+        let state = match minidump_processor::process_minidump_with_options(
+            &dump, &provider, options,
+        )
+        .await
+        {
+            Ok(state) => state,
+            Err(e @ ProcessError::UnknownError) => return Response::error(500, e.name(), e),
+            Err(e) => return Response::error(400, e.name(), e),
+        };
+
+        let mut report = Vec::new();
+        if let Err(e) = state.print_json(&mut report, pretty) {
+            return Response::error(500, "JsonError", e);
+        }
+        Response {
+            status: 200,
+            body: report,
+        }
+    }
+
+    /// Get the processor options and whether to pretty-print for the query of `/process`.
+    fn parse_options(
+        &self,
+        query: &[(String, String)],
+    ) -> Result<(ProcessorOptions<'static>, bool), String> {
+        let mut options = self.options.clone();
+        let mut recover_function_args = None;
+        let mut pretty = false;
+        for (key, value) in query {
+            let parse_bool = || match value.as_str() {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(format!("{key} must be true or false, got '{value}'")),
+            };
+            match key.as_str() {
+                "features" => {
+                    options = self
+                        .overrides
+                        .options(value)
+                        .ok_or_else(|| format!("unknown features '{value}'"))?;
+                }
+                "recover_function_args" => recover_function_args = Some(parse_bool()?),
+                "pretty" => pretty = parse_bool()?,
+                _ => return Err(format!("unknown parameter '{key}'")),
+            }
+        }
+        if let Some(recover_function_args) = recover_function_args {
+            options.recover_function_args = recover_function_args;
+        }
+        Ok((options, pretty))
+    }
+
+    fn metrics(&self) -> serde_json::Value {
+        let mut symbols = json!({});
+        if let Some(symbolizer) = &self.symbols.symbolizer {
+            let pending = symbolizer.pending_stats();
+            let stats = symbolizer.stats();
+            let count = |f: fn(&minidump_unwind::SymbolStats) -> bool| {
+                stats.values().filter(|stats| f(stats)).count()
+            };
+            symbols = json!({
+                "cache_bytes": symbolizer.cached_memory_size(),
+                "requested": pending.symbols_requested,
+                "processed": pending.symbols_processed,
+                "modules_loaded": count(|s| s.loaded_symbols && !s.corrupt_symbols),
+                "modules_missing": count(|s| !s.loaded_symbols),
+                "modules_corrupt": count(|s| s.corrupt_symbols),
+            });
+        }
+        json!({
+            "requests": self.requests.load(Ordering::Relaxed),
+            "failed_requests": self.failed_requests.load(Ordering::Relaxed),
+            "in_flight": self.in_flight.load(Ordering::Relaxed),
+            "symbols": symbols,
+        })
+    }
+}
+
+/// Read a request with a body of at most `max_body_size` bytes from `connection`, or the error
+/// response to send instead.
+async fn read_request<C: Connection>(
+    connection: &mut BufReader<C>,
+    max_body_size: usize,
+) -> io::Result<Result<Request, Response>> {
+    // Read the head, up to the empty line ending it, and at most one byte past the limit so
+    // that a line which never ends can't be buffered forever.
+    let mut head = Vec::new();
+    loop {
+        let start = head.len();
+        let mut line = (&mut *connection).take((MAX_HEAD_SIZE + 1 - head.len()) as u64);
+        if line.read_until(b'\n', &mut head).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if head.len() > MAX_HEAD_SIZE {
+            return Ok(Err(Response::error(
+                431,
+                "HeadersTooLarge",
+                "the request head is too large",
+            )));
+        }
+        if matches!(&head[start..], b"\r\n" | b"\n") && start > 0 {
+            break;
+        }
+    }
+
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut parsed = httparse::Request::new(&mut headers);
+    if let Err(e) = parsed.parse(&head) {
+        return Ok(Err(Response::error(400, "BadRequest", e)));
+    }
+    let method = parsed.method.unwrap_or_default().to_owned();
+    let target = parsed.path.unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            (key.to_owned(), value.to_owned())
+        })
+        .collect();
+
+    let header = |name: &str| {
+        parsed
+            .headers
+            .iter()
+            .find(|header| header.name.eq_ignore_ascii_case(name))
+            .map(|header| String::from_utf8_lossy(header.value).trim().to_owned())
+    };
+    let content_length = match header("Content-Length") {
+        Some(len) => match len.parse::<usize>() {
+            Ok(len) => len,
+            Err(_) => {
+                return Ok(Err(Response::error(
+                    400,
+                    "BadRequest",
+                    format!("invalid Content-Length '{len}'"),
+                )))
+            }
+        },
+        None if method == "POST" => {
+            return Ok(Err(Response::error(
+                411,
+                "LengthRequired",
+                "a Content-Length is required",
+            )))
+        }
+        None => 0,
+    };
+    if content_length > max_body_size {
+        return Ok(Err(Response::error(
+            413,
+            "TooLarge",
+            format!("minidumps over {max_body_size} bytes aren't accepted"),
+        )));
+    }
+    if header("Expect").is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue")) {
+        connection
+            .get_mut()
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .await?;
+    }
+
+    // The body grows as it arrives rather than trusting the Content-Length up front.
+    let mut body = Vec::new();
+    connection
+        .take(content_length as u64)
+        .read_to_end(&mut body)
+        .await?;
+    if body.len() < content_length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(Ok(Request {
+        method,
+        path: path.to_owned(),
+        query,
+        body,
+    }))
+}
@@ -309,6 +309,71 @@ fn test_human_symbols_garbo_url() {
     assert_eq!(stderr, "");
 }
 
+#[test]
+fn test_human_symbols_binary_caches() {
+    // Stage a private copy of the symbols so the caches don't leak into testdata
+    let rel_path = "test_app.pdb/5A9832E5287241C1838ED98914E9B7FF1/test_app.sym";
+    let symbols = test_output("binary-caches");
+    let sym_path = symbols.join(rel_path);
+    std::fs::create_dir_all(sym_path.parent().unwrap()).unwrap();
+    std::fs::copy(
+        PathBuf::from("../testdata/symbols").join(rel_path),
+        &sym_path,
+    )
+    .unwrap();
+    let _ = std::fs::remove_file(sym_path.with_extension("symc"));
+
+    // The first run builds the cache, the second one loads it
+    for _ in 0..2 {
+        let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+        let output = Command::new(bin)
+            .arg("--human")
+            .arg("--build-symbol-caches")
+            .arg("../testdata/test.dmp")
+            .arg(&symbols)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .unwrap();
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert!(output.status.success());
+        insta::assert_snapshot!("human-symbols", stdout);
+        assert_eq!(stderr, "");
+        assert!(sym_path.with_extension("symc").exists());
+    }
+}
+
+#[test]
+fn test_human_symbols_flat_layout() {
+    let symbols = test_output("flat-symbols");
+    std::fs::create_dir_all(&symbols).unwrap();
+    std::fs::copy(
+        "../testdata/symbols/test_app.pdb/5A9832E5287241C1838ED98914E9B7FF1/test_app.sym",
+        symbols.join("5A9832E5287241C1838ED98914E9B7FF1.sym"),
+    )
+    .unwrap();
+
+    let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+    let output = Command::new(bin)
+        .arg("--human")
+        .arg(format!("--symbols-path=flat:{}", symbols.display()))
+        .arg("../testdata/test.dmp")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(output.status.success());
+    insta::assert_snapshot!("human-symbols", stdout);
+    assert_eq!(stderr, "");
+}
+
 #[test]
 fn test_human_brief() {
     let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
@@ -648,6 +713,68 @@ fn test_brief_robots() {
     assert!(!stderr.is_empty());
 }
 
+#[test]
+fn test_validate_symbols() {
+    let sym_path =
+        "../testdata/symbols/test_app.pdb/5A9832E5287241C1838ED98914E9B7FF1/test_app.sym";
+    let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+    let output = Command::new(bin)
+        .arg("--validate-symbols")
+        .arg(sym_path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(stdout, format!("{sym_path}: 0 error(s), 0 warning(s)\n"));
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn test_validate_symbols_errors() {
+    let sym_path = test_output("validate-symbols-errors.sym");
+    std::fs::write(
+        &sym_path,
+        "MODULE Linux x86_64 ffff0000 bar
+FUNC 1000 30 0 func
+1000 10 11 7
+FUNC 1010 10 0 overlapping
+STACK CFI INIT 1000 30 .cfa: $rsp 8 + .ra: .cfa -8 + ^ +
+",
+    )
+    .unwrap();
+
+    let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+    let output = Command::new(bin)
+        .arg("--validate-symbols")
+        .arg(&sym_path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    let sym_path = sym_path.display();
+    assert_eq!(
+        stdout,
+        format!(
+            "{sym_path}:3: error[dangling-file]: FILE 7 is not defined
+{sym_path}:4: error[overlapping-functions]: FUNC 1010-1020 overlaps FUNC 1000-1030 (see line 2)
+{sym_path}:5: error[invalid-cfi]: expression for .ra (.cfa -8 + ^ +) has too few operands for +
+{sym_path}: 3 error(s), 0 warning(s)
+"
+        )
+    );
+    assert_eq!(stderr, "");
+}
+
 fn minimal_minidump() -> SynthMinidump {
     let context = minidump_synth::x86_context(Endian::Little, 0xf00800, 0x1010);
     let stack = Memory::with_section(
@@ -885,3 +1012,568 @@ fn test_linux_human() {
     insta::assert_snapshot!(stdout);
     assert_eq!(stderr, "");
 }
+
+/// Set up a directory of minidumps for --batch: two good ones and a broken one.
+fn batch_input(name: &str) -> PathBuf {
+    let dir = test_output(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy("../testdata/test.dmp", dir.join("a.dmp")).unwrap();
+    std::fs::copy("../testdata/test.dmp", dir.join("b.dmp")).unwrap();
+    std::fs::write(dir.join("c.dmp"), "not a minidump").unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a minidump either").unwrap();
+    dir
+}
+
+#[test]
+fn test_batch() {
+    let dir = batch_input("batch");
+    let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+    let output = Command::new(bin)
+        .arg("--batch")
+        .arg(dir.join("*.dmp"))
+        .arg("--batch-jobs=2")
+        .arg("--symbols-path=../testdata/symbols/")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success());
+
+    let reports: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(reports.len(), 3);
+    for (report, name) in reports.iter().zip(["a.dmp", "b.dmp", "c.dmp"]) {
+        assert_eq!(
+            report["minidump_path"],
+            dir.join(name).display().to_string()
+        );
+    }
+    assert_eq!(reports[0]["status"], "OK");
+    assert_eq!(reports[1]["status"], "OK");
+    assert_eq!(reports[2]["status"], "ERROR_MissingHeader");
+
+    // The reports match what processing each dump on its own produces.
+    let single = Command::new(bin)
+        .arg("--json")
+        .arg("../testdata/test.dmp")
+        .arg("../testdata/symbols/")
+        .output()
+        .unwrap();
+    let mut single: serde_json::Value = serde_json::from_slice(&single.stdout).unwrap();
+    single["minidump_path"] = reports[0]["minidump_path"].clone();
+    assert_eq!(reports[0], single);
+}
+
+#[test]
+fn test_batch_stdin_output_dir() {
+    let dir = batch_input("batch-stdin");
+    let out_dir = test_output("batch-stdin-out");
+    let _ = std::fs::remove_dir_all(&out_dir);
+    std::fs::create_dir_all(&out_dir).unwrap();
+
+    let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+    let mut child = Command::new(bin)
+        .arg("--batch=-")
+        .arg("--output-file")
+        .arg(&out_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    writeln!(
+        child.stdin.take().unwrap(),
+        "{}\n\n{}",
+        dir.join("a.dmp").display(),
+        dir.join("c.dmp").display()
+    )
+    .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let mut files: Vec<_> = std::fs::read_dir(&out_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    assert_eq!(files, ["a.dmp.json", "c.dmp.json"]);
+    let report: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(out_dir.join("a.dmp.json")).unwrap()).unwrap();
+    assert_eq!(report["status"], "OK");
+}
+
+/// Send an HTTP request to a --serve server, returning the status and the JSON body.
+fn http_request(
+    stream: &mut (impl Read + Write),
+    request: &str,
+    body: &[u8],
+) -> (u16, serde_json::Value) {
+    stream.write_all(request.as_bytes()).unwrap();
+    stream.write_all(body).unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    let response = String::from_utf8(response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+/// Start a --serve server on `addr` with extra `args`, returning it and the address it listens
+/// on.
+fn start_server(addr: &str, args: &[&str]) -> (std::process::Child, String) {
+    let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+    let mut child = Command::new(bin)
+        .arg("--serve")
+        .arg(addr)
+        .arg("--symbols-path=../testdata/symbols/")
+        .arg("--symbols-memory-limit-mb=64")
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    std::io::BufRead::read_line(
+        &mut BufReader::new(child.stdout.as_mut().unwrap()),
+        &mut line,
+    )
+    .unwrap();
+    let addr = line
+        .trim()
+        .strip_prefix("listening on ")
+        .unwrap()
+        .to_owned();
+    (child, addr)
+}
+
+#[test]
+fn test_serve() {
+    let (mut child, addr) = start_server("127.0.0.1:0", &[]);
+    let connect = || std::net::TcpStream::connect(&addr).unwrap();
+
+    let dump = std::fs::read("../testdata/test.dmp").unwrap();
+    let (status, report) = http_request(
+        &mut connect(),
+        &format!(
+            "POST /process HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\r\n",
+            dump.len()
+        ),
+        &dump,
+    );
+    assert_eq!(status, 200);
+    // The report is the same as processing the dump on its own.
+    let single = Command::new(env!("CARGO_BIN_EXE_minidump-stackwalk"))
+        .arg("--json")
+        .arg("../testdata/test.dmp")
+        .arg("../testdata/symbols/")
+        .output()
+        .unwrap();
+    let single: serde_json::Value = serde_json::from_slice(&single.stdout).unwrap();
+    assert_eq!(report, single);
+
+    let (status, error) = http_request(
+        &mut connect(),
+        "POST /process?features=bogus HTTP/1.1\r\nContent-Length: 4\r\n\r\n",
+        b"junk",
+    );
+    assert_eq!(status, 400);
+    assert_eq!(error["status"], "ERROR_BadRequest");
+    let (status, error) = http_request(
+        &mut connect(),
+        "POST /process HTTP/1.1\r\nContent-Length: 4\r\n\r\n",
+        b"junk",
+    );
+    assert_eq!(status, 400);
+    assert_eq!(error["status"], "ERROR_MissingHeader");
+    let (status, _) = http_request(&mut connect(), "GET /process HTTP/1.1\r\n\r\n", b"");
+    assert_eq!(status, 405);
+    let (status, _) = http_request(&mut connect(), "GET /nope HTTP/1.1\r\n\r\n", b"");
+    assert_eq!(status, 404);
+
+    let (status, health) = http_request(&mut connect(), "GET /health HTTP/1.1\r\n\r\n", b"");
+    assert_eq!(status, 200);
+    assert_eq!(health["status"], "ok");
+
+    let (status, metrics) = http_request(&mut connect(), "GET /metrics HTTP/1.1\r\n\r\n", b"");
+    assert_eq!(status, 200);
+    assert_eq!(metrics["requests"], 3);
+    assert_eq!(metrics["failed_requests"], 2);
+    assert_eq!(metrics["in_flight"], 0);
+    assert_eq!(metrics["symbols"]["modules_loaded"], 1);
+    assert!(metrics["symbols"]["cache_bytes"].as_u64().unwrap() > 0);
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn test_serve_limits() {
+    let (mut child, addr) = start_server(
+        "127.0.0.1:0",
+        &[
+            "--serve-max-body-mb=1",
+            "--serve-timeout-secs=1",
+            "--serve-connections=1",
+        ],
+    );
+    let connect = || std::net::TcpStream::connect(&addr).unwrap();
+
+    // Too large minidumps are refused before they're sent.
+    let (status, error) = http_request(
+        &mut connect(),
+        "POST /process HTTP/1.1\r\nContent-Length: 2000000\r\n\r\n",
+        b"",
+    );
+    assert_eq!(status, 413);
+    assert_eq!(error["status"], "ERROR_TooLarge");
+
+    // So are too large heads, even if their line never ends: this is one byte over the limit.
+    let (status, error) = http_request(
+        &mut connect(),
+        &format!("GET /{}", "a".repeat(64 * 1024 - 4)),
+        b"",
+    );
+    assert_eq!(status, 431);
+    assert_eq!(error["status"], "ERROR_HeadersTooLarge");
+
+    // A client that doesn't send its whole request in time gets cut off, and holds up the
+    // next connection until then.
+    let start = std::time::Instant::now();
+    let mut slow = connect();
+    slow.write_all(b"POST /process HTTP/1.1\r\nContent-Length: 100\r\n\r\nMDMP")
+        .unwrap();
+    let (status, _) = http_request(&mut connect(), "GET /health HTTP/1.1\r\n\r\n", b"");
+    assert_eq!(status, 200);
+    assert!(start.elapsed() >= std::time::Duration::from_millis(500));
+    let (status, error) = http_request(&mut slow, "", b"");
+    assert_eq!(status, 408);
+    assert_eq!(error["status"], "ERROR_Timeout");
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn test_serve_features() {
+    let (mut child, addr) = start_server("127.0.0.1:0", &["--max-frames=1"]);
+
+    // The command line options still apply on top of the features a request picks.
+    let dump = std::fs::read("../testdata/test.dmp").unwrap();
+    let (status, report) = http_request(
+        &mut std::net::TcpStream::connect(&addr).unwrap(),
+        &format!(
+            "POST /process?features=unstable-all HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            dump.len()
+        ),
+        &dump,
+    );
+    assert_eq!(status, 200);
+    let thread = &report["threads"][0];
+    assert_eq!(thread["frames"].as_array().unwrap().len(), 1);
+    assert_eq!(thread["unwind_stopped"], "frame_limit_reached");
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_serve_unix_socket() {
+    let socket = test_output("serve.sock");
+    let _ = std::fs::remove_file(&socket);
+    let (mut child, addr) = start_server(&format!("unix:{}", socket.display()), &[]);
+    assert_eq!(addr, format!("unix:{}", socket.display()));
+
+    let mut stream = std::os::unix::net::UnixStream::connect(&socket).unwrap();
+    let (status, health) = http_request(&mut stream, "GET /health HTTP/1.1\r\n\r\n", b"");
+    assert_eq!(status, 200);
+    assert_eq!(health["status"], "ok");
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn test_signature_rules() {
+    let rules = test_output("signature-rules.txt");
+    std::fs::write(
+        &rules,
+        "# Skip the crashing frame\nirrelevant test_app.exe@0x429e\nprefix test_app.exe@*\nmax_frames 2\n",
+    )
+    .unwrap();
+    let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+    let output = Command::new(bin)
+        .arg("--json")
+        .arg("--signature-rules")
+        .arg(&rules)
+        .arg("../testdata/test.dmp")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        report["signature"],
+        "test_app.exe@0x41ff | test_app.exe@0x53eb"
+    );
+
+    std::fs::write(&rules, "skip everything\n").unwrap();
+    let output = Command::new(bin)
+        .arg("--signature-rules")
+        .arg(&rules)
+        .arg("../testdata/test.dmp")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_diff() {
+    let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+    let output = Command::new(bin)
+        .arg("--json")
+        .arg("../testdata/test.dmp")
+        .arg("--diff")
+        .arg("../testdata/test.dmp")
+        .arg("../testdata/symbols")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let diff: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(diff["crash"], serde_json::json!([]));
+    assert_eq!(diff["modules"], serde_json::json!([]));
+    assert_eq!(diff["system_info"], serde_json::json!([]));
+    let frames = diff["crashing_thread"].as_array().unwrap();
+    assert!(!frames.is_empty());
+    assert!(frames.iter().all(|frame| frame["status"] == "same"));
+
+    let output = Command::new(bin)
+        .arg("--json")
+        .arg("../testdata/test.dmp")
+        .arg("--diff")
+        .arg("../testdata/linux-mini.dmp")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let diff: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let os = diff["system_info"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|change| change["name"] == "os")
+        .unwrap();
+    assert_eq!(os["old"], "Windows NT");
+    assert_eq!(os["new"], "Linux");
+    let kernel32 = diff["modules"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|change| change["name"] == "kernel32.dll")
+        .unwrap();
+    assert_eq!(kernel32["new"], serde_json::Value::Null);
+    assert!(diff["crashing_thread"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|frame| frame["status"] == "added" || frame["status"] == "removed"));
+}
+
+#[test]
+fn test_output_formats() {
+    let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+    let run = |format: &str| {
+        let output = Command::new(bin)
+            .arg(format)
+            .arg("../testdata/test.dmp")
+            .arg("../testdata/symbols")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let sarif: serde_json::Value = serde_json::from_str(&run("--sarif")).unwrap();
+    assert_eq!(sarif["version"], "2.1.0");
+    let result = &sarif["runs"][0]["results"][0];
+    assert_eq!(result["ruleId"], "EXCEPTION_ACCESS_VIOLATION_WRITE");
+    let location = &result["locations"][0]["physicalLocation"];
+    assert_eq!(
+        location["artifactLocation"]["uri"],
+        "file:///c:/test_app.cc"
+    );
+    assert_eq!(location["region"]["startLine"], 58);
+    assert_eq!(result["stacks"][0]["frames"].as_array().unwrap().len(), 4);
+
+    let markdown = run("--markdown");
+    assert!(markdown.starts_with("# Crash: EXCEPTION\\_ACCESS\\_VIOLATION\\_WRITE\n"));
+    assert!(markdown.contains("| 1 | test\\_app.exe | main | test\\_app.cc:65 | cfi |\n"));
+    assert!(markdown.contains("| 3 | kernel32.dll | +0x16fd6 |  | cfi |\n"));
+
+    let tsv = run("--tsv");
+    let mut lines = tsv.lines();
+    assert_eq!(
+        lines.next(),
+        Some("OS\tWindows NT\t5.1.2600 Service Pack 2")
+    );
+    assert_eq!(
+        lines.next(),
+        Some("CPU\tx86\tGenuineIntel family 6 model 13 stepping 8\t1")
+    );
+    assert_eq!(
+        lines.next(),
+        Some("Crash\tEXCEPTION_ACCESS_VIOLATION_WRITE\t0x45\t0")
+    );
+    assert!(tsv.contains("\n0\t1\ttest_app.exe\tmain\tc:\\test_app.cc\t65\t0x4\n"));
+    assert!(tsv.contains("\n0\t3\tkernel32.dll\t\t\t\t0x16fd6\n"));
+}
+
+#[test]
+fn test_machine_readable() {
+    let dumps = [
+        ("machine-readable", "../testdata/test.dmp"),
+        ("machine-readable-linux", "../testdata/linux-mini.dmp"),
+        (
+            "machine-readable-macos-inlines",
+            "../testdata/pipeline-inlines-macos-segv.dmp",
+        ),
+        (
+            "machine-readable-crashpad",
+            "../testdata/simple-crashpad.dmp",
+        ),
+    ];
+    let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+    for (name, dump) in dumps {
+        let output = Command::new(bin)
+            .arg("-m")
+            .arg(dump)
+            .arg("../testdata/symbols/")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .unwrap();
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert!(output.status.success());
+        insta::assert_snapshot!(name, stdout);
+        assert_eq!(stderr, "");
+    }
+}
+
+#[test]
+fn test_source_context() {
+    let root = test_output("source-root");
+    std::fs::create_dir_all(&root).unwrap();
+    let source: String = (1..=70).map(|line| format!("// line {line}\n")).collect();
+    std::fs::write(root.join("test_app.cc"), source).unwrap();
+
+    let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+    let output = Command::new(bin)
+        .arg("--json")
+        .arg("--source-root")
+        .arg(format!("c:={}", root.display()))
+        .arg("--source-context-lines=2")
+        .arg("../testdata/test.dmp")
+        .arg("../testdata/symbols")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let frames = &report["crashing_thread"]["frames"];
+    assert_eq!(
+        frames[0]["source_context"],
+        serde_json::json!({
+            "start_line": 56,
+            "lines": ["// line 56", "// line 57", "// line 58", "// line 59", "// line 60"],
+        })
+    );
+    assert_eq!(frames[1]["source_context"]["start_line"], 63);
+    // crt0.c isn't in the source root.
+    assert!(frames[2].get("source_context").is_none());
+}
+
+#[test]
+fn test_function_names() {
+    // A copy of test_app.sym with a mangled main function.
+    let symbols = test_output("mangled-symbols");
+    let sym_dir = symbols.join("test_app.pdb/5A9832E5287241C1838ED98914E9B7FF1");
+    std::fs::create_dir_all(&sym_dir).unwrap();
+    let sym = std::fs::read_to_string(
+        "../testdata/symbols/test_app.pdb/5A9832E5287241C1838ED98914E9B7FF1/test_app.sym",
+    )
+    .unwrap()
+    .replace(
+        "FUNC 41b0 86 8 main\n",
+        "FUNC 41b0 86 8 _ZN7testapp4main17h0123456789abcdefE\n",
+    );
+    std::fs::write(sym_dir.join("test_app.sym"), sym).unwrap();
+
+    let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+    let main_frame = |function_names: &str| {
+        let output = Command::new(bin)
+            .arg("--json")
+            .arg(format!("--function-names={function_names}"))
+            .arg("../testdata/test.dmp")
+            .arg(&symbols)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        report["crashing_thread"]["frames"][1].clone()
+    };
+
+    let frame = main_frame("raw");
+    assert_eq!(frame["function"], "_ZN7testapp4main17h0123456789abcdefE");
+    assert_eq!(frame["raw_function"], serde_json::Value::Null);
+
+    let frame = main_frame("demangle");
+    assert_eq!(frame["function"], "testapp::main::h0123456789abcdef");
+    assert_eq!(
+        frame["raw_function"],
+        "_ZN7testapp4main17h0123456789abcdefE"
+    );
+
+    let frame = main_frame("normalize");
+    assert_eq!(frame["function"], "testapp::main");
+    assert_eq!(
+        frame["raw_function"],
+        "_ZN7testapp4main17h0123456789abcdefE"
+    );
+}
+
+#[test]
+fn test_explain_unwind() {
+    let bin = env!("CARGO_BIN_EXE_minidump-stackwalk");
+    let frames = |explain_unwind: bool| {
+        let mut cmd = Command::new(bin);
+        if explain_unwind {
+            cmd.arg("--explain-unwind");
+        }
+        let output = cmd
+            .arg("--json")
+            .arg("../testdata/test.dmp")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        report["crashing_thread"]["frames"].clone()
+    };
+
+    let frames_with_notes = frames(true);
+    // The context frame wasn't unwound.
+    assert!(frames_with_notes[0].get("unwind_notes").is_none());
+    assert_eq!(
+        frames_with_notes[1]["unwind_notes"],
+        serde_json::json!([
+            "no call frame info",
+            "return address at 0x12fe8c (stack pointer + 0x8)",
+            "recovered registers eip esp ebp",
+        ])
+    );
+    assert!(frames(false)[1].get("unwind_notes").is_none());
+}
@@ -10,7 +10,7 @@ use super::impl_prelude::*;
 use minidump::format::CONTEXT_AMD64;
 use minidump::system_info::Os;
 use minidump::{
-    MinidumpContext, MinidumpContextValidity, MinidumpModuleList, MinidumpRawContext, UnifiedMemory,
+    CpuContext, MinidumpContext, MinidumpContextValidity, MinidumpRawContext, UnifiedMemory,
 };
 use std::collections::HashSet;
 use tracing::trace;
@@ -22,6 +22,74 @@ const STACK_POINTER_REGISTER: &str = "rsp";
 const FRAME_POINTER_REGISTER: &str = "rbp";
 // FIXME: rdi and rsi are also preserved on windows (but not in sysv) -- we should handle that?
 const CALLEE_SAVED_REGS: &[&str] = &["rbx", "rbp", "r12", "r13", "r14", "r15"];
+/// The code of Linux's `__restore_rt` signal trampoline: `mov rax, 15 (rt_sigreturn); syscall`.
+const SIGRETURN_CODE: &[u8] = &[0x48, 0xc7, 0xc0, 0x0f, 0x00, 0x00, 0x00, 0x0f, 0x05];
+/// The offset of `uc_mcontext.gregs` in the `ucontext_t` at the stack pointer of the
+/// signal trampoline.
+const SIGNAL_CONTEXT_OFFSET: Pointer = 40;
+/// The registers in `uc_mcontext.gregs`, in order.
+const SIGNAL_CONTEXT_REGS: &[&str] = &[
+    "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15", "rdi", "rsi", "rbp", "rbx", "rdx", "rax",
+    "rcx", "rsp", "rip",
+];
+/// The register macOS's `_sigtramp` keeps its `ucontext_t` pointer in while the handler runs.
+const MACOS_UCONTEXT_REGISTER: &str = "rbx";
+/// The offset of the `uc_mcontext` pointer in a macOS `ucontext_t`.
+const MACOS_UC_MCONTEXT_OFFSET: Pointer = 48;
+/// The offset of the thread state (`__ss`) in a macOS `mcontext_t`, after the exception state.
+const MACOS_THREAD_STATE_OFFSET: Pointer = 16;
+/// The registers in the thread state, in order.
+const MACOS_THREAD_STATE_REGS: &[&str] = &[
+    "rax", "rbx", "rcx", "rdx", "rdi", "rsi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip",
+];
+
+async fn get_caller_by_signal_context<P>(
+    ctx: &CONTEXT_AMD64,
+    args: &GetCallerFrameArgs<'_, P>,
+) -> Option<StackFrame>
+where
+    P: SymbolProvider + Sync,
+{
+    let (address, regs) = if args.callee_is_signal_trampoline(SIGRETURN_CODE).await {
+        trace!("trying signal context");
+        // The handler returned to the trampoline, popping its return address, which leaves
+        // the stack pointer at the `ucontext_t` the kernel saved the interrupted context in.
+        (
+            ctx.rsp.checked_add(SIGNAL_CONTEXT_OFFSET)?,
+            SIGNAL_CONTEXT_REGS,
+        )
+    } else if args.callee_is_macos_signal_trampoline() {
+        trace!("trying macOS signal context");
+        // The kernel passes `_sigtramp` a pointer to the `ucontext_t`, whose `uc_mcontext`
+        // points to the saved interrupted context.
+        let ucontext = ctx.get_register(MACOS_UCONTEXT_REGISTER, args.valid())?;
+        let mcontext: Pointer = args
+            .stack_memory
+            .get_memory_at_address(ucontext.checked_add(MACOS_UC_MCONTEXT_OFFSET)?)?;
+        (
+            mcontext.checked_add(MACOS_THREAD_STATE_OFFSET)?,
+            MACOS_THREAD_STATE_REGS,
+        )
+    } else {
+        return None;
+    };
+
+    let mut caller_ctx = CONTEXT_AMD64::default();
+    for (i, reg) in regs.iter().enumerate() {
+        let value = args
+            .stack_memory
+            .get_memory_at_address(address.checked_add(i as Pointer * POINTER_WIDTH)?)?;
+        caller_ctx.set_register(reg, value)?;
+    }
+    args.note(UnwindNote::SignalContext { address });
+
+    let context = MinidumpContext {
+        raw: MinidumpRawContext::Amd64(caller_ctx),
+        valid: MinidumpContextValidity::All,
+    };
+    Some(StackFrame::from_context(context, FrameTrust::SignalContext))
+}
 
 async fn get_caller_by_cfi<P>(
     ctx: &CONTEXT_AMD64,
@@ -40,9 +108,7 @@ where
 
     let mut stack_walker = CfiStackWalker::from_ctx_and_args(ctx, args, callee_forwarded_regs)?;
 
-    args.symbol_provider
-        .walk_frame(stack_walker.module, &mut stack_walker)
-        .await?;
+    stack_walker.walk(args).await?;
     let caller_ip = stack_walker.caller_ctx.rip;
     let caller_sp = stack_walker.caller_ctx.rsp;
 
@@ -185,6 +251,7 @@ where
         raw: MinidumpRawContext::Amd64(caller_ctx),
         valid: MinidumpContextValidity::Some(valid),
     };
+    args.note_return_address(last_bp + POINTER_WIDTH);
     Some(StackFrame::from_context(context, FrameTrust::FramePointer))
 }
 
@@ -226,16 +293,21 @@ where
 
     // Breakpad devs found that the first frame of an unwind can be really messed up,
     // and therefore benefits from a longer scan. Let's do it too.
-    let scan_range = if let FrameTrust::Context = args.callee_frame.trust {
-        extended_scan_range
-    } else {
-        default_scan_range
-    };
+    let scan_range =
+        if let FrameTrust::Context | FrameTrust::SignalContext = args.callee_frame.trust {
+            extended_scan_range
+        } else {
+            default_scan_range
+        };
 
     for i in 0..scan_range {
         let address_of_ip = last_sp.checked_add(i * POINTER_WIDTH)?;
         let caller_ip = stack_memory.get_memory_at_address(address_of_ip)?;
-        if instruction_seems_valid(caller_ip, args.modules, args.symbol_provider).await {
+        if let Some(trust) = args.accept_scan_candidate(
+            address_of_ip,
+            caller_ip,
+            instruction_seems_valid(caller_ip, args).await,
+        ) {
             // ip is pushed by CALL, so sp is just address_of_ip + ptr
             let caller_sp = address_of_ip.checked_add(POINTER_WIDTH)?;
 
@@ -311,7 +383,7 @@ where
                 raw: MinidumpRawContext::Amd64(caller_ctx),
                 valid: MinidumpContextValidity::Some(valid),
             };
-            return Some(StackFrame::from_context(context, FrameTrust::Scan));
+            return Some(StackFrame::from_context(context, trust));
         }
     }
 
@@ -343,17 +415,21 @@ where
 /// and immediately start doing unreliable scans.
 async fn instruction_seems_valid<P>(
     instruction: Pointer,
-    modules: &MinidumpModuleList,
-    symbol_provider: &P,
-) -> bool
+    args: &GetCallerFrameArgs<'_, P>,
+) -> Result<FrameTrust, ScanRejection>
 where
     P: SymbolProvider + Sync,
 {
-    if is_non_canonical(instruction) || instruction == 0 {
-        return false;
+    if instruction == 0 {
+        return Err(ScanRejection::Null);
+    }
+    if is_non_canonical(instruction) {
+        return Err(ScanRejection::NotAnAddress);
     }
 
-    super::instruction_seems_valid_by_symbols(instruction, modules, symbol_provider).await
+    super::instruction_seems_valid_by_symbols(instruction, args).await?;
+    args.check_call_site(instruction, call_site::is_x86_call)
+        .await
 }
 
 fn stack_seems_valid(
@@ -397,6 +473,9 @@ where
 {
     // .await doesn't like closures, so don't use Option chaining
     let mut frame = None;
+    if frame.is_none() {
+        frame = get_caller_by_signal_context(ctx, args).await;
+    }
     if frame.is_none() {
         frame = get_caller_by_cfi(ctx, args).await;
     }
@@ -433,8 +512,12 @@ where
     // the value to one less than that, so it points within the
     // CALL instruction. This is important because we use this value
     // to lookup the CFI we need to unwind the next frame.
+    //
+    // A signal context's ip is the interrupted instruction itself, so it's left alone.
     let ip = frame.context.get_instruction_pointer();
-    frame.instruction = ip - 1;
+    if frame.trust != FrameTrust::SignalContext {
+        frame.instruction = ip - 1;
+    }
 
     Some(frame)
 }
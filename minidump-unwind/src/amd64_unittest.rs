@@ -12,6 +12,12 @@ struct TestFixture {
     pub modules: MinidumpModuleList,
     pub system_info: SystemInfo,
     pub symbols: HashMap<String, String>,
+    /// The code in the dump's memory, and its address.
+    pub code: Option<(u64, Vec<u8>)>,
+    pub memory_info: Option<UnifiedMemoryInfoList<'static>>,
+    /// The unwind limits and notes (the memory fields are filled in from `code` and
+    /// `memory_info`).
+    pub options: UnwindOptions<'static>,
 }
 
 impl TestFixture {
@@ -34,6 +40,13 @@ impl TestFixture {
                 cpu_count: 1,
             },
             symbols: HashMap::new(),
+            code: None,
+            memory_info: None,
+            // Most tests check how frames were found.
+            options: UnwindOptions {
+                unwind_notes: true,
+                ..UnwindOptions::default()
+            },
         }
     }
 
@@ -52,10 +65,24 @@ impl TestFixture {
             bytes: &stack,
             endian: scroll::LE,
         };
+        let code_memory = self.code.as_ref().map(|(base, bytes)| {
+            UnifiedMemoryList::Memory(MinidumpMemoryList::from_regions(vec![MinidumpMemory {
+                desc: Default::default(),
+                base_address: *base,
+                size: bytes.len() as u64,
+                bytes,
+                endian: scroll::LE,
+            }]))
+        });
         let symbolizer = Symbolizer::new(string_symbol_supplier(self.symbols.clone()));
         let mut stack = CallStack::with_context(context);
 
-        walk_stack(
+        let options = UnwindOptions {
+            code_memory: code_memory.as_ref(),
+            memory_info: self.memory_info.as_ref(),
+            ..self.options
+        };
+        walk_stack_with_options(
             0,
             (),
             &mut stack,
@@ -63,6 +90,7 @@ impl TestFixture {
             &self.modules,
             &self.system_info,
             &symbolizer,
+            &options,
         )
         .await;
 
@@ -72,6 +100,12 @@ impl TestFixture {
     pub fn add_symbols(&mut self, name: String, symbols: String) {
         self.symbols.insert(name, symbols);
     }
+
+    /// Use the memory regions of `maps`, in the format of `/proc/self/maps`.
+    pub fn set_linux_maps(&mut self, maps: &'static str) {
+        let maps = MinidumpLinuxMaps::read(maps.as_bytes(), &[], scroll::LE, None).unwrap();
+        self.memory_info = UnifiedMemoryInfoList::new(None, Some(maps));
+    }
 }
 
 #[tokio::test]
@@ -326,6 +360,53 @@ async fn test_scan_without_symbols() {
     }
 }
 
+#[tokio::test]
+async fn test_unwind_limits() {
+    let mut f = TestFixture::new();
+    // Three frames found by scanning.
+    let stack = || {
+        let stack = Section::new();
+        stack.start().set_const(0x8000000080000000);
+        stack
+            .append_repeated(0, 16)
+            .D64(0x00007500b0000100)
+            .append_repeated(0, 16)
+            .D64(0x00007500b0000200)
+            .append_repeated(0, 16)
+            .D64(0x00007500b0000300)
+            .append_repeated(0, 32)
+    };
+
+    f.raw.rip = 0x00007400c0000200;
+    f.raw.rsp = 0x8000000080000000;
+
+    let s = f.walk_stack(stack()).await;
+    assert_eq!(s.frames.len(), 4);
+    assert_eq!(s.info, CallStackInfo::Ok);
+
+    f.options.max_scanned_frames = Some(2);
+    let s = f.walk_stack(stack()).await;
+    assert_eq!(s.frames.len(), 3);
+    assert_eq!(s.info, CallStackInfo::ScanLimitReached);
+
+    f.options.max_frames = Some(2);
+    let s = f.walk_stack(stack()).await;
+    assert_eq!(s.frames.len(), 2);
+    assert_eq!(s.info, CallStackInfo::FrameLimitReached);
+
+    // Limits that aren't reached don't truncate the stack.
+    f.options.max_frames = Some(4);
+    f.options.max_scanned_frames = Some(3);
+    let s = f.walk_stack(stack()).await;
+    assert_eq!(s.frames.len(), 4);
+    assert_eq!(s.info, CallStackInfo::Ok);
+
+    f.options.deadline = Some(std::time::Instant::now());
+    let s = f.walk_stack(stack()).await;
+    assert_eq!(s.frames.len(), 1);
+    assert_eq!(s.info, CallStackInfo::TimedOut);
+}
+
 #[tokio::test]
 async fn test_scan_with_symbols() {
     // Test that we can refine our scanning using symbols. Specifically we
@@ -402,9 +483,209 @@ async fn test_scan_with_symbols() {
         } else {
             unreachable!();
         }
+
+        let rejected = |offset: u64, value: u64, reason| UnwindNote::ScanRejected {
+            address: stack_start + offset,
+            value,
+            reason,
+        };
+        assert_eq!(
+            frame.unwind_notes,
+            [
+                UnwindNote::NoCallFrameInfo,
+                rejected(0, 0, ScanRejection::Null),
+                rejected(8, 0, ScanRejection::Null),
+                rejected(16, 0x00007400b0000000, ScanRejection::NoModule),
+                rejected(24, 0x00007500b0000000, ScanRejection::NoModule),
+                rejected(32, 0x00007400c0001000, ScanRejection::NoFunction),
+                rejected(40, 0x00007500b000aaaa, ScanRejection::NoFunction),
+                UnwindNote::ReturnAddress {
+                    address: stack_start + 48,
+                    offset: 48,
+                },
+                UnwindNote::RecoveredRegisters(vec!["rbp", "rsp", "rip"]),
+            ]
+        );
     }
 }
 
+#[tokio::test]
+async fn test_scan_with_call_sites() {
+    // Test that scanning rejects return addresses that don't follow a call
+    // instruction when the code is in the dump.
+    let mut f = TestFixture::new();
+    let mut stack = Section::new();
+    let stack_start = 0x8000000080000000u64;
+    stack.start().set_const(stack_start);
+
+    let code_start = 0x00007500b0000100u64;
+    let mut code = vec![0x90; 0x40]; // nop
+    code[0x1b..0x20].copy_from_slice(&[0xe8, 0x10, 0x00, 0x00, 0x00]); // call 0x130
+    f.code = Some((code_start, code));
+
+    let not_return_address = code_start + 0x10;
+    let return_address = code_start + 0x20;
+
+    let frame1_rsp = Label::new();
+    stack = stack
+        // frame 0
+        .D64(not_return_address) // a function pointer, say
+        .D64(return_address) // actual return address
+        // frame 1
+        .mark(&frame1_rsp)
+        .append_repeated(0, 32);
+
+    f.raw.rip = 0x00007400c0000200;
+    f.raw.rsp = stack.start().value().unwrap();
+
+    f.add_symbols(
+        String::from("module1"),
+        // The youngest frame's function.
+        String::from("FUNC 100 400 10 monotreme\n"),
+    );
+    f.add_symbols(
+        String::from("module2"),
+        // The calling frame's function.
+        String::from("FUNC 100 400 10 marsupial\n"),
+    );
+
+    let s = f.walk_stack(stack).await;
+    assert_eq!(s.frames.len(), 2);
+
+    let frame = &s.frames[1];
+    assert_eq!(frame.trust, FrameTrust::VerifiedScan);
+    assert_eq!(frame.instruction, return_address - 1);
+    assert_eq!(
+        frame.context.get_stack_pointer(),
+        frame1_rsp.value().unwrap()
+    );
+    assert_eq!(
+        frame.unwind_notes[..3],
+        [
+            UnwindNote::NoCallFrameInfo,
+            UnwindNote::ScanRejected {
+                address: stack_start,
+                value: not_return_address,
+                reason: ScanRejection::NoCall,
+            },
+            UnwindNote::ReturnAddress {
+                address: stack_start + 8,
+                offset: 8,
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_scan_jit() {
+    // Test that scanning accepts return addresses in executable memory that
+    // isn't part of any module, which is JITed code.
+    let mut f = TestFixture::new();
+    let mut stack = Section::new();
+    let stack_start = 0x8000000080000000u64;
+    stack.start().set_const(stack_start);
+
+    let jit_address = 0x00007600a0000400u64;
+    let data_address = 0x00007600b0000400u64;
+
+    let frame1_rsp = Label::new();
+    stack = stack
+        // frame 0
+        .D64(data_address) // a pointer to data
+        .D64(jit_address) // return address into JITed code
+        // frame 1
+        .mark(&frame1_rsp)
+        .append_repeated(0, 32);
+
+    f.raw.rip = 0x00007400c0000200;
+    f.raw.rsp = stack.start().value().unwrap();
+    f.set_linux_maps(
+        "7600a0000000-7600a0010000 r-xp 00000000 00:00 0 \n\
+         7600b0000000-7600b0010000 rw-p 00000000 00:00 0 \n",
+    );
+
+    let s = f.walk_stack(stack).await;
+    assert_eq!(s.frames.len(), 2);
+    assert!(!s.frames[0].jit);
+
+    let frame = &s.frames[1];
+    assert_eq!(frame.trust, FrameTrust::Scan);
+    assert_eq!(frame.instruction, jit_address - 1);
+    assert!(frame.module.is_none());
+    assert!(frame.jit);
+    assert_eq!(
+        frame.unwind_notes[1],
+        UnwindNote::ScanRejected {
+            address: stack_start,
+            value: data_address,
+            reason: ScanRejection::NoModule,
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_signal_context() {
+    // Test that the context a signal interrupted is restored from the
+    // ucontext_t on the stack of the signal trampoline.
+    let mut f = TestFixture::new();
+    let mut stack = Section::new();
+    let stack_start = 0x8000000080000000u64;
+    stack.start().set_const(stack_start);
+
+    // The trampoline: mov rax, 15; syscall
+    let trampoline = 0x00007400c0000100u64;
+    f.code = Some((
+        trampoline,
+        vec![0x48, 0xc7, 0xc0, 0x0f, 0x00, 0x00, 0x00, 0x0f, 0x05],
+    ));
+
+    let interrupted_rip = 0x00007500b0000123u64;
+    let interrupted_rsp = Label::new();
+    let interrupted_rbp = Label::new();
+    stack = stack
+        // The ucontext_t header: uc_flags, uc_link, uc_stack
+        .append_repeated(0, 40)
+        // uc_mcontext.gregs: r8-r15, rdi, rsi
+        .append_repeated(0, 80)
+        .D64(&interrupted_rbp)
+        .D64(0x5a5beeb38de23be8) // rbx
+        .append_repeated(0, 24) // rdx, rax, rcx
+        .D64(&interrupted_rsp)
+        .D64(interrupted_rip)
+        .append_repeated(0, 16)
+        // The interrupted frame
+        .mark(&interrupted_rsp)
+        .append_repeated(0, 16)
+        .mark(&interrupted_rbp)
+        .append_repeated(0, 16);
+
+    f.raw.rip = trampoline;
+    f.raw.rsp = stack.start().value().unwrap();
+
+    let s = f.walk_stack(stack).await;
+    assert!(s.frames.len() >= 2);
+
+    let frame = &s.frames[1];
+    assert_eq!(frame.trust, FrameTrust::SignalContext);
+    // The interrupted instruction isn't a return address.
+    assert_eq!(frame.instruction, interrupted_rip);
+    assert_eq!(frame.context.valid, MinidumpContextValidity::All);
+    if let MinidumpRawContext::Amd64(ctx) = &frame.context.raw {
+        assert_eq!(ctx.rip, interrupted_rip);
+        assert_eq!(ctx.rsp, interrupted_rsp.value().unwrap());
+        assert_eq!(ctx.rbp, interrupted_rbp.value().unwrap());
+        assert_eq!(ctx.rbx, 0x5a5beeb38de23be8);
+    } else {
+        unreachable!();
+    }
+    assert_eq!(
+        frame.unwind_notes[0],
+        UnwindNote::SignalContext {
+            address: stack_start + 40,
+        }
+    );
+}
+
 const CALLEE_SAVE_REGS: &[&str] = &["rip", "rbx", "rbp", "rsp", "r12", "r13", "r14", "r15"];
 
 fn init_cfi_state() -> (TestFixture, Section, CONTEXT_AMD64, MinidumpContextValidity) {
@@ -531,6 +812,123 @@ async fn test_cfi_at_4001() {
     check_cfi(f, stack, expected, expected_valid).await;
 }
 
+#[tokio::test]
+async fn test_macos_signal_context() {
+    // Test that the context a signal interrupted is restored from the mcontext_t that
+    // _sigtramp's ucontext_t (in rbx) points to.
+    let mut f = TestFixture::new();
+    f.system_info.os = Os::MacOs;
+    let mut stack = Section::new();
+    let stack_start = 0x8000000080000000u64;
+    stack.start().set_const(stack_start);
+
+    let interrupted_rip = 0x00007500b0000123u64;
+    let ucontext = Label::new();
+    let mcontext = Label::new();
+    let interrupted_rsp = Label::new();
+    let interrupted_rbp = Label::new();
+    stack = stack
+        // _sigtramp's frame
+        .append_repeated(0, 16)
+        // The ucontext_t: uc_onstack, uc_sigmask, uc_stack, uc_link, uc_mcsize
+        .mark(&ucontext)
+        .append_repeated(0, 48)
+        .D64(&mcontext)
+        // The mcontext_t: the exception state
+        .mark(&mcontext)
+        .append_repeated(0, 16)
+        // The thread state: rax, rbx, rcx, rdx, rdi, rsi
+        .D64(0)
+        .D64(0x5a5beeb38de23be8)
+        .append_repeated(0, 32)
+        .D64(&interrupted_rbp)
+        .D64(&interrupted_rsp)
+        // r8-r15
+        .append_repeated(0, 64)
+        .D64(interrupted_rip)
+        // The interrupted frame
+        .mark(&interrupted_rsp)
+        .append_repeated(0, 16)
+        .mark(&interrupted_rbp)
+        .append_repeated(0, 16);
+
+    f.add_symbols(
+        String::from("module1"),
+        String::from("FUNC 100 100 0 _sigtramp\n"),
+    );
+    f.raw.rip = 0x00007400c0000120;
+    f.raw.rsp = stack.start().value().unwrap();
+    f.raw.rbx = ucontext.value().unwrap();
+
+    let s = f.walk_stack(stack).await;
+    assert!(s.frames.len() >= 2);
+
+    let frame = &s.frames[1];
+    assert_eq!(frame.trust, FrameTrust::SignalContext);
+    assert_eq!(frame.instruction, interrupted_rip);
+    if let MinidumpRawContext::Amd64(ctx) = &frame.context.raw {
+        assert_eq!(ctx.rip, interrupted_rip);
+        assert_eq!(ctx.rsp, interrupted_rsp.value().unwrap());
+        assert_eq!(ctx.rbp, interrupted_rbp.value().unwrap());
+        assert_eq!(ctx.rbx, 0x5a5beeb38de23be8);
+    } else {
+        unreachable!();
+    }
+    assert_eq!(
+        frame.unwind_notes[0],
+        UnwindNote::SignalContext {
+            address: mcontext.value().unwrap() + 16,
+        }
+    );
+
+    // The same function on Linux isn't a trampoline.
+    f.system_info.os = Os::Linux;
+    let stack = Section::new();
+    stack.start().set_const(stack_start);
+    let s = f.walk_stack(stack.append_repeated(0, 64)).await;
+    assert!(s
+        .frames
+        .iter()
+        .all(|frame| frame.trust != FrameTrust::SignalContext));
+}
+
+#[tokio::test]
+async fn test_cfi_unwind_notes() {
+    let (mut f, mut stack, _, _) = init_cfi_state();
+
+    stack = stack
+        .D64(0x5a5beeb38de23be8) // saved %rbx
+        .D64(0x00007400c0005510) // return address
+        .append_repeated(0, 1000);
+
+    f.raw.set_register("rip", 0x00007400c0004001);
+
+    let s = f.walk_stack(stack).await;
+    assert_eq!(
+        s.frames[1].unwind_notes,
+        [
+            UnwindNote::CallFrameInfoRule(".cfa: $rsp 16 +".to_string()),
+            UnwindNote::CallFrameInfoRule(".ra: .cfa 8 - ^".to_string()),
+            UnwindNote::CallFrameInfoRule("rbx: .cfa 16 - ^".to_string()),
+            UnwindNote::RecoveredRegisters(vec![
+                "rbx", "rbp", "rsp", "r12", "r13", "r14", "r15", "rip"
+            ]),
+        ]
+    );
+
+    // Notes are only recorded when asked for.
+    let (mut f, stack, _, _) = init_cfi_state();
+    let stack = stack
+        .D64(0x5a5beeb38de23be8) // saved %rbx
+        .D64(0x00007400c0005510) // return address
+        .append_repeated(0, 1000);
+    f.raw.set_register("rip", 0x00007400c0004001);
+    f.options.unwind_notes = false;
+    let s = f.walk_stack(stack).await;
+    assert_eq!(s.frames[1].trust, FrameTrust::CallFrameInfo);
+    assert!(s.frames.iter().all(|frame| frame.unwind_notes.is_empty()));
+}
+
 #[tokio::test]
 async fn test_cfi_at_4002() {
     let (mut f, mut stack, mut expected, expected_valid) = init_cfi_state();
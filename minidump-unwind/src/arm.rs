@@ -6,9 +6,7 @@
 
 use super::impl_prelude::*;
 use minidump::system_info::Os;
-use minidump::{
-    CpuContext, MinidumpContext, MinidumpContextValidity, MinidumpModuleList, MinidumpRawContext,
-};
+use minidump::{CpuContext, MinidumpContext, MinidumpContextValidity, MinidumpRawContext};
 use std::collections::HashSet;
 use tracing::trace;
 
@@ -22,6 +20,47 @@ const STACK_POINTER: &str = Registers::StackPointer.name();
 const PROGRAM_COUNTER: &str = Registers::ProgramCounter.name();
 const _LINK_REGISTER: &str = Registers::LinkRegister.name();
 const CALLEE_SAVED_REGS: &[&str] = &["r4", "r5", "r6", "r7", "r8", "r9", "r10", "fp"];
+/// The code of glibc's `__default_rt_sa_restorer` signal trampoline:
+/// `mov r7, 173 (rt_sigreturn); svc 0`.
+const SIGRETURN_CODE: &[u8] = &[0xad, 0x70, 0xa0, 0xe3, 0x00, 0x00, 0x00, 0xef];
+/// The offset of `uc_mcontext.arm_r0` from the stack pointer of the signal trampoline, which
+/// points to a `siginfo_t` followed by the `ucontext_t`.
+const SIGNAL_CONTEXT_OFFSET: Pointer = 128 + 32;
+
+async fn get_caller_by_signal_context<P>(
+    ctx: &ArmContext,
+    args: &GetCallerFrameArgs<'_, P>,
+) -> Option<StackFrame>
+where
+    P: SymbolProvider + Sync,
+{
+    if !args.callee_is_signal_trampoline(SIGRETURN_CODE).await {
+        return None;
+    }
+    trace!("trying signal context");
+
+    // `uc_mcontext` has r0-r15 followed by cpsr, in the same order as our registers.
+    let last_sp = ctx.get_register(STACK_POINTER, args.valid())?;
+    let address = last_sp.checked_add(SIGNAL_CONTEXT_OFFSET)?;
+    let read = |i: usize| -> Option<Pointer> {
+        args.stack_memory
+            .get_memory_at_address(address.checked_add(i as Pointer * POINTER_WIDTH)? as u64)
+    };
+    let mut caller_ctx = ArmContext::default();
+    for (i, reg) in caller_ctx.iregs.iter_mut().enumerate() {
+        *reg = read(i)?;
+    }
+    caller_ctx.cpsr = read(caller_ctx.iregs.len())?;
+    args.note(UnwindNote::SignalContext {
+        address: address as u64,
+    });
+
+    let context = MinidumpContext {
+        raw: MinidumpRawContext::Arm(caller_ctx),
+        valid: MinidumpContextValidity::All,
+    };
+    Some(StackFrame::from_context(context, FrameTrust::SignalContext))
+}
 
 async fn get_caller_by_cfi<P>(
     ctx: &ArmContext,
@@ -36,9 +75,7 @@ where
 
     let mut stack_walker = CfiStackWalker::from_ctx_and_args(ctx, args, callee_forwarded_regs)?;
 
-    args.symbol_provider
-        .walk_frame(stack_walker.module, &mut stack_walker)
-        .await?;
+    stack_walker.walk(args).await?;
     let caller_pc = stack_walker.caller_ctx.get_register_always(PROGRAM_COUNTER);
     let caller_sp = stack_walker.caller_ctx.get_register_always(STACK_POINTER);
 
@@ -150,6 +187,7 @@ where
         raw: MinidumpRawContext::Arm(caller_ctx),
         valid: MinidumpContextValidity::Some(valid),
     };
+    args.note_return_address(last_fp as u64 + POINTER_WIDTH as u64);
     Some(StackFrame::from_context(context, FrameTrust::FramePointer))
 }
 
@@ -186,7 +224,11 @@ where
         let caller_pc = args
             .stack_memory
             .get_memory_at_address(address_of_pc as u64)?;
-        if instruction_seems_valid(caller_pc, args.modules, args.symbol_provider).await {
+        if let Some(trust) = args.accept_scan_candidate(
+            address_of_pc as u64,
+            caller_pc as u64,
+            instruction_seems_valid(caller_pc, args).await,
+        ) {
             // pc is pushed by CALL, so sp is just address_of_pc + ptr
             let caller_sp = address_of_pc.checked_add(POINTER_WIDTH)?;
 
@@ -211,7 +253,7 @@ where
                 raw: MinidumpRawContext::Arm(caller_ctx),
                 valid: MinidumpContextValidity::Some(valid),
             };
-            return Some(StackFrame::from_context(context, FrameTrust::Scan));
+            return Some(StackFrame::from_context(context, trust));
         }
     }
 
@@ -243,13 +285,20 @@ where
 /// and immediately start doing unreliable scans.
 async fn instruction_seems_valid<P>(
     instruction: Pointer,
-    modules: &MinidumpModuleList,
-    symbol_provider: &P,
-) -> bool
+    args: &GetCallerFrameArgs<'_, P>,
+) -> Result<FrameTrust, ScanRejection>
 where
     P: SymbolProvider + Sync,
 {
-    super::instruction_seems_valid_by_symbols(instruction as u64, modules, symbol_provider).await
+    super::instruction_seems_valid_by_symbols(instruction as u64, args).await?;
+    // The low bit of a return address is set when returning to Thumb code.
+    if instruction & 1 == 1 {
+        args.check_call_site((instruction & !1) as u64, call_site::is_thumb_call)
+            .await
+    } else {
+        args.check_call_site(instruction as u64, call_site::is_arm_call)
+            .await
+    }
 }
 
 /*
@@ -281,6 +330,9 @@ where
 {
     // .await doesn't like closures, so don't use Option chaining
     let mut frame = None;
+    if frame.is_none() {
+        frame = get_caller_by_signal_context(ctx, args).await;
+    }
     if frame.is_none() {
         frame = get_caller_by_cfi(ctx, args).await;
     }
@@ -327,8 +379,12 @@ where
     // the value to 2 less than that, so it points to the CALL instruction
     // (arm instructions are all 2 bytes wide). This is important because
     // we use this value to lookup the CFI we need to unwind the next frame.
+    //
+    // A signal context's pc is the interrupted instruction itself, so it's left alone.
     let ip = frame.context.get_instruction_pointer();
-    frame.instruction = ip - 2;
+    if frame.trust != FrameTrust::SignalContext {
+        frame.instruction = ip - 2;
+    }
 
     Some(frame)
 }
@@ -24,6 +24,43 @@ const PROGRAM_COUNTER: &str = "pc";
 const CALLEE_SAVED_REGS: &[&str] = &[
     "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27", "x28", "fp",
 ];
+/// The code of Linux's `__kernel_rt_sigreturn` signal trampoline:
+/// `mov x8, 139 (rt_sigreturn); svc 0`.
+const SIGRETURN_CODE: &[u8] = &[0x68, 0x11, 0x80, 0xd2, 0x01, 0x00, 0x00, 0xd4];
+/// The offset of `uc_mcontext.regs` from the stack pointer of the signal trampoline, which
+/// points to a `siginfo_t` followed by the `ucontext_t`.
+const SIGNAL_CONTEXT_OFFSET: Pointer = 128 + 184;
+
+async fn get_caller_by_signal_context<P>(
+    ctx: &ArmContext,
+    args: &GetCallerFrameArgs<'_, P>,
+) -> Option<StackFrame>
+where
+    P: SymbolProvider + Sync,
+{
+    if !args.callee_is_signal_trampoline(SIGRETURN_CODE).await {
+        return None;
+    }
+    trace!("trying signal context");
+
+    // `uc_mcontext.regs` is x0-x30, followed by sp and pc, in the same order as our registers.
+    let last_sp = ctx.get_register(STACK_POINTER, args.valid())?;
+    let address = last_sp.checked_add(SIGNAL_CONTEXT_OFFSET)?;
+    let mut caller_ctx = ArmContext::default();
+    for (i, reg) in ArmContext::REGISTERS.iter().enumerate() {
+        let value = args
+            .stack_memory
+            .get_memory_at_address(address.checked_add(i as Pointer * POINTER_WIDTH)?)?;
+        caller_ctx.set_register(reg, value)?;
+    }
+    args.note(UnwindNote::SignalContext { address });
+
+    let context = MinidumpContext {
+        raw: MinidumpRawContext::Arm64(caller_ctx),
+        valid: MinidumpContextValidity::All,
+    };
+    Some(StackFrame::from_context(context, FrameTrust::SignalContext))
+}
 
 async fn get_caller_by_cfi<P>(
     ctx: &ArmContext,
@@ -38,9 +75,7 @@ where
 
     let mut stack_walker = CfiStackWalker::from_ctx_and_args(ctx, args, callee_forwarded_regs)?;
 
-    args.symbol_provider
-        .walk_frame(stack_walker.module, &mut stack_walker)
-        .await?;
+    stack_walker.walk(args).await?;
 
     let caller_pc = stack_walker.caller_ctx.get_register_always(PROGRAM_COUNTER);
     let caller_sp = stack_walker.caller_ctx.get_register_always(STACK_POINTER);
@@ -224,6 +259,7 @@ where
         raw: MinidumpRawContext::Arm64(caller_ctx),
         valid: MinidumpContextValidity::Some(valid),
     };
+    args.note_return_address(last_fp + POINTER_WIDTH);
     Some(StackFrame::from_context(context, FrameTrust::FramePointer))
 }
 
@@ -307,16 +343,21 @@ where
 
     // Breakpad devs found that the first frame of an unwind can be really messed up,
     // and therefore benefits from a longer scan. Let's do it too.
-    let scan_range = if let FrameTrust::Context = args.callee_frame.trust {
-        extended_scan_range
-    } else {
-        default_scan_range
-    };
+    let scan_range =
+        if let FrameTrust::Context | FrameTrust::SignalContext = args.callee_frame.trust {
+            extended_scan_range
+        } else {
+            default_scan_range
+        };
 
     for i in 0..scan_range {
         let address_of_pc = last_sp.checked_add(i * POINTER_WIDTH)?;
         let caller_pc = args.stack_memory.get_memory_at_address(address_of_pc)?;
-        if instruction_seems_valid(caller_pc, args.modules, args.symbol_provider).await {
+        if let Some(trust) = args.accept_scan_candidate(
+            address_of_pc,
+            caller_pc,
+            instruction_seems_valid(caller_pc, args).await,
+        ) {
             // pc is pushed by CALL, so sp is just address_of_pc + ptr
             let caller_sp = address_of_pc.checked_add(POINTER_WIDTH)?;
 
@@ -341,7 +382,7 @@ where
                 raw: MinidumpRawContext::Arm64(caller_ctx),
                 valid: MinidumpContextValidity::Some(valid),
             };
-            return Some(StackFrame::from_context(context, FrameTrust::Scan));
+            return Some(StackFrame::from_context(context, trust));
         }
     }
 
@@ -373,17 +414,21 @@ where
 /// and immediately start doing unreliable scans.
 async fn instruction_seems_valid<P>(
     instruction: Pointer,
-    modules: &MinidumpModuleList,
-    symbol_provider: &P,
-) -> bool
+    args: &GetCallerFrameArgs<'_, P>,
+) -> Result<FrameTrust, ScanRejection>
 where
     P: SymbolProvider + Sync,
 {
-    if is_non_canonical(instruction) || instruction == 0 {
-        return false;
+    if instruction == 0 {
+        return Err(ScanRejection::Null);
+    }
+    if is_non_canonical(instruction) {
+        return Err(ScanRejection::NotAnAddress);
     }
 
-    super::instruction_seems_valid_by_symbols(instruction, modules, symbol_provider).await
+    super::instruction_seems_valid_by_symbols(instruction, args).await?;
+    args.check_call_site(instruction, call_site::is_arm64_call)
+        .await
 }
 
 fn is_non_canonical(instruction: Pointer) -> bool {
@@ -420,6 +465,9 @@ where
 {
     // .await doesn't like closures, so don't use Option chaining
     let mut frame = None;
+    if frame.is_none() {
+        frame = get_caller_by_signal_context(ctx, args).await;
+    }
     if frame.is_none() {
         frame = get_caller_by_cfi(ctx, args).await;
     }
@@ -454,7 +502,10 @@ where
         // to a register), so we need to permit the stack pointer to not
         // change for the first frame of the unwind. After that we need
         // more strict validation to avoid infinite loops.
-        let is_leaf = args.callee_frame.trust == FrameTrust::Context && sp == last_sp;
+        let is_leaf = matches!(
+            args.callee_frame.trust,
+            FrameTrust::Context | FrameTrust::SignalContext
+        ) && sp == last_sp;
         if !is_leaf {
             trace!("stack pointer went backwards, assuming unwind complete");
             return None;
@@ -468,8 +519,12 @@ where
     // the value to 4 less than that, so it points to the CALL instruction
     // (arm64 instructions are all 4 bytes wide). This is important because
     // we use this value to lookup the CFI we need to unwind the next frame.
+    //
+    // A signal context's pc is the interrupted instruction itself, so it's left alone.
     let ip = frame.context.get_instruction_pointer();
-    frame.instruction = ip - 4;
+    if frame.trust != FrameTrust::SignalContext {
+        frame.instruction = ip - 4;
+    }
 
     Some(frame)
 }
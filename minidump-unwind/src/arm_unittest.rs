@@ -1182,3 +1182,64 @@ async fn test_frame_pointer_barely_no_overflow() {
         }
     }
 }
+
+#[tokio::test]
+async fn test_signal_context() {
+    // Test that the context a signal interrupted is restored from the
+    // ucontext_t on the stack of the signal trampoline.
+    let mut f = TestFixture::new();
+    f.system_info.os = Os::Linux;
+    let mut stack = Section::new();
+    let stack_start = 0x80000000u32;
+    stack.start().set_const(stack_start as u64);
+
+    let interrupted_pc = 0x50000124u32;
+    let interrupted_sp = Label::new();
+    stack = stack
+        // The siginfo_t
+        .append_repeated(0, 128)
+        // The ucontext_t header: uc_flags, uc_link, uc_stack, then trap_no, error_code
+        // and oldmask
+        .append_repeated(0, 32)
+        // uc_mcontext: r0-r10
+        .D32(0xb5d55e68)
+        .append_repeated(0, 40)
+        .D32(0x8112e110) // fp
+        .D32(0) // ip
+        .D32(&interrupted_sp)
+        .D32(0x50000200) // lr
+        .D32(interrupted_pc)
+        .D32(0x60000010) // cpsr
+        // The interrupted frame
+        .mark(&interrupted_sp)
+        .append_repeated(0, 16);
+
+    f.add_symbols(
+        String::from("module1"),
+        String::from("FUNC 100 10 0 __default_rt_sa_restorer\n"),
+    );
+    f.raw.set_register("pc", 0x40000100);
+    f.raw.set_register("sp", stack_start);
+
+    let s = f.walk_stack(stack).await;
+    assert!(s.frames.len() >= 2);
+
+    let frame = &s.frames[1];
+    assert_eq!(frame.trust, FrameTrust::SignalContext);
+    // The interrupted instruction isn't a return address.
+    assert_eq!(frame.instruction, interrupted_pc as u64);
+    assert_eq!(frame.context.valid, MinidumpContextValidity::All);
+    if let MinidumpRawContext::Arm(ctx) = &frame.context.raw {
+        assert_eq!(ctx.iregs[0], 0xb5d55e68);
+        assert_eq!(ctx.get_register_always("fp"), 0x8112e110);
+        assert_eq!(
+            ctx.get_register_always("sp") as u64,
+            interrupted_sp.value().unwrap()
+        );
+        assert_eq!(ctx.get_register_always("lr"), 0x50000200);
+        assert_eq!(ctx.get_register_always("pc"), interrupted_pc);
+        assert_eq!(ctx.cpsr, 0x60000010);
+    } else {
+        unreachable!();
+    }
+}
@@ -0,0 +1,179 @@
+//! Checks that the code before a candidate return address is a call instruction.
+//!
+//! Stack scanning accepts any value that points into a function, which also matches
+//! function pointers and stale return addresses. A real return address follows the call
+//! that pushed it (or put it in the link register), so the bytes before it are checked for
+//! the call instructions of the CPU.
+
+use std::convert::TryInto;
+
+/// How many bytes before a return address the checks look at.
+pub(crate) const CALL_SITE_SIZE: usize = 8;
+
+/// Whether `code`, the bytes before a return address, ends with an x86 or x86-64 call.
+///
+/// This handles `call rel32` and all the forms of `call r/m` (the prefixes before them
+/// don't matter).
+pub(crate) fn is_x86_call(code: &[u8]) -> bool {
+    let len = code.len();
+    if len >= 5 && code[len - 5] == 0xe8 {
+        return true;
+    }
+    (2..=7.min(len)).any(|size| {
+        let start = len - size;
+        code[start] == 0xff && indirect_call_size(&code[start + 1..]) == Some(size)
+    })
+}
+
+/// The size of a `call r/m` (`FF /2`) instruction, given the bytes after its opcode.
+fn indirect_call_size(operands: &[u8]) -> Option<usize> {
+    let modrm = *operands.first()?;
+    if (modrm >> 3) & 7 != 2 {
+        return None;
+    }
+    let rm = modrm & 7;
+    Some(match modrm >> 6 {
+        0b11 => 2,
+        0b00 if rm == 0b100 => {
+            // The SIB byte may have a 32-bit displacement instead of a base register.
+            let sib = *operands.get(1)?;
+            if sib & 7 == 0b101 {
+                7
+            } else {
+                3
+            }
+        }
+        0b00 if rm == 0b101 => 6,
+        0b00 => 2,
+        0b01 if rm == 0b100 => 4,
+        0b01 => 3,
+        _ if rm == 0b100 => 7,
+        _ => 6,
+    })
+}
+
+/// Whether `code`, the bytes before a return address, ends with an ARM64 `bl`, `blr`, or
+/// pointer-authenticated `blra*`.
+pub(crate) fn is_arm64_call(code: &[u8]) -> bool {
+    let Some(instruction) = last_u32(code) else {
+        return false;
+    };
+    // bl <label>
+    instruction & 0xfc00_0000 == 0x9400_0000
+        // blr <Xn>
+        || instruction & 0xffff_fc1f == 0xd63f_0000
+        // blraa, blrab, blraaz, blrabz
+        || instruction & 0xfeff_f800 == 0xd63f_0800
+}
+
+/// Whether `code`, the bytes before a return address, ends with an ARM `bl` or `blx`.
+pub(crate) fn is_arm_call(code: &[u8]) -> bool {
+    let Some(instruction) = last_u32(code) else {
+        return false;
+    };
+    let condition = instruction >> 28;
+    // bl <label>
+    (condition != 0xf && instruction & 0x0f00_0000 == 0x0b00_0000)
+        // blx <label>
+        || instruction & 0xfe00_0000 == 0xfa00_0000
+        // blx <Rm>
+        || (condition != 0xf && instruction & 0x0fff_fff0 == 0x012f_ff30)
+}
+
+/// Whether `code`, the bytes before a return address, ends with a Thumb `bl` or `blx`.
+pub(crate) fn is_thumb_call(code: &[u8]) -> bool {
+    let halfword = |back: usize| {
+        let start = code.len().checked_sub(back)?;
+        Some(u16::from_le_bytes([code[start], code[start + 1]]))
+    };
+    // blx <Rm>
+    if halfword(2).is_some_and(|hw| hw & 0xff87 == 0x4780) {
+        return true;
+    }
+    // bl <label> and blx <label>
+    match (halfword(4), halfword(2)) {
+        (Some(hw1), Some(hw2)) => hw1 & 0xf800 == 0xf000 && hw2 & 0xc000 == 0xc000,
+        _ => false,
+    }
+}
+
+fn last_u32(code: &[u8]) -> Option<u32> {
+    let start = code.len().checked_sub(4)?;
+    Some(u32::from_le_bytes(code[start..].try_into().ok()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_x86_call() {
+        // call rel32
+        assert!(is_x86_call(&[0x90, 0x90, 0x90, 0xe8, 1, 2, 3, 4]));
+        // call rax
+        assert!(is_x86_call(&[0x90, 0x90, 0xff, 0xd0]));
+        // call r8
+        assert!(is_x86_call(&[0x90, 0x41, 0xff, 0xd0]));
+        // call qword ptr [rax]
+        assert!(is_x86_call(&[0x90, 0xff, 0x10]));
+        // call qword ptr [rax + 0x10]
+        assert!(is_x86_call(&[0x90, 0xff, 0x50, 0x10]));
+        // call qword ptr [rip + 0x1234]
+        assert!(is_x86_call(&[0x90, 0xff, 0x15, 0x34, 0x12, 0, 0]));
+        // call qword ptr [rsp + 8]
+        assert!(is_x86_call(&[0xff, 0x54, 0x24, 0x08]));
+        // call qword ptr [rax*8 + 0x1000]
+        assert!(is_x86_call(&[0x90, 0xff, 0x14, 0xc5, 0, 0x10, 0, 0]));
+
+        // jmp rax
+        assert!(!is_x86_call(&[0x90, 0x90, 0xff, 0xe0]));
+        // mov rax, rbx; ret
+        assert!(!is_x86_call(&[0x90, 0x48, 0x89, 0xd8, 0xc3]));
+        assert!(!is_x86_call(&[]));
+        assert!(!is_x86_call(&[0xff]));
+    }
+
+    #[test]
+    fn test_arm64_call() {
+        let code = |instruction: u32| instruction.to_le_bytes();
+        // bl #0x100
+        assert!(is_arm64_call(&code(0x9400_0040)));
+        // blr x8
+        assert!(is_arm64_call(&code(0xd63f_0100)));
+        // blraaz x8
+        assert!(is_arm64_call(&code(0xd63f_091f)));
+        // b #0x100
+        assert!(!is_arm64_call(&code(0x1400_0040)));
+        // br x8
+        assert!(!is_arm64_call(&code(0xd61f_0100)));
+        // ret
+        assert!(!is_arm64_call(&code(0xd65f_03c0)));
+        assert!(!is_arm64_call(&[0x40, 0, 0]));
+    }
+
+    #[test]
+    fn test_arm_call() {
+        let code = |instruction: u32| instruction.to_le_bytes();
+        // bl #0x100
+        assert!(is_arm_call(&code(0xeb00_003e)));
+        // blx #0x100
+        assert!(is_arm_call(&code(0xfa00_003e)));
+        // blx r3
+        assert!(is_arm_call(&code(0xe12f_ff33)));
+        // b #0x100
+        assert!(!is_arm_call(&code(0xea00_003e)));
+        // bx lr
+        assert!(!is_arm_call(&code(0xe12f_ff1e)));
+
+        // bl #0x100
+        assert!(is_thumb_call(&[0x00, 0xf0, 0x7e, 0xf8]));
+        // blx #0x100
+        assert!(is_thumb_call(&[0x00, 0xf0, 0x7e, 0xe8]));
+        // blx r3
+        assert!(is_thumb_call(&[0x00, 0xbf, 0x98, 0x47]));
+        // b.w #0x100
+        assert!(!is_thumb_call(&[0x00, 0xf0, 0x7e, 0xb8]));
+        // bx lr
+        assert!(!is_thumb_call(&[0x00, 0xbf, 0x70, 0x47]));
+    }
+}
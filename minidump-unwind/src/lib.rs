@@ -10,11 +10,13 @@ mod amd64;
 mod arm;
 mod arm64;
 mod arm64_old;
+mod call_site;
 mod mips;
 pub mod symbols;
 pub mod system_info;
 mod x86;
 
+use minidump::system_info::Os;
 use minidump::*;
 use minidump_common::utils::basename;
 use scroll::ctx::{SizeWith, TryFromCtx};
@@ -22,6 +24,8 @@ use std::borrow::Cow;
 use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::convert::TryFrom;
 use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Instant;
 use tracing::trace;
 
 pub use crate::symbols::*;
@@ -32,20 +36,151 @@ struct GetCallerFrameArgs<'a, P> {
     callee_frame: &'a StackFrame,
     grand_callee_frame: Option<&'a StackFrame>,
     stack_memory: UnifiedMemory<'a, 'a>,
+    code_memory: Option<&'a UnifiedMemoryList<'a>>,
+    memory_info: Option<&'a UnifiedMemoryInfoList<'a>>,
     modules: &'a MinidumpModuleList,
     system_info: &'a SystemInfo,
     symbol_provider: &'a P,
+    /// The notes on how the caller was found, for [`StackFrame::unwind_notes`], `None` if
+    /// they aren't kept.
+    notes: Option<&'a Mutex<Vec<UnwindNote>>>,
 }
 
 impl<P> GetCallerFrameArgs<'_, P> {
     fn valid(&self) -> &MinidumpContextValidity {
         &self.callee_frame.context.valid
     }
+
+    fn note(&self, note: UnwindNote) {
+        if let Some(notes) = self.notes {
+            notes.lock().unwrap().push(note);
+        }
+    }
+
+    /// Note that the caller's return address was found on the stack at `address`.
+    fn note_return_address(&self, address: u64) {
+        let offset = address.wrapping_sub(self.callee_frame.context.get_stack_pointer());
+        self.note(UnwindNote::ReturnAddress { address, offset });
+    }
+
+    /// Check the result of validating `value`, at `address`, as a return address for stack
+    /// scanning, noting why it was rejected or where the accepted one was.
+    fn accept_scan_candidate(
+        &self,
+        address: u64,
+        value: u64,
+        validity: Result<FrameTrust, ScanRejection>,
+    ) -> Option<FrameTrust> {
+        match validity {
+            Ok(trust) => {
+                self.note_return_address(address);
+                Some(trust)
+            }
+            Err(reason) => {
+                self.note(UnwindNote::ScanRejected {
+                    address,
+                    value,
+                    reason,
+                });
+                None
+            }
+        }
+    }
+}
+
+impl<P> GetCallerFrameArgs<'_, P>
+where
+    P: SymbolProvider + Sync,
+{
+    /// Check that the code before `return_address` is a call instruction, according to
+    /// `is_call`.
+    ///
+    /// If the code can't be read, the return address is only as good as a
+    /// [`FrameTrust::Scan`].
+    async fn check_call_site(
+        &self,
+        return_address: u64,
+        is_call: fn(&[u8]) -> bool,
+    ) -> Result<FrameTrust, ScanRejection> {
+        match self.read_code_before(return_address).await {
+            Some(code) if is_call(&code) => Ok(FrameTrust::VerifiedScan),
+            Some(_) => Err(ScanRejection::NoCall),
+            None => Ok(FrameTrust::Scan),
+        }
+    }
+
+    /// Read the code before `address`, from the dump's memory or the module's binary.
+    async fn read_code_before(&self, address: u64) -> Option<Vec<u8>> {
+        let size = call_site::CALL_SITE_SIZE;
+        self.read_code(address.checked_sub(size as u64)?, size)
+            .await
+    }
+
+    /// Read `size` bytes of code at `start`, from the dump's memory or the module's binary.
+    async fn read_code(&self, start: u64, size: usize) -> Option<Vec<u8>> {
+        let in_memory = self.code_memory.and_then(|memory| {
+            let memory = memory.memory_at_address(start)?;
+            let offset = usize::try_from(start - memory.base_address()).ok()?;
+            memory.bytes().get(offset..offset.checked_add(size)?)
+        });
+        if let Some(code) = in_memory {
+            return Some(code.to_vec());
+        }
+        let module = self.modules.module_at_address(start)?;
+        self.symbol_provider.read_code(module, start, size).await
+    }
+
+    /// Check if the callee is a Linux signal trampoline, which a signal handler returns to
+    /// so that the trampoline's `rt_sigreturn` restores the interrupted context.
+    ///
+    /// Trampolines are recognized by their name, or by their code being `sigreturn_code`.
+    async fn callee_is_signal_trampoline(&self, sigreturn_code: &[u8]) -> bool {
+        if !matches!(self.system_info.os, Os::Linux | Os::Android) {
+            return false;
+        }
+        let name = self.callee_frame.function_name.as_deref();
+        if matches!(
+            name,
+            Some("__restore_rt" | "__kernel_rt_sigreturn" | "__default_rt_sa_restorer")
+        ) {
+            return true;
+        }
+        // Trampolines aren't called, the address the handler returns to is their first
+        // instruction.
+        let code = self
+            .read_code(self.callee_frame.resume_address, sigreturn_code.len())
+            .await;
+        code.as_deref() == Some(sigreturn_code)
+    }
+
+    /// Check if the callee is macOS's `_sigtramp`, which calls signal handlers and then
+    /// `sigreturn`s to the interrupted context.
+    fn callee_is_macos_signal_trampoline(&self) -> bool {
+        self.system_info.os == Os::MacOs
+            && matches!(
+                self.callee_frame.function_name.as_deref(),
+                Some("_sigtramp" | "__sigtramp")
+            )
+    }
+}
+
+/// Whether `address` is in executable memory that isn't part of any module, which is
+/// where JITs put the code they generate.
+fn is_jit_code(
+    address: u64,
+    modules: &MinidumpModuleList,
+    memory_info: Option<&UnifiedMemoryInfoList<'_>>,
+) -> bool {
+    modules.module_at_address(address).is_none()
+        && memory_info
+            .and_then(|memory_info| memory_info.memory_info_at_address(address))
+            .is_some_and(|info| info.is_executable())
 }
 
 mod impl_prelude {
     pub(crate) use super::{
-        CfiStackWalker, FrameTrust, GetCallerFrameArgs, StackFrame, SymbolProvider,
+        call_site, CfiStackWalker, FrameTrust, GetCallerFrameArgs, ScanRejection, StackFrame,
+        SymbolProvider, UnwindNote,
     };
 }
 
@@ -58,6 +193,8 @@ pub enum FrameTrust {
     None,
     /// Scanned the stack, found this.
     Scan,
+    /// Scanned the stack, found this after a call instruction.
+    VerifiedScan,
     /// Found while scanning stack using call frame info.
     CfiScan,
     /// Derived from frame pointer.
@@ -66,6 +203,8 @@ pub enum FrameTrust {
     CallFrameInfo,
     /// Explicitly provided by some external stack walker.
     PreWalked,
+    /// Restored from the context saved when a signal handler was called.
+    SignalContext,
     /// Given as instruction pointer in a context.
     Context,
 }
@@ -76,11 +215,13 @@ impl FrameTrust {
     pub fn description(&self) -> &'static str {
         match *self {
             FrameTrust::Context => "given as instruction pointer in context",
+            FrameTrust::SignalContext => "signal context",
             FrameTrust::PreWalked => "recovered by external stack walker",
             FrameTrust::CallFrameInfo => "call frame info",
             FrameTrust::CfiScan => "call frame info with scanning",
             FrameTrust::FramePointer => "previous frame's frame pointer",
             FrameTrust::Scan => "stack scanning",
+            FrameTrust::VerifiedScan => "stack scanning with a call site",
             FrameTrust::None => "unknown",
         }
     }
@@ -88,16 +229,95 @@ impl FrameTrust {
     pub fn as_str(&self) -> &'static str {
         match *self {
             FrameTrust::Context => "context",
+            FrameTrust::SignalContext => "signal_context",
             FrameTrust::PreWalked => "prewalked",
             FrameTrust::CallFrameInfo => "cfi",
             FrameTrust::CfiScan => "cfi_scan",
             FrameTrust::FramePointer => "frame_pointer",
             FrameTrust::Scan => "scan",
+            FrameTrust::VerifiedScan => "verified_scan",
             FrameTrust::None => "non",
         }
     }
 }
 
+/// A note on how the stack walker found a frame's caller, see
+/// [`StackFrame::unwind_notes`][].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnwindNote {
+    /// No call frame info could be evaluated for the callee.
+    NoCallFrameInfo,
+    /// A rule of the call frame info that was evaluated, e.g. `.cfa: $rsp 16 +`.
+    CallFrameInfoRule(String),
+    /// Stack scanning rejected `value`, at `address`, as a return address.
+    ScanRejected {
+        address: u64,
+        value: u64,
+        reason: ScanRejection,
+    },
+    /// The return address was found at `address`, `offset` bytes above the callee's stack
+    /// pointer.
+    ReturnAddress { address: u64, offset: u64 },
+    /// The callee is a signal trampoline, and the caller's registers were restored from the
+    /// context saved at `address`.
+    SignalContext { address: u64 },
+    /// The registers of the caller that were recovered.
+    RecoveredRegisters(Vec<&'static str>),
+}
+
+impl std::fmt::Display for UnwindNote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnwindNote::NoCallFrameInfo => write!(f, "no call frame info"),
+            UnwindNote::CallFrameInfoRule(rule) => write!(f, "call frame info rule {rule}"),
+            UnwindNote::ScanRejected {
+                address,
+                value,
+                reason,
+            } => write!(f, "scan rejected {value:#x} at {address:#x}: {reason}"),
+            UnwindNote::ReturnAddress { address, offset } => {
+                write!(
+                    f,
+                    "return address at {address:#x} (stack pointer + {offset:#x})"
+                )
+            }
+            UnwindNote::SignalContext { address } => {
+                write!(f, "signal context at {address:#x}")
+            }
+            UnwindNote::RecoveredRegisters(registers) => {
+                write!(f, "recovered registers {}", registers.join(" "))
+            }
+        }
+    }
+}
+
+/// Why stack scanning rejected a value as a return address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScanRejection {
+    /// The value is (close to) null.
+    Null,
+    /// The value can't be an instruction address on the CPU.
+    NotAnAddress,
+    /// The value isn't in any module.
+    NoModule,
+    /// The module's symbols have no function at the value.
+    NoFunction,
+    /// The value doesn't follow a call instruction.
+    NoCall,
+}
+
+impl std::fmt::Display for ScanRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ScanRejection::Null => "null",
+            ScanRejection::NotAnAddress => "not an instruction address",
+            ScanRejection::NoModule => "not in a module",
+            ScanRejection::NoFunction => "not in a function",
+            ScanRejection::NoCall => "not after a call",
+        })
+    }
+}
+
 /// The calling convention of a function.
 #[derive(Debug, Clone)]
 pub enum CallingConvention {
@@ -125,6 +345,15 @@ pub struct FunctionArg {
     pub value: Option<u64>,
 }
 
+/// The lines of source code surrounding a frame's source line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceContext {
+    /// The (1-based) line number of the first entry in `lines`.
+    pub start_line: u32,
+    /// The lines of source code, without line terminators.
+    pub lines: Vec<String>,
+}
+
 /// A stack frame for an inlined function.
 ///
 /// See [`StackFrame::inlines`][] for more details.
@@ -132,10 +361,15 @@ pub struct FunctionArg {
 pub struct InlineFrame {
     /// The name of the function
     pub function_name: String,
+    /// The name of the function as written in the symbol file, if `function_name` was
+    /// demangled or normalized
+    pub raw_function_name: Option<String>,
     /// The file name of the stack frame
     pub source_file_name: Option<String>,
     /// The line number of the stack frame
     pub source_line: Option<u32>,
+    /// The source code around `source_line`, if it was requested and could be found.
+    pub source_context: Option<SourceContext>,
 }
 
 /// A single stack frame produced from unwinding a thread's stack.
@@ -213,6 +447,10 @@ pub struct StackFrame {
     /// The function name, may be omitted if debug symbols are not available.
     pub function_name: Option<String>,
 
+    /// The function name as written in the symbol file, if `function_name` was demangled
+    /// or normalized (see minidump-processor's `ProcessorOptions::demangle`).
+    pub raw_function_name: Option<String>,
+
     /// The start address of the function, may be omitted if debug symbols
     /// are not available.
     pub function_base: Option<u64>,
@@ -232,6 +470,12 @@ pub struct StackFrame {
     /// are not available.
     pub source_line_base: Option<u64>,
 
+    /// The source code around `source_line`, if it was requested and could be found.
+    ///
+    /// This is never filled in by the stack walker itself, see minidump-processor's
+    /// `ProcessorOptions::source_files`.
+    pub source_context: Option<SourceContext>,
+
     /// Any inline frames that cover the frame address, ordered "inside to outside",
     /// or "deepest callee to shallowest callee". This is the same order that StackFrames
     /// appear in.
@@ -268,6 +512,15 @@ pub struct StackFrame {
 
     /// Any function args we recovered.
     pub arguments: Option<FunctionArgs>,
+
+    /// Whether the instruction is in code generated at runtime by a JIT, i.e. in executable
+    /// memory that isn't part of any module.
+    pub jit: bool,
+
+    /// Notes on how this frame was found from its callee, e.g. the call frame info rules
+    /// that were evaluated or the values stack scanning rejected. Only recorded with
+    /// [`UnwindOptions::unwind_notes`].
+    pub unwind_notes: Vec<UnwindNote>,
 }
 
 impl StackFrame {
@@ -280,13 +533,17 @@ impl StackFrame {
             module: None,
             unloaded_modules: BTreeMap::new(),
             function_name: None,
+            raw_function_name: None,
             function_base: None,
             parameter_size: None,
             source_file_name: None,
             source_line: None,
             source_line_base: None,
+            source_context: None,
             inlines: Vec::new(),
             arguments: None,
+            jit: false,
+            unwind_notes: Vec::new(),
             trust,
             context,
         }
@@ -313,8 +570,10 @@ impl FrameSymbolizer for StackFrame {
     fn add_inline_frame(&mut self, name: &str, file: Option<&str>, line: Option<u32>) {
         self.inlines.push(InlineFrame {
             function_name: name.to_string(),
+            raw_function_name: None,
             source_file_name: file.map(ToString::to_string),
             source_line: line,
+            source_context: None,
         })
     }
 }
@@ -332,6 +591,72 @@ pub enum CallStackInfo {
     UnsupportedCpu,
     /// This thread wrote the minidump, it was skipped.
     DumpThreadSkipped,
+    /// Unwinding stopped at [`UnwindOptions::max_frames`], the thread has more frames.
+    FrameLimitReached,
+    /// Unwinding stopped at [`UnwindOptions::max_scanned_frames`], the next frame would have
+    /// been found by stack scanning.
+    ScanLimitReached,
+    /// Unwinding stopped at [`UnwindOptions::deadline`].
+    TimedOut,
+    /// This thread wasn't unwound (e.g. only the crashing thread was), it only has its
+    /// context frame.
+    UnwindSkipped,
+}
+
+impl CallStackInfo {
+    /// Why unwinding stopped before the outermost frame, if it was cut short by
+    /// the limits of [`UnwindOptions`] or skipped.
+    pub fn stopped_early(&self) -> Option<&'static str> {
+        match *self {
+            CallStackInfo::FrameLimitReached => Some("frame limit reached"),
+            CallStackInfo::ScanLimitReached => Some("stack scanning limit reached"),
+            CallStackInfo::TimedOut => Some("timed out"),
+            CallStackInfo::UnwindSkipped => Some("thread not unwound"),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            CallStackInfo::Ok => "ok",
+            CallStackInfo::MissingContext => "missing_context",
+            CallStackInfo::MissingMemory => "missing_memory",
+            CallStackInfo::UnsupportedCpu => "unsupported_cpu",
+            CallStackInfo::DumpThreadSkipped => "dump_thread_skipped",
+            CallStackInfo::FrameLimitReached => "frame_limit_reached",
+            CallStackInfo::ScanLimitReached => "scan_limit_reached",
+            CallStackInfo::TimedOut => "timed_out",
+            CallStackInfo::UnwindSkipped => "unwind_skipped",
+        }
+    }
+}
+
+/// How [`walk_stack_with_options`] unwinds a thread.
+///
+/// All fields are `pub`, but the type is `non_exhaustive`, so start from
+/// [`Default::default`], which only uses the stack memory and has no limits.
+///
+/// Corrupted stacks can yield thousands of scanned frames, so the limits bound the time
+/// spent on a thread. When a limit stops the walk, [`CallStack::info`] says which.
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct UnwindOptions<'a> {
+    /// The rest of the minidump's memory, to read the code around instructions (e.g. to
+    /// recognize signal trampolines and verify the call sites of scanned return addresses).
+    pub code_memory: Option<&'a UnifiedMemoryList<'a>>,
+    /// The minidump's memory info, to recognize JIT code (executable memory outside of
+    /// modules).
+    pub memory_info: Option<&'a UnifiedMemoryInfoList<'a>>,
+    /// The most frames to unwind.
+    pub max_frames: Option<usize>,
+    /// The most frames to find with stack scanning.
+    pub max_scanned_frames: Option<usize>,
+    /// When to stop unwinding.
+    pub deadline: Option<Instant>,
+    /// Whether to record how each frame was found, see [`StackFrame::unwind_notes`].
+    ///
+    /// This is off by default, as it slows unwinding down.
+    pub unwind_notes: bool,
 }
 
 /// A stack of `StackFrame`s produced as a result of unwinding a thread.
@@ -350,6 +675,23 @@ pub struct CallStack {
     pub thread_name: Option<String>,
     /// The GetLastError() value stored in the TEB.
     pub last_error_value: Option<CrashReason>,
+    /// The cycles of recursive frames that were collapsed out of `frames`.
+    pub collapsed_recursions: Vec<CollapsedRecursion>,
+}
+
+/// A cycle of recursive frames of a [`CallStack`] that repeated many times, collapsed to a
+/// single copy of the cycle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollapsedRecursion {
+    /// The index in [`CallStack::frames`] of the first frame of the copy of the cycle that
+    /// was kept.
+    pub frame: usize,
+    /// The index the first frame of the cycle had before any frames were collapsed.
+    pub original_frame: usize,
+    /// How many frames the cycle has.
+    pub cycle_len: usize,
+    /// How many times the cycle repeated, including the copy that was kept.
+    pub repetitions: usize,
 }
 
 impl CallStack {
@@ -363,6 +705,7 @@ impl CallStack {
             thread_id: 0,
             thread_name: None,
             last_error_value: None,
+            collapsed_recursions: Vec::new(),
         }
     }
 
@@ -374,6 +717,7 @@ impl CallStack {
             thread_id: id,
             thread_name: None,
             last_error_value: None,
+            collapsed_recursions: Vec::new(),
         }
     }
 
@@ -412,11 +756,26 @@ impl CallStack {
             Ok(())
         }
 
+        fn print_source_context<T: Write>(
+            f: &mut T,
+            context: &Option<SourceContext>,
+            line: Option<u32>,
+        ) -> io::Result<()> {
+            let Some(context) = context else {
+                return Ok(());
+            };
+            for (line_number, text) in (context.start_line..).zip(&context.lines) {
+                let marker = if Some(line_number) == line { '>' } else { ' ' };
+                writeln!(f, "    {marker}{line_number:6} | {text}")?;
+            }
+            Ok(())
+        }
+
         if self.frames.is_empty() {
             writeln!(f, "<no frames>")?;
         }
         let mut frame_count = 0;
-        for frame in &self.frames {
+        for (idx, frame) in self.frames.iter().enumerate() {
             // First print out inlines
             for inline in &frame.inlines {
                 // Frame number
@@ -439,6 +798,7 @@ impl CallStack {
                     write!(f, " [{} : {}]", basename(source_file), source_line,)?;
                 }
                 writeln!(f)?;
+                print_source_context(f, &inline.source_context, inline.source_line)?;
                 // A fake `trust`
                 writeln!(f, "    Found by: inlining")?;
             }
@@ -481,9 +841,21 @@ impl CallStack {
                     // We didn't have a function name, so just give a byte offset from the module
                     write!(f, " + {:#x}", addr - module.base_address())?;
                 }
+            } else if let (Some(func_name), Some(func_base)) =
+                (&frame.function_name, &frame.function_base)
+            {
+                // We didn't find a module, but the function is known (e.g. from a JIT's
+                // symbols), so give a byte offset from the function
+                write!(f, "{func_name} + {:#x}", addr - func_base)?;
+                if frame.jit {
+                    write!(f, " (JIT)")?;
+                }
             } else {
                 // We didn't even find a module, so just print the raw address
                 write!(f, "{addr:#x}")?;
+                if frame.jit {
+                    write!(f, " (JIT)")?;
+                }
 
                 // List off overlapping unloaded modules.
 
@@ -506,12 +878,16 @@ impl CallStack {
                 }
             }
 
-            // Print the valid registers
+            // Print the source code and valid registers
             writeln!(f)?;
+            print_source_context(f, &frame.source_context, frame.source_line)?;
             print_registers(f, &frame.context)?;
 
             // And the trust we have of this result
             writeln!(f, "    Found by: {}", frame.trust.description())?;
+            for note in &frame.unwind_notes {
+                writeln!(f, "    Unwind note: {note}")?;
+            }
 
             // Now print out recovered args
             if let Some(args) = &frame.arguments {
@@ -545,6 +921,21 @@ impl CallStack {
                 // it more readable.
                 writeln!(f)?;
             }
+
+            for recursion in &self.collapsed_recursions {
+                if recursion.frame + recursion.cycle_len == idx + 1 {
+                    writeln!(
+                        f,
+                        "<cycle of {} frames above repeated {} times, {} frames collapsed>",
+                        recursion.cycle_len,
+                        recursion.repetitions,
+                        recursion.cycle_len * (recursion.repetitions - 1),
+                    )?;
+                }
+            }
+        }
+        if let Some(reason) = self.info.stopped_early() {
+            writeln!(f, "<unwinding stopped: {reason}>")?;
         }
         Ok(())
     }
@@ -563,6 +954,9 @@ struct CfiStackWalker<'a, C: CpuContext> {
 
     module: &'a MinidumpModule,
     stack_memory: UnifiedMemory<'a, 'a>,
+
+    /// The rules the symbol provider noted, if notes are kept.
+    rules: Option<Vec<String>>,
 }
 
 impl<'a, C> CfiStackWalker<'a, C>
@@ -597,8 +991,29 @@ where
 
             module,
             stack_memory: args.stack_memory,
+
+            rules: args.notes.map(|_| Vec::new()),
         })
     }
+
+    /// Evaluate the call frame info of the callee with the symbol provider.
+    async fn walk<P>(&mut self, args: &GetCallerFrameArgs<'_, P>) -> Option<()>
+    where
+        P: SymbolProvider + Sync,
+        Self: FrameWalker + Send,
+    {
+        let module = self.module;
+        let result = args.symbol_provider.walk_frame(module, self).await;
+        match result {
+            Some(()) => {
+                for rule in self.rules.iter_mut().flat_map(|rules| rules.drain(..)) {
+                    args.note(UnwindNote::CallFrameInfoRule(rule));
+                }
+            }
+            None => args.note(UnwindNote::NoCallFrameInfo),
+        }
+        result
+    }
 }
 
 impl<'a, C> FrameWalker for CfiStackWalker<'a, C>
@@ -649,6 +1064,14 @@ where
         self.caller_validity.insert(instruction_pointer_reg);
         self.caller_ctx.set_register(instruction_pointer_reg, val)
     }
+    fn wants_notes(&self) -> bool {
+        self.rules.is_some()
+    }
+    fn note_rule(&mut self, rule: std::fmt::Arguments<'_>) {
+        if let Some(rules) = &mut self.rules {
+            rules.push(rule.to_string());
+        }
+    }
 }
 
 #[tracing::instrument(name = "unwind_frame", level = "trace", skip_all, fields(idx = _frame_idx, fname = args.callee_frame.function_name.as_deref().unwrap_or("")))]
@@ -696,6 +1119,9 @@ async fn fill_source_line_info<P>(
         // but we want inlines to be in the same order as the stackwalk itself, which means
         // we want the deepest frame first (the callee-est frame).
         frame.inlines.reverse();
+    } else {
+        // The instruction may be in JITed code that has symbols.
+        let _ = symbol_provider.fill_jit_symbol(frame).await;
     }
 }
 
@@ -722,8 +1148,34 @@ impl<'a, F: FnMut(usize, &StackFrame) + Send + 'a> From<F> for OnWalkedFrame<'a>
     }
 }
 
-#[tracing::instrument(name = "unwind_thread", level = "trace", skip_all, fields(idx = _thread_idx, tid = stack.thread_id, tname = stack.thread_name.as_deref().unwrap_or("")))]
 pub async fn walk_stack<P>(
+    thread_idx: usize,
+    on_walked_frame: impl Into<OnWalkedFrame<'_>>,
+    stack: &mut CallStack,
+    stack_memory: Option<UnifiedMemory<'_, '_>>,
+    modules: &MinidumpModuleList,
+    system_info: &SystemInfo,
+    symbol_provider: &P,
+) where
+    P: SymbolProvider + Sync,
+{
+    walk_stack_with_options(
+        thread_idx,
+        on_walked_frame,
+        stack,
+        stack_memory,
+        modules,
+        system_info,
+        symbol_provider,
+        &UnwindOptions::default(),
+    )
+    .await
+}
+
+/// Like [`walk_stack`], but with more of the minidump and limits, see [`UnwindOptions`].
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(name = "unwind_thread", level = "trace", skip_all, fields(idx = _thread_idx, tid = stack.thread_id, tname = stack.thread_name.as_deref().unwrap_or("")))]
+pub async fn walk_stack_with_options<P>(
     _thread_idx: usize,
     on_walked_frame: impl Into<OnWalkedFrame<'_>>,
     stack: &mut CallStack,
@@ -731,6 +1183,7 @@ pub async fn walk_stack<P>(
     modules: &MinidumpModuleList,
     system_info: &SystemInfo,
     symbol_provider: &P,
+    options: &UnwindOptions<'_>,
 ) where
     P: SymbolProvider + Sync,
 {
@@ -749,12 +1202,14 @@ pub async fn walk_stack<P>(
     // Begin with the context frame, and keep getting callers until there are no more.
     let mut has_new_frame = !stack.frames.is_empty();
     let mut on_walked_frame = on_walked_frame.into();
+    let mut scanned_frames = 0;
     while has_new_frame {
         // Symbolicate the new frame
         let frame_idx = stack.frames.len() - 1;
         let frame = stack.frames.last_mut().unwrap();
 
         fill_source_line_info(frame, modules, symbol_provider).await;
+        frame.jit = is_jit_code(frame.instruction, modules, options.memory_info);
 
         // Report the frame as walked and symbolicated
         if let OnWalkedFrame::Some(on_walked_frame) = &mut on_walked_frame {
@@ -765,6 +1220,15 @@ pub async fn walk_stack<P>(
             break;
         };
 
+        if options
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            trace!("unwinding timed out");
+            stack.info = CallStackInfo::TimedOut;
+            break;
+        }
+
         // Walk the new frame
         let callee_frame = &stack.frames.last().unwrap();
         let grand_callee_frame = stack
@@ -776,21 +1240,63 @@ pub async fn walk_stack<P>(
             Some(name) => trace!("unwinding {}", name),
             None => trace!("unwinding 0x{:016x}", callee_frame.instruction),
         }
+        let notes = options.unwind_notes.then(|| Mutex::new(Vec::new()));
         let new_frame = get_caller_frame(
             frame_idx,
             &GetCallerFrameArgs {
                 callee_frame,
                 grand_callee_frame,
                 stack_memory,
+                code_memory: options.code_memory,
+                memory_info: options.memory_info,
                 modules,
                 system_info,
                 symbol_provider,
+                notes: notes.as_ref(),
             },
         )
         .await;
 
         // Check if we're done
-        if let Some(new_frame) = new_frame {
+        if let Some(mut new_frame) = new_frame {
+            if options
+                .max_frames
+                .is_some_and(|max| stack.frames.len() >= max)
+            {
+                trace!("unwinding stopped at the frame limit");
+                stack.info = CallStackInfo::FrameLimitReached;
+                break;
+            }
+            if matches!(
+                new_frame.trust,
+                FrameTrust::Scan | FrameTrust::VerifiedScan | FrameTrust::CfiScan
+            ) {
+                if options
+                    .max_scanned_frames
+                    .is_some_and(|max| scanned_frames >= max)
+                {
+                    trace!("unwinding stopped at the stack scanning limit");
+                    stack.info = CallStackInfo::ScanLimitReached;
+                    break;
+                }
+                scanned_frames += 1;
+            }
+            if let Some(notes) = notes {
+                let context = &new_frame.context;
+                let recovered = context
+                    .general_purpose_registers()
+                    .iter()
+                    .copied()
+                    .filter(|reg| match &context.valid {
+                        MinidumpContextValidity::All => true,
+                        MinidumpContextValidity::Some(which) => which.contains(reg),
+                    })
+                    .collect();
+                new_frame.unwind_notes = notes.into_inner().unwrap();
+                new_frame
+                    .unwind_notes
+                    .push(UnwindNote::RecoveredRegisters(recovered));
+            }
             stack.frames.push(new_frame);
         } else {
             has_new_frame = false;
@@ -807,9 +1313,8 @@ pub async fn walk_stack<P>(
 /// to refine the quality of each unwinder's instruction_seems_valid implementation.
 async fn instruction_seems_valid_by_symbols<P>(
     instruction: u64,
-    modules: &MinidumpModuleList,
-    symbol_provider: &P,
-) -> bool
+    args: &GetCallerFrameArgs<'_, P>,
+) -> Result<(), ScanRejection>
 where
     P: SymbolProvider + Sync,
 {
@@ -831,60 +1336,71 @@ where
 
     // NULL pointer is definitely not valid
     if instruction == 0 {
-        return false;
+        return Err(ScanRejection::Null);
     }
 
-    if let Some(module) = modules.module_at_address(instruction) {
-        // Create a dummy frame symbolizing implementation to feed into
-        // our symbol provider with the address we're interested in. If
-        // it tries to set a non-empty function name, then we can reasonably
-        // assume the instruction address is valid.
-        //use crate::FrameSymbolizer;
-
-        struct DummyFrame {
-            instruction: u64,
-            has_name: bool,
+    // Create a dummy frame symbolizing implementation to feed into
+    // our symbol provider with the address we're interested in. If
+    // it tries to set a non-empty function name, then we can reasonably
+    // assume the instruction address is valid.
+    struct DummyFrame {
+        instruction: u64,
+        has_name: bool,
+    }
+    impl FrameSymbolizer for DummyFrame {
+        fn get_instruction(&self) -> u64 {
+            self.instruction
         }
-        impl FrameSymbolizer for DummyFrame {
-            fn get_instruction(&self) -> u64 {
-                self.instruction
-            }
-            fn set_function(&mut self, name: &str, _base: u64, _parameter_size: u32) {
-                self.has_name = !name.is_empty();
-            }
-            fn set_source_file(&mut self, _file: &str, _line: u32, _base: u64) {
-                // Do nothing
-            }
+        fn set_function(&mut self, name: &str, _base: u64, _parameter_size: u32) {
+            self.has_name = !name.is_empty();
         }
+        fn set_source_file(&mut self, _file: &str, _line: u32, _base: u64) {
+            // Do nothing
+        }
+    }
 
-        let mut frame = DummyFrame {
-            instruction,
-            has_name: false,
-        };
+    let mut frame = DummyFrame {
+        instruction,
+        has_name: false,
+    };
 
-        if symbol_provider
+    if let Some(module) = args.modules.module_at_address(instruction) {
+        if args
+            .symbol_provider
             .fill_symbol(module, &mut frame)
             .await
             .is_ok()
         {
-            frame.has_name
+            if frame.has_name {
+                Ok(())
+            } else {
+                Err(ScanRejection::NoFunction)
+            }
         } else {
             // If the symbol provider returns an Error, this means that we
             // didn't have any symbols for the *module*. Just assume the
             // instruction is valid in this case so that scanning works
             // when we have no symbols.
-            true
+            Ok(())
         }
+    } else if is_jit_code(instruction, args.modules, args.memory_info) {
+        // The address isn't in a module, but it's executable (MemoryInfoListStream on
+        // windows, /proc/self/maps on linux), so it's likely JITed code.
+        Ok(())
+    } else if args
+        .symbol_provider
+        .fill_jit_symbol(&mut frame)
+        .await
+        .is_ok()
+        && frame.has_name
+    {
+        // The address isn't in a module, but it's in a function of a JIT's symbols.
+        Ok(())
     } else {
         // We couldn't even map this address to a module. Reject the pointer
         // so that we have *some* way to distinguish "normal" pointers
         // from instruction address.
-        //
-        // FIXME: this will reject any pointer into JITed code which otherwise
-        // isn't part of a normal well-defined module. We can potentially use
-        // MemoryInfoListStream (windows) and /proc/self/maps (linux) to refine
-        // this analysis and allow scans to walk through JITed code.
-        false
+        Err(ScanRejection::NoModule)
     }
 }
 
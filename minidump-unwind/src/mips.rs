@@ -1,9 +1,6 @@
 use super::impl_prelude::*;
 use minidump::format::ContextFlagsCpu;
-use minidump::{
-    CpuContext, Endian, MinidumpContext, MinidumpContextValidity, MinidumpModuleList,
-    MinidumpRawContext,
-};
+use minidump::{CpuContext, Endian, MinidumpContext, MinidumpContextValidity, MinidumpRawContext};
 use scroll::ctx::{SizeWith, TryFromCtx};
 use std::collections::HashSet;
 use std::convert::TryFrom;
@@ -36,9 +33,7 @@ where
 
     let mut stack_walker = CfiStackWalker::from_ctx_and_args(ctx, args, callee_forwarded_regs)?;
 
-    args.symbol_provider
-        .walk_frame(stack_walker.module, &mut stack_walker)
-        .await?;
+    stack_walker.walk(args).await?;
     let caller_pc = stack_walker.caller_ctx.get_register_always(PROGRAM_COUNTER);
     let caller_sp = stack_walker.caller_ctx.get_register_always(STACK_POINTER);
 
@@ -106,7 +101,11 @@ where
             .stack_memory
             .get_memory_at_address(address_of_pc as u64)?;
         //trace!("unwind: trying addr 0x{address_of_pc:08x}: 0x{caller_pc:08x}");
-        if instruction_seems_valid(caller_pc as u64, args.modules, args.symbol_provider).await {
+        if let Some(trust) = args.accept_scan_candidate(
+            address_of_pc as u64,
+            caller_pc as u64,
+            instruction_seems_valid(caller_pc as u64, args).await,
+        ) {
             // `ra` is usually saved directly at the bottom of the frame,
             // so sp is just address_of_pc + ptr
             let caller_sp = address_of_pc.checked_add(POINTER_WIDTH)?;
@@ -130,7 +129,7 @@ where
                 raw: MinidumpRawContext::Mips(caller_ctx),
                 valid: MinidumpContextValidity::Some(valid),
             };
-            return Some(StackFrame::from_context(context, FrameTrust::Scan));
+            return Some(StackFrame::from_context(context, trust));
         }
     }
 
@@ -160,7 +159,11 @@ where
     for i in 0..count {
         let address_of_pc = last_sp.checked_add(i * POINTER_WIDTH)?;
         let caller_pc = args.stack_memory.get_memory_at_address(address_of_pc)?;
-        if instruction_seems_valid(caller_pc, args.modules, args.symbol_provider).await {
+        if let Some(trust) = args.accept_scan_candidate(
+            address_of_pc,
+            caller_pc,
+            instruction_seems_valid(caller_pc, args).await,
+        ) {
             // `ra` is usually saved directly at the bottom of the frame,
             // so sp is just address_of_pc + ptr
             let caller_sp = address_of_pc.checked_add(POINTER_WIDTH)?;
@@ -184,7 +187,7 @@ where
                 raw: MinidumpRawContext::Mips(caller_ctx),
                 valid: MinidumpContextValidity::Some(valid),
             };
-            return Some(StackFrame::from_context(context, FrameTrust::Scan));
+            return Some(StackFrame::from_context(context, trust));
         }
     }
 
@@ -193,17 +196,20 @@ where
 
 async fn instruction_seems_valid<P>(
     instruction: Pointer,
-    modules: &MinidumpModuleList,
-    symbol_provider: &P,
-) -> bool
+    args: &GetCallerFrameArgs<'_, P>,
+) -> Result<FrameTrust, ScanRejection>
 where
     P: SymbolProvider + Sync,
 {
     if instruction < 0x1000 {
-        return false;
+        return Err(ScanRejection::Null);
     }
 
-    super::instruction_seems_valid_by_symbols(instruction, modules, symbol_provider).await
+    // Calls are followed by a delay slot, whose instruction could be anything, so call
+    // sites aren't checked.
+    super::instruction_seems_valid_by_symbols(instruction, args)
+        .await
+        .map(|()| FrameTrust::Scan)
 }
 
 pub async fn get_caller_frame<P>(
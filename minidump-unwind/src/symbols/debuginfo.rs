@@ -4,19 +4,43 @@ use super::{async_trait, FileError, FileKind, FillSymbolError, FrameSymbolizer,
 use cachemap2::CacheMap;
 use framehop::Unwinder;
 use memmap2::Mmap;
+use minidump::system_info::Cpu;
 use minidump::{MinidumpModuleList, MinidumpSystemInfo, Module};
 use object::read::{macho::FatArch, Architecture};
 use std::cell::UnsafeCell;
 use std::fs::File;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
+mod arm;
+mod binary;
+mod locate;
+mod memory;
+mod pe;
+mod x86;
+
+use locate::ModuleLocator;
+use memory::ImageSections;
+
+pub use binary::BinaryUnwindSymbolProvider;
+pub use memory::DumpImageSymbolProvider;
+pub use pe::PeUnwindSymbolProvider;
+
 /// A symbol provider which gets information from the minidump modules on the local system.
 ///
+/// Unwinding is supported on x86, x86-64, ARM and ARM64. On other CPUs the provider only
+/// symbolicates.
+///
+/// Module files are looked for at the paths recorded in the minidump, which can be remapped or
+/// searched for in sysroots (see [`DebugInfoSymbolProviderBuilder`]). Files whose debug id
+/// doesn't match the module's are ignored.
+///
 /// Note: this symbol provider will currently only restore the registers necessary for unwinding
 /// the given platform. In the future this may be extended to restore all registers.
 pub struct DebugInfoSymbolProvider {
     unwinder: Box<dyn UnwinderInterface + Send + Sync>,
     symbols: Box<dyn SymbolInterface + Send + Sync>,
+    locator: ModuleLocator,
     /// The caches and unwinder operate on the memory held by the mapped modules, so this field
     /// must not be dropped until after they are dropped.
     _mapped_modules: Box<[Mmap]>,
@@ -25,6 +49,7 @@ pub struct DebugInfoSymbolProvider {
 pub struct DebugInfoSymbolProviderBuilder {
     #[cfg(feature = "debuginfo-symbols")]
     enable_symbols: bool,
+    locator: ModuleLocator,
 }
 
 type ModuleData = std::borrow::Cow<'static, [u8]>;
@@ -94,7 +119,12 @@ impl WalkerRegs for framehop::aarch64::UnwindRegsAarch64 {
 }
 
 trait UnwinderInterface {
-    fn add_module(&mut self, module: FHModule);
+    fn add_module(&mut self, module: &dyn Module, path: &Path, object: &object::File<'static>);
+    /// Add a module whose unwind sections were found without an object file, returning whether
+    /// the unwinder supports that.
+    fn add_module_sections(&mut self, _module: &dyn Module, _sections: ImageSections) -> bool {
+        false
+    }
     fn unwind_frame(&self, walker: &mut (dyn FrameWalker + Send)) -> Option<()>;
 }
 
@@ -103,8 +133,27 @@ where
     U::UnwindRegs: WalkerRegs,
     U::Cache: Default,
 {
-    fn add_module(&mut self, module: FHModule) {
-        self.unwinder.add_module(module);
+    fn add_module(&mut self, module: &dyn Module, path: &Path, object: &object::File<'static>) {
+        let base = module.base_address();
+        let end = base + module.size();
+        self.unwinder.add_module(framehop::Module::new(
+            path.display().to_string(),
+            base..end,
+            base,
+            object_section_info::ObjectSectionInfo(object),
+        ));
+    }
+
+    fn add_module_sections(&mut self, module: &dyn Module, sections: ImageSections) -> bool {
+        let base = module.base_address();
+        let end = base + module.size();
+        self.unwinder.add_module(framehop::Module::new(
+            module.code_file().into_owned(),
+            base..end,
+            base,
+            sections,
+        ));
+        true
     }
 
     fn unwind_frame(&self, walker: &mut (dyn FrameWalker + Send)) -> Option<()> {
@@ -137,6 +186,49 @@ where
     }
 }
 
+/// An UnwinderInterface for CPUs without debug info unwinding support, which never unwinds.
+struct NoUnwinder;
+
+impl UnwinderInterface for NoUnwinder {
+    fn add_module(&mut self, _module: &dyn Module, _path: &Path, _object: &object::File<'static>) {}
+
+    fn unwind_frame(&self, _walker: &mut (dyn FrameWalker + Send)) -> Option<()> {
+        None
+    }
+}
+
+/// Modules indexed by the address range they are loaded at.
+struct ModuleMap<T> {
+    modules: Vec<(Range<u64>, T)>,
+}
+
+impl<T> Default for ModuleMap<T> {
+    fn default() -> Self {
+        ModuleMap {
+            modules: Vec::new(),
+        }
+    }
+}
+
+impl<T> ModuleMap<T> {
+    pub fn insert(&mut self, module: &dyn Module, value: T) {
+        let base = module.base_address();
+        let index = self.modules.partition_point(|(r, _)| r.start < base);
+        self.modules
+            .insert(index, (base..base + module.size(), value));
+    }
+
+    /// Get the module containing `address`, along with its address range.
+    pub fn get(&self, address: u64) -> Option<(&Range<u64>, &T)> {
+        let index = self
+            .modules
+            .partition_point(|(r, _)| r.start <= address)
+            .checked_sub(1)?;
+        let (range, value) = &self.modules[index];
+        range.contains(&address).then_some((range, value))
+    }
+}
+
 #[async_trait]
 trait SymbolInterface {
     async fn fill_symbol(
@@ -173,16 +265,21 @@ mod wholesym_symbol_interface {
     }
 
     impl Impl {
-        pub async fn new(modules: &MinidumpModuleList) -> Self {
+        pub async fn new(modules: &MinidumpModuleList, locator: &ModuleLocator) -> Self {
             let mut symbols = HashMap::new();
             let symbol_manager = SymbolManager::with_config(SymbolManagerConfig::new());
             for module in modules.iter() {
-                let path = effective_debug_file(module, false);
-                if let Ok(sm) = symbol_manager
-                    .load_symbol_map_for_binary_at_path(&path, None)
-                    .await
-                {
-                    symbols.insert(module.into(), Mutex::new(sm));
+                for path in locator.candidates(module, false) {
+                    let Ok(sm) = symbol_manager
+                        .load_symbol_map_for_binary_at_path(&path, None)
+                        .await
+                    else {
+                        continue;
+                    };
+                    if locate::check_debug_id(module, &path, Some(sm.debug_id())) {
+                        symbols.insert(module.into(), Mutex::new(sm));
+                        break;
+                    }
                 }
             }
             Impl { symbols }
@@ -298,6 +395,20 @@ mod object_section_info {
     use object::read::{Object, ObjectSection, ObjectSegment};
     use std::ops::Range;
 
+    /// The address that relative addresses in `object` are relative to.
+    pub fn base_svma<'data, O: Object<'data>>(object: &O) -> u64 {
+        if let Some(text_segment) = object.segments().find(|s| s.name() == Ok(Some("__TEXT"))) {
+            // This is a mach-O image. "Relative addresses" are relative to the
+            // vmaddr of the __TEXT segment.
+            return text_segment.address();
+        }
+
+        // For PE binaries, relative_address_base() returns the image base address.
+        // Otherwise it returns zero. This gives regular ELF images a base address of zero,
+        // which is what we want.
+        object.relative_address_base()
+    }
+
     #[repr(transparent)]
     pub struct ObjectSectionInfo<'a, O>(pub &'a O);
 
@@ -315,16 +426,7 @@ mod object_section_info {
         D: From<&'data [u8]>,
     {
         fn base_svma(&self) -> u64 {
-            if let Some(text_segment) = self.segments().find(|s| s.name() == Ok(Some("__TEXT"))) {
-                // This is a mach-O image. "Relative addresses" are relative to the
-                // vmaddr of the __TEXT segment.
-                return text_segment.address();
-            }
-
-            // For PE binaries, relative_address_base() returns the image base address.
-            // Otherwise it returns zero. This gives regular ELF images a base address of zero,
-            // which is what we want.
-            self.relative_address_base()
+            base_svma(self.0)
         }
 
         fn section_svma_range(&mut self, name: &[u8]) -> Option<Range<u64>> {
@@ -349,44 +451,54 @@ mod object_section_info {
     }
 }
 
-/// Get the file path with debug information for the given module.
-///
-/// If `unwind_info` is true, returns the path that should contain unwind information.
-fn effective_debug_file(module: &dyn Module, unwind_info: bool) -> PathBuf {
-    // Windows x86_64 always stores the unwind info _only_ in the binary.
-    let ignore_debug_file = unwind_info && cfg!(all(windows, target_arch = "x86_64"));
-
-    let code_file = module.code_file();
-    let code_file_path: &Path = code_file.as_ref().as_ref();
-
-    if !ignore_debug_file {
-        if let Some(file) = module.debug_file() {
-            let file_path: &Path = file.as_ref().as_ref();
-            // Anchor relative paths in the code file parent.
-            if file_path.is_relative() {
-                if let Some(parent) = code_file_path.parent() {
-                    let path = parent.join(file_path);
-                    if path.exists() {
-                        return path;
-                    }
-                }
-            }
-            if file_path.exists() {
-                return file_path.to_owned();
-            }
+/// Create an unwinder for `cpu`, along with the architecture of the object files it takes,
+/// `None` if unwinding with debug info isn't supported on it.
+fn new_unwinder(cpu: Cpu) -> Option<(Architecture, Box<dyn UnwinderInterface + Send + Sync>)> {
+    Some(match cpu {
+        Cpu::X86_64 => (Architecture::X86_64, UnwinderImpl::x86_64()),
+        Cpu::Arm64 => (Architecture::Aarch64, UnwinderImpl::aarch64()),
+        Cpu::X86 => (Architecture::I386, Box::<x86::DwarfUnwinder>::default()),
+        Cpu::Arm => (Architecture::Arm, Box::<arm::ExidxUnwinder>::default()),
+        _ => return None,
+    })
+}
+
+/// Create an unwinder for the given CPU, or one that doesn't unwind (leaving the stackwalker
+/// to its other strategies) if unwinding with debug info isn't supported on it.
+fn new_unwinder_or_fallback(
+    cpu: Cpu,
+) -> (
+    Option<Architecture>,
+    Box<dyn UnwinderInterface + Send + Sync>,
+) {
+    match new_unwinder(cpu) {
+        Some((arch, unwinder)) => (Some(arch), unwinder),
+        None => {
+            tracing::warn!(
+                "unwinding with debug info is not supported on {cpu}, only symbolicating"
+            );
+            (None, Box::new(NoUnwinder))
         }
-        // else fall back to code file below
     }
-
-    code_file_path.to_owned()
 }
 
+/// Load the first file with unwind information for the given module which matches it.
 fn load_unwind_module(
     module: &dyn Module,
     arch: Architecture,
-) -> Option<(Mmap, framehop::Module<ModuleData>)> {
-    let path = effective_debug_file(module, true);
-    let file = match File::open(&path) {
+    locator: &ModuleLocator,
+) -> Option<(Mmap, PathBuf, object::read::File<'static>)> {
+    locator
+        .candidates(module, true)
+        .into_iter()
+        .find_map(|path| {
+            let (mapped, object) = load_object(&path, arch)?;
+            locate::check_object(module, &path, &object).then_some((mapped, path, object))
+        })
+}
+
+fn load_object(path: &Path, arch: Architecture) -> Option<(Mmap, object::read::File<'static>)> {
+    let file = match File::open(path) {
         Ok(file) => file,
         Err(e) => {
             tracing::warn!("failed to open {} for debug info: {e}", path.display());
@@ -415,13 +527,13 @@ fn load_unwind_module(
             return None;
         }
         Ok(object::read::FileKind::MachOFat64) => get_fat_macho_data(
-            &path,
+            path,
             data,
             object::read::macho::MachOFatFile64::parse(data),
             arch,
         )?,
         Ok(object::read::FileKind::MachOFat32) => get_fat_macho_data(
-            &path,
+            path,
             data,
             object::read::macho::MachOFatFile32::parse(data),
             arch,
@@ -437,16 +549,7 @@ fn load_unwind_module(
         }
     };
 
-    let base = module.base_address();
-    let end = base + module.size();
-    let fhmodule = framehop::Module::new(
-        path.display().to_string(),
-        base..end,
-        base,
-        object_section_info::ObjectSectionInfo(&objfile),
-    );
-
-    Some((mapped, fhmodule))
+    Some((mapped, objfile))
 }
 
 fn get_fat_macho_data<'data, Fat: FatArch>(
@@ -487,6 +590,7 @@ impl Default for DebugInfoSymbolProviderBuilder {
         DebugInfoSymbolProviderBuilder {
             #[cfg(feature = "debuginfo-symbols")]
             enable_symbols: true,
+            locator: Default::default(),
         }
     }
 }
@@ -510,6 +614,32 @@ impl DebugInfoSymbolProviderBuilder {
         self
     }
 
+    /// Look for files whose recorded path starts with `from` under `to` instead.
+    ///
+    /// This can be called multiple times; the first rule with a matching prefix is used. Files
+    /// that don't match a rule are looked for at their recorded path.
+    pub fn remap_path_prefix(mut self, from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> Self {
+        self.locator.remaps.push((from.into(), to.into()));
+        self
+    }
+
+    /// Add a directory with a copy of the crashed system's root file system.
+    ///
+    /// Files are looked for under each sysroot (in the order they were added) after their
+    /// recorded (or remapped) path, and separate debug files in the sysroot's
+    /// `usr/lib/debug/.build-id` directory.
+    pub fn sysroot(mut self, path: impl Into<PathBuf>) -> Self {
+        self.locator.sysroots.push(path.into());
+        self
+    }
+
+    /// Add a directory to look up separate debug files by build id in, as
+    /// `<path>/.build-id/xx/yyyy.debug`.
+    pub fn debug_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.locator.debug_dirs.push(path.into());
+        self
+    }
+
     /// Create the DebugInfoSymbolProvider.
     pub async fn build(
         self,
@@ -517,32 +647,32 @@ impl DebugInfoSymbolProviderBuilder {
         modules: &MinidumpModuleList,
     ) -> DebugInfoSymbolProvider {
         let mut mapped_modules = Vec::new();
-        use minidump::system_info::Cpu;
-        let (arch, mut unwinder) = match system_info.cpu {
-            Cpu::X86_64 => (Architecture::X86_64, UnwinderImpl::x86_64()),
-            Cpu::Arm64 => (Architecture::Aarch64, UnwinderImpl::aarch64()),
-            _ => unimplemented!(),
-        };
+        let (arch, mut unwinder) = new_unwinder_or_fallback(system_info.cpu);
 
         #[cfg(not(feature = "debuginfo-symbols"))]
         let symbols: Box<dyn SymbolInterface + Send + Sync> = Box::new(NoSymbols);
 
         #[cfg(feature = "debuginfo-symbols")]
         let symbols: Box<dyn SymbolInterface + Send + Sync> = if self.enable_symbols {
-            Box::new(wholesym_symbol_interface::Impl::new(modules).await)
+            Box::new(wholesym_symbol_interface::Impl::new(modules, &self.locator).await)
         } else {
             Box::new(NoSymbols)
         };
 
-        for module in modules.iter() {
-            if let Some((mapped, fhmodule)) = load_unwind_module(module, arch) {
-                mapped_modules.push(mapped);
-                unwinder.add_module(fhmodule);
+        if let Some(arch) = arch {
+            for module in modules.iter() {
+                if let Some((mapped, path, object)) =
+                    load_unwind_module(module, arch, &self.locator)
+                {
+                    unwinder.add_module(module, &path, &object);
+                    mapped_modules.push(mapped);
+                }
             }
         }
         DebugInfoSymbolProvider {
             unwinder,
             symbols,
+            locator: self.locator,
             _mapped_modules: mapped_modules.into(),
         }
     }
@@ -585,12 +715,369 @@ impl super::SymbolProvider for DebugInfoSymbolProvider {
     ) -> Result<PathBuf, FileError> {
         let path = match file_kind {
             FileKind::BreakpadSym => None,
-            FileKind::Binary => Some(PathBuf::from(module.code_file().as_ref())),
-            FileKind::ExtraDebugInfo => module.debug_file().map(|p| PathBuf::from(p.as_ref())),
+            FileKind::Binary => self.locator.find_code_file(module),
+            FileKind::ExtraDebugInfo => self.locator.find_debug_file(module),
         };
-        match path {
-            Some(path) if path.exists() => Ok(path),
-            _ => Err(FileError::NotFound),
+        path.ok_or(FileError::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::symbols::SymbolProvider;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// The image base of [`pe_image`].
+    pub(super) const PE_BASE: u64 = 0x7ff8_0000_0000;
+    /// The address of the function of [`pe_image`], relative to its base.
+    pub(super) const PE_TEXT: u64 = 0x1000;
+    /// The address of the chained function chunk of [`pe_image`], relative to its base.
+    pub(super) const PE_CHAINED: u64 = 0x1020;
+    /// The address of the interrupt handler of [`pe_image`], relative to its base.
+    pub(super) const PE_MACHFRAME: u64 = 0x1040;
+
+    /// An x86-64 PE image whose sections are at the same offsets in the file as in memory,
+    /// with a function at [`PE_TEXT`]:
+    ///
+    /// `push rbx; push rsi; sub rsp, 0x28; nop; add rsp, 0x28; pop rsi; pop rbx; ret`
+    ///
+    /// a chunk of it at [`PE_CHAINED`] whose unwind info is chained to the function's, and an
+    /// interrupt handler at [`PE_MACHFRAME`] whose only unwind code is `UWOP_PUSH_MACHFRAME`.
+    pub(super) fn pe_image() -> Vec<u8> {
+        let mut image = vec![0; 0x4000];
+        let mut put = |offset: usize, bytes: &[u8]| {
+            image[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+        put(0, b"MZ");
+        put(0x3c, &0x80u32.to_le_bytes());
+        put(0x80, b"PE\0\0");
+        // The file header: AMD64, 3 sections, and the size of the optional header.
+        put(0x84, &0x8664u16.to_le_bytes());
+        put(0x86, &3u16.to_le_bytes());
+        put(0x94, &240u16.to_le_bytes());
+        // The optional header: PE32+, image base, alignments, sizes, and 16 directories.
+        let optional = 0x98;
+        put(optional, &0x20bu16.to_le_bytes());
+        put(optional + 24, &PE_BASE.to_le_bytes());
+        put(optional + 32, &0x1000u32.to_le_bytes());
+        put(optional + 36, &0x1000u32.to_le_bytes());
+        put(optional + 56, &0x4000u32.to_le_bytes());
+        put(optional + 60, &0x1000u32.to_le_bytes());
+        put(optional + 108, &16u32.to_le_bytes());
+        // The exception directory, with a RUNTIME_FUNCTION for each function.
+        put(optional + 112 + 3 * 8, &0x3000u32.to_le_bytes());
+        put(optional + 112 + 3 * 8 + 4, &36u32.to_le_bytes());
+        // The sections, with their sizes in memory (their file sizes are page-aligned).
+        let sections: [(&[u8], u32, u32, u32); 3] = [
+            (b".text", 0x1000, 0x43, 0x6000_0020),
+            (b".rdata", 0x2000, 0x28, 0x4000_0040),
+            (b".pdata", 0x3000, 36, 0x4000_0040),
+        ];
+        for (i, (name, rva, size, characteristics)) in sections.iter().enumerate() {
+            let header = optional + 240 + i * 40;
+            put(header, name);
+            put(header + 8, &size.to_le_bytes());
+            put(header + 12, &rva.to_le_bytes());
+            put(header + 16, &0x1000u32.to_le_bytes());
+            put(header + 20, &rva.to_le_bytes());
+            put(header + 36, &characteristics.to_le_bytes());
+        }
+
+        let code = [
+            0x53, // push rbx
+            0x56, // push rsi
+            0x48, 0x83, 0xec, 0x28, // sub rsp, 0x28
+            0x90, // nop
+            0x48, 0x83, 0xc4, 0x28, // add rsp, 0x28
+            0x5e, // pop rsi
+            0x5b, // pop rbx
+            0xc3, // ret
+        ];
+        put(0x1000, &code);
+        // The UNWIND_INFO: version 1, a 6 byte prolog, and 3 unwind codes (offset, op | info):
+        // UWOP_ALLOC_SMALL of 0x28, UWOP_PUSH_NONVOL of rsi, UWOP_PUSH_NONVOL of rbx.
+        put(
+            0x2000,
+            &[1, 6, 3, 0, 6, 2 | 4 << 4, 2, 6 << 4, 1, 3 << 4, 0, 0],
+        );
+        // The chunk: `nop; nop; ret`, with an UNWIND_INFO without unwind codes which has
+        // UNW_FLAG_CHAININFO and is followed by the function's RUNTIME_FUNCTION.
+        let chunk_end = PE_CHAINED as u32 + 3;
+        put(PE_CHAINED as usize, &[0x90, 0x90, 0xc3]);
+        put(0x2010, &[1 | 4 << 3, 0, 0, 0]);
+        put(0x2014, &0x1000u32.to_le_bytes());
+        put(0x2018, &(0x1000 + code.len() as u32).to_le_bytes());
+        put(0x201c, &0x2000u32.to_le_bytes());
+        // The interrupt handler: `nop; iretq`, with a UWOP_PUSH_MACHFRAME without error code.
+        let handler_end = PE_MACHFRAME as u32 + 3;
+        put(PE_MACHFRAME as usize, &[0x90, 0x48, 0xcf]);
+        put(0x2020, &[1, 0, 1, 0, 0, 10, 0, 0]);
+
+        let functions = [
+            (0x1000, 0x1000 + code.len() as u32, 0x2000u32),
+            (PE_CHAINED as u32, chunk_end, 0x2010),
+            (PE_MACHFRAME as u32, handler_end, 0x2020),
+        ];
+        for (i, (begin, end, unwind_info)) in functions.iter().enumerate() {
+            let entry = 0x3000 + i * 12;
+            put(entry, &begin.to_le_bytes());
+            put(entry + 4, &end.to_le_bytes());
+            put(entry + 8, &unwind_info.to_le_bytes());
+        }
+        image
+    }
+
+    /// The address of the function of [`elf_image`], relative to its base.
+    pub(super) const ELF_TEXT: u64 = 0x1000;
+
+    /// An x86-64 ELF shared library with a single function at [`ELF_TEXT`], described by its
+    /// `.eh_frame`:
+    ///
+    /// `push rbx; sub rsp, 0x10; nop; add rsp, 0x10; pop rbx; ret`
+    pub(super) fn elf_image() -> Vec<u8> {
+        let mut image = vec![0; 0x3100];
+        let mut put = |offset: usize, bytes: &[u8]| {
+            image[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+        // The header: ELFCLASS64, little endian, ET_DYN, EM_X86_64, and 4 section headers at
+        // 0x3000, the last of them the section names.
+        put(0, &[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+        put(16, &3u16.to_le_bytes());
+        put(18, &62u16.to_le_bytes());
+        put(20, &1u32.to_le_bytes());
+        put(40, &0x3000u64.to_le_bytes());
+        for (offset, half) in [(52, 64u16), (54, 56), (58, 64), (60, 4), (62, 3)] {
+            put(offset, &half.to_le_bytes());
+        }
+
+        let code = [
+            0x53, // push rbx
+            0x48, 0x83, 0xec, 0x10, // sub rsp, 0x10
+            0x90, // nop
+            0x48, 0x83, 0xc4, 0x10, // add rsp, 0x10
+            0x5b, // pop rbx
+            0xc3, // ret
+        ];
+        put(ELF_TEXT as usize, &code);
+        // The .eh_frame. The CIE: augmentation "zR" with pc-relative sdata4 addresses, a code
+        // alignment of 1, a data alignment of -8, rip as the return address register, and
+        // the rules at a function's entry: DW_CFA_def_cfa rsp+8, DW_CFA_offset rip at cfa-8.
+        put(0x2000, &20u32.to_le_bytes());
+        put(
+            0x2008,
+            &[1, b'z', b'R', 0, 1, 0x78, 16, 1, 0x1b, 0x0c, 7, 8, 0x90, 1],
+        );
+        // The FDE of the function: DW_CFA_advance_loc 1, DW_CFA_def_cfa_offset 16,
+        // DW_CFA_offset rbx at cfa-16, DW_CFA_advance_loc 4, DW_CFA_def_cfa_offset 32.
+        let fde = 0x2018;
+        put(fde, &28u32.to_le_bytes());
+        put(fde + 4, &(fde as u32 + 4 - 0x2000).to_le_bytes());
+        put(fde + 8, &(ELF_TEXT as i32 - (fde as i32 + 8)).to_le_bytes());
+        put(fde + 12, &(code.len() as u32).to_le_bytes());
+        put(fde + 16, &[0, 0x41, 0x0e, 16, 0x83, 2, 0x44, 0x0e, 32]);
+
+        let names = b"\0.text\0.eh_frame\0.shstrtab\0";
+        put(0x2800, names);
+        // The sections after the null one: name, type, flags, address, offset and size.
+        let sections = [
+            (1u32, 1u32, 6u64, ELF_TEXT, code.len() as u64),
+            (7, 1, 2, 0x2000, 0x38),
+            (17, 3, 0, 0x2800, names.len() as u64),
+        ];
+        for (i, (name, kind, flags, address, size)) in sections.iter().enumerate() {
+            let header = 0x3000 + (i + 1) * 64;
+            put(header, &name.to_le_bytes());
+            put(header + 4, &kind.to_le_bytes());
+            put(header + 8, &flags.to_le_bytes());
+            // Only allocated sections have an address.
+            if *flags != 0 {
+                put(header + 16, &address.to_le_bytes());
+            }
+            put(header + 24, &address.to_le_bytes());
+            put(header + 32, &size.to_le_bytes());
+            put(header + 48, &1u64.to_le_bytes());
+        }
+        image
+    }
+
+    /// The address of the `__TEXT` segment of [`macho_image`].
+    pub(super) const MACHO_TEXT_SEGMENT: u64 = 0x1_0000_0000;
+    /// The address of the function of [`macho_image`], relative to its `__TEXT` segment.
+    pub(super) const MACHO_TEXT: u64 = 0x1000;
+
+    /// An x86-64 Mach-O executable with a single function at [`MACHO_TEXT`], whose compact
+    /// unwind info says it sets up an rbp frame:
+    ///
+    /// `push rbp; mov rbp, rsp; nop; pop rbp; ret`
+    pub(super) fn macho_image() -> Vec<u8> {
+        let mut image = vec![0; 0x3000];
+        let mut put = |offset: usize, bytes: &[u8]| {
+            image[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+        // The header: MH_MAGIC_64, x86-64, MH_EXECUTE, and a single load command.
+        put(0, &0xfeed_facfu32.to_le_bytes());
+        put(4, &0x0100_0007u32.to_le_bytes());
+        put(8, &3u32.to_le_bytes());
+        put(12, &2u32.to_le_bytes());
+        put(16, &1u32.to_le_bytes());
+        put(20, &(72u32 + 2 * 80).to_le_bytes());
+        // The LC_SEGMENT_64 of __TEXT, which maps the whole file.
+        let segment = 32;
+        put(segment, &0x19u32.to_le_bytes());
+        put(segment + 4, &(72u32 + 2 * 80).to_le_bytes());
+        put(segment + 8, b"__TEXT");
+        put(segment + 24, &MACHO_TEXT_SEGMENT.to_le_bytes());
+        put(segment + 32, &0x3000u64.to_le_bytes());
+        put(segment + 48, &0x3000u64.to_le_bytes());
+        put(segment + 56, &5u32.to_le_bytes());
+        put(segment + 60, &5u32.to_le_bytes());
+        put(segment + 64, &2u32.to_le_bytes());
+        // The sections, with their offsets in the file and their sizes.
+        let code = [
+            0x55, // push rbp
+            0x48, 0x89, 0xe5, // mov rbp, rsp
+            0x90, // nop
+            0x5d, // pop rbp
+            0xc3, // ret
+        ];
+        let sections: [(&[u8], u64, u64, u32); 2] = [
+            (b"__text", MACHO_TEXT, code.len() as u64, 0x8000_0400),
+            (b"__unwind_info", 0x2000, 68, 0),
+        ];
+        for (i, (name, offset, size, flags)) in sections.iter().enumerate() {
+            let header = segment + 72 + i * 80;
+            put(header, name);
+            put(header + 16, b"__TEXT");
+            put(header + 32, &(MACHO_TEXT_SEGMENT + offset).to_le_bytes());
+            put(header + 40, &size.to_le_bytes());
+            put(header + 48, &(*offset as u32).to_le_bytes());
+            put(header + 64, &flags.to_le_bytes());
+        }
+
+        put(MACHO_TEXT as usize, &code);
+        // The __unwind_info: version 1, no common encodings or personalities, and 2 index
+        // entries, the function's and the sentinel at its end.
+        let end = MACHO_TEXT as u32 + code.len() as u32;
+        for (i, value) in [1u32, 28, 0, 28, 0, 28, 2, 0x1000, 52, 52, end, 0, 52]
+            .iter()
+            .enumerate()
+        {
+            put(0x2000 + i * 4, &value.to_le_bytes());
+        }
+        // A regular second level page with a single entry: UNWIND_X86_64_MODE_RBP_FRAME.
+        put(0x2000 + 52, &2u32.to_le_bytes());
+        put(0x2000 + 56, &8u16.to_le_bytes());
+        put(0x2000 + 58, &1u16.to_le_bytes());
+        put(0x2000 + 60, &0x1000u32.to_le_bytes());
+        put(0x2000 + 64, &0x0100_0000u32.to_le_bytes());
+        image
+    }
+
+    /// Finds `path` as the binary of every module, counting the lookups.
+    pub(super) struct Files {
+        pub path: PathBuf,
+        pub lookups: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl SymbolProvider for Files {
+        async fn fill_symbol(
+            &self,
+            _module: &(dyn Module + Sync),
+            _frame: &mut (dyn FrameSymbolizer + Send),
+        ) -> Result<(), FillSymbolError> {
+            Err(FillSymbolError {})
+        }
+
+        async fn walk_frame(
+            &self,
+            _module: &(dyn Module + Sync),
+            _walker: &mut (dyn FrameWalker + Send),
+        ) -> Option<()> {
+            None
+        }
+
+        async fn get_file_path(
+            &self,
+            _module: &(dyn Module + Sync),
+            file_kind: FileKind,
+        ) -> Result<PathBuf, FileError> {
+            assert_eq!(file_kind, FileKind::Binary);
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            Ok(self.path.clone())
+        }
+    }
+
+    /// A frame walker over a fixed set of callee registers and stack words.
+    #[derive(Default)]
+    pub(super) struct TestWalker {
+        pub instruction: u64,
+        pub callee: HashMap<&'static str, u64>,
+        pub memory: HashMap<u64, u64>,
+        pub caller: HashMap<String, u64>,
+        pub cfa: Option<u64>,
+        pub ra: Option<u64>,
+    }
+
+    impl FrameWalker for TestWalker {
+        fn get_instruction(&self) -> u64 {
+            self.instruction
+        }
+        fn has_grand_callee(&self) -> bool {
+            false
+        }
+        fn get_grand_callee_parameter_size(&self) -> u32 {
+            0
+        }
+        fn get_register_at_address(&self, address: u64) -> Option<u64> {
+            self.memory.get(&address).copied()
+        }
+        fn get_callee_register(&self, name: &str) -> Option<u64> {
+            self.callee.get(name).copied()
+        }
+        fn set_caller_register(&mut self, name: &str, val: u64) -> Option<()> {
+            self.caller.insert(name.to_owned(), val);
+            Some(())
+        }
+        fn clear_caller_register(&mut self, name: &str) {
+            self.caller.remove(name);
+        }
+        fn set_cfa(&mut self, val: u64) -> Option<()> {
+            self.cfa = Some(val);
+            Some(())
+        }
+        fn set_ra(&mut self, val: u64) -> Option<()> {
+            self.ra = Some(val);
+            Some(())
+        }
+    }
+
+    #[test]
+    fn test_unsupported_cpu_fallback() {
+        for (cpu, arch) in [
+            (Cpu::X86_64, Architecture::X86_64),
+            (Cpu::Arm64, Architecture::Aarch64),
+            (Cpu::X86, Architecture::I386),
+            (Cpu::Arm, Architecture::Arm),
+        ] {
+            assert_eq!(new_unwinder_or_fallback(cpu).0, Some(arch));
+        }
+
+        // Other CPUs are only symbolicated: the unwinder never finds a caller.
+        for cpu in [Cpu::Mips, Cpu::Ppc, Cpu::Sparc] {
+            assert!(new_unwinder(cpu).is_none());
+            let (arch, unwinder) = new_unwinder_or_fallback(cpu);
+            assert_eq!(arch, None);
+            let mut walker = TestWalker {
+                instruction: 0x1000,
+                ..TestWalker::default()
+            };
+            walker.callee.insert("pc", 0x1000);
+            assert_eq!(unwinder.unwind_frame(&mut walker), None);
+            assert_eq!(walker.cfa, None);
+            assert_eq!(walker.ra, None);
         }
     }
 }
@@ -0,0 +1,384 @@
+//! Unwinding of 32-bit ARM frames using the exception tables in `.ARM.exidx` and `.ARM.extab`.
+//!
+//! See the "Exception Handling ABI for the Arm Architecture" (EHABI) for the format. Only the
+//! unwinding instructions are interpreted; personality routines and their language-specific data
+//! are skipped.
+
+use super::{object_section_info::base_svma, FrameWalker, ModuleMap, UnwinderInterface};
+use minidump::Module;
+use object::read::{Object, ObjectSection};
+use std::convert::{TryFrom, TryInto};
+use std::path::Path;
+use tracing::trace;
+
+/// Register names, indexed by their ARM register number.
+const REGISTER_NAMES: [&str; 16] = [
+    "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "fp", "r12", "sp", "lr",
+    "pc",
+];
+
+const SP: usize = 13;
+const LR: usize = 14;
+const PC: usize = 15;
+
+/// The exidx entry value marking a function that can't be unwound through.
+const EXIDX_CANTUNWIND: u32 = 1;
+
+/// The most instructions a single function's unwind data can have: 3 bytes in the first word
+/// and up to 255 more words.
+const MAX_INSTRUCTIONS: usize = 3 + 255 * 4;
+
+/// Decode a 31-bit place-relative offset stored in `word` at `svma`.
+fn prel31(word: u32, svma: u64) -> u64 {
+    let offset = ((word << 1) as i32) >> 1;
+    svma.wrapping_add(offset as i64 as u64) & u64::from(u32::MAX)
+}
+
+struct ModuleExidx {
+    base_svma: u64,
+    /// `(function start, exidx entry svma, second word)` of each exidx entry, sorted by function
+    /// start.
+    entries: Vec<(u64, u64, u32)>,
+    extab_svma: u64,
+    extab: &'static [u8],
+}
+
+impl ModuleExidx {
+    fn extab_word(&self, svma: u64) -> Option<u32> {
+        let offset = usize::try_from(svma.checked_sub(self.extab_svma)?).ok()?;
+        let bytes = self.extab.get(offset..offset.checked_add(4)?)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    /// Get the unwind instructions for the function containing `svma`.
+    fn instructions(&self, svma: u64) -> Option<Vec<u8>> {
+        let index = self
+            .entries
+            .partition_point(|&(start, _, _)| start <= svma)
+            .checked_sub(1)?;
+        let (_, entry_svma, word) = self.entries[index];
+        if word == EXIDX_CANTUNWIND {
+            trace!("function at {svma:#x} can't be unwound through");
+            return None;
+        }
+
+        let mut instructions = Vec::new();
+        if word & 0x8000_0000 != 0 {
+            // The instructions are inline in the exidx entry, in the compact model.
+            compact_instructions(word, &mut instructions)?;
+            return Some(instructions);
+        }
+
+        let mut address = prel31(word, entry_svma + 4);
+        let first = self.extab_word(address)?;
+        let count = if first & 0x8000_0000 != 0 {
+            compact_instructions(first, &mut instructions)?
+        } else {
+            // A generic personality routine, followed by the instructions in the same format as
+            // the compact model with personality 1.
+            address += 4;
+            let word = self.extab_word(address)?;
+            instructions.extend_from_slice(&word.to_be_bytes()[1..]);
+            word >> 24
+        };
+        for _ in 0..count {
+            address += 4;
+            instructions.extend_from_slice(&self.extab_word(address)?.to_be_bytes());
+        }
+        Some(instructions)
+    }
+}
+
+/// Decode the instructions in the first word of compact model unwind data, returning the number
+/// of words of instructions that follow.
+fn compact_instructions(word: u32, instructions: &mut Vec<u8>) -> Option<u32> {
+    let [_, b1, b2, b3] = word.to_be_bytes();
+    match (word >> 24) & 0xf {
+        0 => {
+            instructions.extend_from_slice(&[b1, b2, b3]);
+            Some(0)
+        }
+        1 | 2 => {
+            instructions.extend_from_slice(&[b2, b3]);
+            Some(u32::from(b1))
+        }
+        personality => {
+            trace!("unknown compact personality routine {personality}");
+            None
+        }
+    }
+}
+
+/// Register state while executing unwind instructions.
+#[derive(Debug, Default)]
+struct UnwindState {
+    /// The register values, with the virtual stack pointer in `sp`.
+    regs: [Option<u64>; 16],
+    /// A bit mask of the registers restored from the stack.
+    popped: u16,
+}
+
+impl UnwindState {
+    fn vsp(&self) -> Option<u64> {
+        self.regs[SP]
+    }
+
+    fn adjust_vsp(&mut self, delta: i64) -> Option<()> {
+        let vsp = self.vsp()?.wrapping_add(delta as u64) & u64::from(u32::MAX);
+        self.regs[SP] = Some(vsp);
+        Some(())
+    }
+
+    /// Pop the registers in `mask` (bit n is register n) off the stack.
+    fn pop(&mut self, mask: u16, read: &dyn Fn(u64) -> Option<u64>) -> Option<()> {
+        let mut vsp = self.vsp()?;
+        let mut new_sp = None;
+        for reg in (0..16).filter(|reg| mask & (1 << reg) != 0) {
+            let val = read(vsp)?;
+            vsp += 4;
+            if reg == SP {
+                // The popped value replaces vsp once all the registers have been popped.
+                new_sp = Some(val);
+            } else {
+                self.regs[reg] = Some(val);
+            }
+        }
+        self.regs[SP] = Some(new_sp.unwrap_or(vsp));
+        self.popped |= mask;
+        Some(())
+    }
+
+    /// Execute EHABI unwind instructions, reading stack memory with `read`.
+    fn execute(&mut self, instructions: &[u8], read: &dyn Fn(u64) -> Option<u64>) -> Option<()> {
+        let mut bytes = instructions.iter().copied();
+        let mut next = || bytes.next();
+        while let Some(op) = next() {
+            match op {
+                // 00xxxxxx: vsp += (xxxxxx << 2) + 4
+                0x00..=0x3f => self.adjust_vsp((i64::from(op & 0x3f) << 2) + 4)?,
+                // 01xxxxxx: vsp -= (xxxxxx << 2) + 4
+                0x40..=0x7f => self.adjust_vsp(-((i64::from(op & 0x3f) << 2) + 4))?,
+                // 1000iiii iiiiiiii: pop r4-r15 under mask, all zero means refuse to unwind
+                0x80..=0x8f => {
+                    let mask = (u16::from(op & 0xf) << 8 | u16::from(next()?)) << 4;
+                    if mask == 0 {
+                        return None;
+                    }
+                    self.pop(mask, read)?;
+                }
+                // 1001nnnn: vsp = r[nnnn], with r13 and r15 reserved
+                0x90..=0x9f => {
+                    let reg = usize::from(op & 0xf);
+                    if reg == SP || reg == PC {
+                        return None;
+                    }
+                    self.regs[SP] = Some(self.regs[reg]?);
+                }
+                // 10100nnn: pop r4-r[4+nnn]
+                // 10101nnn: pop r4-r[4+nnn], r14
+                0xa0..=0xaf => {
+                    let mut mask = ((1u16 << ((op & 0x7) + 1)) - 1) << 4;
+                    if op & 0x8 != 0 {
+                        mask |= 1 << LR;
+                    }
+                    self.pop(mask, read)?;
+                }
+                // 10110000: finish
+                0xb0 => break,
+                // 10110001 0000iiii: pop r0-r3 under mask
+                0xb1 => {
+                    let mask = next()?;
+                    if mask == 0 || mask & 0xf0 != 0 {
+                        return None;
+                    }
+                    self.pop(u16::from(mask), read)?;
+                }
+                // 10110010 uleb128: vsp += 0x204 + (uleb128 << 2)
+                0xb2 => {
+                    let mut value = 0u64;
+                    let mut shift = 0;
+                    loop {
+                        let byte = next()?;
+                        value |= u64::from(byte & 0x7f).checked_shl(shift)?;
+                        shift += 7;
+                        if byte & 0x80 == 0 {
+                            break;
+                        }
+                    }
+                    self.adjust_vsp(0x204 + i64::try_from(value.checked_mul(4)?).ok()?)?;
+                }
+                // 10110011 sssscccc: pop VFP registers saved by FSTMFDX
+                0xb3 => {
+                    let count = i64::from(next()? & 0xf) + 1;
+                    self.adjust_vsp(count * 8 + 4)?;
+                }
+                // 10111nnn: pop VFP d8-d[8+nnn] saved by FSTMFDX
+                0xb8..=0xbf => self.adjust_vsp(i64::from(op & 0x7) * 8 + 12)?,
+                // 11000nnn: pop wMMX wR10-wR[10+nnn]
+                0xc0..=0xc5 => self.adjust_vsp((i64::from(op & 0x7) + 1) * 8)?,
+                // 11000110 sssscccc: pop wMMX wR[ssss]-wR[ssss+cccc]
+                // 11001000 sssscccc: pop VFP d[16+ssss]-d[16+ssss+cccc] saved by VPUSH
+                // 11001001 sssscccc: pop VFP d[ssss]-d[ssss+cccc] saved by VPUSH
+                0xc6 | 0xc8 | 0xc9 => {
+                    let count = i64::from(next()? & 0xf) + 1;
+                    self.adjust_vsp(count * 8)?;
+                }
+                // 11000111 0000iiii: pop wMMX wCGR0-wCGR3 under mask
+                0xc7 => {
+                    let mask = next()?;
+                    if mask == 0 || mask & 0xf0 != 0 {
+                        return None;
+                    }
+                    self.adjust_vsp(i64::from(mask.count_ones()) * 4)?;
+                }
+                // 11010nnn: pop VFP d8-d[8+nnn] saved by VPUSH
+                0xd0..=0xd7 => self.adjust_vsp((i64::from(op & 0x7) + 1) * 8)?,
+                // Everything else is spare.
+                _ => {
+                    trace!("unsupported unwind instruction {op:#x}");
+                    return None;
+                }
+            }
+        }
+        Some(())
+    }
+}
+
+/// An unwinder for 32-bit ARM using the EHABI exception tables.
+#[derive(Default)]
+pub struct ExidxUnwinder {
+    modules: ModuleMap<ModuleExidx>,
+}
+
+impl UnwinderInterface for ExidxUnwinder {
+    fn add_module(&mut self, module: &dyn Module, path: &Path, object: &object::File<'static>) {
+        if !object.is_little_endian() {
+            return;
+        }
+        let section = |name: &str| {
+            let section = object.section_by_name(name)?;
+            Some((section.address(), section.data().ok()?))
+        };
+        let Some((exidx_svma, exidx)) = section(".ARM.exidx") else {
+            trace!("no exception index table in {}", path.display());
+            return;
+        };
+        let (extab_svma, extab) = section(".ARM.extab").unwrap_or((0, &[]));
+
+        let mut entries: Vec<_> = exidx
+            .chunks_exact(8)
+            .zip((exidx_svma..).step_by(8))
+            .map(|(entry, entry_svma)| {
+                let word = |i: usize| u32::from_le_bytes(entry[i..i + 4].try_into().unwrap());
+                (prel31(word(0), entry_svma), entry_svma, word(4))
+            })
+            .collect();
+        entries.sort_unstable();
+
+        self.modules.insert(
+            module,
+            ModuleExidx {
+                base_svma: base_svma(object),
+                entries,
+                extab_svma,
+                extab,
+            },
+        );
+    }
+
+    fn unwind_frame(&self, walker: &mut (dyn FrameWalker + Send)) -> Option<()> {
+        // For callers this already points into the call instruction rather than at the return
+        // address, so the lookup finds the calling function.
+        let address = walker.get_instruction();
+        let (range, module) = self.modules.get(address)?;
+        let svma = address - range.start + module.base_svma;
+        let instructions = module.instructions(svma)?;
+        if instructions.len() > MAX_INSTRUCTIONS {
+            return None;
+        }
+
+        let mut state = UnwindState::default();
+        for (reg, name) in REGISTER_NAMES.iter().enumerate() {
+            state.regs[reg] = walker.get_callee_register(name);
+        }
+        state.execute(&instructions, &|address| {
+            walker.get_register_at_address(address)
+        })?;
+
+        // Without a popped pc, the function returns through lr.
+        let ra = if state.popped & (1 << PC) != 0 {
+            state.regs[PC]?
+        } else {
+            state.regs[LR]?
+        };
+        walker.set_cfa(state.vsp()?)?;
+        walker.set_ra(ra)?;
+        for (reg, name) in REGISTER_NAMES.iter().enumerate().take(LR + 1) {
+            if reg != SP && state.popped & (1 << reg) != 0 {
+                walker.set_caller_register(name, state.regs[reg]?)?;
+            }
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn execute(instructions: &[u8], sp: u64) -> Option<UnwindState> {
+        // Each stack word holds its own address.
+        let read = |address: u64| Some(address);
+        let mut state = UnwindState::default();
+        state.regs[SP] = Some(sp);
+        state.regs[LR] = Some(0x1234);
+        state.regs[7] = Some(0x2000);
+        state.execute(instructions, &read)?;
+        Some(state)
+    }
+
+    #[test]
+    fn test_prel31() {
+        assert_eq!(prel31(0x10, 0x1000), 0x1010);
+        assert_eq!(prel31(0x7fff_fff0, 0x1000), 0xff0);
+    }
+
+    #[test]
+    fn test_compact_instructions() {
+        let mut instructions = Vec::new();
+        assert_eq!(compact_instructions(0x80a8b0b0, &mut instructions), Some(0));
+        assert_eq!(instructions, [0xa8, 0xb0, 0xb0]);
+
+        instructions.clear();
+        assert_eq!(compact_instructions(0x8102b0b0, &mut instructions), Some(2));
+        assert_eq!(instructions, [0xb0, 0xb0]);
+
+        assert_eq!(compact_instructions(0x83000000, &mut instructions), None);
+    }
+
+    #[test]
+    fn test_execute() {
+        // vsp += 8; pop {r4, r5, lr}
+        let state = execute(&[0x01, 0xa9, 0xb0], 0x1000).unwrap();
+        assert_eq!(state.regs[4], Some(0x1008));
+        assert_eq!(state.regs[5], Some(0x100c));
+        assert_eq!(state.regs[LR], Some(0x1010));
+        assert_eq!(state.vsp(), Some(0x1014));
+        assert_eq!(state.popped, 1 << 4 | 1 << 5 | 1 << LR);
+
+        // vsp = r7; pop {r7, lr}; pop {d8-d9}
+        let state = execute(&[0x97, 0x84, 0x08, 0xd1], 0x1000).unwrap();
+        assert_eq!(state.regs[7], Some(0x2000));
+        assert_eq!(state.regs[LR], Some(0x2004));
+        assert_eq!(state.vsp(), Some(0x2018));
+
+        // vsp += 0x204 + (0x81 << 2), with a two byte uleb128
+        let state = execute(&[0xb2, 0x81, 0x01], 0x1000).unwrap();
+        assert_eq!(state.vsp(), Some(0x1000 + 0x204 + (0x81 << 2)));
+        assert_eq!(state.regs[LR], Some(0x1234));
+
+        // Refuse to unwind, and a spare instruction.
+        assert!(execute(&[0x80, 0x00], 0x1000).is_none());
+        assert!(execute(&[0xb4], 0x1000).is_none());
+    }
+}
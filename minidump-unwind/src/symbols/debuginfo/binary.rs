@@ -0,0 +1,295 @@
+//! Unwinding with the unwind tables of binaries found by another provider.
+
+use super::object_section_info::base_svma;
+use super::{
+    async_trait, load_object, locate, new_unwinder, FileError, FileKind, FillSymbolError,
+    FrameSymbolizer, FrameWalker, UnwinderInterface,
+};
+use crate::symbols::module_cache::ModuleCache;
+use crate::symbols::SymbolProvider;
+use memmap2::Mmap;
+use minidump::system_info::Cpu;
+use minidump::{MinidumpSystemInfo, Module};
+use object::{Object, ObjectSection, SectionKind};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::trace;
+
+/// A symbol provider which unwinds with the unwind tables of Mach-O and ELF binaries found by
+/// another provider.
+///
+/// The binaries are located with the `get_file_path` of another provider, usually a
+/// [`Symbolizer`][crate::Symbolizer] (which finds them next to the symbol files, or on a symbol
+/// server), so dumps can be unwound on another machine than the one they came from. Mach-O
+/// binaries (including fat ones) are unwound with their compact unwind info (`__unwind_info`)
+/// and `__eh_frame`, ELF binaries with their `.eh_frame` and `.debug_frame`. PE binaries are
+/// left to [`PeUnwindSymbolProvider`][super::PeUnwindSymbolProvider].
+///
+/// This provider doesn't provide any symbols itself, so it's meant to be added to a
+/// [`MultiSymbolProvider`][crate::MultiSymbolProvider] after the provider of the symbol files,
+/// whose `STACK CFI` takes precedence.
+///
+/// Only the binaries of the 64 most recently unwound modules stay mapped.
+pub struct BinaryUnwindSymbolProvider<P> {
+    files: P,
+    cpu: Cpu,
+    /// The loaded binaries, by module, `None` if there is no usable one.
+    binaries: ModuleCache<Binary>,
+}
+
+/// How many modules' binaries a [`BinaryUnwindSymbolProvider`] keeps loaded.
+const MAX_CACHED_BINARIES: usize = 64;
+
+/// A binary and an unwinder for it.
+pub(super) struct Binary {
+    unwinder: Box<dyn UnwinderInterface + Send + Sync>,
+    /// The code sections, by their address relative to the module base.
+    code: Vec<(Range<u64>, &'static [u8])>,
+    /// The unwinder and the code operate on the memory of the mapped file, so this field
+    /// must not be dropped until after they are dropped.
+    _mapped: Mmap,
+}
+
+impl<P: SymbolProvider + Sync> BinaryUnwindSymbolProvider<P> {
+    /// Unwind the frames of a minidump from the system described by `system_info` with the
+    /// binaries that `files` finds.
+    pub fn new(system_info: &MinidumpSystemInfo, files: P) -> Self {
+        Self {
+            files,
+            cpu: system_info.cpu,
+            binaries: ModuleCache::new(MAX_CACHED_BINARIES),
+        }
+    }
+
+    async fn binary(&self, module: &(dyn Module + Sync)) -> Option<Arc<Binary>> {
+        let key = ModuleCache::<Binary>::key(module)?;
+        if let Some(binary) = self.binaries.get(&key) {
+            return binary;
+        }
+        let binary = match self.files.get_file_path(module, FileKind::Binary).await {
+            Ok(path) => Binary::load(module, &path, self.cpu, false).map(Arc::new),
+            Err(_) => None,
+        };
+        self.binaries.insert(key, binary.clone());
+        binary
+    }
+}
+
+impl Binary {
+    /// Load the binary of `module` at `path` for unwinding on `cpu`, `None` if it doesn't
+    /// match the module, or if it is a PE file and `pe` is false or the other way around.
+    pub(super) fn load(module: &dyn Module, path: &Path, cpu: Cpu, pe: bool) -> Option<Self> {
+        let (arch, mut unwinder) = new_unwinder(cpu)?;
+        let (mapped, object) = load_object(path, arch)?;
+        if matches!(object, object::File::Pe32(_) | object::File::Pe64(_)) != pe {
+            trace!("binary unwind: leaving {} alone", path.display());
+            return None;
+        }
+        if object.architecture() != arch {
+            trace!("binary unwind: {} isn't a {arch:?} binary", path.display());
+            return None;
+        }
+        if !locate::check_object(module, path, &object) {
+            return None;
+        }
+        unwinder.add_module(module, path, &object);
+        let base = base_svma(&object);
+        let code = object
+            .sections()
+            .filter(|section| section.kind() == SectionKind::Text)
+            .filter_map(|section| {
+                let start = section.address().checked_sub(base)?;
+                let data = section.data().ok()?;
+                Some((start..start + data.len() as u64, data))
+            })
+            .collect();
+        Some(Binary {
+            unwinder,
+            code,
+            _mapped: mapped,
+        })
+    }
+
+    pub(super) fn unwind_frame(&self, walker: &mut (dyn FrameWalker + Send)) -> Option<()> {
+        self.unwinder.unwind_frame(walker)
+    }
+
+    /// Read `size` bytes of the code at `address` in `module`.
+    pub(super) fn read_code(
+        &self,
+        module: &(dyn Module + Sync),
+        address: u64,
+        size: usize,
+    ) -> Option<Vec<u8>> {
+        let address = address.checked_sub(module.base_address())?;
+        let (range, data) = self
+            .code
+            .iter()
+            .find(|(range, _)| range.contains(&address))?;
+        let start = (address - range.start) as usize;
+        Some(data.get(start..start.checked_add(size)?)?.to_vec())
+    }
+}
+
+#[async_trait]
+impl<P: SymbolProvider + Send + Sync> SymbolProvider for BinaryUnwindSymbolProvider<P> {
+    async fn fill_symbol(
+        &self,
+        _module: &(dyn Module + Sync),
+        _frame: &mut (dyn FrameSymbolizer + Send),
+    ) -> Result<(), FillSymbolError> {
+        Err(FillSymbolError {})
+    }
+
+    async fn walk_frame(
+        &self,
+        module: &(dyn Module + Sync),
+        walker: &mut (dyn FrameWalker + Send),
+    ) -> Option<()> {
+        let binary = self.binary(module).await?;
+        binary.unwind_frame(walker)
+    }
+
+    async fn read_code(
+        &self,
+        module: &(dyn Module + Sync),
+        address: u64,
+        size: usize,
+    ) -> Option<Vec<u8>> {
+        let binary = self.binary(module).await?;
+        binary.read_code(module, address, size)
+    }
+
+    async fn get_file_path(
+        &self,
+        _module: &(dyn Module + Sync),
+        _file_kind: FileKind,
+    ) -> Result<PathBuf, FileError> {
+        // The files come from the wrapped provider, which reports them itself.
+        Err(FileError::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{elf_image, macho_image, Files, TestWalker, ELF_TEXT, MACHO_TEXT};
+    use super::*;
+    use breakpad_symbols::SimpleModule;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_unusable_binary() {
+        let provider = BinaryUnwindSymbolProvider {
+            files: Files {
+                path: PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"),
+                lookups: AtomicUsize::new(0),
+            },
+            cpu: Cpu::X86_64,
+            binaries: ModuleCache::new(MAX_CACHED_BINARIES),
+        };
+        let module = SimpleModule {
+            base_address: Some(0x1_0000_0000),
+            size: Some(0x1000),
+            code_file: Some("/usr/lib/libfoo.dylib".into()),
+            code_identifier: Some(debugid::CodeId::new("abcdef".into())),
+            ..SimpleModule::default()
+        };
+
+        // A file that isn't an object file is only looked at once.
+        assert!(provider.binary(&module).await.is_none());
+        assert!(provider.binary(&module).await.is_none());
+        assert_eq!(provider.files.lookups.load(Ordering::Relaxed), 1);
+    }
+
+    /// A provider which finds `image` as the binary of every module, and a module loaded at
+    /// `base` for it.
+    fn provider(
+        dir: &tempfile::TempDir,
+        image: Vec<u8>,
+        base: u64,
+    ) -> (BinaryUnwindSymbolProvider<Files>, SimpleModule) {
+        let path = dir.path().join("foo");
+        std::fs::write(&path, image).unwrap();
+        let provider = BinaryUnwindSymbolProvider {
+            files: Files {
+                path,
+                lookups: AtomicUsize::new(0),
+            },
+            cpu: Cpu::X86_64,
+            binaries: ModuleCache::new(MAX_CACHED_BINARIES),
+        };
+        let module = SimpleModule {
+            base_address: Some(base),
+            size: Some(0x4000),
+            code_file: Some("/usr/lib/foo".into()),
+            code_identifier: Some(debugid::CodeId::new("abcdef".into())),
+            ..SimpleModule::default()
+        };
+        (provider, module)
+    }
+
+    /// A walker for the frame at `pc`, with `rbp` and `stack` at rsp.
+    fn walker(pc: u64, rbp: u64, stack: &[u64]) -> TestWalker {
+        let mut walker = TestWalker {
+            instruction: pc,
+            ..TestWalker::default()
+        };
+        walker.callee.insert("rip", pc);
+        walker.callee.insert("rsp", 0x1000);
+        walker.callee.insert("rbp", rbp);
+        for (i, &value) in stack.iter().enumerate() {
+            walker.memory.insert(0x1000 + i as u64 * 8, value);
+        }
+        walker
+    }
+
+    #[tokio::test]
+    async fn test_unwind_elf() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = 0x7f00_0000_0000;
+        let (provider, module) = provider(&dir, elf_image(), base);
+
+        // At the nop, after `push rbx; sub rsp, 0x10`.
+        let mut walker = walker(base + ELF_TEXT + 5, 0xb9b9, &[0, 0, 0xb0b0, 0x2222]);
+        assert_eq!(provider.walk_frame(&module, &mut walker).await, Some(()));
+        assert_eq!(walker.ra, Some(0x2222));
+        assert_eq!(walker.cfa, Some(0x1000 + 4 * 8));
+        assert_eq!(walker.caller.get("rbp"), Some(&0xb9b9));
+        assert_eq!(provider.files.lookups.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unwind_macho() {
+        let dir = tempfile::tempdir().unwrap();
+        // Loaded somewhere else than its __TEXT segment's address.
+        let base = 0x1_0400_0000;
+        let (provider, module) = provider(&dir, macho_image(), base);
+
+        // At the nop, after `push rbp; mov rbp, rsp`.
+        let mut walker = walker(base + MACHO_TEXT + 4, 0x1000, &[0x5555, 0x2222]);
+        assert_eq!(provider.walk_frame(&module, &mut walker).await, Some(()));
+        assert_eq!(walker.ra, Some(0x2222));
+        assert_eq!(walker.cfa, Some(0x1000 + 2 * 8));
+        assert_eq!(walker.caller.get("rbp"), Some(&0x5555));
+    }
+
+    #[tokio::test]
+    async fn test_read_code() {
+        let dir = tempfile::tempdir().unwrap();
+        // Loaded somewhere else than its __TEXT segment's address.
+        let base = 0x1_0400_0000;
+        let (provider, module) = provider(&dir, macho_image(), base);
+
+        assert_eq!(
+            provider.read_code(&module, base + MACHO_TEXT, 4).await,
+            Some(vec![0x55, 0x48, 0x89, 0xe5])
+        );
+        // Past the end of __text.
+        assert_eq!(
+            provider.read_code(&module, base + MACHO_TEXT + 4, 4).await,
+            None
+        );
+        assert_eq!(provider.read_code(&module, base, 4).await, None);
+    }
+}
@@ -0,0 +1,305 @@
+//! Finding the files with debug information for a module when processing happens somewhere
+//! other than the machine that crashed.
+
+use debugid::DebugId;
+use minidump::Module;
+use object::read::Object;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+/// Where to look for the files referred to by a minidump's modules.
+#[derive(Debug, Default)]
+pub(super) struct ModuleLocator {
+    /// `(from, to)` path prefix rewrites, the first matching one is used.
+    pub remaps: Vec<(PathBuf, PathBuf)>,
+    /// Directories holding a copy of the crashed system's root file system.
+    pub sysroots: Vec<PathBuf>,
+    /// Directories holding a `.build-id` tree of separate debug files.
+    pub debug_dirs: Vec<PathBuf>,
+}
+
+impl ModuleLocator {
+    /// Apply the first matching prefix remapping to `path`.
+    fn remap(&self, path: &Path) -> PathBuf {
+        self.remaps
+            .iter()
+            .find_map(|(from, to)| Some(to.join(path.strip_prefix(from).ok()?)))
+            .unwrap_or_else(|| path.to_owned())
+    }
+
+    /// The places a file recorded at `path` in the minidump may be: the (remapped) path itself,
+    /// then the same path under each sysroot.
+    fn paths(&self, path: &Path) -> Vec<PathBuf> {
+        let relative: PathBuf = path
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+        std::iter::once(self.remap(path))
+            .chain(self.sysroots.iter().map(|sysroot| sysroot.join(&relative)))
+            .collect()
+    }
+
+    /// The `.build-id/xx/yyyy.debug` paths of a separate debug file with the build id `build_id`
+    /// (in hex), in the debug directories and the `/usr/lib/debug` of each sysroot.
+    fn build_id_paths(&self, build_id: &str) -> Vec<PathBuf> {
+        if build_id.len() <= 2 || !build_id.is_char_boundary(2) {
+            return Vec::new();
+        }
+        let (dir, file) = build_id.split_at(2);
+        let rel_path = Path::new(".build-id")
+            .join(dir.to_ascii_lowercase())
+            .join(format!("{}.debug", file.to_ascii_lowercase()));
+        self.debug_dirs
+            .iter()
+            .cloned()
+            .chain(self.sysroots.iter().map(|s| s.join("usr/lib/debug")))
+            .map(|dir| dir.join(&rel_path))
+            .collect()
+    }
+
+    /// The places the module's separate debug file may be.
+    fn debug_file_paths(&self, module: &dyn Module) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        if let Some(file) = module.debug_file() {
+            let file_path: &Path = file.as_ref().as_ref();
+            // Anchor relative paths in the code file parent.
+            if file_path.is_relative() {
+                let code_file = module.code_file();
+                if let Some(parent) = Path::new(code_file.as_ref()).parent() {
+                    paths.extend(self.paths(&parent.join(file_path)));
+                }
+            }
+            paths.extend(self.paths(file_path));
+        }
+        if let Some(code_id) = module.code_identifier() {
+            paths.extend(self.build_id_paths(code_id.as_str()));
+        }
+        paths
+    }
+
+    /// The places the module's binary may be.
+    fn code_file_paths(&self, module: &dyn Module) -> Vec<PathBuf> {
+        self.paths(Path::new(module.code_file().as_ref()))
+    }
+
+    /// Find the module's binary.
+    pub fn find_code_file(&self, module: &dyn Module) -> Option<PathBuf> {
+        self.code_file_paths(module)
+            .into_iter()
+            .find(|p| p.is_file())
+    }
+
+    /// Find the module's separate debug file.
+    pub fn find_debug_file(&self, module: &dyn Module) -> Option<PathBuf> {
+        self.debug_file_paths(module)
+            .into_iter()
+            .find(|p| p.is_file())
+    }
+
+    /// Get the existing files which may have debug information for the given module, best
+    /// first.
+    ///
+    /// If `unwind_info` is true, only files that should contain unwind information are returned.
+    pub fn candidates(&self, module: &dyn Module, unwind_info: bool) -> Vec<PathBuf> {
+        // Windows x86_64 always stores the unwind info _only_ in the binary.
+        let ignore_debug_file = unwind_info && cfg!(all(windows, target_arch = "x86_64"));
+
+        let mut candidates = Vec::new();
+        if !ignore_debug_file {
+            candidates.extend(self.debug_file_paths(module));
+        }
+        candidates.extend(self.code_file_paths(module));
+
+        let mut seen = HashSet::new();
+        candidates.retain(|path| path.is_file() && seen.insert(path.clone()));
+        candidates
+    }
+}
+
+/// Get the debug id of an object file, computed the same way as for minidump modules.
+fn object_debug_id(object: &object::File<'_>) -> Option<DebugId> {
+    if let Ok(Some(info)) = object.pdb_info() {
+        return DebugId::from_guid_age(&info.guid(), info.age()).ok();
+    }
+    if let Ok(Some(uuid)) = object.mach_uuid() {
+        // The uuid is in big-endian order, a guid is little-endian.
+        let mut guid = uuid;
+        guid[..4].reverse();
+        guid[4..6].reverse();
+        guid[6..8].reverse();
+        return DebugId::from_guid_age(&guid, 0).ok();
+    }
+    if let Ok(Some(build_id)) = object.build_id().map(|id| id.filter(|id| !id.is_empty())) {
+        // Like in minidumps, the first 16 bytes of the build id are treated as a guid.
+        let mut guid = [0; 16];
+        let len = build_id.len().min(guid.len());
+        guid[..len].copy_from_slice(&build_id[..len]);
+        return DebugId::from_guid_age(&guid, 0).ok();
+    }
+    None
+}
+
+/// Check that `debug_id` (of a file found for `module`) matches the module, logging stale files.
+///
+/// Files or modules without a debug id can't be checked and are accepted.
+pub(super) fn check_debug_id(module: &dyn Module, path: &Path, debug_id: Option<DebugId>) -> bool {
+    match (module.debug_identifier(), debug_id) {
+        (Some(expected), Some(actual)) if expected != actual => {
+            tracing::warn!(
+                "ignoring {}: its debug id {actual} doesn't match the module's {expected}",
+                path.display()
+            );
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Check that the object file at `path` matches `module` (see [`check_debug_id`]).
+pub(super) fn check_object(module: &dyn Module, path: &Path, object: &object::File<'_>) -> bool {
+    check_debug_id(module, path, object_debug_id(object))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use breakpad_symbols::SimpleModule;
+
+    /// A minimal 64-bit ELF file whose only sections are a GNU build id note and the section
+    /// names.
+    fn elf_with_build_id(build_id: &[u8]) -> Vec<u8> {
+        let mut note = Vec::new();
+        note.extend_from_slice(&4u32.to_le_bytes());
+        note.extend_from_slice(&(build_id.len() as u32).to_le_bytes());
+        note.extend_from_slice(&3u32.to_le_bytes()); // NT_GNU_BUILD_ID
+        note.extend_from_slice(b"GNU\0");
+        note.extend_from_slice(build_id);
+        note.resize(note.len().next_multiple_of(8), 0);
+        let mut names = b"\0.note.gnu.build-id\0.shstrtab\0".to_vec();
+        names.resize(names.len().next_multiple_of(8), 0);
+        let shoff = 64 + note.len() + names.len();
+
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1];
+        elf.resize(16, 0);
+        elf.extend_from_slice(&3u16.to_le_bytes()); // ET_DYN
+        elf.extend_from_slice(&62u16.to_le_bytes()); // EM_X86_64
+        elf.extend_from_slice(&1u32.to_le_bytes());
+        elf.extend_from_slice(&0u64.to_le_bytes()); // entry
+        elf.extend_from_slice(&0u64.to_le_bytes()); // phoff
+        elf.extend_from_slice(&(shoff as u64).to_le_bytes());
+        elf.extend_from_slice(&0u32.to_le_bytes()); // flags
+                                                    // ehsize, phentsize, phnum, shentsize, shnum, shstrndx
+        for half in [64u16, 56, 0, 64, 3, 2] {
+            elf.extend_from_slice(&half.to_le_bytes());
+        }
+        elf.extend_from_slice(&note);
+        elf.extend_from_slice(&names);
+
+        // The null section header, then the SHT_NOTE and SHT_STRTAB sections.
+        elf.resize(shoff + 64, 0);
+        let mut section = |name: u32, kind: u32, offset: usize, size: usize| {
+            elf.extend_from_slice(&name.to_le_bytes());
+            elf.extend_from_slice(&kind.to_le_bytes());
+            elf.extend_from_slice(&[0; 16]); // flags, addr
+            elf.extend_from_slice(&(offset as u64).to_le_bytes());
+            elf.extend_from_slice(&(size as u64).to_le_bytes());
+            elf.extend_from_slice(&[0; 8]); // link, info
+            elf.extend_from_slice(&4u64.to_le_bytes()); // addralign
+            elf.extend_from_slice(&0u64.to_le_bytes()); // entsize
+        };
+        section(1, 7, 64, note.len());
+        section(20, 3, 64 + note.len(), names.len());
+        elf
+    }
+
+    fn module(debug_id: Option<&str>) -> SimpleModule {
+        SimpleModule {
+            code_file: Some("/usr/lib/libfoo.so".into()),
+            debug_id: debug_id.map(|id| id.parse().unwrap()),
+            ..SimpleModule::default()
+        }
+    }
+
+    fn locator() -> ModuleLocator {
+        ModuleLocator {
+            remaps: vec![("/data/app".into(), "/srv/apps".into())],
+            sysroots: vec!["/srv/sysroot".into()],
+            debug_dirs: vec!["/srv/debug".into()],
+        }
+    }
+
+    #[test]
+    fn test_paths() {
+        let locator = locator();
+        assert_eq!(
+            locator.paths(Path::new("/data/app/com.example/lib/libfoo.so")),
+            [
+                PathBuf::from("/srv/apps/com.example/lib/libfoo.so"),
+                PathBuf::from("/srv/sysroot/data/app/com.example/lib/libfoo.so"),
+            ]
+        );
+        assert_eq!(
+            locator.paths(Path::new("/system/lib/libc.so")),
+            [
+                PathBuf::from("/system/lib/libc.so"),
+                PathBuf::from("/srv/sysroot/system/lib/libc.so"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_id_paths() {
+        let locator = locator();
+        assert_eq!(
+            locator.build_id_paths("ABCDEF0123"),
+            [
+                PathBuf::from("/srv/debug/.build-id/ab/cdef0123.debug"),
+                PathBuf::from("/srv/sysroot/usr/lib/debug/.build-id/ab/cdef0123.debug"),
+            ]
+        );
+        assert!(locator.build_id_paths("ab").is_empty());
+    }
+
+    #[test]
+    fn test_check_debug_id() {
+        let path = Path::new("/srv/symbols/libfoo.so");
+        let id = DebugId::from_guid_age(&[1; 16], 0).unwrap();
+        let other = DebugId::from_guid_age(&[2; 16], 0).unwrap();
+
+        assert!(check_debug_id(
+            &module(Some(&id.to_string())),
+            path,
+            Some(id)
+        ));
+        assert!(!check_debug_id(
+            &module(Some(&id.to_string())),
+            path,
+            Some(other)
+        ));
+        // Either side without a debug id can't be checked.
+        assert!(check_debug_id(&module(None), path, Some(id)));
+        assert!(check_debug_id(&module(Some(&id.to_string())), path, None));
+    }
+
+    #[test]
+    fn test_check_object() {
+        let path = Path::new("/srv/symbols/libfoo.so");
+        let build_id: Vec<u8> = (1..=20).collect();
+        let data = elf_with_build_id(&build_id);
+        let object = object::File::parse(&data[..]).unwrap();
+        assert_eq!(object.build_id().unwrap(), Some(&build_id[..]));
+
+        // The module's debug id is the first 16 bytes of the build id as a guid.
+        let matching = module(Some("04030201-0605-0807-090a-0b0c0d0e0f10"));
+        assert!(check_object(&matching, path, &object));
+
+        // A stale binary, rebuilt since the crash.
+        let stale = module(Some("04030201-0605-0807-090a-0b0c0d0e0f11"));
+        assert!(!check_object(&stale, path, &object));
+
+        // A file with an empty build id can't be checked.
+        let data = elf_with_build_id(&[]);
+        let no_build_id = object::File::parse(&data[..]).unwrap();
+        assert!(check_object(&stale, path, &no_build_id));
+    }
+}
@@ -0,0 +1,625 @@
+//! Unwinding with the unwind tables of module images captured in the minidump's memory.
+//!
+//! Section headers usually aren't loaded, so the tables are found the way a runtime finds
+//! them: through the PE data directories, the ELF `PT_GNU_EH_FRAME` program header, or the
+//! Mach-O segment load commands.
+
+use super::{
+    async_trait, FileError, FileKind, FillSymbolError, FrameSymbolizer, FrameWalker, ModuleData,
+    ModuleMap, NoUnwinder, UnwinderImpl, UnwinderInterface,
+};
+use framehop::{ExplicitModuleSectionInfo, ModuleSectionInfo};
+use minidump::system_info::Cpu;
+use minidump::{MinidumpModuleList, MinidumpSystemInfo, Module, UnifiedMemoryList};
+use scroll::{Pread, LE};
+use std::borrow::Cow;
+use std::ops::Range;
+use std::path::PathBuf;
+use tracing::trace;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELFCLASS64: u8 = 2;
+const PT_LOAD: u32 = 1;
+const PT_GNU_EH_FRAME: u32 = 0x6474_e550;
+
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+const LC_SEGMENT_64: u32 = 0x19;
+
+const IMAGE_NT_SIGNATURE: u32 = 0x4550;
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+const IMAGE_NT_OPTIONAL_HDR64_MAGIC: u16 = 0x20b;
+const IMAGE_DIRECTORY_ENTRY_EXCEPTION: usize = 3;
+
+/// Sections bigger than this are assumed to come from corrupt headers.
+const MAX_SECTION_SIZE: u64 = 256 * 1024 * 1024;
+
+/// A symbol provider which unwinds with the unwind tables of the module images captured in a
+/// minidump.
+///
+/// Full-memory Windows minidumps (and some Linux ones) contain the mapped bytes of the loaded
+/// modules, so their unwind tables can be used without the binaries:
+///
+/// * the exception directory (`.pdata`) of x86-64 PE images,
+/// * `.eh_frame_hdr` and `.eh_frame` of x86-64 and ARM64 ELF images,
+/// * `__unwind_info` and `__eh_frame` of x86-64 and ARM64 Mach-O images.
+///
+/// The tables are copied when the provider is created. It doesn't provide any symbols, and is
+/// meant to be added to a [`MultiSymbolProvider`][crate::MultiSymbolProvider] after the
+/// providers of symbol files and binaries, as a last resort before frame pointers and stack
+/// scanning.
+pub struct DumpImageSymbolProvider {
+    unwinder: Box<dyn UnwinderInterface + Send + Sync>,
+    /// The modules added to `unwinder`.
+    unwinder_modules: ModuleMap<()>,
+}
+
+impl DumpImageSymbolProvider {
+    /// Find the unwind tables of `modules` in `memory`.
+    pub fn new(
+        system_info: &MinidumpSystemInfo,
+        modules: &MinidumpModuleList,
+        memory: &UnifiedMemoryList,
+    ) -> Self {
+        let mut unwinder: Box<dyn UnwinderInterface + Send + Sync> = match system_info.cpu {
+            Cpu::X86_64 => UnwinderImpl::x86_64(),
+            Cpu::Arm64 => UnwinderImpl::aarch64(),
+            _ => Box::new(NoUnwinder),
+        };
+        let mut unwinder_modules = ModuleMap::default();
+
+        for module in modules.iter() {
+            let base = module.base_address();
+            let read = |address: u64, len: u64| -> Option<Vec<u8>> {
+                // Don't read past the module.
+                if address.checked_add(len)? > base.checked_add(module.size())? {
+                    return None;
+                }
+                read_memory(memory, address, len)
+            };
+            let magic = match read(base, 4) {
+                Some(magic) => magic,
+                None => continue,
+            };
+
+            let sections = if magic.starts_with(b"MZ") {
+                pe_sections(base, &read)
+            } else if magic == ELF_MAGIC {
+                elf_sections(base, &read).map(ImageSections::from)
+            } else if magic.pread_with::<u32>(0, LE).ok() == Some(MH_MAGIC_64) {
+                macho_sections(base, &read).map(ImageSections::from)
+            } else {
+                None
+            };
+            if let Some(sections) = sections {
+                if unwinder.add_module_sections(module, sections) {
+                    trace!(
+                        "dump images: found the unwind tables of {}",
+                        module.code_file()
+                    );
+                    unwinder_modules.insert(module, ());
+                }
+            }
+        }
+
+        Self {
+            unwinder,
+            unwinder_modules,
+        }
+    }
+}
+
+#[async_trait]
+impl super::super::SymbolProvider for DumpImageSymbolProvider {
+    async fn fill_symbol(
+        &self,
+        _module: &(dyn Module + Sync),
+        _frame: &mut (dyn FrameSymbolizer + Send),
+    ) -> Result<(), FillSymbolError> {
+        Err(FillSymbolError {})
+    }
+
+    async fn walk_frame(
+        &self,
+        module: &(dyn Module + Sync),
+        walker: &mut (dyn FrameWalker + Send),
+    ) -> Option<()> {
+        // The unwinder falls back to frame pointers for unknown modules, which the stackwalker
+        // does better itself.
+        self.unwinder_modules.get(module.base_address())?;
+        self.unwinder.unwind_frame(walker)
+    }
+
+    async fn get_file_path(
+        &self,
+        _module: &(dyn Module + Sync),
+        _file_kind: FileKind,
+    ) -> Result<PathBuf, FileError> {
+        Err(FileError::NotFound)
+    }
+}
+
+/// Read `len` bytes at `address`, which may span several memory regions.
+fn read_memory(memory: &UnifiedMemoryList, address: u64, len: u64) -> Option<Vec<u8>> {
+    if len > MAX_SECTION_SIZE {
+        return None;
+    }
+    let end = address.checked_add(len)?;
+    let mut data = Vec::new();
+    let mut address = address;
+    while address < end {
+        let region = memory.memory_at_address(address)?;
+        let offset = address - region.base_address();
+        let available = (region.size() - offset).min(end - address);
+        data.extend_from_slice(
+            region
+                .bytes()
+                .get(offset as usize..(offset + available) as usize)?,
+        );
+        address += available;
+    }
+    Some(data)
+}
+
+fn data(bytes: Vec<u8>) -> ModuleData {
+    Cow::Owned(bytes)
+}
+
+/// The unwind sections of an image found in memory.
+///
+/// [`ExplicitModuleSectionInfo`] has no fields for the `.pdata`, `.rdata` and `.xdata`
+/// sections of PE images, so those are kept next to it.
+#[derive(Default)]
+pub(super) struct ImageSections {
+    explicit: ExplicitModuleSectionInfo<ModuleData>,
+    /// The PE sections, by name, with their addresses.
+    pe: Vec<(&'static [u8], Range<u64>, Option<ModuleData>)>,
+}
+
+impl From<ExplicitModuleSectionInfo<ModuleData>> for ImageSections {
+    fn from(explicit: ExplicitModuleSectionInfo<ModuleData>) -> Self {
+        Self {
+            explicit,
+            pe: vec![],
+        }
+    }
+}
+
+impl ModuleSectionInfo<ModuleData> for ImageSections {
+    fn base_svma(&self) -> u64 {
+        self.explicit.base_svma
+    }
+
+    fn section_svma_range(&mut self, name: &[u8]) -> Option<Range<u64>> {
+        match self.pe.iter().find(|(pe_name, _, _)| *pe_name == name) {
+            Some((_, range, _)) => Some(range.clone()),
+            None => self.explicit.section_svma_range(name),
+        }
+    }
+
+    fn section_data(&mut self, name: &[u8]) -> Option<ModuleData> {
+        match self.pe.iter_mut().find(|(pe_name, _, _)| *pe_name == name) {
+            Some((_, _, data)) => data.take(),
+            None => self.explicit.section_data(name),
+        }
+    }
+
+    fn segment_svma_range(&mut self, name: &[u8]) -> Option<Range<u64>> {
+        self.explicit.segment_svma_range(name)
+    }
+
+    fn segment_data(&mut self, name: &[u8]) -> Option<ModuleData> {
+        self.explicit.segment_data(name)
+    }
+}
+
+/// Find the exception directory (`.pdata`) of the x86-64 PE image loaded at `base`, along with
+/// the `.rdata` and `.xdata` sections its unwind info is in and the `.text` section.
+fn pe_sections(base: u64, read: &dyn Fn(u64, u64) -> Option<Vec<u8>>) -> Option<ImageSections> {
+    let nt_headers = base.checked_add(read(base, 64)?.pread_with::<u32>(0x3c, LE).ok()? as u64)?;
+    // The signature, the file header and the 64-bit optional header.
+    let headers = read(nt_headers, 4 + 20 + 240)?;
+    let signature: u32 = headers.pread_with(0, LE).ok()?;
+    let machine: u16 = headers.pread_with(4, LE).ok()?;
+    let number_of_sections: u16 = headers.pread_with(6, LE).ok()?;
+    let optional_header_size: u16 = headers.pread_with(20, LE).ok()?;
+    let magic: u16 = headers.pread_with(24, LE).ok()?;
+    if signature != IMAGE_NT_SIGNATURE
+        || machine != IMAGE_FILE_MACHINE_AMD64
+        || magic != IMAGE_NT_OPTIONAL_HDR64_MAGIC
+    {
+        return None;
+    }
+    let image_base: u64 = headers.pread_with(24 + 24, LE).ok()?;
+    let number_of_directories: u32 = headers.pread_with(24 + 108, LE).ok()?;
+    if number_of_directories as usize <= IMAGE_DIRECTORY_ENTRY_EXCEPTION {
+        return None;
+    }
+    let directory = 24 + 112 + IMAGE_DIRECTORY_ENTRY_EXCEPTION * 8;
+    let exception_rva: u32 = headers.pread_with(directory, LE).ok()?;
+    let exception_size: u32 = headers.pread_with(directory + 4, LE).ok()?;
+    if exception_size == 0 {
+        return None;
+    }
+
+    let section_headers = read(
+        nt_headers.checked_add(24 + optional_header_size as u64)?,
+        number_of_sections as u64 * 40,
+    )?;
+    let mut sections = ImageSections::default();
+    sections.explicit.base_svma = image_base;
+    let mut add = |name: &'static [u8], rva: u32, size: u32| -> Option<()> {
+        let start = image_base.checked_add(rva as u64)?;
+        let svma = start..start.checked_add(size as u64)?;
+        // A missing section only breaks unwinding the functions that need it.
+        let contents = base
+            .checked_add(rva as u64)
+            .and_then(|address| read(address, size as u64))
+            .map(data);
+        if name == b".text" {
+            sections.explicit.text_svma = Some(svma);
+            sections.explicit.text = contents;
+        } else {
+            sections.pe.push((name, svma, contents));
+        }
+        Some(())
+    };
+    add(b".pdata", exception_rva, exception_size)?;
+    for header in section_headers.chunks_exact(40) {
+        let section_name = name(&header[..8]);
+        let virtual_size: u32 = header.pread_with(8, LE).ok()?;
+        let rva: u32 = header.pread_with(12, LE).ok()?;
+        let wanted: [&'static [u8]; 3] = [b".rdata", b".xdata", b".text"];
+        if let Some(&wanted) = wanted.iter().find(|&&n| section_name.as_bytes() == n) {
+            add(wanted, rva, virtual_size)?;
+        }
+    }
+    Some(sections)
+}
+
+/// Find the `.eh_frame_hdr` and `.eh_frame` of the 64-bit ELF image loaded at `base`.
+fn elf_sections(
+    base: u64,
+    read: &dyn Fn(u64, u64) -> Option<Vec<u8>>,
+) -> Option<ExplicitModuleSectionInfo<ModuleData>> {
+    let header = read(base, 64)?;
+    if header[4] != ELFCLASS64 {
+        return None;
+    }
+    let phoff: u64 = header.pread_with(32, LE).ok()?;
+    let phentsize: u16 = header.pread_with(54, LE).ok()?;
+    let phnum: u16 = header.pread_with(56, LE).ok()?;
+    let phdrs = read(base.checked_add(phoff)?, phentsize as u64 * phnum as u64)?;
+
+    let mut loads = vec![];
+    let mut eh_frame_hdr = None;
+    for i in 0..phnum as usize {
+        let phdr = &phdrs[i * phentsize as usize..];
+        let p_type: u32 = phdr.pread_with(0, LE).ok()?;
+        let vaddr: u64 = phdr.pread_with(16, LE).ok()?;
+        let memsz: u64 = phdr.pread_with(40, LE).ok()?;
+        match p_type {
+            PT_LOAD => loads.push(vaddr..vaddr.saturating_add(memsz)),
+            PT_GNU_EH_FRAME => eh_frame_hdr = Some(vaddr..vaddr.saturating_add(memsz)),
+            _ => {}
+        }
+    }
+    let eh_frame_hdr_svma = eh_frame_hdr?;
+    // The image is loaded at its first segment's page.
+    let base_svma = loads.iter().map(|load| load.start).min()? & !0xfff;
+    let avma = |svma: u64| base.checked_add(svma.checked_sub(base_svma)?);
+
+    let eh_frame_hdr = read(
+        avma(eh_frame_hdr_svma.start)?,
+        eh_frame_hdr_svma.end - eh_frame_hdr_svma.start,
+    )?;
+    let bases = gimli::BaseAddresses::default().set_eh_frame_hdr(eh_frame_hdr_svma.start);
+    let parsed = gimli::EhFrameHdr::new(&eh_frame_hdr, gimli::LittleEndian)
+        .parse(&bases, 8)
+        .ok()?;
+    let eh_frame_start = match parsed.eh_frame_ptr() {
+        gimli::Pointer::Direct(address) => address,
+        gimli::Pointer::Indirect(_) => return None,
+    };
+    // The size of `.eh_frame` is only recorded in the section headers, so take the rest of its
+    // segment. The lookup table in `.eh_frame_hdr` makes sure nothing past the end is read.
+    let segment = loads.iter().find(|load| load.contains(&eh_frame_start))?;
+    let eh_frame_svma = eh_frame_start..segment.end;
+    let eh_frame = read(avma(eh_frame_start)?, segment.end - eh_frame_start)?;
+
+    Some(ExplicitModuleSectionInfo {
+        base_svma,
+        eh_frame_svma: Some(eh_frame_svma),
+        eh_frame: Some(data(eh_frame)),
+        eh_frame_hdr_svma: Some(eh_frame_hdr_svma),
+        eh_frame_hdr: Some(data(eh_frame_hdr)),
+        ..Default::default()
+    })
+}
+
+/// Find the `__unwind_info` and `__eh_frame` of the 64-bit Mach-O image loaded at `base`.
+fn macho_sections(
+    base: u64,
+    read: &dyn Fn(u64, u64) -> Option<Vec<u8>>,
+) -> Option<ExplicitModuleSectionInfo<ModuleData>> {
+    let header = read(base, 32)?;
+    let ncmds: u32 = header.pread_with(16, LE).ok()?;
+    let sizeofcmds: u32 = header.pread_with(20, LE).ok()?;
+    let commands = read(base.checked_add(32)?, sizeofcmds as u64)?;
+
+    let mut text_segment = None;
+    let mut sections: Vec<(String, Range<u64>)> = vec![];
+    let mut offset = 0;
+    for _ in 0..ncmds {
+        let cmd: u32 = commands.pread_with(offset, LE).ok()?;
+        let cmdsize: u32 = commands.pread_with(offset + 4, LE).ok()?;
+        // A command smaller than its own header would be parsed again and again.
+        if cmdsize < 8 || offset + cmdsize as usize > commands.len() {
+            return None;
+        }
+        if cmd == LC_SEGMENT_64 {
+            let segname = name(commands.get(offset + 8..offset + 24)?);
+            let vmaddr: u64 = commands.pread_with(offset + 24, LE).ok()?;
+            let vmsize: u64 = commands.pread_with(offset + 32, LE).ok()?;
+            let nsects: u32 = commands.pread_with(offset + 64, LE).ok()?;
+            if segname == "__TEXT" {
+                text_segment = Some(vmaddr..vmaddr.saturating_add(vmsize));
+            }
+            for i in 0..nsects as usize {
+                let section = offset + 72 + i * 80;
+                let sectname = name(commands.get(section..section + 16)?);
+                let addr: u64 = commands.pread_with(section + 32, LE).ok()?;
+                let size: u64 = commands.pread_with(section + 40, LE).ok()?;
+                sections.push((sectname, addr..addr.saturating_add(size)));
+            }
+        }
+        offset += cmdsize as usize;
+    }
+
+    let text_segment_svma = text_segment?;
+    let base_svma = text_segment_svma.start;
+    let range = |wanted: &str| {
+        sections
+            .iter()
+            .find(|(name, _)| name == wanted)
+            .map(|(_, range)| range.clone())
+    };
+    let contents = |range: &Range<u64>| {
+        read(
+            base.checked_add(range.start.checked_sub(base_svma)?)?,
+            range.end - range.start,
+        )
+    };
+
+    let unwind_info = range("__unwind_info").and_then(|range| contents(&range));
+    let eh_frame_svma = range("__eh_frame");
+    let eh_frame = eh_frame_svma.as_ref().and_then(contents);
+    if unwind_info.is_none() && eh_frame.is_none() {
+        return None;
+    }
+    Some(ExplicitModuleSectionInfo {
+        base_svma,
+        text_svma: range("__text"),
+        stubs_svma: range("__stubs"),
+        stub_helper_svma: range("__stub_helper"),
+        unwind_info: unwind_info.map(data),
+        eh_frame_svma,
+        eh_frame: eh_frame.map(data),
+        text_segment_svma: Some(text_segment_svma),
+        ..Default::default()
+    })
+}
+
+/// A fixed-size, NUL-padded name of a Mach-O segment or section, or of a PE section.
+fn name(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{pe_image, TestWalker, PE_BASE, PE_TEXT};
+    use super::*;
+    use breakpad_symbols::SimpleModule;
+
+    const BASE: u64 = 0x7f00_0000_0000;
+
+    /// The base of an image that ends at the very end of the address space.
+    const HIGH_BASE: u64 = 0u64.wrapping_sub(0x4000);
+
+    fn reader(image: &[u8]) -> impl Fn(u64, u64) -> Option<Vec<u8>> + '_ {
+        reader_at(BASE, image)
+    }
+
+    fn reader_at(base: u64, image: &[u8]) -> impl Fn(u64, u64) -> Option<Vec<u8>> + '_ {
+        move |address, len| {
+            let start = address.checked_sub(base)? as usize;
+            image.get(start..start + len as usize).map(<[u8]>::to_vec)
+        }
+    }
+
+    fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    #[test]
+    fn test_pe_sections() {
+        let mut image = pe_image();
+        let sections = pe_sections(BASE, &reader(&image)).unwrap();
+        assert_eq!(sections.explicit.base_svma, PE_BASE);
+        assert_eq!(
+            sections.explicit.text_svma,
+            Some(PE_BASE + PE_TEXT..PE_BASE + PE_TEXT + 0x43)
+        );
+
+        // The image is unwound where it was loaded, whatever its preferred base.
+        let mut unwinder = UnwinderImpl::x86_64();
+        let module = SimpleModule {
+            base_address: Some(BASE),
+            size: Some(image.len() as u64),
+            ..SimpleModule::default()
+        };
+        assert!(unwinder.add_module_sections(&module, sections));
+        // At `pop rbx` in the epilog, which needs the code of .text.
+        let mut walker = TestWalker {
+            instruction: BASE + PE_TEXT + 12,
+            ..TestWalker::default()
+        };
+        walker.callee.insert("rip", BASE + PE_TEXT + 12);
+        walker.callee.insert("rsp", 0x1000);
+        walker.callee.insert("rbp", 0xb9b9);
+        walker.memory.insert(0x1000, 0xb0b0);
+        walker.memory.insert(0x1008, 0x2222);
+        assert_eq!(unwinder.unwind_frame(&mut walker), Some(()));
+        assert_eq!(walker.ra, Some(0x2222));
+        assert_eq!(walker.cfa, Some(0x1010));
+
+        // Without an exception directory there's nothing to unwind with.
+        put(&mut image, 0x98 + 112 + 3 * 8 + 4, &0u32.to_le_bytes());
+        assert!(pe_sections(BASE, &reader(&image)).is_none());
+    }
+
+    #[test]
+    fn test_malformed_pe() {
+        let malformed = |offset: usize, bytes: &[u8]| {
+            let mut image = pe_image();
+            put(&mut image, offset, bytes);
+            let sections = pe_sections(HIGH_BASE, &reader_at(HIGH_BASE, &image));
+            sections
+        };
+        assert!(malformed(0, b"MZ").is_some());
+
+        // NT headers past the end of the address space.
+        assert!(malformed(0x3c, &0xffff_fff0u32.to_le_bytes()).is_none());
+        // Section headers past the end of the address space.
+        assert!(malformed(0x94, &0xffffu16.to_le_bytes()).is_none());
+        // An image base that puts the sections past the end of the address space.
+        assert!(malformed(0x98 + 24, &u64::MAX.to_le_bytes()).is_none());
+        // A section past the end of the address space is only missing.
+        let rdata = 0x98 + 240 + 40;
+        let sections = malformed(rdata + 12, &0xffff_0000u32.to_le_bytes()).unwrap();
+        let (_, _, contents) = sections
+            .pe
+            .iter()
+            .find(|(name, _, _)| *name == b".rdata")
+            .unwrap();
+        assert!(contents.is_none());
+    }
+
+    /// A 64-bit ELF image whose `.eh_frame_hdr` points to an `.eh_frame` at 0x2100.
+    fn elf_image() -> Vec<u8> {
+        let mut image = vec![0; 0x3000];
+        put(&mut image, 0, ELF_MAGIC);
+        image[4] = ELFCLASS64;
+        put(&mut image, 32, &64u64.to_le_bytes());
+        put(&mut image, 54, &56u16.to_le_bytes());
+        put(&mut image, 56, &2u16.to_le_bytes());
+        // A PT_LOAD covering everything, and the PT_GNU_EH_FRAME.
+        put(&mut image, 64, &PT_LOAD.to_le_bytes());
+        put(&mut image, 64 + 40, &0x3000u64.to_le_bytes());
+        put(&mut image, 120, &PT_GNU_EH_FRAME.to_le_bytes());
+        put(&mut image, 120 + 16, &0x2000u64.to_le_bytes());
+        put(&mut image, 120 + 40, &0x10u64.to_le_bytes());
+        // The .eh_frame_hdr, with a pc-relative sdata4 pointer to .eh_frame at 0x2100.
+        put(&mut image, 0x2000, &[1, 0x1b, 0xff, 0xff]);
+        put(&mut image, 0x2004, &(0x2100i32 - 0x2004).to_le_bytes());
+        image
+    }
+
+    #[test]
+    fn test_elf_sections() {
+        let mut image = elf_image();
+        let sections = elf_sections(BASE, &reader(&image)).unwrap();
+        assert_eq!(sections.base_svma, 0);
+        assert_eq!(sections.eh_frame_hdr_svma, Some(0x2000..0x2010));
+        assert_eq!(sections.eh_frame_svma, Some(0x2100..0x3000));
+        assert_eq!(sections.eh_frame.unwrap().len(), 0xf00);
+
+        // Without a PT_GNU_EH_FRAME there's nothing to unwind with.
+        put(&mut image, 120, &0u32.to_le_bytes());
+        assert!(elf_sections(BASE, &reader(&image)).is_none());
+    }
+
+    #[test]
+    fn test_malformed_elf() {
+        let malformed = |offset: usize, bytes: &[u8]| {
+            let mut image = elf_image();
+            image.resize(0x4000, 0);
+            put(&mut image, offset, bytes);
+            let sections = elf_sections(HIGH_BASE, &reader_at(HIGH_BASE, &image));
+            sections
+        };
+        assert!(malformed(0, ELF_MAGIC).is_some());
+
+        // Program headers past the end of the address space.
+        assert!(malformed(32, &u64::MAX.to_le_bytes()).is_none());
+        // An .eh_frame_hdr past the end of the address space.
+        assert!(malformed(120 + 16, &0xffff_0000u64.to_le_bytes()).is_none());
+    }
+
+    /// A 64-bit Mach-O image whose __TEXT segment has __text and __unwind_info.
+    fn macho_image() -> Vec<u8> {
+        let mut image = vec![0; 0x3000];
+        put(&mut image, 0, &MH_MAGIC_64.to_le_bytes());
+        put(&mut image, 16, &1u32.to_le_bytes());
+        put(&mut image, 20, &(72u32 + 2 * 80).to_le_bytes());
+        // The __TEXT segment at 0x1_0000_0000, with __text and __unwind_info.
+        let segment = 32;
+        put(&mut image, segment, &LC_SEGMENT_64.to_le_bytes());
+        put(&mut image, segment + 4, &(72u32 + 2 * 80).to_le_bytes());
+        put(&mut image, segment + 8, b"__TEXT");
+        put(&mut image, segment + 24, &0x1_0000_0000u64.to_le_bytes());
+        put(&mut image, segment + 32, &0x3000u64.to_le_bytes());
+        put(&mut image, segment + 64, &2u32.to_le_bytes());
+        let text = segment + 72;
+        put(&mut image, text, b"__text");
+        put(&mut image, text + 32, &0x1_0000_1000u64.to_le_bytes());
+        put(&mut image, text + 40, &0x1000u64.to_le_bytes());
+        let unwind_info = text + 80;
+        put(&mut image, unwind_info, b"__unwind_info");
+        put(
+            &mut image,
+            unwind_info + 32,
+            &0x1_0000_2000u64.to_le_bytes(),
+        );
+        put(&mut image, unwind_info + 40, &0x80u64.to_le_bytes());
+        put(&mut image, 0x2000, &[1, 2, 3, 4]);
+        image
+    }
+
+    #[test]
+    fn test_macho_sections() {
+        let image = macho_image();
+        let sections = macho_sections(BASE, &reader(&image)).unwrap();
+        assert_eq!(sections.base_svma, 0x1_0000_0000);
+        assert_eq!(sections.text_svma, Some(0x1_0000_1000..0x1_0000_2000));
+        assert_eq!(
+            sections.text_segment_svma,
+            Some(0x1_0000_0000..0x1_0000_3000)
+        );
+        assert_eq!(&sections.unwind_info.unwrap()[..4], &[1, 2, 3, 4]);
+        assert!(sections.eh_frame.is_none());
+    }
+
+    #[test]
+    fn test_malformed_macho() {
+        let malformed = |offset: usize, bytes: &[u8]| {
+            let mut image = macho_image();
+            image.resize(0x4000, 0);
+            put(&mut image, offset, bytes);
+            let sections = macho_sections(HIGH_BASE, &reader_at(HIGH_BASE, &image));
+            sections
+        };
+        assert!(malformed(0, &MH_MAGIC_64.to_le_bytes()).is_some());
+
+        // Load commands which would be parsed again and again.
+        let mut image = macho_image();
+        put(&mut image, 16, &u32::MAX.to_le_bytes());
+        put(&mut image, 32 + 4, &0u32.to_le_bytes());
+        assert!(macho_sections(BASE, &reader(&image)).is_none());
+        // A load command past the end of the load commands.
+        assert!(malformed(32 + 4, &0x1000u32.to_le_bytes()).is_none());
+        // An __unwind_info past the end of the address space.
+        let unwind_info = 32 + 72 + 80;
+        assert!(malformed(unwind_info + 32, &0xffff_0000_0000_0000u64.to_le_bytes()).is_none());
+    }
+}
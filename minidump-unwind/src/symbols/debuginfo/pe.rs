@@ -0,0 +1,204 @@
+//! Unwinding Windows x86-64 frames with the unwind info in their PE binaries.
+//!
+//! Every function of an x86-64 PE image that isn't a leaf function has a `RUNTIME_FUNCTION`
+//! entry in the exception directory (`.pdata`), which points to an `UNWIND_INFO` (usually in
+//! `.xdata` or `.rdata`) describing the function's prolog. framehop undoes the prolog's
+//! operations the same way Windows itself unwinds (see
+//! <https://learn.microsoft.com/en-us/cpp/build/exception-handling-x64>).
+
+use super::binary::Binary;
+use super::{async_trait, FileError, FileKind, FillSymbolError, FrameSymbolizer, FrameWalker};
+use crate::symbols::module_cache::ModuleCache;
+use crate::symbols::SymbolProvider;
+use minidump::system_info::Cpu;
+use minidump::Module;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// How many modules' binaries a [`PeUnwindSymbolProvider`] keeps loaded.
+const MAX_CACHED_IMAGES: usize = 64;
+
+/// A symbol provider which unwinds x86-64 frames of Windows modules with the unwind info in
+/// their PE binaries.
+///
+/// The binaries are located with the `get_file_path` of another provider, usually a
+/// [`Symbolizer`][crate::Symbolizer] (which finds them next to the symbol files, or on a symbol
+/// server like Microsoft's). This provider doesn't provide any symbols itself, so it's meant to
+/// be added to a [`MultiSymbolProvider`][crate::MultiSymbolProvider] after the provider of the
+/// symbol files, whose `STACK CFI` takes precedence.
+///
+/// The binaries are mapped rather than read, and only those of the 64 most recently unwound
+/// modules stay mapped.
+pub struct PeUnwindSymbolProvider<P> {
+    files: P,
+    /// The loaded binaries, by module, `None` if there is no usable one.
+    images: ModuleCache<Binary>,
+}
+
+impl<P: SymbolProvider + Sync> PeUnwindSymbolProvider<P> {
+    /// Unwind with the PE binaries that `files` finds.
+    pub fn new(files: P) -> Self {
+        Self {
+            files,
+            images: ModuleCache::new(MAX_CACHED_IMAGES),
+        }
+    }
+
+    async fn image(&self, module: &(dyn Module + Sync)) -> Option<Arc<Binary>> {
+        let key = ModuleCache::<Binary>::key(module)?;
+        if let Some(image) = self.images.get(&key) {
+            return image;
+        }
+        let image = match self.files.get_file_path(module, FileKind::Binary).await {
+            Ok(path) => Binary::load(module, &path, Cpu::X86_64, true).map(Arc::new),
+            Err(_) => None,
+        };
+        self.images.insert(key, image.clone());
+        image
+    }
+}
+
+#[async_trait]
+impl<P: SymbolProvider + Send + Sync> SymbolProvider for PeUnwindSymbolProvider<P> {
+    async fn fill_symbol(
+        &self,
+        _module: &(dyn Module + Sync),
+        _frame: &mut (dyn FrameSymbolizer + Send),
+    ) -> Result<(), FillSymbolError> {
+        Err(FillSymbolError {})
+    }
+
+    async fn walk_frame(
+        &self,
+        module: &(dyn Module + Sync),
+        walker: &mut (dyn FrameWalker + Send),
+    ) -> Option<()> {
+        // Only x86-64 frames have an rsp.
+        walker.get_callee_register("rsp")?;
+        let image = self.image(module).await?;
+        image.unwind_frame(walker)
+    }
+
+    async fn read_code(
+        &self,
+        module: &(dyn Module + Sync),
+        address: u64,
+        size: usize,
+    ) -> Option<Vec<u8>> {
+        let image = self.image(module).await?;
+        image.read_code(module, address, size)
+    }
+
+    async fn get_file_path(
+        &self,
+        _module: &(dyn Module + Sync),
+        _file_kind: FileKind,
+    ) -> Result<PathBuf, FileError> {
+        // The files come from the wrapped provider, which reports them itself.
+        Err(FileError::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{
+        pe_image, Files, TestWalker, PE_BASE, PE_CHAINED, PE_MACHFRAME, PE_TEXT,
+    };
+    use super::*;
+    use breakpad_symbols::SimpleModule;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn provider(path: PathBuf) -> PeUnwindSymbolProvider<Files> {
+        PeUnwindSymbolProvider::new(Files {
+            path,
+            lookups: AtomicUsize::new(0),
+        })
+    }
+
+    fn module() -> SimpleModule {
+        SimpleModule {
+            base_address: Some(PE_BASE),
+            size: Some(0x4000),
+            code_file: Some("C:\\\\Windows\\\\System32\\\\foo.dll".into()),
+            code_identifier: Some(debugid::CodeId::new("abcdef4000".into())),
+            ..SimpleModule::default()
+        }
+    }
+
+    /// A walker for the frame at `offset` in the test image, with `stack` at rsp.
+    fn walker(offset: u64, stack: &[u64]) -> TestWalker {
+        let mut walker = TestWalker {
+            instruction: PE_BASE + offset,
+            ..TestWalker::default()
+        };
+        walker.callee.insert("rip", PE_BASE + offset);
+        walker.callee.insert("rsp", 0x1000);
+        walker.callee.insert("rbp", 0xb9b9);
+        for (i, &value) in stack.iter().enumerate() {
+            walker.memory.insert(0x1000 + i as u64 * 8, value);
+        }
+        walker
+    }
+
+    #[tokio::test]
+    async fn test_unwind() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo.dll");
+        std::fs::write(&path, pe_image()).unwrap();
+        let provider = provider(path);
+        let module = module();
+
+        // In the body, after `push rbx; push rsi; sub rsp, 0x28`.
+        let mut body = walker(PE_TEXT + 6, &[0, 0, 0, 0, 0, 0x5151, 0xb0b0, 0x2222]);
+        assert_eq!(provider.walk_frame(&module, &mut body).await, Some(()));
+        assert_eq!(body.ra, Some(0x2222));
+        assert_eq!(body.cfa, Some(0x1000 + 8 * 8));
+
+        // At `pop rbx` in the epilog, where the prolog's unwind codes would read the wrong slots.
+        let mut epilog = walker(PE_TEXT + 12, &[0xb0b0, 0x2222]);
+        assert_eq!(provider.walk_frame(&module, &mut epilog).await, Some(()));
+        assert_eq!(epilog.ra, Some(0x2222));
+        assert_eq!(epilog.cfa, Some(0x1000 + 2 * 8));
+
+        // Functions without a RUNTIME_FUNCTION are leaf functions.
+        let mut leaf = walker(PE_TEXT + 0x100, &[0x3333]);
+        assert_eq!(provider.walk_frame(&module, &mut leaf).await, Some(()));
+        assert_eq!(leaf.ra, Some(0x3333));
+        assert_eq!(leaf.cfa, Some(0x1000 + 8));
+
+        // In a chunk of the function, whose chained unwind info undoes the whole prolog.
+        let mut chained = walker(PE_CHAINED, &[0, 0, 0, 0, 0, 0x5151, 0xb0b0, 0x2222]);
+        assert_eq!(provider.walk_frame(&module, &mut chained).await, Some(()));
+        assert_eq!(chained.ra, Some(0x2222));
+        assert_eq!(chained.cfa, Some(0x1000 + 8 * 8));
+
+        // In an interrupt handler, where the CPU pushed rip, cs, rflags, rsp and ss.
+        let mut machframe = walker(PE_MACHFRAME, &[0x4444, 0x33, 0x246, 0x5000, 0x2b]);
+        assert_eq!(provider.walk_frame(&module, &mut machframe).await, Some(()));
+        assert_eq!(machframe.ra, Some(0x4444));
+        assert_eq!(machframe.cfa, Some(0x5000));
+
+        assert_eq!(
+            provider
+                .read_code(&module, PE_BASE + PE_TEXT, 2)
+                .await
+                .unwrap(),
+            [0x53, 0x56]
+        );
+        // The binary was only looked for once.
+        assert_eq!(provider.files.lookups.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_not_pe() {
+        let provider = provider(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"));
+        let mut walker = walker(PE_TEXT, &[0x2222]);
+        assert_eq!(provider.walk_frame(&module(), &mut walker).await, None);
+
+        // Frames of other CPUs are left alone without looking for the binary.
+        let mut walker = TestWalker::default();
+        walker.callee.insert("esp", 0x1000);
+        assert_eq!(provider.walk_frame(&module(), &mut walker).await, None);
+        assert_eq!(provider.files.lookups.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,309 @@
+//! Unwinding of 32-bit x86 frames using the DWARF CFI in `.eh_frame` or `.debug_frame`.
+//!
+//! framehop only supports 64-bit architectures, so this is a minimal unwinder built directly
+//! on gimli. DWARF expressions aren't evaluated, so frames whose CFA is computed by one (e.g.
+//! PLT stubs) are left to the stackwalker's other strategies.
+
+use super::{object_section_info::base_svma, FrameWalker, ModuleMap, UnwinderInterface};
+use gimli::{
+    BaseAddresses, CfaRule, CieOrFde, DebugFrame, EhFrame, EndianSlice, LittleEndian, Register,
+    RegisterRule, UnwindContext, UnwindSection, UnwindTableRow,
+};
+use minidump::Module;
+use object::read::{Object, ObjectSection};
+use std::path::Path;
+use tracing::trace;
+
+type Reader = EndianSlice<'static, LittleEndian>;
+
+/// Register names, indexed by their i386 DWARF register number.
+const REGISTER_NAMES: [&str; 9] = [
+    "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "eip",
+];
+
+/// The DWARF register holding the return address.
+const RETURN_ADDRESS: Register = Register(8);
+
+/// The callee-saves registers (ebx, ebp, esi and edi), which are recovered for the caller.
+const CALLEE_SAVED: [Register; 4] = [Register(3), Register(5), Register(6), Register(7)];
+
+fn register_name(register: Register) -> Option<&'static str> {
+    REGISTER_NAMES.get(register.0 as usize).copied()
+}
+
+/// A CFI section with an index of its FDEs, sorted by address.
+struct CfiTable<S> {
+    section: S,
+    bases: BaseAddresses,
+    /// `(start, end, offset)` of each FDE.
+    fdes: Vec<(u64, u64, usize)>,
+}
+
+impl<S: UnwindSection<Reader>> CfiTable<S> {
+    fn new(section: S, bases: BaseAddresses) -> Option<Self> {
+        let mut fdes = Vec::new();
+        let mut entries = section.entries(&bases);
+        loop {
+            match entries.next() {
+                Ok(Some(CieOrFde::Fde(partial))) => {
+                    if let Ok(fde) = partial.parse(S::cie_from_offset) {
+                        let start = fde.initial_address();
+                        fdes.push((start, start + fde.len(), fde.offset()));
+                    }
+                }
+                Ok(Some(CieOrFde::Cie(_))) => {}
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::warn!("failed to read CFI entries: {e}");
+                    break;
+                }
+            }
+        }
+        if fdes.is_empty() {
+            return None;
+        }
+        fdes.sort_unstable();
+        Some(CfiTable {
+            section,
+            bases,
+            fdes,
+        })
+    }
+
+    /// Get the unwind rules that apply at `svma`.
+    fn row(&self, svma: u64, ctx: &mut UnwindContext<usize>) -> Option<UnwindTableRow<usize>> {
+        let index = self
+            .fdes
+            .partition_point(|&(start, _, _)| start <= svma)
+            .checked_sub(1)?;
+        let (_, end, offset) = self.fdes[index];
+        if svma >= end {
+            return None;
+        }
+        let fde = self
+            .section
+            .fde_from_offset(&self.bases, offset.into(), S::cie_from_offset)
+            .ok()?;
+        fde.unwind_info_for_address(&self.section, &self.bases, ctx, svma)
+            .ok()
+            .cloned()
+    }
+}
+
+struct ModuleCfi {
+    base_svma: u64,
+    eh_frame: Option<CfiTable<EhFrame<Reader>>>,
+    debug_frame: Option<CfiTable<DebugFrame<Reader>>>,
+}
+
+/// An unwinder for 32-bit x86 using DWARF CFI.
+#[derive(Default)]
+pub struct DwarfUnwinder {
+    modules: ModuleMap<ModuleCfi>,
+}
+
+impl UnwinderInterface for DwarfUnwinder {
+    fn add_module(&mut self, module: &dyn Module, path: &Path, object: &object::File<'static>) {
+        if !object.is_little_endian() {
+            return;
+        }
+        let section = |name: &str| {
+            let section = object.section_by_name(name)?;
+            Some((section.address(), section.data().ok()?))
+        };
+
+        let mut bases = BaseAddresses::default();
+        if let Some((address, _)) = section(".text") {
+            bases = bases.set_text(address);
+        }
+        if let Some((address, _)) = section(".got") {
+            bases = bases.set_got(address);
+        }
+        let eh_frame = section(".eh_frame").and_then(|(address, data)| {
+            let mut eh_frame = EhFrame::new(data, LittleEndian);
+            eh_frame.set_address_size(4);
+            CfiTable::new(eh_frame, bases.clone().set_eh_frame(address))
+        });
+        let debug_frame = section(".debug_frame").and_then(|(_, data)| {
+            let mut debug_frame = DebugFrame::new(data, LittleEndian);
+            debug_frame.set_address_size(4);
+            CfiTable::new(debug_frame, bases.clone())
+        });
+
+        if eh_frame.is_none() && debug_frame.is_none() {
+            trace!("no DWARF CFI in {}", path.display());
+            return;
+        }
+        self.modules.insert(
+            module,
+            ModuleCfi {
+                base_svma: base_svma(object),
+                eh_frame,
+                debug_frame,
+            },
+        );
+    }
+
+    fn unwind_frame(&self, walker: &mut (dyn FrameWalker + Send)) -> Option<()> {
+        // For callers this already points into the call instruction rather than at the return
+        // address, so the lookup finds the calling function.
+        let address = walker.get_instruction();
+        let (range, module) = self.modules.get(address)?;
+        let svma = address - range.start + module.base_svma;
+
+        let mut ctx = UnwindContext::new();
+        let row = module
+            .eh_frame
+            .as_ref()
+            .and_then(|table| table.row(svma, &mut ctx))
+            .or_else(|| {
+                module
+                    .debug_frame
+                    .as_ref()
+                    .and_then(|table| table.row(svma, &mut ctx))
+            })?;
+
+        let cfa = match *row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                let base = walker.get_callee_register(register_name(register)?)?;
+                base.wrapping_add(offset as u64) & u64::from(u32::MAX)
+            }
+            CfaRule::Expression(_) => {
+                trace!("CFA is a DWARF expression, which isn't supported");
+                return None;
+            }
+        };
+        let ra = recover_register(walker, cfa, &row.register(RETURN_ADDRESS))?;
+        walker.set_cfa(cfa)?;
+        walker.set_ra(ra)?;
+
+        for register in CALLEE_SAVED {
+            let name = register_name(register)?;
+            match row.register(register) {
+                // CFI usually doesn't mention callee-saves registers that a function leaves
+                // alone, and the walker already forwards those.
+                RegisterRule::SameValue | RegisterRule::Undefined => {}
+                rule => match recover_register(walker, cfa, &rule) {
+                    Some(val) => {
+                        walker.set_caller_register(name, val);
+                    }
+                    None => walker.clear_caller_register(name),
+                },
+            }
+        }
+        Some(())
+    }
+}
+
+/// Recover the caller's value of a register according to `rule`.
+fn recover_register(
+    walker: &(dyn FrameWalker + Send),
+    cfa: u64,
+    rule: &RegisterRule<usize>,
+) -> Option<u64> {
+    match *rule {
+        RegisterRule::Offset(offset) => {
+            walker.get_register_at_address(cfa.wrapping_add(offset as u64) & u64::from(u32::MAX))
+        }
+        RegisterRule::ValOffset(offset) => {
+            Some(cfa.wrapping_add(offset as u64) & u64::from(u32::MAX))
+        }
+        RegisterRule::Register(register) => walker.get_callee_register(register_name(register)?),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::TestWalker;
+    use super::*;
+    use minidump::MinidumpModule;
+
+    const MODULE_BASE: u64 = 0x10000;
+
+    /// An `.eh_frame` with a CIE and the FDE of a function at 0x1000..0x1100 whose prologue
+    /// is `push ebp; mov ebp, esp`.
+    const EH_FRAME: &[u8] = &[
+        // CIE: length, id, version 1, no augmentation, code alignment 1, data alignment -4,
+        // return address in eip.
+        16, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0x7c, 8,
+        // DW_CFA_def_cfa: esp + 4; DW_CFA_offset: eip at cfa - 4; padding.
+        0x0c, 4, 4, 0x88, 1, 0, 0,
+        // FDE: length, CIE pointer, initial location, address range.
+        20, 0, 0, 0, 24, 0, 0, 0, 0x00, 0x10, 0, 0, 0x00, 0x01, 0, 0,
+        // After 1 byte, DW_CFA_def_cfa_offset: 8 and DW_CFA_offset: ebp at cfa - 8, then after
+        // 2 more, DW_CFA_def_cfa_register: ebp.
+        0x41, 0x0e, 8, 0x85, 2, 0x42, 0x0d, 5,
+    ];
+
+    fn unwinder() -> DwarfUnwinder {
+        let mut eh_frame = EhFrame::new(EH_FRAME, LittleEndian);
+        eh_frame.set_address_size(4);
+        let table = CfiTable::new(eh_frame, BaseAddresses::default()).unwrap();
+        assert_eq!(table.fdes, [(0x1000, 0x1100, 20)]);
+
+        let mut unwinder = DwarfUnwinder::default();
+        unwinder.modules.insert(
+            &MinidumpModule::new(MODULE_BASE, 0x2000, "test.so"),
+            ModuleCfi {
+                base_svma: 0,
+                eh_frame: Some(table),
+                debug_frame: None,
+            },
+        );
+        unwinder
+    }
+
+    fn walker(offset: u64, esp: u64, ebp: u64) -> TestWalker {
+        let mut walker = TestWalker {
+            instruction: MODULE_BASE + offset,
+            ..TestWalker::default()
+        };
+        walker.callee.insert("esp", esp);
+        walker.callee.insert("ebp", ebp);
+        // The return address at 0x2000 and the caller's ebp pushed below it.
+        walker.memory.insert(0x2000, 0x12345);
+        walker.memory.insert(0x1ffc, 0x3000);
+        walker
+    }
+
+    #[test]
+    fn test_unwind_frame() {
+        let unwinder = unwinder();
+
+        // At the entry point the CFA is right above the return address.
+        let mut entry = walker(0x1000, 0x2000, 0x3000);
+        assert_eq!(unwinder.unwind_frame(&mut entry), Some(()));
+        assert_eq!(entry.cfa, Some(0x2004));
+        assert_eq!(entry.ra, Some(0x12345));
+        assert!(entry.caller.is_empty());
+
+        // After `push ebp`, the caller's ebp is recovered from the stack.
+        let mut pushed = walker(0x1001, 0x1ffc, 0x5555);
+        assert_eq!(unwinder.unwind_frame(&mut pushed), Some(()));
+        assert_eq!(pushed.cfa, Some(0x2004));
+        assert_eq!(pushed.ra, Some(0x12345));
+        assert_eq!(pushed.caller.get("ebp"), Some(&0x3000));
+
+        // In the body the CFA is computed from ebp, whatever esp is.
+        let mut body = walker(0x1080, 0x1f00, 0x1ffc);
+        assert_eq!(unwinder.unwind_frame(&mut body), Some(()));
+        assert_eq!(body.cfa, Some(0x2004));
+        assert_eq!(body.ra, Some(0x12345));
+        assert_eq!(body.caller.get("ebp"), Some(&0x3000));
+    }
+
+    #[test]
+    fn test_no_cfi() {
+        let unwinder = unwinder();
+
+        // Outside of the FDE, and outside of the module.
+        assert_eq!(unwinder.unwind_frame(&mut walker(0x1100, 0x2000, 0)), None);
+        assert_eq!(unwinder.unwind_frame(&mut walker(0x4000, 0x2000, 0)), None);
+
+        // The return address isn't in memory.
+        let mut unmapped = walker(0x1000, 0x2000, 0);
+        unmapped.memory.clear();
+        assert_eq!(unwinder.unwind_frame(&mut unmapped), None);
+    }
+}
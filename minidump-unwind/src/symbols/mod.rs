@@ -5,6 +5,17 @@
 //! * [SymbolProvider][] - provides symbolication, cfi evaluation, and debug statistics
 //!     * Implemented by [Symbolizer][] and [debuginfo::DebugInfoSymbolProvider][] (requires the
 //!       `debuginfo` feature to be enabled).
+//!     * Implemented by [debuginfo::PeUnwindSymbolProvider][] (requires the `debuginfo`
+//!       feature), which only does cfi evaluation, with the unwind info of Windows x86-64
+//!       binaries found by another provider.
+//!     * Implemented by [debuginfo::DumpImageSymbolProvider][] (requires the `debuginfo`
+//!       feature), which only does cfi evaluation, with the unwind tables of module images in
+//!       the minidump's memory.
+//!     * Implemented by [debuginfo::BinaryUnwindSymbolProvider][] (requires the `debuginfo`
+//!       feature), which only does cfi evaluation, with the unwind tables of Mach-O and ELF
+//!       binaries found by another provider.
+//!     * Implemented by [PerfMapSymbolProvider][], which only symbolicates code outside of
+//!       modules, with the perf map or jitdump a JIT wrote for the process.
 //!
 //! * [SymbolSupplier][] - maps a [Module][] to a [SymbolFile][]
 //!     * minidump-unwind does not directly use this, it's just there so the Symbolizer can
@@ -35,6 +46,17 @@
 //! * [SymbolFile][] - part of [LocateSymbolsResult][] that a [SymbolProvider][] returns to the
 //!   Symbolizer.
 //!     * Never handled by minidump-unwind, public for the trait.
+//! * [SymbolCache][] - a memory-mapped binary form of a [SymbolFile][] that the Symbolizer
+//!   prefers when a [SymbolSupplier][] can locate one.
+//! * [SimpleSymbolSupplier][] and `HttpSymbolSupplier` (requires the `http` feature) - the
+//!   suppliers behind [simple_symbol_supplier][] and [http_symbol_supplier][], for callers that
+//!   need to configure them further (e.g. to pre-build binary symbol caches).
+//! * [SymbolDiagnostic][] - a problem found by `SymbolFile::validate`, with its
+//!   [DiagnosticKind][] and [Severity][].
+//! * [SymbolLayout][] - the directory layout of a symbol path or server used by those suppliers.
+//! * [CallbackSymbolSupplier][] - a [SymbolSupplier][] that gets files from an async
+//!   [SymbolFetcher][] callback (as [SymbolData][]), e.g. for symbols kept in an object store.
+//!     * Can keep parsed symbols in a [SymbolDataCache][], such as [MemorySymbolDataCache][].
 //! * [SymbolError][] - possible errors a [SymbolProvider][] can yield.
 //!     * Never handled by minidump-unwind, public for the trait.
 //! * [FillSymbolError][] - possible errors for `fill_symbol`.
@@ -48,18 +70,29 @@
 
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use minidump::Module;
 
 pub use breakpad_symbols::{
-    DebugInfoResult, FileError, FileKind, FillSymbolError, FrameSymbolizer, FrameWalker,
-    LocateSymbolsResult, PendingSymbolStats, SymbolError, SymbolFile, SymbolStats, SymbolSupplier,
-    Symbolizer,
+    CallbackSymbolSupplier, DebugInfoResult, DemangleOptions, DiagnosticKind, FileError, FileKind,
+    FillSymbolError, FrameSymbolizer, FrameWalker, LocateSymbolsResult, MemorySymbolDataCache,
+    PendingSymbolStats, Severity, SimpleSymbolSupplier, SymbolCache, SymbolData, SymbolDataCache,
+    SymbolDiagnostic, SymbolError, SymbolFetcher, SymbolFile, SymbolLayout, SymbolStats,
+    SymbolSupplier, Symbolizer,
 };
 
+#[cfg(feature = "http")]
+pub use breakpad_symbols::HttpSymbolSupplier;
+
 #[cfg(feature = "debuginfo-unwind")]
 pub mod debuginfo;
+#[cfg(feature = "debuginfo-unwind")]
+mod module_cache;
+mod perf_map;
+
+pub use perf_map::PerfMapSymbolProvider;
 
 /// The [`SymbolProvider`] is the main extension point for minidump processing.
 ///
@@ -115,6 +148,30 @@ pub trait SymbolProvider {
         file_kind: FileKind,
     ) -> Result<PathBuf, FileError>;
 
+    /// Read `size` bytes of the code of `module` at `address`, e.g. from its binary.
+    ///
+    /// This is used by stack scanning to check that a potential return address
+    /// follows a call instruction. The default implementation has no code.
+    async fn read_code(
+        &self,
+        _module: &(dyn Module + Sync),
+        _address: u64,
+        _size: usize,
+    ) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Like `fill_symbol`, for an instruction address that isn't in any module,
+    /// e.g. in code generated by a JIT.
+    ///
+    /// The default implementation has no symbols.
+    async fn fill_jit_symbol(
+        &self,
+        _frame: &mut (dyn FrameSymbolizer + Send),
+    ) -> Result<(), FillSymbolError> {
+        Err(FillSymbolError {})
+    }
+
     /// Collect various statistics on the symbols.
     ///
     /// Keys are implementation dependent.
@@ -171,6 +228,22 @@ impl SymbolProvider for &(dyn SymbolProvider + Sync) {
         (*self).get_file_path(module, file_kind).await
     }
 
+    async fn read_code(
+        &self,
+        module: &(dyn Module + Sync),
+        address: u64,
+        size: usize,
+    ) -> Option<Vec<u8>> {
+        (*self).read_code(module, address, size).await
+    }
+
+    async fn fill_jit_symbol(
+        &self,
+        frame: &mut (dyn FrameSymbolizer + Send),
+    ) -> Result<(), FillSymbolError> {
+        (*self).fill_jit_symbol(frame).await
+    }
+
     fn stats(&self) -> HashMap<String, SymbolStats> {
         (*self).stats()
     }
@@ -180,6 +253,59 @@ impl SymbolProvider for &(dyn SymbolProvider + Sync) {
     }
 }
 
+/// Allows one provider (and the symbols it has loaded) to be shared by several
+/// [`MultiSymbolProvider`]s, e.g. when processing many minidumps concurrently.
+#[async_trait]
+impl<P: SymbolProvider + Send + Sync + ?Sized> SymbolProvider for Arc<P> {
+    async fn fill_symbol(
+        &self,
+        module: &(dyn Module + Sync),
+        frame: &mut (dyn FrameSymbolizer + Send),
+    ) -> Result<(), FillSymbolError> {
+        (**self).fill_symbol(module, frame).await
+    }
+
+    async fn walk_frame(
+        &self,
+        module: &(dyn Module + Sync),
+        walker: &mut (dyn FrameWalker + Send),
+    ) -> Option<()> {
+        (**self).walk_frame(module, walker).await
+    }
+
+    async fn get_file_path(
+        &self,
+        module: &(dyn Module + Sync),
+        file_kind: FileKind,
+    ) -> Result<PathBuf, FileError> {
+        (**self).get_file_path(module, file_kind).await
+    }
+
+    async fn read_code(
+        &self,
+        module: &(dyn Module + Sync),
+        address: u64,
+        size: usize,
+    ) -> Option<Vec<u8>> {
+        (**self).read_code(module, address, size).await
+    }
+
+    async fn fill_jit_symbol(
+        &self,
+        frame: &mut (dyn FrameSymbolizer + Send),
+    ) -> Result<(), FillSymbolError> {
+        (**self).fill_jit_symbol(frame).await
+    }
+
+    fn stats(&self) -> HashMap<String, SymbolStats> {
+        (**self).stats()
+    }
+
+    fn pending_stats(&self) -> PendingSymbolStats {
+        (**self).pending_stats()
+    }
+}
+
 #[derive(Default)]
 pub struct MultiSymbolProvider {
     providers: Vec<Box<dyn SymbolProvider + Send + Sync>>,
@@ -241,6 +367,33 @@ impl SymbolProvider for MultiSymbolProvider {
         best_result
     }
 
+    async fn read_code(
+        &self,
+        module: &(dyn Module + Sync),
+        address: u64,
+        size: usize,
+    ) -> Option<Vec<u8>> {
+        for p in self.providers.iter() {
+            let result = p.read_code(module, address, size).await;
+            if result.is_some() {
+                return result;
+            }
+        }
+        None
+    }
+
+    async fn fill_jit_symbol(
+        &self,
+        frame: &mut (dyn FrameSymbolizer + Send),
+    ) -> Result<(), FillSymbolError> {
+        let mut best_result = Err(FillSymbolError {});
+        for p in self.providers.iter() {
+            let new_result = p.fill_jit_symbol(frame).await;
+            best_result = best_result.or(new_result);
+        }
+        best_result
+    }
+
     fn stats(&self) -> HashMap<String, SymbolStats> {
         let mut result = HashMap::new();
         for p in self.providers.iter() {
@@ -0,0 +1,94 @@
+//! A bounded cache of the files that providers load for modules.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use minidump::Module;
+
+/// What a provider loaded for each module, `None` if there is nothing usable, keeping at most
+/// `capacity` modules.
+///
+/// When it's full, the least recently used module is evicted, so a long-running process (e.g.
+/// one processing many minidumps with the same providers) doesn't keep every binary it has
+/// ever seen mapped.
+pub(crate) struct ModuleCache<T> {
+    capacity: usize,
+    entries: Mutex<Entries<T>>,
+}
+
+struct Entries<T> {
+    /// The loaded values and when they were last used.
+    values: HashMap<String, (Option<Arc<T>>, u64)>,
+    clock: u64,
+}
+
+impl<T> ModuleCache<T> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: Mutex::new(Entries {
+                values: HashMap::new(),
+                clock: 0,
+            }),
+        }
+    }
+
+    /// The key of `module`, `None` if it has no code identifier to tell its versions apart.
+    pub(crate) fn key(module: &(dyn Module + Sync)) -> Option<String> {
+        Some(format!(
+            "{}/{}",
+            module.code_file(),
+            module.code_identifier()?
+        ))
+    }
+
+    /// The value for `key`, if it's cached.
+    pub(crate) fn get(&self, key: &str) -> Option<Option<Arc<T>>> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        let (value, last_used) = entries.values.get_mut(key)?;
+        *last_used = clock;
+        Some(value.clone())
+    }
+
+    /// Cache the value for `key`, evicting the least recently used one if the cache is full.
+    pub(crate) fn insert(&self, key: String, value: Option<Arc<T>>) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        if entries.values.len() >= self.capacity && !entries.values.contains_key(&key) {
+            let oldest = entries
+                .values
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.values.remove(&oldest);
+            }
+        }
+        entries.values.insert(key, (value, clock));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_eviction() {
+        let cache = ModuleCache::new(2);
+        cache.insert("a".to_owned(), Some(Arc::new(1)));
+        cache.insert("b".to_owned(), None);
+        // Using `a` makes `b` the least recently used.
+        assert_eq!(cache.get("a"), Some(Some(Arc::new(1))));
+        cache.insert("c".to_owned(), Some(Arc::new(3)));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(Some(Arc::new(1))));
+        assert_eq!(cache.get("c"), Some(Some(Arc::new(3))));
+        // Replacing a value doesn't evict anything.
+        cache.insert("c".to_owned(), None);
+        assert_eq!(cache.get("a"), Some(Some(Arc::new(1))));
+        assert_eq!(cache.get("c"), Some(None));
+    }
+}
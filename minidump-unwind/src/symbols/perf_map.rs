@@ -0,0 +1,441 @@
+//! This module provides a `SymbolProvider` which symbolicates JITed code with a perf map or a
+//! jitdump.
+//!
+//! JIT runtimes (e.g. node with `--perf-basic-prof`, .NET with `DOTNET_PerfMapEnabled=1`, or
+//! the JVM with a perf map agent) write the names of the functions they generate to
+//! `/tmp/perf-<pid>.map`, so that `perf` can symbolicate them. Each line is `START SIZE name`,
+//! with START and SIZE in hex, for example:
+//!
+//! ```text
+//! 3ef414c0 398 RegExp:[{(]
+//! 7f3a1c0 1a4 LazyCompile:~main /srv/app/index.js:1
+//! ```
+//!
+//! Some (e.g. node with `--perf-prof`, or .NET with `DOTNET_PerfMapEnabled=1`) can instead
+//! write a `jit-<pid>.dump`, the binary format of `perf inject --jit` (see
+//! `tools/perf/Documentation/jitdump-specification.txt` in the Linux sources). Only its code
+//! load and code move records are used: the debug info and unwinding info records are
+//! skipped.
+
+use super::{
+    async_trait, FileError, FileKind, FillSymbolError, FrameSymbolizer, FrameWalker, SymbolProvider,
+};
+use minidump::Module;
+use scroll::{Endian, Pread, BE, LE};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use tracing::trace;
+
+/// The magic number at the start of a jitdump, in the byte order of the JIT that wrote it.
+const JITDUMP_MAGIC: u32 = 0x4a69_5444;
+/// The size of the jitdump header this reads, later versions may have a bigger one.
+const JITDUMP_HEADER_SIZE: u32 = 40;
+/// The size of the header of each jitdump record.
+const JITDUMP_RECORD_HEADER_SIZE: u32 = 16;
+const JIT_CODE_LOAD: u32 = 0;
+const JIT_CODE_MOVE: u32 = 1;
+
+/// A symbol provider which symbolicates the code a JIT generated with the perf map or the
+/// jitdump it wrote.
+///
+/// The code of a perf map isn't part of any module, so this only implements
+/// [`SymbolProvider::fill_jit_symbol`]. It's meant to be added to a
+/// [`MultiSymbolProvider`][super::MultiSymbolProvider] along with the providers of the
+/// modules' symbols.
+#[derive(Debug, Default)]
+pub struct PerfMapSymbolProvider {
+    /// The functions, by start address, with their end address and name.
+    functions: BTreeMap<u64, (u64, String)>,
+}
+
+impl PerfMapSymbolProvider {
+    /// Load the perf map and the jitdump of the process `pid` from `dir`, i.e.
+    /// `<dir>/perf-<pid>.map` and `<dir>/jit-<pid>.dump`, whichever exist.
+    pub fn for_process(dir: &Path, pid: u32) -> io::Result<Self> {
+        let mut provider = Self::default();
+        let perf_map = open_if_exists(&dir.join(format!("perf-{pid}.map")))?;
+        let jitdump = open_if_exists(&dir.join(format!("jit-{pid}.dump")))?;
+        if perf_map.is_none() && jitdump.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("neither perf-{pid}.map nor jit-{pid}.dump exists"),
+            ));
+        }
+        if let Some(file) = perf_map {
+            provider.read_perf_map(BufReader::new(file))?;
+        }
+        if let Some(file) = jitdump {
+            provider.read_jitdump(BufReader::new(file))?;
+        }
+        Ok(provider)
+    }
+
+    /// Read a perf map.
+    ///
+    /// Lines that aren't `START SIZE name` are skipped. When the same start address appears
+    /// more than once (because the JIT reused the memory), the last line wins.
+    pub fn from_reader(reader: impl BufRead) -> io::Result<Self> {
+        let mut provider = Self::default();
+        provider.read_perf_map(reader)?;
+        Ok(provider)
+    }
+
+    /// Read a jitdump.
+    ///
+    /// Code that was moved is symbolicated at its new address. A truncated last record, e.g.
+    /// from a JIT that crashed while writing it, is ignored.
+    pub fn from_jitdump(reader: impl Read) -> io::Result<Self> {
+        let mut provider = Self::default();
+        provider.read_jitdump(reader)?;
+        Ok(provider)
+    }
+
+    fn read_perf_map(&mut self, reader: impl BufRead) -> io::Result<()> {
+        for line in reader.lines() {
+            let line = line?;
+            match parse_line(&line) {
+                Some((start, size, name)) => self.insert(start, size, name.to_owned()),
+                None => trace!("perf map: skipping line {line:?}"),
+            }
+        }
+        Ok(())
+    }
+
+    fn read_jitdump(&mut self, mut reader: impl Read) -> io::Result<()> {
+        let mut header = [0; JITDUMP_HEADER_SIZE as usize];
+        reader.read_exact(&mut header)?;
+        let endian = match header.pread_with::<u32>(0, LE) {
+            Ok(JITDUMP_MAGIC) => LE,
+            Ok(magic) if magic.swap_bytes() == JITDUMP_MAGIC => BE,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not a jitdump file",
+                ))
+            }
+        };
+        let header_size: u32 = header.pread_with(8, endian).unwrap_or_default();
+        skip(&mut reader, header_size.saturating_sub(JITDUMP_HEADER_SIZE))?;
+
+        loop {
+            let mut record = [0; JITDUMP_RECORD_HEADER_SIZE as usize];
+            match reader.read_exact(&mut record) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+            let id: u32 = record.pread_with(0, endian).unwrap_or_default();
+            let size: u32 = record.pread_with(4, endian).unwrap_or_default();
+            let body_size = size.saturating_sub(JITDUMP_RECORD_HEADER_SIZE) as usize;
+            let mut body = Vec::new();
+            (&mut reader)
+                .take(body_size as u64)
+                .read_to_end(&mut body)?;
+            if body.len() < body_size {
+                trace!("jitdump: skipping the truncated last record");
+                break;
+            }
+            match id {
+                JIT_CODE_LOAD => self.code_load(&body, endian),
+                JIT_CODE_MOVE => self.code_move(&body, endian),
+                _ => None,
+            };
+        }
+        Ok(())
+    }
+
+    /// Add the function of a `JIT_CODE_LOAD` record: pid, tid, vma, code address, code size,
+    /// code index, the NUL-terminated name, and the code.
+    fn code_load(&mut self, body: &[u8], endian: Endian) -> Option<()> {
+        let start: u64 = body.pread_with(16, endian).ok()?;
+        let size: u64 = body.pread_with(24, endian).ok()?;
+        let name = body.get(40..)?.split(|&b| b == 0).next()?;
+        self.insert(start, size, String::from_utf8_lossy(name).into_owned());
+        Some(())
+    }
+
+    /// Move a function as a `JIT_CODE_MOVE` record says: pid, tid, vma, old code address, new
+    /// code address, code size and code index.
+    fn code_move(&mut self, body: &[u8], endian: Endian) -> Option<()> {
+        let old: u64 = body.pread_with(16, endian).ok()?;
+        let new: u64 = body.pread_with(24, endian).ok()?;
+        let size: u64 = body.pread_with(32, endian).ok()?;
+        let (_, name) = self.functions.remove(&old)?;
+        self.insert(new, size, name);
+        Some(())
+    }
+
+    /// Add the function `name` of `size` bytes at `start`, replacing the one that was there.
+    fn insert(&mut self, start: u64, size: u64, name: String) {
+        if size > 0 {
+            self.functions
+                .insert(start, (start.saturating_add(size), name));
+        }
+    }
+
+    /// The function containing `address`, with its start address.
+    fn function_at(&self, address: u64) -> Option<(u64, &str)> {
+        let (&start, (end, name)) = self.functions.range(..=address).next_back()?;
+        (address < *end).then_some((start, name.as_str()))
+    }
+}
+
+/// Open the file at `path`, `None` if it doesn't exist.
+fn open_if_exists(path: &Path) -> io::Result<Option<File>> {
+    match File::open(path) {
+        Ok(file) => {
+            trace!("perf map: loading {}", path.display());
+            Ok(Some(file))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Skip `len` bytes of `reader`.
+fn skip(reader: &mut impl Read, len: u32) -> io::Result<()> {
+    io::copy(&mut reader.take(len as u64), &mut io::sink())?;
+    Ok(())
+}
+
+/// Parse a `START SIZE name` line.
+fn parse_line(line: &str) -> Option<(u64, u64, &str)> {
+    let hex = |value: &str| {
+        let value = value.strip_prefix("0x").unwrap_or(value);
+        u64::from_str_radix(value, 16).ok()
+    };
+    let mut parts = line.trim_end().splitn(3, ' ');
+    let start = hex(parts.next()?)?;
+    let size = hex(parts.next()?)?;
+    let name = parts.next().filter(|name| !name.is_empty())?;
+    Some((start, size, name))
+}
+
+#[async_trait]
+impl SymbolProvider for PerfMapSymbolProvider {
+    async fn fill_symbol(
+        &self,
+        _module: &(dyn Module + Sync),
+        _frame: &mut (dyn FrameSymbolizer + Send),
+    ) -> Result<(), FillSymbolError> {
+        Err(FillSymbolError {})
+    }
+
+    async fn fill_jit_symbol(
+        &self,
+        frame: &mut (dyn FrameSymbolizer + Send),
+    ) -> Result<(), FillSymbolError> {
+        let (start, name) = self
+            .function_at(frame.get_instruction())
+            .ok_or(FillSymbolError {})?;
+        frame.set_function(name, start, 0);
+        Ok(())
+    }
+
+    async fn walk_frame(
+        &self,
+        _module: &(dyn Module + Sync),
+        _walker: &mut (dyn FrameWalker + Send),
+    ) -> Option<()> {
+        None
+    }
+
+    async fn get_file_path(
+        &self,
+        _module: &(dyn Module + Sync),
+        _file_kind: FileKind,
+    ) -> Result<PathBuf, FileError> {
+        Err(FileError::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Default)]
+    struct TestFrame {
+        instruction: u64,
+        function: Option<(String, u64)>,
+    }
+
+    impl FrameSymbolizer for TestFrame {
+        fn get_instruction(&self) -> u64 {
+            self.instruction
+        }
+        fn set_function(&mut self, name: &str, base: u64, _parameter_size: u32) {
+            self.function = Some((name.to_owned(), base));
+        }
+        fn set_source_file(&mut self, _file: &str, _line: u32, _base: u64) {}
+    }
+
+    async fn symbolicate(provider: &PerfMapSymbolProvider, instruction: u64) -> Option<String> {
+        let mut frame = TestFrame {
+            instruction,
+            ..TestFrame::default()
+        };
+        let result = provider.fill_jit_symbol(&mut frame).await;
+        // Only found functions are Ok.
+        assert_eq!(result.is_ok(), frame.function.is_some());
+        frame
+            .function
+            .map(|(name, base)| format!("{name} @ {base:#x}"))
+    }
+
+    /// A jitdump in the byte order of `endian`, with `records` of (id, body).
+    fn jitdump(endian: Endian, records: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let u32_bytes = |value: u32| match endian {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        };
+        // The header: magic, version, header size, ELF machine, padding, pid, timestamp
+        // and flags.
+        let mut dump = Vec::new();
+        for value in [JITDUMP_MAGIC, 1, JITDUMP_HEADER_SIZE, 62, 0, 1234] {
+            dump.extend_from_slice(&u32_bytes(value));
+        }
+        dump.extend_from_slice(&[0; 16]);
+        for (id, body) in records {
+            dump.extend_from_slice(&u32_bytes(*id));
+            dump.extend_from_slice(&u32_bytes(JITDUMP_RECORD_HEADER_SIZE + body.len() as u32));
+            dump.extend_from_slice(&[0; 8]);
+            dump.extend_from_slice(body);
+        }
+        dump
+    }
+
+    /// The body of a record made of `values` in the byte order of `endian`, followed by
+    /// `rest`.
+    fn body(endian: Endian, values: &[u64], rest: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for &value in values {
+            body.extend_from_slice(&match endian {
+                Endian::Little => value.to_le_bytes(),
+                Endian::Big => value.to_be_bytes(),
+            });
+        }
+        body.extend_from_slice(rest);
+        body
+    }
+
+    /// A `JIT_CODE_LOAD` record of `name` with `size` bytes of code at `address`.
+    fn code_load(endian: Endian, address: u64, size: u64, name: &str) -> (u32, Vec<u8>) {
+        let mut rest = name.as_bytes().to_vec();
+        rest.push(0);
+        rest.resize(rest.len() + size as usize, 0x90);
+        // The pid and tid, then the vma, code address, code size and code index.
+        (
+            JIT_CODE_LOAD,
+            body(endian, &[0, address, address, size, 1], &rest),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_perf_map() {
+        let map = "\
+3ef414c0 398 RegExp:[{(]
+0x7f3a1c0 0x1a4 LazyCompile:~main /srv/app/index.js:1
+not a perf map line
+7f3a400 40 Stub:old
+7f3a400 80 Stub:new
+";
+        let provider = PerfMapSymbolProvider::from_reader(map.as_bytes()).unwrap();
+
+        assert_eq!(
+            symbolicate(&provider, 0x3ef414c0).await.as_deref(),
+            Some("RegExp:[{(] @ 0x3ef414c0")
+        );
+        assert_eq!(
+            symbolicate(&provider, 0x7f3a1c0 + 0x1a3).await.as_deref(),
+            Some("LazyCompile:~main /srv/app/index.js:1 @ 0x7f3a1c0")
+        );
+        assert_eq!(symbolicate(&provider, 0x7f3a1c0 + 0x1a4).await, None);
+        assert_eq!(
+            symbolicate(&provider, 0x7f3a470).await.as_deref(),
+            Some("Stub:new @ 0x7f3a400")
+        );
+        assert_eq!(symbolicate(&provider, 0x1000).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_jitdump() {
+        for endian in [LE, BE] {
+            let records = [
+                code_load(
+                    endian,
+                    0x7f00_1000,
+                    0x40,
+                    "LazyCompile:*add /srv/app/math.js:3",
+                ),
+                code_load(
+                    endian,
+                    0x7f00_2000,
+                    0x80,
+                    "Builtin:ArgumentsAdaptorTrampoline",
+                ),
+                // The second function is moved to 0x7f00_3000.
+                (
+                    JIT_CODE_MOVE,
+                    body(endian, &[0, 0, 0x7f00_2000, 0x7f00_3000, 0x80, 2], &[]),
+                ),
+                // Debug info, which isn't used.
+                (2, body(endian, &[0x7f00_1000, 0], &[])),
+            ];
+            let mut dump = jitdump(endian, &records);
+            // A record the JIT didn't finish writing.
+            dump.extend_from_slice(
+                &jitdump(endian, &[code_load(endian, 0x7f00_4000, 0x10, "x")])[40..60],
+            );
+            let provider = PerfMapSymbolProvider::from_jitdump(&dump[..]).unwrap();
+
+            assert_eq!(
+                symbolicate(&provider, 0x7f00_1010).await.as_deref(),
+                Some("LazyCompile:*add /srv/app/math.js:3 @ 0x7f001000")
+            );
+            assert_eq!(symbolicate(&provider, 0x7f00_2010).await, None);
+            assert_eq!(
+                symbolicate(&provider, 0x7f00_307f).await.as_deref(),
+                Some("Builtin:ArgumentsAdaptorTrampoline @ 0x7f003000")
+            );
+            assert_eq!(symbolicate(&provider, 0x7f00_4000).await, None);
+        }
+
+        assert!(
+            PerfMapSymbolProvider::from_jitdump(&b"not a jitdump, just some text"[..]).is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_for_process() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            PerfMapSymbolProvider::for_process(dir.path(), 1234)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+
+        // The perf map and the jitdump are both used.
+        std::fs::write(
+            dir.path().join("perf-1234.map"),
+            "1000 10 perf_map_function\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("jit-1234.dump"),
+            jitdump(LE, &[code_load(LE, 0x2000, 0x10, "jitdump_function")]),
+        )
+        .unwrap();
+        let provider = PerfMapSymbolProvider::for_process(dir.path(), 1234).unwrap();
+        assert_eq!(
+            symbolicate(&provider, 0x1000).await.as_deref(),
+            Some("perf_map_function @ 0x1000")
+        );
+        assert_eq!(
+            symbolicate(&provider, 0x2000).await.as_deref(),
+            Some("jitdump_function @ 0x2000")
+        );
+    }
+}
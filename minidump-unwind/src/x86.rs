@@ -8,7 +8,7 @@
 
 use super::impl_prelude::*;
 use minidump::format::CONTEXT_X86;
-use minidump::{MinidumpContext, MinidumpContextValidity, MinidumpModuleList, MinidumpRawContext};
+use minidump::{CpuContext, MinidumpContext, MinidumpContextValidity, MinidumpRawContext};
 use std::collections::HashSet;
 use tracing::trace;
 
@@ -18,6 +18,64 @@ const INSTRUCTION_REGISTER: &str = "eip";
 const STACK_POINTER_REGISTER: &str = "esp";
 const FRAME_POINTER_REGISTER: &str = "ebp";
 const CALLEE_SAVED_REGS: &[&str] = &["ebp", "ebx", "edi", "esi"];
+/// The code of Linux's `__restore_rt` and `__kernel_rt_sigreturn` signal trampolines:
+/// `mov eax, 173 (rt_sigreturn); int 0x80`.
+const SIGRETURN_CODE: &[u8] = &[0xb8, 0xad, 0x00, 0x00, 0x00, 0xcd, 0x80];
+/// The offset of the `puc` pointer to the `ucontext_t` from the stack pointer of the signal
+/// trampoline.
+const SIGNAL_CONTEXT_POINTER_OFFSET: Pointer = 8;
+/// The offset of `uc_mcontext` in the `ucontext_t`.
+const SIGNAL_CONTEXT_OFFSET: Pointer = 20;
+/// The registers in `uc_mcontext`, with their index in it.
+const SIGNAL_CONTEXT_REGS: &[(&str, Pointer)] = &[
+    ("edi", 4),
+    ("esi", 5),
+    ("ebp", 6),
+    ("esp", 7),
+    ("ebx", 8),
+    ("edx", 9),
+    ("ecx", 10),
+    ("eax", 11),
+    ("eip", 14),
+    ("eflags", 16),
+];
+
+async fn get_caller_by_signal_context<P>(
+    ctx: &CONTEXT_X86,
+    args: &GetCallerFrameArgs<'_, P>,
+) -> Option<StackFrame>
+where
+    P: SymbolProvider + Sync,
+{
+    if !args.callee_is_signal_trampoline(SIGRETURN_CODE).await {
+        return None;
+    }
+    trace!("trying signal context");
+
+    // The handler returned to the trampoline, popping its return address, which leaves the
+    // stack pointer at the signal number, followed by pointers to the `siginfo_t` and the
+    // `ucontext_t` the kernel saved the interrupted context in.
+    let ucontext: Pointer = args
+        .stack_memory
+        .get_memory_at_address(ctx.esp.checked_add(SIGNAL_CONTEXT_POINTER_OFFSET)? as u64)?;
+    let address = ucontext.checked_add(SIGNAL_CONTEXT_OFFSET)?;
+    let mut caller_ctx = CONTEXT_X86::default();
+    for &(reg, index) in SIGNAL_CONTEXT_REGS {
+        let value = args
+            .stack_memory
+            .get_memory_at_address(address.checked_add(index * POINTER_WIDTH)? as u64)?;
+        caller_ctx.set_register(reg, value)?;
+    }
+    args.note(UnwindNote::SignalContext {
+        address: address as u64,
+    });
+
+    let context = MinidumpContext {
+        raw: MinidumpRawContext::X86(caller_ctx),
+        valid: MinidumpContextValidity::All,
+    };
+    Some(StackFrame::from_context(context, FrameTrust::SignalContext))
+}
 
 async fn get_caller_by_cfi<P>(
     ctx: &CONTEXT_X86,
@@ -36,9 +94,7 @@ where
 
     let mut stack_walker = CfiStackWalker::from_ctx_and_args(ctx, args, callee_forwarded_regs)?;
 
-    args.symbol_provider
-        .walk_frame(stack_walker.module, &mut stack_walker)
-        .await?;
+    stack_walker.walk(args).await?;
     let caller_ip = stack_walker.caller_ctx.eip;
     let caller_sp = stack_walker.caller_ctx.esp;
 
@@ -162,6 +218,7 @@ where
         raw: MinidumpRawContext::X86(caller_ctx),
         valid: MinidumpContextValidity::Some(valid),
     };
+    args.note_return_address(last_bp as u64 + POINTER_WIDTH as u64);
     Some(StackFrame::from_context(context, FrameTrust::FramePointer))
 }
 
@@ -201,18 +258,23 @@ where
 
     // Breakpad devs found that the first frame of an unwind can be really messed up,
     // and therefore benefits from a longer scan. Let's do it too.
-    let scan_range = if let FrameTrust::Context = args.callee_frame.trust {
-        extended_scan_range
-    } else {
-        default_scan_range
-    };
+    let scan_range =
+        if let FrameTrust::Context | FrameTrust::SignalContext = args.callee_frame.trust {
+            extended_scan_range
+        } else {
+            default_scan_range
+        };
 
     for i in 0..scan_range {
         let address_of_ip = last_sp.checked_add(i * POINTER_WIDTH)?;
         let caller_ip = args
             .stack_memory
             .get_memory_at_address(address_of_ip as u64)?;
-        if instruction_seems_valid(caller_ip, args.modules, args.symbol_provider).await {
+        if let Some(trust) = args.accept_scan_candidate(
+            address_of_ip as u64,
+            caller_ip as u64,
+            instruction_seems_valid(caller_ip, args).await,
+        ) {
             // ip is pushed by CALL, so sp is just address_of_ip + ptr
             let caller_sp = address_of_ip.checked_add(POINTER_WIDTH)?;
 
@@ -292,7 +354,7 @@ where
                 raw: MinidumpRawContext::X86(caller_ctx),
                 valid: MinidumpContextValidity::Some(valid),
             };
-            return Some(StackFrame::from_context(context, FrameTrust::Scan));
+            return Some(StackFrame::from_context(context, trust));
         }
     }
 
@@ -324,17 +386,18 @@ where
 /// and immediately start doing unreliable scans.
 async fn instruction_seems_valid<P>(
     instruction: Pointer,
-    modules: &MinidumpModuleList,
-    symbol_provider: &P,
-) -> bool
+    args: &GetCallerFrameArgs<'_, P>,
+) -> Result<FrameTrust, ScanRejection>
 where
     P: SymbolProvider + Sync,
 {
     if instruction == 0 {
-        return false;
+        return Err(ScanRejection::Null);
     }
 
-    super::instruction_seems_valid_by_symbols(instruction as u64, modules, symbol_provider).await
+    super::instruction_seems_valid_by_symbols(instruction as u64, args).await?;
+    args.check_call_site(instruction as u64, call_site::is_x86_call)
+        .await
 }
 
 /*
@@ -366,6 +429,9 @@ where
 {
     // .await doesn't like closures, so don't use Option chaining
     let mut frame = None;
+    if frame.is_none() {
+        frame = get_caller_by_signal_context(ctx, args).await;
+    }
     if frame.is_none() {
         frame = get_caller_by_cfi(ctx, args).await;
     }
@@ -402,8 +468,12 @@ where
     // the value to one less than that, so it points within the
     // CALL instruction. This is important because we use this value
     // to lookup the CFI we need to unwind the next frame.
+    //
+    // A signal context's ip is the interrupted instruction itself, so it's left alone.
     let ip = frame.context.get_instruction_pointer();
-    frame.instruction = ip - 1;
+    if frame.trust != FrameTrust::SignalContext {
+        frame.instruction = ip - 1;
+    }
 
     Some(frame)
 }